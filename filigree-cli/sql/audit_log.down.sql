@@ -0,0 +1 @@
+DROP TABLE {{model_schema}}.audit_log;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS {{model_schema}}.audit_log (
+  id bigserial PRIMARY KEY,
+  organization_id {{auth.id_sql_type}},
+  actor_id uuid,
+  object_type text NOT NULL,
+  object_id uuid NOT NULL,
+  operation text NOT NULL,
+  data_before jsonb,
+  data_after jsonb,
+  request_id uuid,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_object ON {{model_schema}}.audit_log (object_id, created_at, id);
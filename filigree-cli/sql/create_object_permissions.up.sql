@@ -1,6 +1,6 @@
 CREATE TABLE {{auth_schema}}.object_permissions (
   organization_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.organizations (id) ON DELETE CASCADE{% endif %},
+    {{auth_ref_prefix}}organizations (id) ON DELETE CASCADE{% endif %},
   actor_id {{auth.id_sql_type}} NOT NULL,
   object_id uuid NOT NULL,
   permission text NOT NULL,
@@ -3,5 +3,5 @@ CREATE TABLE IF NOT EXISTS {{model_schema}}.delete_log (
   object_id uuid NOT NULL,
   object_type text NOT NULL,
   data jsonb NOT NULL,
-  deleted_at timestamptz NOT NULL DEFAULT now()
+  deleted_at timestamptz NOT NULL DEFAULT {% if sql_dialect == "sqlite" %}(unixepoch()){% else %}now(){% endif %}
 );
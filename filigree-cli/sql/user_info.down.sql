@@ -1,5 +1,17 @@
 {% if auth.builtin %} DROP TABLE {{auth_schema}}.user_invites;
 
+DROP TABLE {{auth_schema}}.saml_requests;
+
+DROP TABLE {{auth_schema}}.organization_saml_providers;
+
+DROP TABLE {{auth_schema}}.webauthn_challenges;
+
+DROP TABLE {{auth_schema}}.webauthn_credentials;
+
+DROP TABLE {{auth_schema}}.user_mfa_recovery_codes;
+
+DROP TABLE {{auth_schema}}.user_mfa;
+
 DROP TABLE {{auth_schema}}.oauth_authorization_sessions;
 
 DROP TABLE {{auth_schema}}.oauth_logins;
@@ -1,12 +1,12 @@
 CREATE TABLE {{auth_schema}}.user_roles (
   organization_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.organizations (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}organizations (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   user_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   role_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.roles (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}roles (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   PRIMARY KEY (organization_id, user_id, role_id)
 );
@@ -14,44 +14,56 @@ CREATE TABLE {{auth_schema}}.user_roles (
 CREATE TABLE {{auth_schema}}.user_sessions (
   id {{auth.id_sql_type}} PRIMARY KEY,
   user_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE,
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE,
   hash uuid NOT NULL{% endif %},
-  expires_at timestamptz NOT NULL
+  expires_at timestamptz NOT NULL{% if auth.builtin %},
+  -- The user has entered their password but not their second factor yet. These sessions can only
+  -- be used to finish logging in.
+  mfa_pending boolean NOT NULL DEFAULT FALSE,
+  -- The number of wrong second factor codes submitted for a pending session
+  mfa_failed_attempts int NOT NULL DEFAULT 0{% endif %}
 );
 
 {% if auth.builtin %}
 -- A list of users and what organizations they belong to. Users can potentially be in more than one organization.
 CREATE TABLE {{auth_schema}}.organization_members (
   organization_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.organizations (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}organizations (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   user_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   active boolean NOT NULL DEFAULT TRUE,
+  -- The user's ID in the organization's identity provider, when provisioned through SCIM
+  scim_external_id text,
   PRIMARY KEY (organization_id, user_id)
 );
 
-CREATE INDEX user_sessions_user_id ON {{auth_schema}}.user_sessions (user_id);
+CREATE UNIQUE INDEX organization_members_scim_external_id ON
+  {{auth_ref_prefix}}organization_members (organization_id, scim_external_id)
+  WHERE scim_external_id IS NOT NULL;
+
+CREATE INDEX user_sessions_user_id ON {{auth_ref_prefix}}user_sessions (user_id);
 
 CREATE TABLE {{auth_schema}}.api_keys (
   api_key_id uuid PRIMARY KEY,
   hash bytea NOT NULL,
   organization_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.organizations (id) ON DELETE CASCADE{% endif %},
+    {{auth_ref_prefix}}organizations (id) ON DELETE CASCADE{% endif %},
   user_id {{auth.id_sql_type}} {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE{% endif %},
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE{% endif %},
   inherits_user_permissions bool NOT NULL DEFAULT FALSE,
   description text NOT NULL DEFAULT '',
   active boolean NOT NULL DEFAULT TRUE,
-  expires_at timestamptz NOT NULL
+  expires_at timestamptz NOT NULL,
+  scim bool NOT NULL DEFAULT FALSE
 );
 
 -- Methods for a user to log in.
 CREATE TABLE {{auth_schema}}.email_logins (
   email text PRIMARY KEY,
   user_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   verified bool NOT NULL,
   reset_token uuid,
@@ -60,18 +72,18 @@ CREATE TABLE {{auth_schema}}.email_logins (
   passwordless_login_expires_at timestamptz
 );
 
-CREATE INDEX email_logins_user_id ON {{auth_schema}}.email_logins (user_id);
+CREATE INDEX email_logins_user_id ON {{auth_ref_prefix}}email_logins (user_id);
 
 CREATE TABLE {{auth_schema}}.oauth_logins (
   oauth_provider text NOT NULL,
   oauth_account_id text NOT NULL,
   user_id {{auth.id_sql_type}} NOT NULL {% if auth.builtin %}REFERENCES
-    {{auth_schema}}.users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
+    {{auth_ref_prefix}}users (id) ON DELETE CASCADE DEFERRABLE INITIALLY
     IMMEDIATE{% endif %},
   PRIMARY KEY (oauth_provider, oauth_account_id)
 );
 
-CREATE INDEX oauth_logins_user_id ON {{auth_schema}}.oauth_logins (user_id);
+CREATE INDEX oauth_logins_user_id ON {{auth_ref_prefix}}oauth_logins (user_id);
 
 CREATE TABLE {{auth_schema}}.oauth_authorization_sessions (
   key text PRIMARY KEY,
@@ -82,6 +94,80 @@ CREATE TABLE {{auth_schema}}.oauth_authorization_sessions (
   expires_at timestamptz NOT NULL
 );
 
+-- Two-factor authentication settings for each user
+CREATE TABLE {{auth_schema}}.user_mfa (
+  user_id {{auth.id_sql_type}} PRIMARY KEY REFERENCES {{auth_ref_prefix}}users (id) ON
+    DELETE CASCADE,
+  -- The base32-encoded TOTP secret
+  totp_secret text NOT NULL,
+  -- False until the user confirms the enrollment with a valid code
+  enabled boolean NOT NULL DEFAULT FALSE,
+  -- The most recent TOTP time step used to log in, to prevent a code from being reused.
+  last_used_step bigint,
+  created_at timestamptz NOT NULL DEFAULT {% if sql_dialect == "sqlite" %}(unixepoch()){% else %}now(){% endif %}
+);
+
+-- One-time codes for when the user can not access their authenticator.
+CREATE TABLE {{auth_schema}}.user_mfa_recovery_codes (
+  user_id {{auth.id_sql_type}} NOT NULL REFERENCES {{auth_ref_prefix}}users (id) ON
+    DELETE CASCADE,
+  code_hash bytea NOT NULL,
+  used_at timestamptz,
+  PRIMARY KEY (user_id, code_hash)
+);
+
+-- WebAuthn credentials (passkeys) registered by each user
+CREATE TABLE {{auth_schema}}.webauthn_credentials (
+  -- The credential ID chosen by the authenticator
+  id bytea PRIMARY KEY,
+  user_id {{auth.id_sql_type}} NOT NULL REFERENCES {{auth_ref_prefix}}users (id) ON
+    DELETE CASCADE,
+  -- The credential's public key, in COSE format
+  public_key bytea NOT NULL,
+  -- The COSE algorithm identifier for the public key
+  algorithm int NOT NULL,
+  -- The authenticator's signature counter, used to detect cloned authenticators.
+  sign_count bigint NOT NULL DEFAULT 0,
+  name text NOT NULL DEFAULT '',
+  created_at timestamptz NOT NULL DEFAULT {% if sql_dialect == "sqlite" %}(unixepoch()){% else %}now(){% endif %},
+  last_used_at timestamptz
+);
+
+CREATE INDEX webauthn_credentials_user_id ON {{auth_ref_prefix}}webauthn_credentials (user_id);
+
+-- Challenges for WebAuthn ceremonies that are in progress
+CREATE TABLE {{auth_schema}}.webauthn_challenges (
+  challenge bytea PRIMARY KEY,
+  -- The user registering a credential. This is NULL for logins, since the user is not known yet.
+  user_id {{auth.id_sql_type}} REFERENCES {{auth_ref_prefix}}users (id) ON DELETE CASCADE,
+  -- 'register' or 'login'
+  purpose text NOT NULL,
+  expires_at timestamptz NOT NULL
+);
+
+-- SAML identity provider settings for organizations that use single sign-on
+CREATE TABLE {{auth_schema}}.organization_saml_providers (
+  organization_id {{auth.id_sql_type}} PRIMARY KEY REFERENCES {{auth_ref_prefix}}organizations (id)
+    ON DELETE CASCADE,
+  idp_entity_id text NOT NULL,
+  -- The IdP's login URL, for the HTTP-Redirect binding
+  idp_sso_url text NOT NULL,
+  -- The IdP's DER-encoded signing certificate
+  idp_certificate bytea NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT {% if sql_dialect == "sqlite" %}(unixepoch()){% else %}now(){% endif %},
+  updated_at timestamptz NOT NULL DEFAULT {% if sql_dialect == "sqlite" %}(unixepoch()){% else %}now(){% endif %}
+);
+
+-- SAML logins that are in progress
+CREATE TABLE {{auth_schema}}.saml_requests (
+  -- The ID of the AuthnRequest
+  id text PRIMARY KEY,
+  organization_id {{auth.id_sql_type}} NOT NULL REFERENCES {{auth_ref_prefix}}organizations (id)
+    ON DELETE CASCADE,
+  redirect_to text,
+  expires_at timestamptz NOT NULL
+);
+
 CREATE TABLE {{auth_schema}}.user_invites (
   email text NOT NULL,
   token uuid NOT NULL,
@@ -94,11 +180,22 @@ CREATE TABLE {{auth_schema}}.user_invites (
   organization_id {{auth.id_sql_type}},
   -- The roles that the user will be added with, if inviting to an existing organization.
   -- If omitted, the organization's default role will be used.
+{% if sql_dialect == "sqlite" %}
+  -- A JSON array of role IDs
+  role_ids text,
+  invite_sent_at timestamptz NOT NULL DEFAULT (unixepoch())
+);
+
+-- SQLite has no NULLS NOT DISTINCT, so index on an expression instead.
+CREATE UNIQUE INDEX user_invites_email_org ON user_invites (email,
+  ifnull(organization_id, ''));
+{% else %}
   role_ids {{auth.id_sql_type}}[],
   invite_sent_at timestamptz NOT NULL DEFAULT now()
 );
 
 CREATE UNIQUE INDEX user_invites_email_org ON {{auth_schema}}.user_invites (email,
   organization_id) NULLS NOT DISTINCT;
+{% endif %}
 
 {% endif %}
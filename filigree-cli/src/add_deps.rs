@@ -6,6 +6,7 @@ use semver::{Version, VersionReq};
 
 use crate::{
     config::{AuthProvider, Config, EmailProvider},
+    model::SqlDialect,
     Error,
 };
 
@@ -97,6 +98,14 @@ pub fn add_fixed_deps(
     if !config.storage.bucket.is_empty() || !config.storage.provider.is_empty() {
         filigree_features.push("storage");
         filigree_features.push("storage_aws");
+
+        for provider in config.storage.provider.values() {
+            match provider.storage_type() {
+                "gcs" => filigree_features.push("storage_gcp"),
+                "azure" => filigree_features.push("storage_azure"),
+                _ => {}
+            }
+        }
     }
 
     match &config.email.provider {
@@ -104,10 +113,41 @@ pub fn add_fixed_deps(
         EmailProvider::Resend => {
             filigree_features.push("resend");
         }
+        EmailProvider::Postmark => {
+            filigree_features.push("postmark");
+        }
+        EmailProvider::Sendgrid => {
+            filigree_features.push("sendgrid");
+        }
+        EmailProvider::Ses => {
+            filigree_features.push("ses");
+        }
+        EmailProvider::Mailgun => {
+            filigree_features.push("mailgun");
+        }
+        EmailProvider::Smtp => {
+            filigree_features.push("smtp");
+        }
+    }
+
+    if config.email.inbound {
+        filigree_features.push("inbound_email");
+    }
+
+    if config.use_webhooks {
+        filigree_features.push("webhooks");
+    }
+
+    if config.use_image_processing {
+        filigree_features.push("images");
     }
 
     filigree_features.extend(config.web.filigree_features());
 
+    if config.sql_dialect == SqlDialect::SQLite {
+        filigree_features.push("sqlite");
+    }
+
     add_dep_with_default_features(
         cwd,
         manifest,
@@ -121,6 +161,10 @@ pub fn add_fixed_deps(
         add_dep(cwd, manifest, name, version, features)?;
     }
 
+    if config.sql_dialect == SqlDialect::SQLite {
+        add_dep(cwd, manifest, "sqlx", "0.8.0", &["sqlite"])?;
+    }
+
     if config.use_queue {
         crate::config::job::add_deps(cwd, manifest)?;
     }
@@ -79,17 +79,17 @@ pub struct Config {
 
     #[serde(default)]
     pub formatter: FormatterConfig,
-    // Maybe support SQLite some day
-    // /// The SQL dialect to use. Defaults to postgresql
-    // #[serde(default = "Config::default_sql_dialect")]
-    // pub sql_dialect: SqlDialect,
+    /// The SQL dialect to use, either "postgresql" or "sqlite". Defaults to postgresql.
+    #[serde(default = "Config::default_sql_dialect")]
+    pub sql_dialect: SqlDialect,
     /// TODO put this in a models config, along with the create permission setting
     /// The auth scope for models that don't specify a different one.
     pub default_auth_scope: ModelAuthScope,
-    // TODO implement this
-    // /// Set to true to enable project-based object organization
-    // #[serde(default)]
-    // pub use_projects: bool,
+    /// Set to true to enable project-based object organization. This adds a built-in `Project`
+    /// model, and models can then set `auth_scope = "project"` to inherit their permissions from
+    /// the user's permissions on the containing project.
+    #[serde(default)]
+    pub use_projects: bool,
     /// Configuration for the database
     #[serde(default)]
     pub database: DatabaseConfig,
@@ -124,6 +124,14 @@ pub struct Config {
 
     #[serde(skip)]
     pub(crate) use_queue: bool,
+
+    /// Set when any model has webhooks enabled
+    #[serde(skip)]
+    pub(crate) use_webhooks: bool,
+
+    /// Set when any file model has image processing enabled
+    #[serde(skip)]
+    pub(crate) use_image_processing: bool,
 }
 
 impl Config {
@@ -144,6 +152,28 @@ impl Config {
     pub const fn default_sql_dialect() -> SqlDialect {
         SqlDialect::Postgresql
     }
+
+    /// The schema for model tables, falling back to the SQL dialect's default schema.
+    pub fn model_schema(&self) -> &str {
+        match self.sql_dialect {
+            SqlDialect::Postgresql => self
+                .database
+                .model_schema()
+                .unwrap_or(self.sql_dialect.default_schema()),
+            SqlDialect::SQLite => self.sql_dialect.default_schema(),
+        }
+    }
+
+    /// The schema for auth tables, falling back to the SQL dialect's default schema.
+    pub fn auth_schema(&self) -> &str {
+        match self.sql_dialect {
+            SqlDialect::Postgresql => self
+                .database
+                .auth_schema()
+                .unwrap_or(self.sql_dialect.default_schema()),
+            SqlDialect::SQLite => self.sql_dialect.default_schema(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -158,10 +188,10 @@ pub struct AuthConfig {
 }
 
 impl AuthConfig {
-    pub fn template_context(&self) -> serde_json::Value {
+    pub fn template_context(&self, dialect: SqlDialect) -> serde_json::Value {
         json!({
             "provider": &self.provider,
-            "id_sql_type": self.id_type().to_sql_type(SqlDialect::Postgresql),
+            "id_sql_type": self.id_type().to_sql_type(dialect),
             // This comes up a lot so we add a special flag for it.
             "builtin": matches!(self.provider, AuthProvider::BuiltIn),
             "has_default_models": self.has_default_models(),
@@ -282,6 +312,7 @@ pub struct DatabaseConfig {
     pub max_connections: u16,
 
     /// Place database tables in this PostgreSQL schema. Defaults to "public" if omitted.
+    /// This is ignored when using SQLite.
     pub model_schema: Option<String>,
 
     /// Place auth-related tables in this PostgreSQL schema. This includes
@@ -326,6 +357,19 @@ pub struct EmailConfig {
 
     /// The address that emails are sent from, if not otherwise specified.
     pub from: String,
+
+    /// Save outgoing emails to the database and send them from a background job, so that a
+    /// provider outage doesn't fail requests that send email. Failed sends are retried with
+    /// exponential backoff, and the delivery status of each email is recorded in the
+    /// `outbound_emails` table.
+    #[serde(default)]
+    pub outbox: bool,
+
+    /// Add webhook routes at `/api/inbound_email` that receive emails from Postmark, Mailgun,
+    /// Resend, or an SMTP relay, and pass them to `src/emails/inbound.rs`. Each route is enabled
+    /// by setting its signing secret in the environment.
+    #[serde(default)]
+    pub inbound: bool,
 }
 
 /// A choice of email service
@@ -336,6 +380,16 @@ pub enum EmailProvider {
     None,
     /// Send emails using Resend
     Resend,
+    /// Send emails using Postmark
+    Postmark,
+    /// Send emails using SendGrid
+    Sendgrid,
+    /// Send emails using Amazon SES
+    Ses,
+    /// Send emails using Mailgun
+    Mailgun,
+    /// Send emails through an SMTP server
+    Smtp,
 }
 
 impl std::fmt::Display for EmailProvider {
@@ -343,6 +397,11 @@ impl std::fmt::Display for EmailProvider {
         match self {
             EmailProvider::None => write!(f, "none"),
             EmailProvider::Resend => write!(f, "resend"),
+            EmailProvider::Postmark => write!(f, "postmark"),
+            EmailProvider::Sendgrid => write!(f, "sendgrid"),
+            EmailProvider::Ses => write!(f, "ses"),
+            EmailProvider::Mailgun => write!(f, "mailgun"),
+            EmailProvider::Smtp => write!(f, "smtp"),
         }
     }
 }
@@ -422,7 +481,7 @@ impl FullConfig {
 
         let dir = config_file_path.parent().ok_or(Error::ReadConfigFile)?;
 
-        let config = Config::from_path(&config_file_path)?;
+        let mut config = Config::from_path(&config_file_path)?;
 
         let base_dir = dir.parent().ok_or(Error::ReadConfigFile)?.to_path_buf();
         let api_dir = base_dir.join(&config.api_dir);
@@ -486,6 +545,37 @@ impl FullConfig {
             })
             .collect::<Result<Vec<_>, _>>()?;
 
+        config.use_webhooks = models.iter().any(|m| m.webhooks);
+        if config.use_webhooks {
+            // Webhook events are delivered through the job queue.
+            config
+                .job
+                .entry(job::WEBHOOK_DELIVERY_JOB.to_string())
+                .or_insert_with(job::JobConfig::webhook_delivery);
+            config.use_queue = true;
+        }
+
+        config.use_image_processing = models
+            .iter()
+            .any(|m| m.files.iter().any(|f| f.image.is_some()));
+        if config.use_image_processing {
+            // Images are resized in the background after they are uploaded.
+            config
+                .job
+                .entry(job::PROCESS_IMAGE_JOB.to_string())
+                .or_insert_with(job::JobConfig::process_image);
+            config.use_queue = true;
+        }
+
+        if config.email.outbox {
+            // Queued emails are sent through the job queue.
+            config
+                .job
+                .entry(job::SEND_EMAIL_JOB.to_string())
+                .or_insert_with(job::JobConfig::send_email);
+            config.use_queue = true;
+        }
+
         let state_dir = dir.join(".state");
 
         let mut state = State::from_dir(&state_dir);
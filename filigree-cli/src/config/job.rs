@@ -52,6 +52,9 @@ pub struct JobConfig {
     /// How long to wait for this job to run before retrying or failing.
     pub timeout: Option<std::time::Duration>,
 
+    /// How many times to retry this job if it fails. Uses the queue's default if not specified.
+    pub retries: Option<u32>,
+
     /// Whether or not to automatically heartbeat this job, to extend the timeout
     /// while it is still running.
     #[serde(default)]
@@ -68,6 +71,33 @@ pub struct JobConfig {
 }
 
 impl JobConfig {
+    /// The default configuration for [WEBHOOK_DELIVERY_JOB], if the job is not configured
+    /// explicitly. Failed deliveries are retried with backoff.
+    pub fn webhook_delivery() -> Self {
+        Self {
+            retries: Some(8),
+            ..Default::default()
+        }
+    }
+
+    /// The default configuration for [SEND_EMAIL_JOB], if the job is not configured explicitly.
+    /// Failed sends are retried with backoff.
+    pub fn send_email() -> Self {
+        Self {
+            retries: Some(8),
+            ..Default::default()
+        }
+    }
+
+    /// The default configuration for [PROCESS_IMAGE_JOB], if the job is not configured
+    /// explicitly.
+    pub fn process_image() -> Self {
+        Self {
+            retries: Some(3),
+            ..Default::default()
+        }
+    }
+
     pub fn template_context(&self, name: &str) -> serde_json::Value {
         json!({
             "name": name,
@@ -77,11 +107,19 @@ impl JobConfig {
             "priority": self.priority,
             "weight": self.weight,
             "timeout": self.timeout,
+            "retries": self.retries,
             "schedules": self.schedule.iter().map(|s| s.template_context()).collect::<Vec<_>>(),
         })
     }
 }
 
+/// The job that sends webhook events, added when any model has webhooks enabled
+pub const WEBHOOK_DELIVERY_JOB: &str = "webhook_delivery";
+/// The job that sends emails from the outbox, added when `email.outbox` is enabled
+pub const SEND_EMAIL_JOB: &str = "send_email";
+/// The job that resizes uploaded images, added when any file model has `image` set
+pub const PROCESS_IMAGE_JOB: &str = "process_image";
+
 fn default_priority() -> i32 {
     1
 }
@@ -64,6 +64,26 @@ pub enum StorageProviderConfig {
 }
 
 impl StorageProviderConfig {
+    /// The storage service type that this provider uses, as accepted by the `PROVIDER_TYPE`
+    /// environment variable.
+    pub fn storage_type(&self) -> &'static str {
+        use filigree::storage::StorageConfig;
+
+        match self {
+            Self::Preconfigured(StoragePreset::S3 { .. }) => "s3",
+            Self::Preconfigured(StoragePreset::DigitalOceanSpaces { .. }) => "s3",
+            Self::Preconfigured(StoragePreset::BackblazeB2 { .. }) => "s3",
+            Self::Preconfigured(StoragePreset::CloudflareR2 { .. }) => "s3",
+            Self::Preconfigured(StoragePreset::GoogleCloudStorage) => "gcs",
+            Self::Preconfigured(StoragePreset::AzureBlobStorage { .. }) => "azure",
+            Self::Custom(StorageConfig::S3(_)) => "s3",
+            Self::Custom(StorageConfig::Gcs(_)) => "gcs",
+            Self::Custom(StorageConfig::Azure(_)) => "azure",
+            Self::Custom(StorageConfig::Local(_)) => "local",
+            Self::Custom(StorageConfig::Memory) => "memory",
+        }
+    }
+
     /// Regenerate this structure as Rust code
     pub fn template_text(&self) -> String {
         match self {
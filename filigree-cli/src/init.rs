@@ -223,6 +223,7 @@ pub fn run(cmd: Command) -> Result<(), Report<Error>> {
 
         tracing: Default::default(),
         formatter: Default::default(),
+        sql_dialect: Config::default_sql_dialect(),
         database: DatabaseConfig {
             migrate_on_start: true,
             ..Default::default()
@@ -230,11 +231,14 @@ pub fn run(cmd: Command) -> Result<(), Report<Error>> {
         email: EmailConfig {
             provider: crate::config::EmailProvider::None,
             from: "support@example.com".to_string(),
+            outbox: false,
+            inbound: false,
         },
         auth: AuthConfig::default(),
         secrets: Default::default(),
         shared_types: Default::default(),
         default_auth_scope: crate::model::ModelAuthScope::Model,
+        use_projects: false,
         users: crate::config::UsersConfig::default(),
         extend: crate::config::ExtendConfig::default(),
         storage: crate::config::storage::StorageConfig::default(),
@@ -242,6 +246,8 @@ pub fn run(cmd: Command) -> Result<(), Report<Error>> {
         job: Default::default(),
         worker: Default::default(),
         use_queue: false,
+        use_webhooks: false,
+        use_image_processing: false,
     };
 
     // Create a basic Cargo.toml
@@ -4,7 +4,7 @@ use clap::{Parser, Subcommand};
 use error_stack::Report;
 use thiserror::Error;
 
-use crate::config::FullConfig;
+use crate::{config::FullConfig, model::ModelAuthScope};
 
 mod add_deps;
 mod config;
@@ -82,10 +82,26 @@ pub enum Error {
     JoinedModelWithHas(String),
     #[error("Model {0} is a joining model, but has fields, which is not currently supported")]
     JoinedModelWithFields(String),
+    #[error("Model {0} uses {1} auth scope, but global, child, and joining models must use model auth scope")]
+    InvalidAuthScope(String, ModelAuthScope),
+    #[error("Model {0} uses project auth scope, but use_projects is not enabled")]
+    ProjectsNotEnabled(String),
     #[error("Model {0}'s files configuration referenced nonexistent bucket {1}")]
     InvalidStorageBucket(String, String),
     #[error("Model {0} field {1} reference {2}")]
     FieldReferenceConfig(String, String, &'static str),
+    #[error("Model {0} field {1} is a search field, but {2}")]
+    SearchFieldConfig(String, String, &'static str),
+    #[error("Model {0} has audit enabled, but {1}")]
+    AuditConfig(String, &'static str),
+    #[error("Model {0} has webhooks enabled, but {1}")]
+    WebhookConfig(String, &'static str),
+    #[error("Model {0} has bulk endpoints enabled, but {1}")]
+    BulkEndpointsConfig(String, &'static str),
+    #[error("Model {0} uses cursor pagination, but {1}")]
+    CursorPaginationConfig(String, String),
+    #[error("Model {0} scans uploaded files, but {1}")]
+    UploadScanConfig(String, &'static str),
 }
 
 pub fn main() -> Result<(), Report<Error>> {
@@ -10,14 +10,17 @@ use glob::glob;
 use itertools::Itertools;
 use sql_migration_sim::{
     ast::{
-        AlterColumnOperation, AlterTableOperation, ColumnOption, ColumnOptionDef, Ident,
+        AlterColumnOperation, AlterTableOperation, ColumnOption, ColumnOptionDef, Expr, Ident,
         ObjectName, Statement, TableConstraint,
     },
     index_full_name, normalized_name, table_constraint_name, Column, Schema, SchemaObjectType,
     Table,
 };
 
-use crate::{model::Model, Error};
+use crate::{
+    model::{Model, SqlDialect},
+    Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct SingleMigration<'a> {
@@ -31,8 +34,22 @@ fn last_migration_path(state_dir: &Path) -> PathBuf {
     state_dir.join("schema.sql.gen")
 }
 
-pub fn read_previous_migration(state_dir: &Path) -> Result<Schema, Report<Error>> {
-    let mut schema = Schema::new_with_dialect(sql_migration_sim::dialect::PostgreSqlDialect {});
+fn new_schema(dialect: SqlDialect) -> Schema {
+    match dialect {
+        SqlDialect::Postgresql => {
+            Schema::new_with_dialect(sql_migration_sim::dialect::PostgreSqlDialect {})
+        }
+        SqlDialect::SQLite => {
+            Schema::new_with_dialect(sql_migration_sim::dialect::SQLiteDialect {})
+        }
+    }
+}
+
+pub fn read_previous_migration(
+    state_dir: &Path,
+    dialect: SqlDialect,
+) -> Result<Schema, Report<Error>> {
+    let mut schema = new_schema(dialect);
 
     let file = last_migration_path(state_dir);
 
@@ -66,8 +83,9 @@ struct ParsedMigration<'a> {
 
 fn parse_new_migrations<'a>(
     migrations: &'a [SingleMigration<'a>],
+    dialect: SqlDialect,
 ) -> Result<(Schema, Vec<ParsedMigration<'a>>), Report<Error>> {
-    let mut new_schema = Schema::new_with_dialect(sql_migration_sim::dialect::PostgreSqlDialect {});
+    let mut new_schema = new_schema(dialect);
 
     let migrations = migrations
         .into_iter()
@@ -92,20 +110,21 @@ pub fn resolve_migration(
     migrations_dir: &Path,
     state_dir: &Path,
     new_migrations: &[SingleMigration<'_>],
+    dialect: SqlDialect,
 ) -> Result<SingleMigration<'static>, Report<Error>> {
     let any_migrations_exist = glob(migrations_dir.join("*.sql").to_string_lossy().as_ref())
         .ok()
         .map(|mut g| g.next().is_some())
         .unwrap_or(false);
     let existing_schema = if any_migrations_exist {
-        read_previous_migration(state_dir)?
+        read_previous_migration(state_dir, dialect)?
     } else {
         // If the migrations were cleared out, then we need to regenerate the whole thing
         // regardless of what's in the state directory.
         Schema::new()
     };
 
-    let (new_schema, migrations) = parse_new_migrations(new_migrations)?;
+    let (new_schema, migrations) = parse_new_migrations(new_migrations, dialect)?;
 
     let existing_pg_schemas = existing_schema
         .tables
@@ -182,8 +201,11 @@ pub fn resolve_migration(
                         return true;
                     };
 
+                    // Indexes on existing tables are created after the table changes, since
+                    // they may use new columns.
                     let full_name = index_full_name(name, table_name);
                     indices_to_create.contains(&full_name.to_string())
+                        && tables_to_create.contains(&normalized_name(table_name).to_string())
                 }
                 Statement::CreateFunction { name, .. } => {
                     functions_to_create.contains(&name.to_string())
@@ -194,6 +216,23 @@ pub fn resolve_migration(
             .join("\n")
     });
 
+    let create_index_on_existing_tables = migrations
+        .iter()
+        .flat_map(|m| &m.statements)
+        .filter(|s| match s {
+            Statement::CreateIndex {
+                name: Some(name),
+                table_name,
+                ..
+            } => {
+                let full_name = index_full_name(name, table_name);
+                indices_to_create.contains(&full_name.to_string())
+                    && !tables_to_create.contains(&normalized_name(table_name).to_string())
+            }
+            _ => false,
+        })
+        .map(|s| format!("{s};"));
+
     let models_by_table = migrations
         .iter()
         .filter_map(|m| {
@@ -212,18 +251,21 @@ pub fn resolve_migration(
 
         let model = models_by_table.get(table_name).map(|m| *m);
         let changes = diff_table(model, old_table, new_table);
+        let recreated_indexes = recreated_column_indexes(table_name, &changes, &migrations);
 
         up_changes.extend(
             changes
                 .iter()
                 .map(|c| TableChangeUpMigration(c).to_string()),
         );
+        up_changes.extend(recreated_indexes.iter().cloned());
         down_changes.extend(
             changes
                 .iter()
                 .rev()
                 .map(|c| TableChangeDownMigration(c, old_table).to_string()),
         );
+        down_changes.extend(recreated_indexes);
     }
 
     let up_drop_migration = existing_schema
@@ -248,6 +290,7 @@ pub fn resolve_migration(
     let up_migration = up_drop_migration
         .chain(create_migration)
         .chain(up_changes)
+        .chain(create_index_on_existing_tables)
         .join("\n\n");
 
     let down_migration = new_schema
@@ -676,7 +719,19 @@ fn diff_table(model: Option<&Model>, old_table: &Table, new_table: &Table) -> Ve
                 .filter_map(|o| process_column_option_to_table_constraint(&column.name, o)),
         );
 
-        if let Some(matching_column) = matching_column {
+        if let Some(matching_column) =
+            matching_column.filter(|c| generation_expr(c) != generation_expr(column))
+        {
+            // Generated columns can't be altered, so recreate the column when its expression changes.
+            changes.push(TableChange::RemoveColumn {
+                table: new_table.name.clone(),
+                column: (*matching_column).clone(),
+            });
+            changes.push(TableChange::AddColumn {
+                table: new_table.name.clone(),
+                column: column.clone(),
+            });
+        } else if let Some(matching_column) = matching_column {
             if matching_column.name() != column_name {
                 changes.push(TableChange::RenameColumn {
                     table: new_table.name.clone(),
@@ -759,6 +814,64 @@ fn diff_table(model: Option<&Model>, old_table: &Table, new_table: &Table) -> Ve
     changes
 }
 
+/// Dropping a column also drops the indexes that use it. Return the statements to recreate the
+/// indexes on columns that are being dropped and added again.
+fn recreated_column_indexes(
+    table_name: &str,
+    changes: &[TableChange],
+    migrations: &[ParsedMigration],
+) -> Vec<String> {
+    let removed = changes
+        .iter()
+        .filter_map(|c| match c {
+            TableChange::RemoveColumn { column, .. } => Some(column.name()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let recreated = changes
+        .iter()
+        .filter_map(|c| match c {
+            TableChange::AddColumn { column, .. } if removed.contains(column.name()) => {
+                Some(column.name())
+            }
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    if recreated.is_empty() {
+        return Vec::new();
+    }
+
+    migrations
+        .iter()
+        .flat_map(|m| &m.statements)
+        .filter(|s| match s {
+            Statement::CreateIndex {
+                table_name: index_table,
+                columns,
+                ..
+            } => {
+                normalized_name(index_table).to_string() == table_name
+                    && columns.iter().any(|c| {
+                        matches!(&c.expr, Expr::Identifier(ident) if recreated.contains(ident.value.as_str()))
+                    })
+            }
+            _ => false,
+        })
+        .map(|s| format!("{s};"))
+        .collect()
+}
+
+/// The expression for a generated column
+fn generation_expr(column: &Column) -> Option<&Expr> {
+    column.options.iter().find_map(|o| match &o.option {
+        ColumnOption::Generated {
+            generation_expr, ..
+        } => generation_expr.as_ref(),
+        _ => None,
+    })
+}
+
 fn diff_column(old_column: &Column, new_column: &Column) -> Vec<AlterColumnOperation> {
     let mut changes = vec![];
     if new_column.data_type != old_column.data_type {
@@ -4,6 +4,17 @@ use crate::{
     model::{field::ReferentialAction, ModelField, ModelFieldReference, PerEndpoint, SqlType},
 };
 
+/// The name of the built-in model that is added when `use_projects` is enabled.
+pub const PROJECT_MODEL: &str = "Project";
+/// The name of the built-in model that holds an organization's webhook subscriptions, added when
+/// any model has webhooks enabled.
+pub const WEBHOOK_MODEL: &str = "Webhook";
+/// The name of the built-in model that logs each attempt to deliver a webhook event.
+pub const WEBHOOK_DELIVERY_MODEL: &str = "WebhookDelivery";
+/// The name of the built-in model that holds emails waiting to be sent, added when
+/// `email.outbox` is enabled.
+pub const OUTBOUND_EMAIL_MODEL: &str = "OutboundEmail";
+
 fn simple_model_field(name: &str, typ: SqlType) -> ModelField {
     ModelField {
         name: name.to_string(),
@@ -68,7 +79,7 @@ impl Model {
             .map(|m| m.fields.clone())
             .unwrap_or_default();
 
-        vec![
+        let mut models = vec![
             Model {
                 name: "User".to_string(),
                 plural: None,
@@ -79,6 +90,9 @@ impl Model {
                     create: false,
                     update: true,
                     delete: true,
+                    bulk_create: false,
+                    bulk_update: false,
+                    bulk_delete: false,
                 }),
                 endpoints: Vec::new(),
                 auth_scope: Some(crate::model::ModelAuthScope::Model),
@@ -93,12 +107,16 @@ impl Model {
                 files: Vec::new(),
                 shared_types: Vec::new(),
                 allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
                 joins: None,
                 belongs_to: vec![],
                 has: vec![],
                 file_for: None,
                 is_auth_model: true,
-                schema: config.database.auth_schema().map(|s| s.to_string()),
+                schema: Some(config.auth_schema().to_string()),
                 fields: [
                     ModelField {
                         sortable: super::field::SortableType::DefaultAscending,
@@ -141,6 +159,10 @@ impl Model {
                 extra_sql: String::new(),
                 pagination: Default::default(),
                 allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
                 files: Vec::new(),
                 shared_types: Vec::new(),
                 joins: None,
@@ -148,7 +170,7 @@ impl Model {
                 has: vec![],
                 file_for: None,
                 is_auth_model: true,
-                schema: config.database.auth_schema().map(|s| s.to_string()),
+                schema: Some(config.auth_schema().to_string()),
                 fields: [
                     ModelField {
                         sortable: super::field::SortableType::DefaultAscending,
@@ -176,6 +198,21 @@ impl Model {
                         default_sql: "true".into(),
                         ..simple_model_field("active", SqlType::Boolean)
                     },
+                    ModelField {
+                        description: Some(
+                            "Require members to use two-factor authentication".to_string(),
+                        ),
+                        default_sql: "false".into(),
+                        ..simple_model_field("require_mfa", SqlType::Boolean)
+                    },
+                    ModelField {
+                        description: Some(
+                            "Require members to log in through the organization's SAML identity provider"
+                                .to_string(),
+                        ),
+                        default_sql: "false".into(),
+                        ..simple_model_field("require_sso", SqlType::Boolean)
+                    },
                 ]
                 .into_iter()
                 .chain(external_auth_fields.clone().into_iter())
@@ -200,12 +237,16 @@ impl Model {
                 files: Vec::new(),
                 shared_types: Vec::new(),
                 allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
                 joins: None,
                 belongs_to: vec![],
                 has: vec![],
                 file_for: None,
                 is_auth_model: true,
-                schema: config.database.auth_schema().map(|s| s.to_string()),
+                schema: Some(config.auth_schema().to_string()),
                 fields: [
                     ModelField {
                         sortable: super::field::SortableType::DefaultAscending,
@@ -221,6 +262,256 @@ impl Model {
                 .chain(extra_role_fields.into_iter())
                 .collect(),
             },
-        ]
+        ];
+
+        if config.use_projects {
+            models.push(Model {
+                name: PROJECT_MODEL.to_string(),
+                plural: None,
+                id_prefix: Some("prj".to_string()),
+                global: false,
+                standard_endpoints: crate::model::Endpoints::All(true),
+                indexes: vec![],
+                index_created_at: false,
+                index_updated_at: false,
+                default_sort_field: Some("name".to_string()),
+                // Project membership is tracked through the project's object permissions.
+                auth_scope: Some(crate::model::ModelAuthScope::Object),
+                endpoints: Vec::new(),
+                extra_create_table_sql: String::new(),
+                extra_sql: String::new(),
+                pagination: Default::default(),
+                files: Vec::new(),
+                shared_types: Vec::new(),
+                allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
+                joins: None,
+                belongs_to: vec![],
+                has: vec![],
+                file_for: None,
+                is_auth_model: false,
+                schema: Some(config.model_schema().to_string()),
+                fields: vec![
+                    ModelField {
+                        sortable: super::field::SortableType::DefaultAscending,
+                        ..simple_model_field("name", SqlType::Text)
+                    },
+                    ModelField {
+                        nullable: true,
+                        ..simple_model_field("description", SqlType::Text)
+                    },
+                ],
+            });
+        }
+
+        if config.use_webhooks {
+            models.push(Model {
+                name: WEBHOOK_MODEL.to_string(),
+                plural: None,
+                id_prefix: Some("whk".to_string()),
+                global: false,
+                standard_endpoints: crate::model::Endpoints::All(true),
+                indexes: vec![],
+                index_created_at: false,
+                index_updated_at: false,
+                default_sort_field: Some("-created_at".to_string()),
+                auth_scope: Some(crate::model::ModelAuthScope::Model),
+                endpoints: Vec::new(),
+                extra_create_table_sql: String::new(),
+                extra_sql: String::new(),
+                pagination: Default::default(),
+                files: Vec::new(),
+                shared_types: Vec::new(),
+                allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
+                joins: None,
+                belongs_to: vec![],
+                has: vec![],
+                file_for: None,
+                is_auth_model: false,
+                schema: Some(config.model_schema().to_string()),
+                fields: vec![
+                    simple_model_field("url", SqlType::Text),
+                    ModelField {
+                        description: Some(
+                            "The key used to sign each delivery, so the receiver can verify it"
+                                .to_string(),
+                        ),
+                        // The secret can be set or replaced, but never read back.
+                        access: Access::Write,
+                        ..simple_model_field("secret", SqlType::Text)
+                    },
+                    ModelField {
+                        description: Some(
+                            "A comma-separated list of events to send, such as `post.created`. \
+                            `post.*` sends every event for a model, and `*` sends all events."
+                                .to_string(),
+                        ),
+                        default_sql: "'*'".to_string(),
+                        ..simple_model_field("events", SqlType::Text)
+                    },
+                    ModelField {
+                        default_sql: "true".to_string(),
+                        ..simple_model_field("enabled", SqlType::Boolean)
+                    },
+                ],
+            });
+
+            models.push(Model {
+                name: WEBHOOK_DELIVERY_MODEL.to_string(),
+                plural: Some("WebhookDeliveries".to_string()),
+                id_prefix: Some("whd".to_string()),
+                global: false,
+                // Deliveries are only created by the delivery job.
+                standard_endpoints: crate::model::Endpoints::Only(PerEndpoint {
+                    get: true,
+                    list: true,
+                    create: false,
+                    update: false,
+                    delete: false,
+                    bulk_create: false,
+                    bulk_update: false,
+                    bulk_delete: false,
+                }),
+                indexes: vec![],
+                index_created_at: true,
+                index_updated_at: false,
+                default_sort_field: Some("-created_at".to_string()),
+                auth_scope: Some(crate::model::ModelAuthScope::Model),
+                endpoints: Vec::new(),
+                extra_create_table_sql: String::new(),
+                extra_sql: String::new(),
+                pagination: Default::default(),
+                files: Vec::new(),
+                shared_types: Vec::new(),
+                allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
+                joins: None,
+                belongs_to: vec![],
+                has: vec![],
+                file_for: None,
+                is_auth_model: false,
+                schema: Some(config.model_schema().to_string()),
+                fields: vec![
+                    ModelField {
+                        rust_type: Some("crate::models::webhook::WebhookId".to_string()),
+                        filterable: super::field::FilterableType::Exact,
+                        indexed: true,
+                        references: Some(ModelFieldReference::new(
+                            WEBHOOK_MODEL,
+                            "id",
+                            Some(ReferentialAction::Cascade),
+                        )),
+                        ..simple_model_field("webhook_id", SqlType::Uuid)
+                    },
+                    ModelField {
+                        filterable: super::field::FilterableType::Exact,
+                        ..simple_model_field("event", SqlType::Text)
+                    },
+                    // The same event ID is sent in every attempt to deliver the event.
+                    simple_model_field("event_id", SqlType::Uuid),
+                    ModelField {
+                        omit_in_list: true,
+                        ..simple_model_field("payload", SqlType::Json)
+                    },
+                    ModelField {
+                        nullable: true,
+                        ..simple_model_field("status_code", SqlType::Int)
+                    },
+                    ModelField {
+                        nullable: true,
+                        ..simple_model_field("error", SqlType::Text)
+                    },
+                    ModelField {
+                        filterable: super::field::FilterableType::Exact,
+                        ..simple_model_field("success", SqlType::Boolean)
+                    },
+                ],
+            });
+        }
+
+        if config.email.outbox {
+            models.push(Model {
+                name: OUTBOUND_EMAIL_MODEL.to_string(),
+                plural: None,
+                id_prefix: Some("eml".to_string()),
+                global: true,
+                // Queued emails often contain login tokens, so they are not exposed over the API.
+                standard_endpoints: crate::model::Endpoints::All(false),
+                indexes: vec![],
+                index_created_at: true,
+                index_updated_at: false,
+                default_sort_field: Some("-created_at".to_string()),
+                auth_scope: Some(crate::model::ModelAuthScope::Model),
+                endpoints: Vec::new(),
+                extra_create_table_sql: String::new(),
+                extra_sql: String::new(),
+                pagination: Default::default(),
+                files: Vec::new(),
+                shared_types: Vec::new(),
+                allow_id_in_create: false,
+                soft_delete: false,
+                audit: false,
+                optimistic_locking: false,
+                webhooks: false,
+                joins: None,
+                belongs_to: vec![],
+                has: vec![],
+                file_for: None,
+                is_auth_model: false,
+                schema: Some(config.model_schema().to_string()),
+                fields: vec![
+                    simple_model_field("recipients", SqlType::Text),
+                    simple_model_field("subject", SqlType::Text),
+                    ModelField {
+                        description: Some(
+                            "The rendered email, including any attachments. This is cleared \
+                            once the email is sent, since it may contain login tokens."
+                                .to_string(),
+                        ),
+                        omit_in_list: true,
+                        nullable: true,
+                        ..simple_model_field("email", SqlType::Json)
+                    },
+                    ModelField {
+                        description: Some("One of `pending`, `sent`, or `failed`".to_string()),
+                        default_sql: "'pending'".to_string(),
+                        filterable: super::field::FilterableType::Exact,
+                        indexed: true,
+                        ..simple_model_field("status", SqlType::Text)
+                    },
+                    ModelField {
+                        default_sql: "0".to_string(),
+                        ..simple_model_field("attempts", SqlType::Int)
+                    },
+                    ModelField {
+                        description: Some(
+                            "The ID that the email service assigned to the message".to_string(),
+                        ),
+                        nullable: true,
+                        ..simple_model_field("provider_message_id", SqlType::Text)
+                    },
+                    ModelField {
+                        nullable: true,
+                        ..simple_model_field("last_error", SqlType::Text)
+                    },
+                    ModelField {
+                        nullable: true,
+                        ..simple_model_field("sent_at", SqlType::Timestamp)
+                    },
+                ],
+            });
+        }
+
+        models
     }
 }
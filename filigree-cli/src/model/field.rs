@@ -22,6 +22,7 @@ pub struct ModelFieldTemplateContext {
     pub rust_type: String,
     pub is_custom_rust_type: bool,
     pub is_object_id: bool,
+    pub base_zod_type: String,
     pub client_type: String,
     pub default_sql: String,
     pub default_rust: String,
@@ -74,6 +75,20 @@ impl ModelFieldTemplateContext {
             }
         }
     }
+
+    /// Rust syntax that binds this field from every item in a slice of payloads, as an array
+    /// for use with `UNNEST`. `$payload` is replaced with the name of the slice.
+    pub fn param_array_binding(&self) -> String {
+        let is_custom_type = self.is_custom_rust_type && matches!(self.base_type, SqlType::Json);
+        let item = match (self.nullable, is_custom_type) {
+            (true, true) => format!("p.{}.as_ref().map(sqlx::types::Json)", self.rust_name),
+            (true, false) => format!("p.{}.as_ref()", self.rust_name),
+            (false, true) => format!("sqlx::types::Json(&p.{})", self.rust_name),
+            (false, false) => format!("&p.{}", self.rust_name),
+        };
+
+        format!("$payload.iter().map(|p| {item}).collect::<Vec<_>>() as _")
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -269,6 +284,7 @@ impl ModelField {
             base_rust_type: self.base_rust_type().to_string(),
             rust_type: self.rust_type().to_string(),
             is_custom_rust_type: self.rust_type.is_some(),
+            base_zod_type: self.base_zod_type().to_string(),
             client_type: self.typ.to_client_type().to_string(),
             default_sql: self.default_sql.clone(),
             default_rust: self.default_rust.clone(),
@@ -374,6 +390,18 @@ impl ModelFieldReference {
         Ok(())
     }
 
+    /// Return a copy of this reference with the schema removed from the table name
+    pub fn without_schema(&self) -> Self {
+        let table = self
+            .table
+            .as_deref()
+            .map(|t| t.rsplit('.').next().unwrap_or(t).to_string());
+        Self {
+            table,
+            ..self.clone()
+        }
+    }
+
     pub fn with_deferrable(mut self, deferrable: Deferrable) -> Self {
         self.deferrable = Some(deferrable);
         self
@@ -573,6 +601,8 @@ pub enum FilterableType {
     None,
     Exact,
     Range,
+    /// Include the field in the model's full-text search, used by the `q` list parameter
+    Search,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -1,14 +1,15 @@
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 use cargo_toml::Manifest;
 use convert_case::{Case, Casing};
 use error_stack::Report;
+use filigree::images::{ImageOutputFormat, ImageVariantSpec};
 use serde::Deserialize;
 use serde_json::json;
 
 use super::{
     field::{Access, FilterableType, ModelField, SqlType},
-    Endpoints, HasModel, Model, Pagination, ReferenceFetchType,
+    Endpoints, HasModel, Model, Pagination, ReferenceFetchType, SqlDialect,
 };
 use crate::{config::Config, Error};
 
@@ -79,24 +80,125 @@ pub struct FileModelOptions {
     /// Limit the maximum size of uploaded files
     pub upload_size_limit: Option<usize>,
 
+    /// Only accept uploads with these content types, which are detected from the contents of
+    /// the file rather than the type sent by the client. Entries can end in `/*` to allow any
+    /// subtype, such as `image/*`. Files that are not a known binary format are detected as
+    /// `text/plain` if they are valid UTF-8, and `application/octet-stream` otherwise.
+    pub allowed_content_types: Option<Vec<String>>,
+
+    /// Scan each upload for viruses after it is saved, using the app's configured
+    /// [UploadScanner](filigree::uploads::UploadScanner). The result is recorded in a
+    /// `scan_status` field, and files are not available for download while the scan is pending
+    /// or if the scanner found a threat. Files uploaded while no scanner is configured are
+    /// marked as `unscanned`. Only supported with PostgreSQL.
+    ///
+    /// Files in a bucket with a public URL can still be downloaded directly from the bucket.
+    #[serde(default)]
+    pub scan_uploads: bool,
+
+    /// Generate endpoints for clients to upload and download files directly from the storage
+    /// bucket using presigned URLs, instead of streaming them through the server. An upload
+    /// has two steps: the client requests an upload URL and uploads the file to it, and then
+    /// calls the confirm endpoint, which records the file's size and hash.
+    ///
+    /// Local and in-memory storage support this through routes on the server, but Google Cloud
+    /// Storage does not support presigned URLs.
+    #[serde(default)]
+    pub presigned_urls: bool,
+
+    /// How long presigned URLs are valid, in seconds. Defaults to one hour.
+    #[serde(default = "default_presigned_url_expiry")]
+    pub presigned_url_expiry: u64,
+
     /// If omitted or false, the file will be deleted from storage when the model is deleted.
     /// If true, the file will be retained in object storage even after the model is deleted.
     #[serde(default)]
     pub retain_file_on_delete: bool,
+
+    /// Treat uploaded files as images. After each upload, a background job strips the image's
+    /// EXIF metadata, records its dimensions, and generates the configured variants.
+    pub image: Option<ImageOptions>,
+
+    /// The URL path of the parent model's endpoints, filled in when the file model is generated.
+    #[serde(skip)]
+    parent_url_path: String,
+    /// The URL path of this model's endpoints under the parent, filled in when the file model
+    /// is generated.
+    #[serde(skip)]
+    url_path: String,
+}
+
+/// Image processing options for a file model
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ImageOptions {
+    /// Resized versions of the image to generate. The key is the name of the variant and the
+    /// value is its size and how to fit the image into it, such as `thumb = "200x200 cover"`.
+    /// The fit can be `cover`, `contain`, or `fill`, and defaults to `contain`.
+    #[serde(default)]
+    pub variants: BTreeMap<String, ImageVariantSpec>,
+
+    /// The format in which to save the variants: `jpeg`, `png`, or `webp`. Defaults to `jpeg`.
+    #[serde(default)]
+    pub format: ImageOutputFormat,
+}
+
+impl ImageOptions {
+    fn template_context(&self) -> serde_json::Value {
+        let variants = self
+            .variants
+            .iter()
+            .map(|(name, spec)| {
+                json!({
+                    "name": name,
+                    "spec": spec.template_text(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "variants": variants,
+            "format": format!("{:?}", self.format),
+        })
+    }
 }
 
 fn default_filename_template() -> String {
     String::from("{id}-{filename}")
 }
 
+fn default_presigned_url_expiry() -> u64 {
+    3600
+}
+
 impl FileModelOptions {
     pub fn validate(&self, model_name: &str, config: &Config) -> Result<(), Error> {
         config.storage.bucket.get(&self.bucket).ok_or_else(|| {
             Error::InvalidStorageBucket(model_name.to_string(), self.bucket.clone())
         })?;
+
+        if self.scan_uploads && config.sql_dialect != SqlDialect::Postgresql {
+            return Err(Error::UploadScanConfig(
+                model_name.to_string(),
+                "upload scanning is only supported with PostgreSQL",
+            ));
+        }
+
         Ok(())
     }
 
+    /// Return true if the content type is allowed by `allowed_content_types`.
+    fn allows_content_type(&self, content_type: &str) -> bool {
+        let Some(allowed) = self.allowed_content_types.as_ref() else {
+            return true;
+        };
+
+        let (main_type, _) = content_type.split_once('/').unwrap_or_default();
+        allowed.iter().any(|t| match t.strip_suffix("/*") {
+            Some(prefix) => prefix == main_type,
+            None => t == content_type,
+        })
+    }
+
     pub fn add_deps(&self, api_dir: &Path, manifest: &mut Manifest) -> Result<(), Report<Error>> {
         if let Some(hash) = &self.meta.hash {
             hash.add_deps(api_dir, manifest)?;
@@ -115,8 +217,21 @@ impl FileModelOptions {
             "hash": self.meta.hash.as_ref().map(|h| h.template_context()),
             "record_size": self.meta.size,
             "upload_size_limit": self.upload_size_limit,
+            "detect_content_type": self.meta.content_type || self.allowed_content_types.is_some(),
+            "record_content_type": self.meta.content_type,
+            "allowed_content_types": self.allowed_content_types,
+            // Used to decide which generated tests can run
+            "allows_text": self.allows_content_type("text/plain"),
+            "allows_png": self.allows_content_type("image/png"),
+            "allows_binary": self.allows_content_type("application/octet-stream"),
             "record_filename": self.meta.filename,
             "retain_file_on_delete": self.retain_file_on_delete,
+            "scan_uploads": self.scan_uploads,
+            "presigned_urls": self.presigned_urls,
+            "presigned_url_expiry": self.presigned_url_expiry,
+            "image": self.image.as_ref().map(|i| i.template_context()),
+            "parent_url_path": self.parent_url_path,
+            "url_path": self.url_path,
         })
     }
 
@@ -205,9 +320,20 @@ impl FileModelOptions {
     }
 
     pub fn generate_model(&self, parent: &Model) -> Model {
+        let name = self.model_name(parent);
+        let options = FileModelOptions {
+            parent_url_path: parent.plural().to_case(Case::Snake),
+            url_path: if self.many {
+                format!("{name}s").to_case(Case::Snake)
+            } else {
+                name.to_case(Case::Snake)
+            },
+            ..self.clone()
+        };
+
         Model {
-            name: self.model_name(parent),
-            file_for: Some((parent.name.clone(), self.clone())),
+            name,
+            file_for: Some((parent.name.clone(), options)),
             // file upload submodel does not have embedded file upload submodels
             files: Vec::new(),
             shared_types: Vec::new(),
@@ -227,6 +353,10 @@ impl FileModelOptions {
             extra_sql: String::new(),
             global: false,
             allow_id_in_create: false,
+            soft_delete: false,
+            audit: false,
+            optimistic_locking: false,
+            webhooks: false,
             auth_scope: None,
             endpoints: Vec::new(),
             indexes: vec![],
@@ -298,6 +428,67 @@ impl FileModelOptions {
             });
         }
 
+        if self.meta.content_type {
+            // This is set from the uploaded file's contents, so clients can't change it.
+            fields.push(ModelField {
+                name: "file_content_type".to_string(),
+                typ: SqlType::Text,
+                nullable: true,
+                access: Access::Read,
+                filterable: FilterableType::Exact,
+                ..Default::default()
+            });
+        }
+
+        if self.scan_uploads {
+            // This is set after the upload has been scanned.
+            fields.push(ModelField {
+                name: "scan_status".to_string(),
+                typ: SqlType::Text,
+                rust_type: Some("filigree::uploads::ScanStatus".to_string()),
+                zod_type: Some("z.enum(['pending', 'clean', 'infected', 'unscanned'])".to_string()),
+                nullable: false,
+                access: Access::Read,
+                default_sql: "'pending'".to_string(),
+                filterable: FilterableType::Exact,
+                ..Default::default()
+            });
+        }
+
+        if self.image.is_some() {
+            // These are set by the image processing job.
+            fields.push(ModelField {
+                name: "image_width".to_string(),
+                typ: SqlType::Int,
+                nullable: true,
+                access: Access::Read,
+                ..Default::default()
+            });
+            fields.push(ModelField {
+                name: "image_height".to_string(),
+                typ: SqlType::Int,
+                nullable: true,
+                access: Access::Read,
+                ..Default::default()
+            });
+            // A map of variant name to the variant's URL and dimensions
+            fields.push(ModelField {
+                name: "image_variants".to_string(),
+                typ: SqlType::Json,
+                nullable: true,
+                access: Access::Read,
+                ..Default::default()
+            });
+            // Why the image could not be processed, if it failed
+            fields.push(ModelField {
+                name: "image_error".to_string(),
+                typ: SqlType::Text,
+                nullable: true,
+                access: Access::Read,
+                ..Default::default()
+            });
+        }
+
         fields
     }
 }
@@ -317,6 +508,11 @@ pub struct FileUploadRecordMetadata {
 
     /// Add a `hash` field to the model, and hash the file with the specified algorithm as it is uploaded.
     pub hash: Option<HashType>,
+
+    /// Generate a `content_type` field in this model, and record the content type detected
+    /// from the contents of the uploaded file.
+    #[serde(default)]
+    pub content_type: bool,
 }
 
 /// The hashing algorithm to use when uploading files
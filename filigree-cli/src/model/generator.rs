@@ -12,12 +12,14 @@ use rayon::prelude::*;
 use serde::Serialize;
 
 use super::{
+    base_models::PROJECT_MODEL,
     field::{
         Access, FilterableType, ModelField, ModelFieldReference, ModelFieldTemplateContext,
         ReferencePopulation, ReferentialAction, SqlType,
     },
     generate_types::StructsContext,
     Endpoints, HasModel, Model, ModelAuthScope, Pagination, PerEndpoint, SqlDialect,
+    TEXT_SEARCH_CONFIG,
 };
 use crate::{
     config::{web::WebFramework, Config},
@@ -106,6 +108,7 @@ pub struct ChildContext {
     pub through: Option<ThroughContext>,
     pub join: Option<JoinContext>,
     pub file_upload: Option<serde_json::Value>,
+    pub audit: bool,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -157,6 +160,10 @@ pub struct TemplateContext {
     pub rust_imports: String,
     pub ts_imports: String,
     pub allow_id_in_create: bool,
+    pub soft_delete: bool,
+    pub audit: bool,
+    pub optimistic_locking: bool,
+    pub webhooks: bool,
     pub belongs_to_fields: Vec<BelongsToFieldContext>,
     pub can_populate_get: bool,
     pub can_populate_list: bool,
@@ -167,6 +174,8 @@ pub struct TemplateContext {
     pub write_permission: String,
     pub extra_sql: String,
     pub extra_create_table_sql: String,
+    /// The expression for the generated `search_vector` column, if the model has search fields.
+    pub search_vector_sql: Option<String>,
     pub index_created_at: bool,
     pub index_updated_at: bool,
     pub pagination: Pagination,
@@ -249,17 +258,21 @@ impl<'a> ModelGenerator<'a> {
         config: &'a Config,
     ) -> (Vec<SingleMigration<'static>>, Vec<SingleMigration<'static>>) {
         let mut ctx = tera::Context::new();
-        ctx.insert(
-            "model_schema",
-            config.database.model_schema().unwrap_or("public"),
-        );
-        ctx.insert(
-            "auth_schema",
-            config.database.auth_schema().unwrap_or("public"),
-        );
-        ctx.insert("auth", &config.auth.template_context());
+        ctx.insert("model_schema", config.model_schema());
+        ctx.insert("auth_schema", config.auth_schema());
+        ctx.insert("auth", &config.auth.template_context(config.sql_dialect));
+        ctx.insert("sql_dialect", &config.sql_dialect);
+        // SQLite doesn't allow a schema on the table in foreign keys and indexes.
+        let auth_ref_prefix = match config.sql_dialect {
+            SqlDialect::Postgresql => format!("{}.", config.auth_schema()),
+            SqlDialect::SQLite => String::new(),
+        };
+        ctx.insert("auth_ref_prefix", &auth_ref_prefix);
 
-        let schema_up = {
+        // SQLite has no schemas beyond attached databases, so there's nothing to create there.
+        let schema_up = if config.sql_dialect == SqlDialect::SQLite {
+            None
+        } else {
             let mut schema_up = String::new();
             let model_schema = config.database.model_schema().unwrap_or_default();
             let auth_schema = config.database.auth_schema().unwrap_or_default();
@@ -285,7 +298,8 @@ impl<'a> ModelGenerator<'a> {
 
         let mut before_up = vec![
             schema_up,
-            Some(SingleMigration {
+            // These are Postgres functions, and are only a convenience for manual queries.
+            (config.sql_dialect == SqlDialect::Postgresql).then(|| SingleMigration {
                 name: "object_id_functions".to_string(),
                 model: None,
                 up: Cow::from(include_str!("../../sql/object_id_functions.up.sql")),
@@ -297,6 +311,12 @@ impl<'a> ModelGenerator<'a> {
                 up: Cow::from(include_str!("../../sql/delete_log.up.sql")),
                 down: Cow::from(include_str!("../../sql/delete_log.down.sql")),
             }),
+            Some(SingleMigration {
+                name: "audit_log".to_string(),
+                model: None,
+                up: Cow::from(include_str!("../../sql/audit_log.up.sql")),
+                down: Cow::from(include_str!("../../sql/audit_log.down.sql")),
+            }),
         ]
         .into_iter()
         .flatten()
@@ -490,8 +510,17 @@ impl<'a> ModelGenerator<'a> {
             vec![Cow::Owned(id_field)]
         };
 
+        // Updates pass the version they are based on. This is optional in the payload since it
+        // can also come from the If-Match header.
+        let version_field = (for_update && self.optimistic_locking).then(|| {
+            let mut field = self.version_field();
+            field.nullable = true;
+            Cow::Owned(field)
+        });
+
         Ok(id_fields
             .into_iter()
+            .chain(version_field)
             .chain(self.all_fields()?.filter(|f| f.writable() && !f.never_read))
             .chain(
                 self.write_payload_child_fields(for_update)?
@@ -524,7 +553,7 @@ impl<'a> ModelGenerator<'a> {
     ) -> Result<TemplateContext, Error> {
         let mut rust_imports = HashSet::new();
         let mut ts_imports = HashSet::new();
-        let sql_dialect = Config::default_sql_dialect();
+        let sql_dialect = self.config.sql_dialect;
         let full_default_sort_field = self.default_sort_field.as_deref().unwrap_or("-updated_at");
         let default_sort_field = if full_default_sort_field.starts_with('-') {
             &full_default_sort_field[1..]
@@ -554,7 +583,18 @@ impl<'a> ModelGenerator<'a> {
 
         let mut fields = self
             .all_fields()?
-            .map(|field| field.template_context())
+            .map(|field| {
+                let mut ctx = field.template_context();
+                ctx.sql_type = field.typ.to_sql_type(sql_dialect);
+                if sql_dialect == SqlDialect::SQLite {
+                    // SQLite doesn't allow a schema on the table in a foreign key.
+                    ctx.foreign_key_sql = field
+                        .references
+                        .as_ref()
+                        .map(|r| r.without_schema().to_string());
+                }
+                ctx
+            })
             .collect::<Vec<_>>();
 
         let join_primary_keys = if let Some(model_names) = self.joins.as_ref() {
@@ -580,6 +620,21 @@ impl<'a> ModelGenerator<'a> {
             .filter(|s| !s.is_empty())
             .join(",\n");
 
+        let search_vector_sql = {
+            let search_fields = fields
+                .iter()
+                .filter(|f| matches!(f.filterable, FilterableType::Search))
+                .map(|f| format!("coalesce({}, '')", f.sql_name))
+                .collect::<Vec<_>>();
+
+            (!search_fields.is_empty()).then(|| {
+                format!(
+                    "to_tsvector('{TEXT_SEARCH_CONFIG}', {})",
+                    search_fields.join(" || ' ' || ")
+                )
+            })
+        };
+
         let base_dir = PathBuf::from("src/models").join(self.module_name());
 
         let children = self
@@ -710,6 +765,7 @@ impl<'a> ModelGenerator<'a> {
                         .file_for
                         .as_ref()
                         .map(|f| f.1.template_context()),
+                    audit: child_model.audit,
                 };
 
                 Ok::<_, Error>(result)
@@ -855,6 +911,10 @@ impl<'a> ModelGenerator<'a> {
             rust_imports,
             ts_imports,
             allow_id_in_create: self.allow_id_in_create,
+            soft_delete: self.soft_delete,
+            audit: self.audit,
+            optimistic_locking: self.optimistic_locking,
+            webhooks: self.webhooks,
             belongs_to_fields,
             can_populate_get,
             can_populate_list,
@@ -865,6 +925,7 @@ impl<'a> ModelGenerator<'a> {
             write_permission: format!("{}::write", self.name),
             extra_sql: self.extra_sql.clone(),
             extra_create_table_sql,
+            search_vector_sql,
             index_created_at: self.index_created_at,
             index_updated_at: self.index_updated_at,
             pagination: self.pagination.clone(),
@@ -888,13 +949,8 @@ impl<'a> ModelGenerator<'a> {
                 .check_in_query(),
             file_for: self.file_for.as_ref().map(|f| f.0.clone()),
             file_upload: self.file_for.as_ref().map(|f| f.1.template_context()),
-            auth: self.config.auth.template_context(),
-            auth_schema: self
-                .config
-                .database
-                .auth_schema()
-                .unwrap_or("public")
-                .to_string(),
+            auth: self.config.auth.template_context(self.config.sql_dialect),
+            auth_schema: self.config.auth_schema().to_string(),
             id_type,
             id_fields: self.object_id_fields(),
             new_object_id,
@@ -978,6 +1034,49 @@ impl<'a> ModelGenerator<'a> {
             })
         };
 
+        let project_field = if matches!(self.auth_scope, Some(ModelAuthScope::Project)) {
+            let project_model = self
+                .model_map
+                .get(PROJECT_MODEL, &self.name, "project_id")?;
+            Some(ModelField {
+                name: "project_id".to_string(),
+                typ: SqlType::Uuid,
+                label: None,
+                description: None,
+                rust_type: Some(format!(
+                    "crate::models::{}::{}",
+                    project_model.module_name(),
+                    project_model.object_id_type()
+                )),
+                zod_type: Some("z.string()".to_string()),
+                nullable: false,
+                globally_unique: false,
+                unique: false,
+                indexed: true,
+                sortable: SortableType::None,
+                filterable: FilterableType::Exact,
+                extra_sql_modifiers: String::new(),
+                access: Access::ReadWrite,
+                omit_in_list: false,
+                default_sql: String::new(),
+                default_rust: String::new(),
+                never_read: false,
+                fixed: false,
+                previous_name: None,
+                references: Some(ModelFieldReference {
+                    model: None,
+                    table: Some(project_model.full_table()),
+                    field: "id".to_string(),
+                    on_delete: Some(ReferentialAction::Cascade),
+                    on_update: None,
+                    deferrable: None,
+                    populate: None,
+                }),
+            })
+        } else {
+            None
+        };
+
         let id_fields = if self.joins.is_none() {
             Some(self.id_field())
         } else {
@@ -987,6 +1086,7 @@ impl<'a> ModelGenerator<'a> {
 
         let other_fields = [
             org_field,
+            project_field,
             Some(ModelField {
                 name: "updated_at".to_string(),
                 typ: SqlType::Timestamp,
@@ -1004,7 +1104,7 @@ impl<'a> ModelGenerator<'a> {
                 access: Access::Read,
                 omit_in_list: false,
                 references: None,
-                default_sql: "now()".to_string(),
+                default_sql: self.config.sql_dialect.now_default().to_string(),
                 default_rust: String::new(),
                 never_read: false,
                 fixed: true,
@@ -1027,12 +1127,36 @@ impl<'a> ModelGenerator<'a> {
                 access: Access::Read,
                 omit_in_list: false,
                 references: None,
-                default_sql: "now()".to_string(),
+                default_sql: self.config.sql_dialect.now_default().to_string(),
                 default_rust: String::new(),
                 never_read: false,
                 fixed: true,
                 previous_name: None,
             }),
+            self.soft_delete.then(|| ModelField {
+                name: "deleted_at".to_string(),
+                typ: SqlType::Timestamp,
+                label: None,
+                description: None,
+                rust_type: None,
+                zod_type: None,
+                nullable: true,
+                globally_unique: false,
+                unique: false,
+                indexed: false,
+                filterable: FilterableType::None,
+                sortable: SortableType::None,
+                extra_sql_modifiers: String::new(),
+                access: Access::Read,
+                omit_in_list: false,
+                references: None,
+                default_sql: String::new(),
+                default_rust: String::new(),
+                never_read: false,
+                fixed: true,
+                previous_name: None,
+            }),
+            self.optimistic_locking.then(|| self.version_field()),
         ]
         .into_iter()
         .flatten();
@@ -1040,6 +1164,33 @@ impl<'a> ModelGenerator<'a> {
         Ok(id_fields.into_iter().chain(other_fields))
     }
 
+    /// The `version` field for models that use optimistic locking
+    fn version_field(&self) -> ModelField {
+        ModelField {
+            name: "version".to_string(),
+            typ: SqlType::Int,
+            label: None,
+            description: None,
+            rust_type: None,
+            zod_type: None,
+            nullable: false,
+            globally_unique: false,
+            unique: false,
+            indexed: false,
+            filterable: FilterableType::None,
+            sortable: SortableType::None,
+            extra_sql_modifiers: String::new(),
+            access: Access::Read,
+            omit_in_list: false,
+            references: None,
+            default_sql: "1".to_string(),
+            default_rust: String::new(),
+            never_read: false,
+            fixed: true,
+            previous_name: None,
+        }
+    }
+
     /// Fields that reference a parent model. This includes the return result of `join_fields`.
     fn belongs_to_fields(
         &self,
@@ -15,6 +15,7 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, OneOrMany};
 
 use self::{
+    base_models::PROJECT_MODEL,
     field::{Access, ModelField, ModelFieldReference, SqlType},
     file::FileModelOptions,
 };
@@ -83,6 +84,28 @@ pub struct Model {
     #[serde(default)]
     pub allow_id_in_create: bool,
 
+    /// If true, deleting an object sets its `deleted_at` column instead of removing the row.
+    /// Deleted objects are hidden from `get` and `list`, and can be restored until they are
+    /// permanently deleted.
+    #[serde(default)]
+    pub soft_delete: bool,
+
+    /// If true, creating, updating, and deleting an object also records the change, along with
+    /// who made it, in the `audit_log` table.
+    #[serde(default)]
+    pub audit: bool,
+
+    /// If true, the model gets a `version` column that increases on every update. Updates must
+    /// pass the version they were based on, in the `If-Match` header or the `version` field of the
+    /// payload, and fail if the object has changed since then.
+    #[serde(default)]
+    pub optimistic_locking: bool,
+
+    /// If true, creating, updating, and deleting an object sends `<model>.created`,
+    /// `<model>.updated`, and `<model>.deleted` events to the organization's webhook subscriptions.
+    #[serde(default)]
+    pub webhooks: bool,
+
     /// Set how permissions are tracked on this model. If omitted, it will use [Config#default_auth_scope]
     #[serde(default)]
     pub auth_scope: Option<ModelAuthScope>,
@@ -275,9 +298,12 @@ impl Model {
         };
 
         self.indexes.extend(other_model.indexes.into_iter());
+        self.audit = self.audit || other_model.audit;
+        self.webhooks = self.webhooks || other_model.webhooks;
 
         // Don't merge `global`, `plural`, or `name` since these must not change
-        // for things to work properly.
+        // for things to work properly. `soft_delete` and `optimistic_locking` are also not merged
+        // since the built-in queries for the base models always delete and update rows directly.
     }
 
     /// Return true if this table depends on the `other` table in some way.
@@ -288,6 +314,11 @@ impl Model {
             return self.name != "User";
         }
 
+        if other.name == PROJECT_MODEL && matches!(self.auth_scope, Some(ModelAuthScope::Project)) {
+            // The project_id field is also a standard field, so it isn't in `self.fields`.
+            return true;
+        }
+
         if self
             .joins
             .as_ref()
@@ -308,10 +339,12 @@ impl Model {
                 return false;
             };
 
-            r.table
+            // References to a model are filled in with the schema-qualified table name.
+            let table = r
+                .table
                 .as_deref()
-                .expect("reference table was not filled in")
-                == other.table()
+                .expect("reference table was not filled in");
+            table == other.table() || table == other.full_table()
         }) {
             return true;
         }
@@ -332,12 +365,49 @@ impl Model {
         }
     }
 
+    /// Sort items so that each model comes after all the models that it depends on. A plain
+    /// `sort_by` with [Model::order_by_dependency] does not guarantee this, since dependencies
+    /// only define a partial order.
+    pub fn sort_dependencies_first<T>(items: Vec<T>, model: impl Fn(&T) -> &Model) -> Vec<T> {
+        let mut remaining = items;
+        remaining.sort_by(|a, b| model(a).name.cmp(&model(b).name));
+
+        let mut sorted = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .position(|item| {
+                    let m = model(item);
+                    !remaining
+                        .iter()
+                        .any(|other| model(other).name != m.name && m.depends_on(model(other)))
+                })
+                // Fall back to alphabetical order if there is a cycle.
+                .unwrap_or(0);
+            sorted.push(remaining.remove(next));
+        }
+
+        sorted
+    }
+
     pub fn apply_config(&mut self, config: &Config) {
         if self.schema.is_none() {
-            self.schema = if self.is_auth_model {
-                config.database.auth_schema().map(|s| s.to_string())
+            let schema = if self.is_auth_model {
+                config.auth_schema()
             } else {
-                config.database.model_schema().map(|s| s.to_string())
+                config.model_schema()
+            };
+            self.schema = Some(schema.to_string());
+        }
+
+        if self.auth_scope.is_none() {
+            // Global models have no per-organization permissions, and child and joining models
+            // are only accessed through their parent, so they always use model-level permissions.
+            self.auth_scope = if self.global || self.joins.is_some() || !self.belongs_to.is_empty()
+            {
+                Some(ModelAuthScope::Model)
+            } else {
+                Some(config.default_auth_scope)
             };
         }
     }
@@ -346,6 +416,7 @@ impl Model {
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Pagination {
     /// Disable pagination completely unless it's explicitly requested.
+    #[serde(default)]
     pub disable: bool,
     #[serde(default = "default_per_page")]
     pub default_per_page: u32,
@@ -353,6 +424,22 @@ pub struct Pagination {
     pub max_per_page: u32,
     /// Maximum number of pages possible to return.
     pub max_page: Option<u32>,
+    /// How the client moves between pages
+    #[serde(default)]
+    pub mode: PaginationMode,
+}
+
+/// How list results are paginated
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaginationMode {
+    /// Pages are selected with `page` and `per_page`
+    #[default]
+    Offset,
+    /// Each page returns an opaque `next_cursor`, which is passed as `after` to fetch the
+    /// following page. This stays stable while rows are being inserted and avoids the cost
+    /// of large offsets. Only supported with PostgreSQL.
+    Cursor,
 }
 
 impl Default for Pagination {
@@ -362,6 +449,7 @@ impl Default for Pagination {
             default_per_page: default_per_page(),
             max_per_page: default_max_per_page(),
             max_page: None,
+            mode: PaginationMode::default(),
         }
     }
 }
@@ -374,13 +462,98 @@ const fn default_max_per_page() -> u32 {
     200
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+/// The Postgres text search configuration used for fields with `filterable = "search"`
+pub const TEXT_SEARCH_CONFIG: &str = "english";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum SqlDialect {
     Postgresql,
     SQLite,
 }
 
+impl SqlDialect {
+    /// The schema that tables are placed in when no schema is configured
+    pub fn default_schema(&self) -> &'static str {
+        match self {
+            Self::Postgresql => "public",
+            Self::SQLite => "main",
+        }
+    }
+
+    /// The parameter syntax for the binding with the given 1-based index
+    pub fn placeholder(&self, index: usize) -> String {
+        match self {
+            Self::Postgresql => format!("${index}"),
+            Self::SQLite => format!("?{index}"),
+        }
+    }
+
+    /// An expression for the current time
+    pub fn now(&self) -> &'static str {
+        match self {
+            Self::Postgresql => "now()",
+            // Timestamps are stored as unix time in SQLite
+            Self::SQLite => "unixepoch()",
+        }
+    }
+
+    /// An expression for the current time, suitable for a column's DEFAULT clause
+    pub fn now_default(&self) -> &'static str {
+        match self {
+            Self::Postgresql => "now()",
+            // SQLite requires parentheses around expressions in a DEFAULT clause
+            Self::SQLite => "(unixepoch())",
+        }
+    }
+
+    /// An aggregate that is true if the expression is true for any row
+    pub fn bool_or(&self, expr: &str) -> String {
+        match self {
+            Self::Postgresql => format!("bool_or({expr})"),
+            Self::SQLite => format!("max({expr})"),
+        }
+    }
+
+    /// A condition that checks if the ID `value` is one of the elements of `array`. In SQLite,
+    /// UUIDs are stored as blobs and arrays are bound as a JSON array of UUID strings.
+    pub fn in_id_array(&self, value: &str, array: &str) -> String {
+        match self {
+            Self::Postgresql => format!("{value} = ANY({array})"),
+            Self::SQLite => format!(
+                "hex({value}) IN (SELECT upper(replace(value, '-', '')) FROM json_each({array}))"
+            ),
+        }
+    }
+
+    /// A condition that checks if the ID `value` is not any of the elements of `array`.
+    pub fn not_in_id_array(&self, value: &str, array: &str) -> String {
+        match self {
+            Self::Postgresql => format!("{value} <> ALL ({array})"),
+            Self::SQLite => format!(
+                "hex({value}) NOT IN (SELECT upper(replace(value, '-', '')) FROM json_each({array}))"
+            ),
+        }
+    }
+
+    /// The function that builds a JSON object from alternating keys and values
+    pub fn json_build_object(&self) -> &'static str {
+        match self {
+            Self::Postgresql => "JSONB_BUILD_OBJECT",
+            Self::SQLite => "json_object",
+        }
+    }
+
+    /// Aggregate the expression into an array, returning an empty array if there are no rows.
+    /// `array_type` is the Postgres type of the array elements.
+    pub fn array_agg(&self, expr: &str, array_type: &str) -> String {
+        match self {
+            Self::Postgresql => format!("COALESCE(ARRAY_AGG({expr}), ARRAY[]::{array_type}[])"),
+            Self::SQLite => format!("COALESCE(json_group_array({expr}), json_array())"),
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub enum Endpoints {
@@ -405,17 +578,22 @@ impl Endpoints {
 
     pub fn merge_from(&mut self, other: Endpoints) {
         match (&self, other) {
-            (Endpoints::All(true), _) => {}
             (_, Endpoints::All(false)) => {}
             (Endpoints::All(false), b) => *self = b,
-            (Endpoints::Only(_), Endpoints::All(true)) => *self = Endpoints::All(true),
-            (Endpoints::Only(a), Endpoints::Only(b)) => {
+            (Endpoints::All(true), Endpoints::All(true)) => {}
+            (a, b) => {
+                // `true` doesn't enable the bulk endpoints, so merge field by field.
+                let a = a.per_endpoint();
+                let b = b.per_endpoint();
                 *self = Endpoints::Only(PerEndpoint {
                     get: a.get || b.get,
                     list: a.list || b.list,
                     create: a.create || b.create,
                     update: a.update || b.update,
                     delete: a.delete || b.delete,
+                    bulk_create: a.bulk_create || b.bulk_create,
+                    bulk_update: a.bulk_update || b.bulk_update,
+                    bulk_delete: a.bulk_delete || b.bulk_delete,
                 })
             }
         }
@@ -429,11 +607,30 @@ pub struct PerEndpoint {
     pub create: bool,
     pub update: bool,
     pub delete: bool,
+    /// Create many objects at once, in a single statement. The bulk endpoints are not enabled
+    /// by setting `standard_endpoints = true`, and must be turned on explicitly.
+    #[serde(default)]
+    pub bulk_create: bool,
+    /// Update many objects at once, in a single statement.
+    #[serde(default)]
+    pub bulk_update: bool,
+    /// Delete many objects at once, in a single statement.
+    #[serde(default)]
+    pub bulk_delete: bool,
 }
 
 impl PerEndpoint {
     pub fn any_enabled(&self) -> bool {
-        self.get || self.list || self.create || self.update || self.delete
+        self.get
+            || self.list
+            || self.create
+            || self.update
+            || self.delete
+            || self.any_bulk_enabled()
+    }
+
+    pub fn any_bulk_enabled(&self) -> bool {
+        self.bulk_create || self.bulk_update || self.bulk_delete
     }
 }
 
@@ -445,6 +642,9 @@ impl From<bool> for PerEndpoint {
             create: b,
             update: b,
             delete: b,
+            bulk_create: false,
+            bulk_update: false,
+            bulk_delete: false,
         }
     }
 }
@@ -455,23 +655,20 @@ impl From<bool> for PerEndpoint {
 pub enum ModelAuthScope {
     /// There is a single set of owner/editor/viewer permissions that applies to all objects of this model.
     Model,
-    // TODO projects not implemented yet
-    // /// Object permissions are inherited from the user's permissions on the project that
-    // /// contains the object.
-    // Project,
-    // TODO implement this soon, and make it configurable whether objects are globally readable by
-    // default or not
-    // /// Permissions on existing objects are set per-object.
-    // /// Creators of an object automatically get owner permission on the object.
-    // Object,
+    /// Object permissions are inherited from the user's permissions on the project that
+    /// contains the object.
+    Project,
+    /// Permissions on existing objects are set per-object.
+    /// Creators of an object automatically get owner permission on the object.
+    Object,
 }
 
 impl std::fmt::Display for ModelAuthScope {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ModelAuthScope::Model => write!(f, "model"),
-            // ModelAuthScope::Project => write!(f, "project"),
-            // ModelAuthScope::Object => write!(f, "object"),
+            ModelAuthScope::Project => write!(f, "project"),
+            ModelAuthScope::Object => write!(f, "object"),
         }
     }
 }
@@ -482,10 +679,10 @@ impl ModelAuthScope {
         match self {
             // For model auth scope, we always know the permissions when doing the call.
             ModelAuthScope::Model => false,
-            // // For project auth scope we join the project ID against the permissions table.
-            // ModelAuthScope::Project => true,
-            // // For object auth scope we join the object ID against the permissions table.
-            // ModelAuthScope::Object => true,
+            // For project auth scope we join the project ID against the permissions table.
+            ModelAuthScope::Project => true,
+            // For object auth scope we join the object ID against the permissions table.
+            ModelAuthScope::Object => true,
         }
     }
 }
@@ -6,6 +6,7 @@ mod query_builder;
 
 /// Common binding names used in a lot of places
 pub mod bindings {
+    pub const ACTOR_ID: &str = "actor_id";
     pub const ACTOR_IDS: &str = "actor_ids";
     pub const ID: &str = "id";
     pub const JOIN_ID_0: &str = "join_id_0";
@@ -15,6 +16,12 @@ pub mod bindings {
     pub const ORGANIZATION: &str = "organization_id";
     pub const LIMIT: &str = "limit";
     pub const OFFSET: &str = "offset";
+    pub const PERMISSION: &str = "permission";
+    pub const INCLUDE_DELETED: &str = "include_deleted";
+    pub const SEARCH: &str = "search";
+    pub const REQUEST_ID: &str = "request_id";
+    pub const VERSION: &str = "version";
+    pub const VERSIONS: &str = "versions";
 }
 
 use std::collections::HashMap;
@@ -86,6 +93,8 @@ pub fn generate_query_bindings(args: &HashMap<String, tera::Value>) -> tera::Res
                         Some("id.0.as_uuid()".to_string())
                     } else if name == bindings::JOIN_ID_1 {
                         Some("id.1.as_uuid()".to_string())
+                    } else if name == bindings::REQUEST_ID {
+                        Some("filigree::requests::current_request_id()".to_string())
                     } else {
                         None
                     }
@@ -135,6 +144,10 @@ impl<'a> SqlBuilder<'a> {
                 queries::list::list(self, true),
                 queries::select::select_one(self, false),
                 queries::select::select_one(self, true),
+                queries::lookup_object_permissions::create_query(self),
+                queries::audit::list_audit_log_query(self),
+                queries::update::current_version(self),
+                queries::update::update_any_version(self),
             ]
             .into_iter()
             .flatten(),
@@ -143,12 +156,20 @@ impl<'a> SqlBuilder<'a> {
             // Vec-returning queries
             [
                 queries::update::update_one_with_parent(self),
+                queries::bulk::bulk_queries(self),
                 queries::upsert::upsert_queries(self),
                 queries::delete::delete_children_queries(self),
+                queries::delete::soft_delete_queries(self),
+                queries::object_permissions::object_permissions_queries(self),
             ]
             .into_iter()
             .flatten(),
         )
         .collect()
     }
+
+    /// Create a [QueryBuilder](query_builder::QueryBuilder) for the model's SQL dialect
+    pub(super) fn query_builder(&self) -> query_builder::QueryBuilder {
+        query_builder::QueryBuilder::with_dialect(self.context.sql_dialect)
+    }
 }
@@ -1,11 +1,17 @@
+use std::fmt::Write;
+
 use super::{bindings, query_builder::QueryBuilder, SqlBuilder};
-use crate::templates::sql_string;
+use crate::{
+    model::{base_models::PROJECT_MODEL, ModelAuthScope},
+    templates::sql_string,
+};
 
 impl<'a> SqlBuilder<'a> {
     /// A subquery that checks if a user has any of the given permissions
     pub fn permissions_check_where_clause(&self, q: &mut QueryBuilder, perms: &[impl AsRef<str>]) {
         let organization = q.create_binding(bindings::ORGANIZATION);
         let actor_ids = q.create_binding(bindings::ACTOR_IDS);
+        let actor_match = q.dialect.in_id_array("actor_id", &actor_ids);
 
         let perms = perms
             .iter()
@@ -19,7 +25,7 @@ impl<'a> SqlBuilder<'a> {
             FROM {auth_schema}.permissions
             WHERE
             organization_id = {organization}
-            AND actor_id = ANY({actor_ids})
+            AND {actor_match}
             AND permission IN ({perms})
           )"##,
             auth_schema = self.context.auth_schema
@@ -37,27 +43,36 @@ impl<'a> SqlBuilder<'a> {
         let user_perm = sql_string(user_perm);
         let organization = q.create_binding(bindings::ORGANIZATION);
         let actor_ids = q.create_binding(bindings::ACTOR_IDS);
+        let dialect = q.dialect;
         let s = format!(
             r##"
             SELECT
-              COALESCE(bool_or(permission in ('org_admin', {owner_perm}) ), false) as is_owner,
-              COALESCE(bool_or(permission in ('org_admin', {owner_perm}, {user_perm}) ), false) as is_user
+              COALESCE({is_owner}, false) as is_owner,
+              COALESCE({is_user}, false) as is_user
             FROM {auth_schema}.permissions
             WHERE
                 organization_id = {organization}
-                AND actor_id = ANY({actor_ids})
+                AND {actor_match}
                 AND permission in ('org_admin', {owner_perm}, {user_perm})
            "##,
-            auth_schema = self.context.auth_schema
+            auth_schema = self.context.auth_schema,
+            is_owner = dialect.bool_or(&format!("permission in ('org_admin', {owner_perm}) ")),
+            is_user = dialect.bool_or(&format!(
+                "permission in ('org_admin', {owner_perm}, {user_perm}) "
+            )),
+            actor_match = dialect.in_id_array("actor_id", &actor_ids),
         );
 
         q.push(&s);
     }
 
-    /// A subquery that looks up an object's permissions when using an object auth model
-    fn object_permissions_value_query(
+    /// A subquery that looks up an object's permissions when using an object auth model.
+    /// `object_id` is the SQL expression that refers to the object's ID, such as a binding or a
+    /// column. Organization admins are always treated as owners.
+    pub fn object_permissions_value_query(
         &self,
         q: &mut QueryBuilder,
+        object_id: &str,
         owner_perm: &str,
         write_perm: &str,
         read_perm: &str,
@@ -68,27 +83,89 @@ impl<'a> SqlBuilder<'a> {
 
         let organization = q.create_binding(bindings::ORGANIZATION);
         let actor_ids = q.create_binding(bindings::ACTOR_IDS);
-        let object_id = q.create_binding(bindings::ID);
+        let dialect = q.dialect;
+        let actor_match = dialect.in_id_array("actor_id", &actor_ids);
 
         let s = format!(
             r##"
             SELECT
               CASE
-                WHEN bool_or(permission in ('org_admin', {owner_perm})) THEN 'owner'
-                WHEN bool_or(permission = {write_perm}) THEN 'write'
-                WHEN bool_or(permission = {read_perm}) THEN 'read'
+                WHEN {is_owner} THEN 'owner'
+                WHEN {is_writer} THEN 'write'
+                WHEN {is_reader} THEN 'read'
                 ELSE NULL
               END _permission
-            FROM {auth_schema}.object_permissions
-            WHERE
-                organization_id = {organization}
-                AND actor_id = ANY({actor_ids})
-                AND object_id = {object_id}
-                AND permission in ('org_admin', {owner_perm}, {write_perm}, {read_perm})
+            FROM (
+              SELECT permission
+              FROM {auth_schema}.object_permissions
+              WHERE
+                  organization_id = {organization}
+                  AND {actor_match}
+                  AND object_id = {object_id}
+                  AND permission in ({owner_perm}, {write_perm}, {read_perm})
+              UNION ALL
+              SELECT permission
+              FROM {auth_schema}.permissions
+              WHERE
+                  organization_id = {organization}
+                  AND {actor_match}
+                  AND permission = 'org_admin'
+            ) perms
         "##,
-            auth_schema = self.context.auth_schema
+            auth_schema = self.context.auth_schema,
+            is_owner = dialect.bool_or(&format!("permission in ('org_admin', {owner_perm})")),
+            is_writer = dialect.bool_or(&format!("permission = {write_perm}")),
+            is_reader = dialect.bool_or(&format!("permission = {read_perm}")),
         );
 
         q.push(&s);
     }
+
+    /// The owner, write, and read permission names that control access to objects of this model.
+    fn auth_scope_permission_names(&self) -> [String; 3] {
+        match self.context.auth_scope {
+            ModelAuthScope::Project => [
+                format!("{PROJECT_MODEL}::owner"),
+                format!("{PROJECT_MODEL}::write"),
+                format!("{PROJECT_MODEL}::read"),
+            ],
+            _ => [
+                self.context.owner_permission.clone(),
+                self.context.write_permission.clone(),
+                self.context.read_permission.clone(),
+            ],
+        }
+    }
+
+    /// A subquery that looks up the permission level that the user has on an object, for models
+    /// that use the object or project auth scope. `object_id` is the SQL expression that refers
+    /// to the object's ID for object scope, or to its project's ID for project scope.
+    pub fn auth_scope_permissions_value_query(&self, q: &mut QueryBuilder, object_id: &str) {
+        let [owner_perm, write_perm, read_perm] = self.auth_scope_permission_names();
+        self.object_permissions_value_query(q, object_id, &owner_perm, &write_perm, &read_perm);
+    }
+
+    /// A where clause that checks that the user has one of the given permission levels
+    /// ("owner", "write", "read") on the row in `table`, which may be a table name or an alias.
+    pub fn auth_check_where_clause(&self, q: &mut QueryBuilder, table: &str, levels: &[&str]) {
+        let object_id = match self.context.auth_scope {
+            ModelAuthScope::Project => format!("{table}.project_id"),
+            _ => format!("{table}.id"),
+        };
+        self.permission_level_check(q, &object_id, levels);
+    }
+
+    /// A where clause that checks that the user has one of the given permission levels
+    /// on the object or project referenced by `object_id`.
+    pub fn permission_level_check(&self, q: &mut QueryBuilder, object_id: &str, levels: &[&str]) {
+        q.push("(");
+        self.auth_scope_permissions_value_query(q, object_id);
+
+        let levels = levels
+            .iter()
+            .map(|s| sql_string(s))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(q, ") IN ({levels})").unwrap();
+    }
 }
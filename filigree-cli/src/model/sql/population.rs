@@ -24,9 +24,6 @@ impl<'a> SqlBuilder<'a> {
             ReferenceFetchType::Id => {
                 let mut output = String::new();
                 output.push_str("(SELECT ");
-                if child.relationship.many {
-                    output.push_str("COALESCE(ARRAY_AGG(");
-                }
 
                 let (schema, table, id_field) = if let Some(through) = &child.through {
                     (
@@ -37,10 +34,12 @@ impl<'a> SqlBuilder<'a> {
                 } else {
                     (child.schema.as_str(), child.table.as_str(), "id")
                 };
-                write!(output, "ct.{id_field}").unwrap();
+                let id_expr = format!("ct.{id_field}");
 
                 if child.relationship.many {
-                    output.push_str("), ARRAY[]::uuid[])");
+                    output.push_str(&self.context.sql_dialect.array_agg(&id_expr, "uuid"));
+                } else {
+                    output.push_str(&id_expr);
                 }
 
                 write!(
@@ -66,15 +65,14 @@ impl<'a> SqlBuilder<'a> {
             ReferenceFetchType::Data => {
                 let mut output = String::new();
                 output.push_str("(SELECT ");
-                if child.relationship.many {
-                    output.push_str("COALESCE(ARRAY_AGG(");
-                }
 
                 let fields = Self::jsonb_build_object_contents(&child.fields, "t");
-                write!(output, "JSONB_BUILD_OBJECT({fields})").unwrap();
+                let object = format!("{}({fields})", self.context.sql_dialect.json_build_object());
 
                 if child.relationship.many {
-                    output.push_str("), ARRAY[]::jsonb[])");
+                    output.push_str(&self.context.sql_dialect.array_agg(&object, "jsonb"));
+                } else {
+                    output.push_str(&object);
                 }
 
                 if let Some(through) = &child.through {
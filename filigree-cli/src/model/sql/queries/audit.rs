@@ -0,0 +1,258 @@
+use std::fmt::Write;
+
+use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+use crate::{model::field::ModelFieldTemplateContext, templates::sql_string};
+
+/// A change that is recorded in the audit log
+#[derive(Copy, Clone, Debug)]
+pub enum AuditOperation {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    HardDelete,
+    /// An insert that updates the existing row on conflict. The audit log records it as a
+    /// create or an update, depending on whether the row existed before.
+    Upsert,
+}
+
+impl AuditOperation {
+    /// The SQL expression for the operation recorded in the audit log.
+    fn sql_value(&self) -> &'static str {
+        match self {
+            Self::Create => "'create'",
+            Self::Update => "'update'",
+            Self::Delete => "'delete'",
+            Self::Restore => "'restore'",
+            Self::HardDelete => "'hard_delete'",
+            Self::Upsert => "CASE WHEN before.id IS NULL THEN 'create' ELSE 'update' END",
+        }
+    }
+
+    /// If the statement updates the row in place, so that the audit log should contain only
+    /// the fields that changed.
+    fn is_update(&self, soft_delete: bool) -> bool {
+        match self {
+            Self::Update | Self::Restore | Self::Upsert => true,
+            Self::Delete => soft_delete,
+            Self::Create | Self::HardDelete => false,
+        }
+    }
+}
+
+/// Begin a statement that also writes to the audit log, if the model is audited. The caller
+/// pushes its INSERT, UPDATE, or DELETE statement without a RETURNING clause and then calls
+/// [finish_audit].
+///
+/// The statement runs in a CTE, so that the audit log row is written in the same statement as
+/// the change itself.
+pub fn start_audit(data: &SqlBuilder, q: &mut QueryBuilder, operation: AuditOperation) {
+    start_audit_with(data, q, operation, |q| data.push_id_where_clause(q));
+}
+
+/// Like [start_audit], for a statement that changes every object in the `ids` binding.
+pub fn start_bulk_audit(data: &SqlBuilder, q: &mut QueryBuilder, operation: AuditOperation) {
+    start_audit_with(data, q, operation, |q| {
+        q.push("id = ANY(");
+        q.push_binding(bindings::IDS);
+        q.push(")");
+    });
+}
+
+/// Like [start_audit], for an upsert of a child object. `push_match_clause` matches the row
+/// that the upsert would update, if it exists.
+pub fn start_upsert_audit(
+    data: &SqlBuilder,
+    q: &mut QueryBuilder,
+    push_match_clause: impl FnOnce(&mut QueryBuilder),
+) {
+    start_audit_with(data, q, AuditOperation::Upsert, push_match_clause);
+}
+
+fn start_audit_with(
+    data: &SqlBuilder,
+    q: &mut QueryBuilder,
+    operation: AuditOperation,
+    push_id_where_clause: impl FnOnce(&mut QueryBuilder),
+) {
+    if !data.context.audit {
+        return;
+    }
+
+    if operation.is_update(data.context.soft_delete) {
+        // Every statement in the query sees the same snapshot, so this gets the row
+        // as it was before the update.
+        write!(
+            q,
+            "WITH before AS (SELECT * FROM {schema}.{table} WHERE ",
+            schema = data.context.schema,
+            table = data.context.table
+        )
+        .unwrap();
+        push_id_where_clause(q);
+        if !data.context.global {
+            q.push(" AND organization_id = ");
+            q.push_binding(bindings::ORGANIZATION);
+        }
+        q.push("),\nchanged AS (\n");
+    } else {
+        q.push("WITH changed AS (\n");
+    }
+}
+
+/// Finish a statement started with [start_audit]. If `return_object` is true the query returns
+/// the readable fields of the changed object, and otherwise just its ID.
+pub fn finish_audit(
+    data: &SqlBuilder,
+    q: &mut QueryBuilder,
+    operation: AuditOperation,
+    return_object: bool,
+) {
+    if !data.context.audit {
+        return;
+    }
+
+    let fields = data
+        .context
+        .fields
+        .iter()
+        .filter(|f| !f.never_read)
+        .cloned()
+        .collect::<Vec<_>>();
+    let object = |table: &str| {
+        format!(
+            "jsonb_build_object({})",
+            SqlBuilder::jsonb_build_object_contents(&fields, table)
+        )
+    };
+
+    let update = operation.is_update(data.context.soft_delete);
+    // Record only the fields that changed.
+    let changed_before = "(SELECT jsonb_object_agg(key, value) FROM jsonb_each(obj.old)
+            WHERE value IS DISTINCT FROM obj.new -> key)";
+    let changed_after = "(SELECT jsonb_object_agg(key, value) FROM jsonb_each(obj.new)
+            WHERE value IS DISTINCT FROM obj.old -> key)";
+    let (data_before, data_after) = if matches!(operation, AuditOperation::Upsert) {
+        // When no row existed before, the upsert inserted a new one.
+        (
+            format!("CASE WHEN before.id IS NULL THEN NULL ELSE {changed_before} END"),
+            format!("CASE WHEN before.id IS NULL THEN obj.new ELSE {changed_after} END"),
+        )
+    } else if update {
+        (changed_before.to_string(), changed_after.to_string())
+    } else if matches!(operation, AuditOperation::Create) {
+        ("NULL".to_string(), object("changed"))
+    } else {
+        (object("changed"), "NULL".to_string())
+    };
+
+    let organization_id = if data.context.global {
+        "NULL"
+    } else {
+        "changed.organization_id"
+    };
+
+    let actor_id = q.create_binding(bindings::ACTOR_ID);
+    let request_id = q.create_binding(bindings::REQUEST_ID);
+    write!(
+        q,
+        r##" RETURNING {table}.*),
+audit AS (
+    INSERT INTO {schema}.audit_log
+    (organization_id, actor_id, object_type, object_id, operation, data_before, data_after, request_id)
+    SELECT {organization_id}, {actor_id}::uuid, {model}, changed.id, {operation},
+        {data_before},
+        {data_after},
+        {request_id}::uuid
+    FROM changed"##,
+        schema = data.context.schema,
+        table = data.context.table,
+        model = sql_string(&data.context.model_name),
+        operation = operation.sql_value(),
+    )
+    .unwrap();
+
+    if update {
+        // An upsert may not have a row from before the change.
+        let join = if matches!(operation, AuditOperation::Upsert) {
+            "LEFT JOIN"
+        } else {
+            "JOIN"
+        };
+        write!(
+            q,
+            "
+    {join} before ON before.id = changed.id
+    CROSS JOIN LATERAL (SELECT {old} AS old, {new} AS new) obj",
+            old = object("before"),
+            new = object("changed"),
+        )
+        .unwrap();
+    }
+
+    q.push("\n)\nSELECT ");
+    if return_object {
+        let columns = data
+            .context
+            .fields
+            .iter()
+            .filter(|f| !f.never_read)
+            .map(not_null_column)
+            .collect::<Vec<_>>()
+            .join(",\n");
+        q.push(&columns);
+    } else {
+        q.push("id");
+    }
+    q.push(" FROM changed");
+}
+
+/// Columns selected from a CTE have unknown nullability, so mark the non-nullable columns
+/// explicitly for sqlx.
+fn not_null_column(field: &ModelFieldTemplateContext) -> String {
+    if field.nullable {
+        return field.sql_full_name.clone();
+    }
+
+    let type_override = field
+        .sql_full_name
+        .split_once(": ")
+        .map(|(_, t)| format!(": {}", t.trim_end_matches('"')))
+        .unwrap_or_default();
+    format!(
+        r##"{} AS "{}!{}""##,
+        field.sql_name, field.rust_name, type_override
+    )
+}
+
+/// Fetch the audit log entries for an object, newest first.
+pub fn list_audit_log_query(data: &SqlBuilder) -> Option<SqlQueryContext> {
+    if !data.context.audit {
+        return None;
+    }
+
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "SELECT actor_id, operation, data_before, data_after, request_id, created_at
+        FROM {schema}.audit_log
+        WHERE object_type = {model} AND object_id = ",
+        schema = data.context.schema,
+        model = sql_string(&data.context.model_name),
+    )
+    .unwrap();
+    q.push_binding(bindings::ID);
+
+    if !data.context.global {
+        q.push(" AND organization_id = ");
+        q.push_binding(bindings::ORGANIZATION);
+    }
+
+    // Entries written in the same transaction share a timestamp, so break ties with the ID.
+    q.push(" ORDER BY created_at DESC, id DESC LIMIT ");
+    q.push_binding(bindings::LIMIT);
+    q.push(" OFFSET ");
+    q.push_binding(bindings::OFFSET);
+
+    Some(q.finish("list_audit_log"))
+}
@@ -0,0 +1,253 @@
+//! Queries that create, update, or delete many objects in one statement. The payloads are bound
+//! as one array per column and expanded into rows with `UNNEST`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::{
+    audit::{finish_audit, start_bulk_audit, AuditOperation},
+    bindings, QueryBuilder, SqlBuilder, SqlQueryContext,
+};
+use crate::model::{field::ModelFieldTemplateContext, ModelAuthScope};
+
+pub fn bulk_queries(data: &SqlBuilder) -> Vec<SqlQueryContext> {
+    let endpoints = &data.context.endpoints;
+    [
+        endpoints.bulk_create.then(|| bulk_insert(data)),
+        endpoints.bulk_update.then(|| bulk_update(data)),
+        endpoints.bulk_delete.then(|| bulk_delete(data)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn writable_fields<'a>(data: &'a SqlBuilder) -> Vec<&'a ModelFieldTemplateContext> {
+    data.context
+        .fields
+        .iter()
+        .filter(|f| f.writable && f.name != "id")
+        .collect()
+}
+
+/// `UNNEST($1::UUID[], $2::TEXT[], ...) AS input(id, field, ...)`
+fn push_unnest(
+    q: &mut QueryBuilder,
+    fields: &[&ModelFieldTemplateContext],
+    extra_columns: &[(&str, &str, &str)],
+) {
+    let columns = [("id", bindings::IDS, "UUID")]
+        .into_iter()
+        .chain(
+            fields
+                .iter()
+                .map(|f| (f.sql_name.as_str(), f.param_binding_name(), f.sql_type)),
+        )
+        .chain(extra_columns.iter().copied())
+        .collect::<Vec<_>>();
+
+    q.push("UNNEST(");
+    {
+        let mut sep = q.separated(", ");
+        for (_, binding, sql_type) in &columns {
+            sep.push_binding(binding);
+            sep.push_unseparated("::");
+            sep.push_unseparated(sql_type);
+            sep.push_unseparated("[]");
+        }
+    }
+
+    let names = columns
+        .iter()
+        .map(|(name, _, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    write!(q, ") AS input({names})").unwrap();
+}
+
+fn finish_with_array_bindings(
+    q: QueryBuilder,
+    name: &str,
+    fields: &[&ModelFieldTemplateContext],
+) -> SqlQueryContext {
+    let mut result = q.finish(name);
+    result.field_params = fields
+        .iter()
+        .map(|f| (f.param_binding_name().to_string(), f.param_array_binding()))
+        .collect::<HashMap<_, _>>();
+    result
+}
+
+/// The readable fields of the changed rows, qualified with the table name since the `input`
+/// rows are also in scope.
+fn push_returning(data: &SqlBuilder, q: &mut QueryBuilder) {
+    let returning = data
+        .context
+        .fields
+        .iter()
+        .filter(|f| !f.never_read)
+        .map(|f| format!("{}.{}", data.context.table, f.sql_full_name))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    q.push(" RETURNING ");
+    q.push(&returning);
+}
+
+fn bulk_insert(data: &SqlBuilder) -> SqlQueryContext {
+    let fields = writable_fields(data);
+    let mut q = data.query_builder();
+    start_bulk_audit(data, &mut q, AuditOperation::Create);
+    write!(
+        q,
+        "INSERT INTO {schema}.{table} (id",
+        schema = data.context.schema,
+        table = data.context.table
+    )
+    .unwrap();
+
+    if !data.context.global {
+        q.push(", organization_id");
+    }
+
+    for field in &fields {
+        q.push(", ");
+        q.push(&field.sql_name);
+    }
+
+    q.push(")\nSELECT input.id");
+    if !data.context.global {
+        q.push(", ");
+        q.push_binding(bindings::ORGANIZATION);
+    }
+
+    for field in &fields {
+        q.push(", input.");
+        q.push(&field.sql_name);
+    }
+
+    q.push("\nFROM ");
+    push_unnest(&mut q, &fields, &[]);
+
+    if data.context.audit {
+        finish_audit(data, &mut q, AuditOperation::Create, true);
+    } else {
+        push_returning(data, &mut q);
+    }
+
+    finish_with_array_bindings(q, "bulk_insert", &fields)
+}
+
+fn bulk_update(data: &SqlBuilder) -> SqlQueryContext {
+    let fields = writable_fields(data);
+    let table = &data.context.table;
+    let full_table = format!("{}.{}", data.context.schema, table);
+
+    let mut q = data.query_builder();
+    start_bulk_audit(data, &mut q, AuditOperation::Update);
+    write!(q, "UPDATE {full_table} SET ").unwrap();
+
+    for field in &fields {
+        write!(q, "{name} = input.{name},\n", name = field.sql_name).unwrap();
+    }
+
+    if data.context.optimistic_locking {
+        write!(q, "version = {table}.version + 1,\n").unwrap();
+    }
+
+    write!(q, "updated_at = {}\nFROM ", data.context.sql_dialect.now()).unwrap();
+
+    let versions = [("version", bindings::VERSIONS, "INTEGER")];
+    let extra_columns: &[_] = if data.context.optimistic_locking {
+        &versions
+    } else {
+        &[]
+    };
+    push_unnest(&mut q, &fields, extra_columns);
+
+    write!(q, "\nWHERE {table}.id = input.id").unwrap();
+
+    if !data.context.global {
+        write!(q, " AND {table}.organization_id = ").unwrap();
+        q.push_binding(bindings::ORGANIZATION);
+    }
+
+    if data.context.soft_delete {
+        write!(q, " AND {table}.deleted_at IS NULL").unwrap();
+    }
+
+    if data.context.optimistic_locking {
+        write!(q, " AND {table}.version = input.version").unwrap();
+    }
+
+    if data.context.auth_check_in_query {
+        q.push(" AND ");
+        data.auth_check_where_clause(&mut q, &full_table, &["owner", "write"]);
+
+        if matches!(data.context.auth_scope, ModelAuthScope::Project)
+            && fields.iter().any(|f| f.name == "project_id")
+        {
+            // Moving an object to another project also requires write access to that project.
+            q.push(" AND ");
+            data.permission_level_check(&mut q, "input.project_id", &["owner", "write"]);
+        }
+    }
+
+    if data.context.audit {
+        finish_audit(data, &mut q, AuditOperation::Update, true);
+    } else {
+        push_returning(data, &mut q);
+    }
+
+    finish_with_array_bindings(q, "bulk_update", &fields)
+}
+
+fn bulk_delete(data: &SqlBuilder) -> SqlQueryContext {
+    let operation = AuditOperation::Delete;
+    let mut q = data.query_builder();
+    start_bulk_audit(data, &mut q, operation);
+
+    if data.context.soft_delete {
+        write!(
+            q,
+            "UPDATE {schema}.{table} SET deleted_at = {now}, updated_at = {now} WHERE ",
+            schema = data.context.schema,
+            table = data.context.table,
+            now = data.context.sql_dialect.now()
+        )
+        .unwrap();
+    } else {
+        write!(
+            q,
+            "DELETE FROM {schema}.{table} WHERE ",
+            schema = data.context.schema,
+            table = data.context.table
+        )
+        .unwrap();
+    }
+
+    q.push("id = ANY(");
+    q.push_binding(bindings::IDS);
+    q.push(")");
+
+    if !data.context.global {
+        q.push(" AND organization_id = ");
+        q.push_binding(bindings::ORGANIZATION);
+    }
+
+    if data.context.soft_delete {
+        q.push(" AND deleted_at IS NULL");
+    }
+
+    if data.context.auth_check_in_query {
+        q.push(" AND ");
+        let table = format!("{}.{}", data.context.schema, data.context.table);
+        data.auth_check_where_clause(&mut q, &table, &["owner"]);
+    }
+
+    if data.context.audit {
+        finish_audit(data, &mut q, operation, false);
+    } else {
+        q.push(" RETURNING id");
+    }
+
+    q.finish("bulk_delete")
+}
@@ -1,10 +1,53 @@
 use std::fmt::Write;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+use super::{
+    audit::{finish_audit, start_audit, AuditOperation},
+    bindings, QueryBuilder, SqlBuilder, SqlQueryContext,
+};
 use crate::model::generator::BelongsToFieldContext;
 
 pub fn create_delete_query(data: &SqlBuilder) -> SqlQueryContext {
-    let mut q = QueryBuilder::new();
+    if !data.context.soft_delete {
+        return hard_delete_query(data, "delete", AuditOperation::Delete);
+    }
+
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Delete);
+    write!(
+        q,
+        "UPDATE {schema}.{table} SET deleted_at = {now}, updated_at = {now} WHERE \n",
+        schema = data.context.schema,
+        table = data.context.table,
+        now = data.context.sql_dialect.now()
+    )
+    .unwrap();
+
+    push_delete_where_clause(data, &mut q);
+    q.push(" AND deleted_at IS NULL");
+    finish_audit(data, &mut q, AuditOperation::Delete, false);
+
+    q.finish("delete")
+}
+
+/// For soft-deleted models, the queries to permanently delete and to restore an object.
+pub fn soft_delete_queries(data: &SqlBuilder) -> Vec<SqlQueryContext> {
+    if !data.context.soft_delete {
+        return Vec::new();
+    }
+
+    vec![
+        hard_delete_query(data, "hard_delete", AuditOperation::HardDelete),
+        restore_query(data),
+    ]
+}
+
+fn hard_delete_query(
+    data: &SqlBuilder,
+    name: &str,
+    audit_operation: AuditOperation,
+) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, audit_operation);
     write!(
         q,
         "DELETE FROM {schema}.{table} WHERE \n",
@@ -13,7 +56,34 @@ pub fn create_delete_query(data: &SqlBuilder) -> SqlQueryContext {
     )
     .unwrap();
 
-    data.push_id_where_clause(&mut q);
+    push_delete_where_clause(data, &mut q);
+    finish_audit(data, &mut q, audit_operation, false);
+
+    q.finish(name)
+}
+
+fn restore_query(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Restore);
+    write!(
+        q,
+        "UPDATE {schema}.{table} SET deleted_at = NULL, updated_at = {now} WHERE \n",
+        schema = data.context.schema,
+        table = data.context.table,
+        now = data.context.sql_dialect.now()
+    )
+    .unwrap();
+
+    push_delete_where_clause(data, &mut q);
+    q.push(" AND deleted_at IS NOT NULL");
+    finish_audit(data, &mut q, AuditOperation::Restore, false);
+
+    q.finish("restore")
+}
+
+/// Restrict a query to the object being deleted, when the user has owner permissions on it.
+fn push_delete_where_clause(data: &SqlBuilder, q: &mut QueryBuilder) {
+    data.push_id_where_clause(q);
 
     if !data.context.global {
         q.push(" AND organization_id = ");
@@ -22,10 +92,9 @@ pub fn create_delete_query(data: &SqlBuilder) -> SqlQueryContext {
 
     if data.context.auth_check_in_query {
         q.push(" AND ");
-        data.permissions_check_where_clause(&mut q, &[&data.context.owner_permission]);
+        let table = format!("{}.{}", data.context.schema, data.context.table);
+        data.auth_check_where_clause(q, &table, &["owner"]);
     }
-
-    q.finish("delete")
 }
 
 pub fn delete_children_queries(data: &SqlBuilder) -> Vec<SqlQueryContext> {
@@ -46,7 +115,7 @@ fn delete_all_children_query(
     data: &SqlBuilder,
     belongs_to_field: &BelongsToFieldContext,
 ) -> SqlQueryContext {
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
     write!(
         q,
         r##"DELETE FROM {schema}.{table} WHERE "##,
@@ -78,7 +147,7 @@ fn delete_removed_children_query(
     data: &SqlBuilder,
     belongs_to_field: &BelongsToFieldContext,
 ) -> SqlQueryContext {
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
     write!(
         q,
         r##"DELETE FROM {schema}.{table} WHERE "##,
@@ -98,13 +167,19 @@ fn delete_removed_children_query(
         where_sep.push(&belongs_to_field.sql_name);
         where_sep.push_unseparated(" = ");
         where_sep.push_binding_unseparated(bindings::PARENT_ID);
-
-        where_sep.push(data.other_id_field(&belongs_to_field.sql_name));
-        where_sep.push_unseparated(" <> ALL (");
-        where_sep.push_binding_unseparated(bindings::IDS);
-        where_sep.push_unseparated(")");
     }
 
+    let ids = q.create_binding(bindings::IDS);
+    let other_id_field = data.other_id_field(&belongs_to_field.sql_name);
+    write!(
+        q,
+        " AND {}",
+        data.context
+            .sql_dialect
+            .not_in_id_array(other_id_field, &ids)
+    )
+    .unwrap();
+
     q.finish(format!(
         "delete_removed_children_of_{}",
         belongs_to_field.model_snake_case_name
@@ -115,7 +190,7 @@ fn delete_with_parent_query(
     data: &SqlBuilder,
     belongs_to_field: &BelongsToFieldContext,
 ) -> SqlQueryContext {
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
     write!(
         q,
         r##"DELETE FROM {schema}.{table} WHERE "##,
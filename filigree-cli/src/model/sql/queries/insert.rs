@@ -1,7 +1,11 @@
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+use super::{
+    audit::{finish_audit, start_audit, AuditOperation},
+    bindings, SqlBuilder, SqlQueryContext,
+};
 
 pub fn insert(data: &SqlBuilder) -> SqlQueryContext {
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Create);
     q.push("INSERT INTO ");
     q.push(&data.context.schema);
     q.push(".");
@@ -50,17 +54,22 @@ pub fn insert(data: &SqlBuilder) -> SqlQueryContext {
         }
     }
 
-    q.push(") RETURNING ");
+    q.push(")");
 
-    let returning = data
-        .context
-        .fields
-        .iter()
-        .filter(|f| !f.never_read)
-        .map(|f| f.sql_full_name.as_str())
-        .collect::<Vec<_>>()
-        .join(",\n");
-    q.push(&returning);
+    if data.context.audit {
+        finish_audit(data, &mut q, AuditOperation::Create, true);
+    } else {
+        let returning = data
+            .context
+            .fields
+            .iter()
+            .filter(|f| !f.never_read)
+            .map(|f| f.sql_full_name.as_str())
+            .collect::<Vec<_>>()
+            .join(",\n");
+        q.push(" RETURNING ");
+        q.push(&returning);
+    }
 
     q.finish_with_field_bindings("insert", &data_fields)
 }
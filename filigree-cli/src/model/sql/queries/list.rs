@@ -1,18 +1,24 @@
 use std::fmt::Write;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
-use crate::model::field::FilterableType;
+use super::{bindings, SqlBuilder, SqlQueryContext};
+use crate::model::{field::FilterableType, PaginationMode, TEXT_SEARCH_CONFIG};
 
 pub fn list(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContext> {
     if populate_children && data.context.children.is_empty() {
         return None;
     }
 
-    let mut q = QueryBuilder::new();
+    let cursor_mode = data.context.pagination.mode == PaginationMode::Cursor;
+    let mut q = data.query_builder();
 
     q.push("SELECT ");
 
     let org_binding = q.create_binding(bindings::ORGANIZATION);
+    let search_binding = data
+        .context
+        .search_vector_sql
+        .is_some()
+        .then(|| q.create_binding(bindings::SEARCH));
     let mut select_sep = q.separated(", ");
 
     let fields = data
@@ -26,6 +32,13 @@ pub fn list(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContex
         select_sep.push(&f.sql_name);
     }
 
+    if cursor_mode {
+        // The sort field is filled in at runtime, once the requested ordering is known. The
+        // value is encoded as JSON so that it can be parsed back into the field's Rust type.
+        select_sep.push("to_jsonb(__insertion_point_cursor_field)::text AS \"_cursor_value\"");
+        select_sep.push("CAST(tb.id AS TEXT) AS \"_cursor_id\"");
+    }
+
     if populate_children {
         data.context
             .children
@@ -55,7 +68,7 @@ pub fn list(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContex
             let name = format!("ref_{}", r.name);
             let object = SqlBuilder::jsonb_build_object_contents(&r.fields, &name);
             let clause = format!(
-                r##"(SELECT JSONB_BUILD_OBJECT({object})
+                r##"(SELECT {build_object}({object})
                 FROM {table} {name}
                 WHERE tb.{r_id_field} IS NOT NULL
                     AND {name}.id = tb.{r_id_field}
@@ -64,7 +77,8 @@ pub fn list(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContex
                 "##,
                 table = r.table,
                 r_id_field = r.id_field,
-                full_name = r.full_name
+                full_name = r.full_name,
+                build_object = data.context.sql_dialect.json_build_object(),
             );
 
             select_sep.push(&clause);
@@ -88,21 +102,53 @@ pub fn list(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContex
             where_sep.push_binding_unseparated(bindings::ORGANIZATION);
         }
 
-        let has_filterable = fields
-            .iter()
-            .any(|f| !matches!(f.filterable, FilterableType::None));
+        if data.context.soft_delete {
+            where_sep.push("(deleted_at IS NULL OR ");
+            where_sep.push_binding_unseparated(bindings::INCLUDE_DELETED);
+            where_sep.push_unseparated(")");
+        }
+
+        if let Some(search) = &search_binding {
+            where_sep.push(&format!(
+                "({search}::text IS NULL OR search_vector @@ websearch_to_tsquery('{TEXT_SEARCH_CONFIG}', {search}))"
+            ));
+        }
+
+        // In cursor mode the filters also hold the cursor position, so they are always present.
+        let has_filterable = cursor_mode
+            || fields
+                .iter()
+                .any(|f| matches!(f.filterable, FilterableType::Exact | FilterableType::Range));
         if has_filterable {
             where_sep.push("__insertion_point_filters");
         }
     }
 
-    // TODO add auth query once project/object-level permissions are implemented
+    if data.context.auth_check_in_query {
+        q.push(" AND ");
+        data.auth_check_where_clause(&mut q, "tb", &["owner", "write", "read"]);
+    }
+
+    q.push(" ORDER BY ");
+    if let Some(search) = search_binding.as_ref().filter(|_| !cursor_mode) {
+        // Rank search matches first. Without a search term the rank is NULL for every row
+        // and the requested ordering applies. Cursor pagination needs the ordering to match
+        // the cursor, so it skips the ranking.
+        write!(
+            q,
+            "ts_rank(search_vector, websearch_to_tsquery('{TEXT_SEARCH_CONFIG}', {search})) DESC, "
+        )
+        .unwrap();
+    }
+    q.push("__insertion_point_order_by");
 
     if !data.context.pagination.disable {
         q.push(" LIMIT ");
         q.push_binding(bindings::LIMIT);
-        q.push(" OFFSET ");
-        q.push_binding(bindings::OFFSET);
+        if !cursor_mode {
+            q.push(" OFFSET ");
+            q.push_binding(bindings::OFFSET);
+        }
     }
 
     let filename = if populate_children {
@@ -1,19 +1,25 @@
-use std::fmt::Write;
+use super::{bindings, SqlBuilder, SqlQueryContext};
+use crate::model::ModelAuthScope;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+/// Look up the permission level that the user has on a specific object.
+pub fn create_query(data: &SqlBuilder) -> Option<SqlQueryContext> {
+    let mut q = data.query_builder();
+    let id = q.create_binding(bindings::ID);
 
-pub fn create_query(data: &SqlBuilder) -> SqlQueryContext {
-    /*
-    {{ macros::permissions_value(
-      auth_schema=auth_schema,
-      auth_scope=auth_scope,
-      organization="$1",
-      actor_ids="$2",
-      object_id="$3",
-      owner_perm=owner_permission,
-      read_perm=read_permission,
-      write_perm=write_permission)
-    }}
-    */
-    todo!();
+    let object_id = match data.context.auth_scope {
+        ModelAuthScope::Model => return None,
+        ModelAuthScope::Object => id,
+        ModelAuthScope::Project => {
+            let organization = q.create_binding(bindings::ORGANIZATION);
+            format!(
+                "(SELECT project_id FROM {schema}.{table} WHERE id = {id} AND organization_id = {organization})",
+                schema = data.context.schema,
+                table = data.context.table
+            )
+        }
+    };
+
+    data.auth_scope_permissions_value_query(&mut q, &object_id);
+
+    Some(q.finish("lookup_object_permissions"))
 }
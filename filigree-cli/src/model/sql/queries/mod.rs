@@ -1,7 +1,10 @@
+pub mod audit;
+pub mod bulk;
 pub mod delete;
 pub mod insert;
 pub mod list;
 pub mod lookup_object_permissions;
+pub mod object_permissions;
 pub mod select;
 pub mod update;
 pub mod upsert;
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use super::{bindings, SqlBuilder, SqlQueryContext};
+use crate::model::ModelAuthScope;
+
+/// Queries to manage the per-object permissions of models that use the object auth scope.
+pub fn object_permissions_queries(data: &SqlBuilder) -> Vec<SqlQueryContext> {
+    if !matches!(data.context.auth_scope, ModelAuthScope::Object) {
+        return Vec::new();
+    }
+
+    vec![
+        grant_object_permission(data),
+        actor_in_organization(data),
+        revoke_object_permission(data),
+        list_object_permissions(data),
+        delete_object_permissions(data),
+    ]
+}
+
+fn grant_object_permission(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "INSERT INTO {auth_schema}.object_permissions
+        (organization_id, actor_id, object_id, permission)
+        VALUES (",
+        auth_schema = data.context.auth_schema
+    )
+    .unwrap();
+
+    {
+        let mut sep = q.separated(", ");
+        sep.push_binding(bindings::ORGANIZATION);
+        sep.push_binding(bindings::ACTOR_ID);
+        sep.push_binding(bindings::ID);
+        sep.push_binding(bindings::PERMISSION);
+    }
+
+    q.push(") ON CONFLICT DO NOTHING");
+
+    q.finish("grant_object_permission")
+}
+
+/// Check that an actor is a user or role in the organization, before granting it a permission.
+fn actor_in_organization(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    let organization = q.create_binding(bindings::ORGANIZATION);
+    let actor_id = q.create_binding(bindings::ACTOR_ID);
+
+    write!(
+        q,
+        "SELECT EXISTS (
+            SELECT 1 FROM {auth_schema}.organization_members
+            WHERE organization_id = {organization} AND user_id = {actor_id}",
+        auth_schema = data.context.auth_schema
+    )
+    .unwrap();
+
+    if data.context.auth["has_default_models"]
+        .as_bool()
+        .unwrap_or(false)
+    {
+        write!(
+            q,
+            "
+            UNION ALL
+            SELECT 1 FROM {auth_schema}.roles
+            WHERE organization_id = {organization} AND id = {actor_id}",
+            auth_schema = data.context.auth_schema
+        )
+        .unwrap();
+    }
+
+    q.push("\n) AS \"exists!\"");
+
+    q.finish("actor_in_organization")
+}
+
+fn revoke_object_permission(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "DELETE FROM {auth_schema}.object_permissions WHERE ",
+        auth_schema = data.context.auth_schema
+    )
+    .unwrap();
+
+    {
+        let mut where_sep = q.separated(" AND ");
+        where_sep.push("organization_id = ");
+        where_sep.push_binding_unseparated(bindings::ORGANIZATION);
+        where_sep.push("object_id = ");
+        where_sep.push_binding_unseparated(bindings::ID);
+        where_sep.push("actor_id = ");
+        where_sep.push_binding_unseparated(bindings::ACTOR_ID);
+    }
+
+    q.finish("revoke_object_permission")
+}
+
+fn list_object_permissions(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "SELECT actor_id, permission FROM {auth_schema}.object_permissions WHERE ",
+        auth_schema = data.context.auth_schema
+    )
+    .unwrap();
+
+    {
+        let mut where_sep = q.separated(" AND ");
+        where_sep.push("organization_id = ");
+        where_sep.push_binding_unseparated(bindings::ORGANIZATION);
+        where_sep.push("object_id = ");
+        where_sep.push_binding_unseparated(bindings::ID);
+    }
+
+    q.push(" ORDER BY actor_id, permission");
+
+    q.finish("list_object_permissions")
+}
+
+fn delete_object_permissions(data: &SqlBuilder) -> SqlQueryContext {
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "DELETE FROM {auth_schema}.object_permissions WHERE ",
+        auth_schema = data.context.auth_schema
+    )
+    .unwrap();
+
+    {
+        let mut where_sep = q.separated(" AND ");
+        where_sep.push("organization_id = ");
+        where_sep.push_binding_unseparated(bindings::ORGANIZATION);
+        where_sep.push("object_id = ");
+        where_sep.push_binding_unseparated(bindings::ID);
+    }
+
+    q.finish("delete_object_permissions")
+}
@@ -1,13 +1,13 @@
 use std::fmt::Write;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+use super::{bindings, SqlBuilder, SqlQueryContext};
 
 pub fn select_one(data: &SqlBuilder, populate_children: bool) -> Option<SqlQueryContext> {
     if populate_children && data.context.children.is_empty() {
         return None;
     }
 
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
     let id = if data.context.join.is_none() {
         q.create_binding(bindings::ID)
     } else {
@@ -58,17 +58,16 @@ pub fn select_one(data: &SqlBuilder, populate_children: bool) -> Option<SqlQuery
             let r_name = format!("ref_{}", r.name);
             let clause = format!(
                 r##"CASE WHEN {r_name}.id IS NOT NULL THEN
-                    JSONB_BUILD_OBJECT({object})
+                    {build_object}({object})
                 ELSE NULL END AS "{full_name}""##,
                 full_name = r.full_name,
+                build_object = data.context.sql_dialect.json_build_object(),
                 object = SqlBuilder::jsonb_build_object_contents(&r.fields, &r_name)
             );
             select_sep.push(&clause);
         }
     }
 
-    // TODO add auth query once project/object-level permissions are implemented
-
     write!(
         q,
         " FROM {schema}.{table} tb ",
@@ -102,6 +101,15 @@ pub fn select_one(data: &SqlBuilder, populate_children: bool) -> Option<SqlQuery
         write!(q, " AND tb.organization_id = {organization}").unwrap();
     }
 
+    if data.context.soft_delete {
+        q.push(" AND tb.deleted_at IS NULL");
+    }
+
+    if data.context.auth_check_in_query {
+        q.push(" AND ");
+        data.auth_check_where_clause(&mut q, "tb", &["owner", "write", "read"]);
+    }
+
     let filename = if populate_children {
         "select_one_populated"
     } else {
@@ -1,7 +1,12 @@
 use std::fmt::Write;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
-use crate::model::{field::ModelFieldTemplateContext, generator::BelongsToFieldContext};
+use super::{
+    audit::{finish_audit, start_audit, AuditOperation},
+    bindings, QueryBuilder, SqlBuilder, SqlQueryContext,
+};
+use crate::model::{
+    field::ModelFieldTemplateContext, generator::BelongsToFieldContext, ModelAuthScope,
+};
 
 pub fn update(data: &SqlBuilder) -> SqlQueryContext {
     let fields = data
@@ -10,10 +15,32 @@ pub fn update(data: &SqlBuilder) -> SqlQueryContext {
         .iter()
         .filter(|f| f.writable)
         .collect::<Vec<_>>();
-    let q = update_query(data, &fields, None);
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Update);
+    update_query(data, &mut q, &fields, None, true);
+    finish_audit(data, &mut q, AuditOperation::Update, false);
     q.finish_with_field_bindings("update", &fields)
 }
 
+/// Update an object without checking its version, for `If-Match: *`.
+pub fn update_any_version(data: &SqlBuilder) -> Option<SqlQueryContext> {
+    if !data.context.optimistic_locking {
+        return None;
+    }
+
+    let fields = data
+        .context
+        .fields
+        .iter()
+        .filter(|f| f.writable)
+        .collect::<Vec<_>>();
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Update);
+    update_query(data, &mut q, &fields, None, false);
+    finish_audit(data, &mut q, AuditOperation::Update, false);
+    Some(q.finish_with_field_bindings("update_any_version", &fields))
+}
+
 pub fn update_one_with_parent(data: &SqlBuilder) -> Vec<SqlQueryContext> {
     if data.context.join.is_some() {
         // For joining models, the parent is in the ID itself.
@@ -37,7 +64,12 @@ pub fn update_one_with_parent_query(
         .iter()
         .filter(|f| f.writable)
         .collect::<Vec<_>>();
-    let q = update_query(data, &fields, Some(&belongs_to.sql_name));
+    let mut q = data.query_builder();
+    start_audit(data, &mut q, AuditOperation::Update);
+    // Updates through the parent object replace the child wholesale, so they don't check the
+    // version.
+    update_query(data, &mut q, &fields, Some(&belongs_to.sql_name), false);
+    finish_audit(data, &mut q, AuditOperation::Update, false);
 
     q.finish_with_field_bindings(
         format!(
@@ -48,12 +80,48 @@ pub fn update_one_with_parent_query(
     )
 }
 
-fn update_query<'a>(
-    data: &'a SqlBuilder,
+/// Fetch the current version of an object, to tell apart an update that failed because the
+/// object changed from one where it doesn't exist or the user can't write to it.
+pub fn current_version(data: &SqlBuilder) -> Option<SqlQueryContext> {
+    if !data.context.optimistic_locking {
+        return None;
+    }
+
+    let mut q = data.query_builder();
+    write!(
+        q,
+        "SELECT version FROM {schema}.{table} WHERE ",
+        schema = data.context.schema,
+        table = data.context.table
+    )
+    .unwrap();
+    data.push_id_where_clause(&mut q);
+
+    if !data.context.global {
+        q.push(" AND organization_id = ");
+        q.push_binding(bindings::ORGANIZATION);
+    }
+
+    if data.context.soft_delete {
+        q.push(" AND deleted_at IS NULL");
+    }
+
+    if data.context.auth_check_in_query {
+        let table = format!("{}.{}", data.context.schema, data.context.table);
+        q.push(" AND ");
+        data.auth_check_where_clause(&mut q, &table, &["owner", "write"]);
+    }
+
+    Some(q.finish("current_version"))
+}
+
+fn update_query(
+    data: &SqlBuilder,
+    query: &mut QueryBuilder,
     fields: &[&ModelFieldTemplateContext],
     parent_field: Option<&str>,
-) -> QueryBuilder {
-    let mut query = QueryBuilder::new();
+    check_version: bool,
+) {
     write!(
         query,
         "UPDATE {schema}.{table} SET ",
@@ -69,12 +137,19 @@ fn update_query<'a>(
         query.push(",\n");
     }
 
-    query.push(
-        "updated_at = NOW()
+    if data.context.optimistic_locking {
+        query.push("version = version + 1,\n");
+    }
+
+    write!(
+        query,
+        "updated_at = {now}
         WHERE ",
-    );
+        now = data.context.sql_dialect.now()
+    )
+    .unwrap();
 
-    data.push_id_where_clause(&mut query);
+    data.push_id_where_clause(query);
 
     if let Some(parent_field) = parent_field {
         query.push(" AND ");
@@ -88,5 +163,27 @@ fn update_query<'a>(
         query.push_binding(bindings::ORGANIZATION);
     }
 
-    query
+    if data.context.soft_delete {
+        query.push(" AND deleted_at IS NULL");
+    }
+
+    if data.context.optimistic_locking && check_version {
+        query.push(" AND version = ");
+        query.push_binding(bindings::VERSION);
+    }
+
+    if data.context.auth_check_in_query {
+        let table = format!("{}.{}", data.context.schema, data.context.table);
+        query.push(" AND ");
+        data.auth_check_where_clause(query, &table, &["owner", "write"]);
+
+        if matches!(data.context.auth_scope, ModelAuthScope::Project)
+            && fields.iter().any(|f| f.name == "project_id")
+        {
+            // Moving the object to another project also requires write access to that project.
+            let project_id = query.create_binding("project_id");
+            query.push(" AND ");
+            data.permission_level_check(query, &project_id, &["owner", "write"]);
+        }
+    }
 }
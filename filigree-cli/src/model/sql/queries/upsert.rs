@@ -2,7 +2,10 @@ use std::fmt::Write;
 
 use itertools::Itertools;
 
-use super::{bindings, QueryBuilder, SqlBuilder, SqlQueryContext};
+use super::{
+    audit::{finish_audit, start_upsert_audit, AuditOperation},
+    bindings, QueryBuilder, SqlBuilder, SqlQueryContext,
+};
 use crate::model::{field::ModelFieldTemplateContext, generator::BelongsToFieldContext};
 
 pub fn upsert_queries(data: &SqlBuilder) -> Vec<SqlQueryContext> {
@@ -70,10 +73,26 @@ fn upsert(
     belongs_to_field: &BelongsToFieldContext,
     single: bool,
 ) -> QueryBuilder {
-    let mut q = QueryBuilder::new();
+    let mut q = data.query_builder();
 
     // TODO add permissions check when doing project or object level permissions
 
+    // Only the single child upsert is audited, since the multiple child version binds its
+    // values at runtime and can't use the typed output columns.
+    let audit = single && data.context.audit;
+    if audit {
+        start_upsert_audit(data, &mut q, |q| {
+            if !belongs_to_field.globally_unique {
+                data.push_id_where_clause(q);
+                q.push(" AND ");
+            }
+
+            q.push(&belongs_to_field.sql_name);
+            q.push(" = ");
+            q.push_binding(bindings::PARENT_ID);
+        });
+    }
+
     write!(
         q,
         "INSERT INTO {schema}.{table} (",
@@ -143,7 +162,16 @@ fn upsert(
             q.push(",\n");
         }
 
-        q.push("updated_at = now()");
+        if data.context.optimistic_locking {
+            write!(
+                q,
+                "version = {table}.version + 1,\n",
+                table = data.context.table
+            )
+            .unwrap();
+        }
+
+        write!(q, "updated_at = {}", data.context.sql_dialect.now()).unwrap();
 
         q.push("\nWHERE ");
         if !data.context.global {
@@ -162,6 +190,11 @@ fn upsert(
         q.push_binding(bindings::PARENT_ID);
     }
 
+    if audit {
+        finish_audit(data, &mut q, AuditOperation::Upsert, true);
+        return q;
+    }
+
     {
         q.push("\nRETURNING ");
         let mut sep = q.separated(", ");
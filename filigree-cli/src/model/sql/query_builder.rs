@@ -3,29 +3,40 @@
 use std::{
     borrow::{Borrow, Cow},
     collections::HashMap,
-    fmt::Write,
 };
 
 use super::SqlQueryContext;
-use crate::model::field::ModelFieldTemplateContext;
+use crate::model::{field::ModelFieldTemplateContext, SqlDialect};
 
 /// A simple query builder that keeps track of named bindings
 pub struct QueryBuilder {
     pub bindings: Vec<String>,
     pub query: String,
+    pub dialect: SqlDialect,
 }
 
 impl QueryBuilder {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_dialect(SqlDialect::Postgresql)
+    }
+
+    /// Create a [QueryBuilder] that generates parameters for the given SQL dialect.
+    pub fn with_dialect(dialect: SqlDialect) -> Self {
         Self {
             bindings: Vec::new(),
             query: String::new(),
+            dialect,
         }
     }
 
     /// Initialize the [QueryBuilder] with an initial query fragment and bindings.
     pub fn with_initial(query: String, bindings: Vec<String>) -> Self {
-        Self { bindings, query }
+        Self {
+            bindings,
+            query,
+            dialect: SqlDialect::Postgresql,
+        }
     }
 
     /// Add a string to the query
@@ -34,7 +45,7 @@ impl QueryBuilder {
     }
 
     /// Create or reuse a binding, but don't add anything to the query. This returns the
-    /// number of the binding, which can be prefixed with a `$` to use in a query.
+    /// number of the binding, which can be prefixed with a `$` (or `?` in SQLite) to use in a query.
     pub fn create_binding_index(&mut self, name: &str) -> usize {
         self.bindings
             .iter()
@@ -46,16 +57,17 @@ impl QueryBuilder {
             + 1
     }
 
-    /// Create a binding and return a string with a prefixed `$` that can be pasted directly into a query.
+    /// Create a binding and return a string with the parameter syntax that can be pasted directly into a query.
     pub fn create_binding(&mut self, name: &str) -> String {
         let index = self.create_binding_index(name);
-        format!("${}", index)
+        self.dialect.placeholder(index)
     }
 
     /// Create or reuse a binding, and add a the corresponding parameter syntax to the query.
     pub fn push_binding(&mut self, name: &str) {
         let index = self.create_binding_index(name);
-        write!(&mut self.query, "${}", index).unwrap();
+        let placeholder = self.dialect.placeholder(index);
+        self.query.push_str(&placeholder);
     }
 
     pub fn finish(self, name: impl ToString) -> SqlQueryContext {
@@ -232,6 +244,17 @@ mod tests {
         assert_eq!(builder.bindings, vec!["name", "age"]);
     }
 
+    #[test]
+    fn query_builder_sqlite_bindings() {
+        let mut builder = QueryBuilder::with_dialect(SqlDialect::SQLite);
+        let index1 = builder.create_binding("name");
+        builder.push_binding("age");
+        builder.push_binding("name");
+        assert_eq!(index1, "?1");
+        assert_eq!(builder.query, "?2?1");
+        assert_eq!(builder.bindings, vec!["name", "age"]);
+    }
+
     #[test]
     fn query_builder_finish() {
         let mut builder = QueryBuilder::new();
@@ -1,3 +1,7 @@
+use super::{
+    field::{FilterableType, SortableType, SqlType},
+    ModelAuthScope, PaginationMode, SqlDialect,
+};
 use crate::{config::Config, write::ModelMap, Error};
 
 pub fn validate_model_configuration(config: &Config, models: &ModelMap) -> Result<(), Error> {
@@ -10,6 +14,126 @@ pub fn validate_model_configuration(config: &Config, models: &ModelMap) -> Resul
             if let Some(reference) = &field.references {
                 reference.validate(&model.name, &field.name)?;
             }
+
+            if matches!(field.filterable, FilterableType::Search) {
+                if config.sql_dialect != SqlDialect::Postgresql {
+                    return Err(Error::SearchFieldConfig(
+                        model.name.clone(),
+                        field.name.clone(),
+                        "full-text search is only supported with PostgreSQL",
+                    ));
+                }
+
+                if !matches!(field.typ, SqlType::Text) {
+                    return Err(Error::SearchFieldConfig(
+                        model.name.clone(),
+                        field.name.clone(),
+                        "only text fields can be searched",
+                    ));
+                }
+            }
+        }
+
+        if model.audit {
+            if config.sql_dialect != SqlDialect::Postgresql {
+                return Err(Error::AuditConfig(
+                    model.name.clone(),
+                    "the audit log is only supported with PostgreSQL",
+                ));
+            }
+
+            if model.joins.is_some() {
+                return Err(Error::AuditConfig(
+                    model.name.clone(),
+                    "joining models have no single ID to record",
+                ));
+            }
+        }
+
+        if model.webhooks {
+            if config.sql_dialect != SqlDialect::Postgresql {
+                return Err(Error::WebhookConfig(
+                    model.name.clone(),
+                    "webhooks are only supported with PostgreSQL",
+                ));
+            }
+
+            if model.global {
+                return Err(Error::WebhookConfig(
+                    model.name.clone(),
+                    "global models have no organization to send events to",
+                ));
+            }
+
+            if model.joins.is_some() {
+                return Err(Error::WebhookConfig(
+                    model.name.clone(),
+                    "joining models have no single ID to send",
+                ));
+            }
+        }
+
+        if model.standard_endpoints.per_endpoint().any_bulk_enabled() {
+            if config.sql_dialect != SqlDialect::Postgresql {
+                return Err(Error::BulkEndpointsConfig(
+                    model.name.clone(),
+                    "bulk endpoints are only supported with PostgreSQL",
+                ));
+            }
+
+            if model.joins.is_some() {
+                return Err(Error::BulkEndpointsConfig(
+                    model.name.clone(),
+                    "joining models are not supported",
+                ));
+            }
+        }
+
+        if model.pagination.mode == PaginationMode::Cursor {
+            if config.sql_dialect != SqlDialect::Postgresql {
+                return Err(Error::CursorPaginationConfig(
+                    model.name.clone(),
+                    "cursor pagination is only supported with PostgreSQL".to_string(),
+                ));
+            }
+
+            if model.pagination.disable {
+                return Err(Error::CursorPaginationConfig(
+                    model.name.clone(),
+                    "pagination is disabled".to_string(),
+                ));
+            }
+
+            if model.joins.is_some() {
+                return Err(Error::CursorPaginationConfig(
+                    model.name.clone(),
+                    "joining models have no single ID to use in the cursor".to_string(),
+                ));
+            }
+
+            // The cursor compares the sort field as part of a row value, which never matches
+            // when the field is NULL.
+            if let Some(field) = model
+                .fields
+                .iter()
+                .find(|f| f.nullable && !matches!(f.sortable, SortableType::None))
+            {
+                return Err(Error::CursorPaginationConfig(
+                    model.name.clone(),
+                    format!("sortable field {} is nullable", field.name),
+                ));
+            }
+        }
+
+        let auth_scope = model.auth_scope.unwrap_or(config.default_auth_scope);
+        if !matches!(auth_scope, ModelAuthScope::Model)
+            && (model.global || model.joins.is_some() || !model.belongs_to.is_empty())
+        {
+            return Err(Error::InvalidAuthScope(model.name.clone(), auth_scope));
+        }
+
+        if matches!(auth_scope, ModelAuthScope::Project) && !config.use_projects {
+            return Err(Error::ProjectsNotEnabled(model.name.clone()));
         }
 
         if model.joins.is_some() {
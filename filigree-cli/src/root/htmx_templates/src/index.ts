@@ -1,5 +1,6 @@
 import './app.postcss';
 import { startLiveReload } from './livereload.js';
+import { initPasskeyLogin } from './passkeys.js';
 
 import Alpine from 'alpinejs';
 import morph from '@alpinejs/morph';
@@ -15,6 +16,8 @@ window.Alpine = Alpine;
 Alpine.plugin(morph);
 Alpine.start();
 
+initPasskeyLogin();
+
 if (process.env.LIVE_RELOAD === 'true') {
   startLiveReload();
 }
@@ -0,0 +1,130 @@
+// Browser helpers for registering and logging in with passkeys. The API sends binary values as
+// base64url strings, which have to be converted to and from ArrayBuffers for the WebAuthn API.
+
+function toBuffer(value: string): ArrayBuffer {
+  const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+  const padded = base64.padEnd(base64.length + ((4 - (base64.length % 4)) % 4), '=');
+  const binary = atob(padded);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes.buffer;
+}
+
+function fromBuffer(buffer: ArrayBuffer): string {
+  let binary = '';
+  for (const byte of new Uint8Array(buffer)) {
+    binary += String.fromCharCode(byte);
+  }
+  return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+async function post(url: string, body?: unknown) {
+  const response = await fetch(url, {
+    method: 'POST',
+    headers: { 'content-type': 'application/json' },
+    body: JSON.stringify(body ?? {}),
+  });
+
+  const data = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    throw new Error(data?.error?.message ?? `Request failed with status ${response.status}`);
+  }
+  return data;
+}
+
+/** Return true if the browser supports passkeys. */
+export function passkeysSupported() {
+  return typeof window !== 'undefined' && 'PublicKeyCredential' in window;
+}
+
+/** Log in with a passkey. If an email is given, only that user's passkeys are offered. */
+export async function loginWithPasskey(email?: string) {
+  const options = await post('/api/auth/passkeys/login/start', { email: email || undefined });
+
+  const credential = (await navigator.credentials.get({
+    publicKey: {
+      ...options,
+      challenge: toBuffer(options.challenge),
+      allowCredentials: options.allowCredentials.map((c: { type: 'public-key'; id: string }) => ({
+        ...c,
+        id: toBuffer(c.id),
+      })),
+    },
+  })) as PublicKeyCredential | null;
+
+  if (!credential) {
+    throw new Error('No passkey was selected');
+  }
+
+  const response = credential.response as AuthenticatorAssertionResponse;
+  return post('/api/auth/passkeys/login/finish', {
+    id: fromBuffer(credential.rawId),
+    response: {
+      clientDataJSON: fromBuffer(response.clientDataJSON),
+      authenticatorData: fromBuffer(response.authenticatorData),
+      signature: fromBuffer(response.signature),
+      userHandle: response.userHandle ? fromBuffer(response.userHandle) : undefined,
+    },
+  });
+}
+
+/** Register a new passkey for the logged-in user. */
+export async function registerPasskey(name?: string) {
+  const options = await post('/api/auth/passkeys/register/start');
+
+  const credential = (await navigator.credentials.create({
+    publicKey: {
+      ...options,
+      challenge: toBuffer(options.challenge),
+      user: { ...options.user, id: toBuffer(options.user.id) },
+      excludeCredentials: options.excludeCredentials.map(
+        (c: { type: 'public-key'; id: string }) => ({
+          ...c,
+          id: toBuffer(c.id),
+        })
+      ),
+    },
+  })) as PublicKeyCredential | null;
+
+  if (!credential) {
+    throw new Error('Passkey registration was cancelled');
+  }
+
+  const response = credential.response as AuthenticatorAttestationResponse;
+  return post('/api/auth/passkeys/register/finish', {
+    id: fromBuffer(credential.rawId),
+    name,
+    response: {
+      clientDataJSON: fromBuffer(response.clientDataJSON),
+      attestationObject: fromBuffer(response.attestationObject),
+    },
+  });
+}
+
+/** Handle clicks on any element with a `data-passkey-login` attribute by logging in with a
+ * passkey. The `data-redirect-to` attribute sets where to go after logging in, and errors are
+ * shown in the element matching the selector in `data-passkey-message`. */
+export function initPasskeyLogin() {
+  document.addEventListener('click', async (event) => {
+    const button = (event.target as Element | null)?.closest<HTMLElement>('[data-passkey-login]');
+    if (!button) {
+      return;
+    }
+
+    event.preventDefault();
+    const messageSelector = button.dataset.passkeyMessage;
+    const messageElement = messageSelector ? document.querySelector(messageSelector) : null;
+    const emailInput = button.closest('form')?.querySelector<HTMLInputElement>('input[name=email]');
+
+    try {
+      await loginWithPasskey(emailInput?.value);
+      window.location.href = button.dataset.redirectTo || '/';
+    } catch (e) {
+      if (messageElement) {
+        messageElement.textContent = e instanceof Error ? e.message : String(e);
+      }
+    }
+  });
+}
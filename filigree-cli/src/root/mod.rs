@@ -9,7 +9,11 @@ use rayon::prelude::*;
 
 use self::pages::{NON_PAGE_NODE_PATH, PAGE_PATH};
 use crate::{
-    config::{web::WebFramework, Config},
+    config::{
+        job::{PROCESS_IMAGE_JOB, SEND_EMAIL_JOB, WEBHOOK_DELIVERY_JOB},
+        web::WebFramework,
+        Config,
+    },
     model::generator::ModelGenerator,
     templates::{Renderer, RootApiTemplates, RootHtmxTemplates, RootSvelteTemplates},
     write::{RenderedFile, RenderedFileLocation},
@@ -38,7 +42,7 @@ pub fn render_files(
             .unwrap_or(&config.product_name),
     );
     context.insert("crate_name", &crate_name.to_case(Case::Snake));
-    context.insert("auth", &config.auth.template_context());
+    context.insert("auth", &config.auth.template_context(config.sql_dialect));
     context.insert("email", &config.email);
     context.insert("error_reporting", &config.error_reporting);
     context.insert("server", &config.server);
@@ -59,6 +63,8 @@ pub fn render_files(
     if config.use_queue {
         context.insert("queue", &config.queue.template_context());
     }
+    context.insert("webhooks", &config.use_webhooks);
+    context.insert("image_processing", &config.use_image_processing);
 
     let server_hosts = config
         .server
@@ -73,6 +79,8 @@ pub fn render_files(
     );
     context.insert("users", &config.users);
     context.insert("db", &config.database.template_context());
+    context.insert("sql_dialect", &config.sql_dialect);
+    context.insert("use_projects", &config.use_projects);
 
     let user_model = models
         .iter()
@@ -145,6 +153,9 @@ pub fn render_files(
     };
 
     let job_template_path = "root/jobs/_one_job.rs.tera";
+    let webhook_job_template_path = "root/jobs/_webhook_delivery.rs.tera";
+    let send_email_job_template_path = "root/jobs/_send_email.rs.tera";
+    let process_image_job_template_path = "root/jobs/_process_image.rs.tera";
     let skip_files = [
         // Just source for other templates
         "root/auth/fetch_base.sql.tera",
@@ -152,6 +163,9 @@ pub fn render_files(
         "root/build.rs.tera",
         // Rendered custom for each job at the end
         job_template_path,
+        webhook_job_template_path,
+        send_email_job_template_path,
+        process_image_job_template_path,
         // These are rendered by [render_pages]
         "root/pages/mod.rs.tera",
         "root/pages/_page_handlers.rs.tera",
@@ -173,6 +187,10 @@ pub fn render_files(
                 return false;
             }
 
+            if !config.email.inbound && file.as_ref() == "root/emails/inbound.rs.tera" {
+                return false;
+            }
+
             true
         })
         .map(|(location, file)| {
@@ -195,13 +213,26 @@ pub fn render_files(
         .par_iter()
         .map(|(k, v)| {
             let module_name = k.to_case(Case::Snake);
-            let context = tera::Context::from_value(v.template_context(k)).unwrap();
+            let mut context = tera::Context::from_value(v.template_context(k)).unwrap();
 
             let output_path = base_path.join(format!("jobs/{module_name}.rs"));
 
+            // The webhook delivery, email, and image jobs are implemented by Filigree, and only
+            // their queue settings come from the configuration.
+            let template_path = if config.use_webhooks && k == WEBHOOK_DELIVERY_JOB {
+                webhook_job_template_path
+            } else if config.email.outbox && k == SEND_EMAIL_JOB {
+                send_email_job_template_path
+            } else if config.use_image_processing && k == PROCESS_IMAGE_JOB {
+                context.insert("models", &all_models);
+                process_image_job_template_path
+            } else {
+                job_template_path
+            };
+
             renderer.render_with_full_path(
                 output_path,
-                job_template_path,
+                template_path,
                 RenderedFileLocation::Rust,
                 &context,
             )
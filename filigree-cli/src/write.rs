@@ -249,7 +249,7 @@ pub fn write(config: FullConfig, args: Command) -> Result<(), Report<Error>> {
     let root_files = root_files.expect("root_files was not set")?;
     let page_files = page_files.unwrap_or(Ok(Vec::new()))?;
 
-    let mut model_migrations = generators
+    let model_migrations = generators
         .iter()
         .map(|gen| {
             let up = gen.render_up_migration()?;
@@ -265,15 +265,9 @@ pub fn write(config: FullConfig, args: Command) -> Result<(), Report<Error>> {
         })
         .collect::<Result<Vec<_>, _>>()?;
 
-    // When a child model belongs to a parent model, ensure that the child comes later.
-    model_migrations.sort_by(|m1, m2| {
-        let m1 = &m1.model.unwrap();
-        let m2 = &m2.model.unwrap();
-        // The normal ordering places child tables first, so reverse it here. For migrations we want the child table to
-        // come second because the foreign key constraint is on the child table so the parent must
-        // be created first.
-        m1.order_by_dependency(m2).reverse()
-    });
+    // When a child model belongs to a parent model, ensure that the child comes later, since the
+    // foreign key constraint is on the child table so the parent must be created first.
+    let model_migrations = Model::sort_dependencies_first(model_migrations, |m| m.model.unwrap());
 
     let (first_fixed_migrations, last_fixed_migrations) = ModelGenerator::fixed_migrations(&config);
 
@@ -294,7 +288,8 @@ pub fn write(config: FullConfig, args: Command) -> Result<(), Report<Error>> {
             )
         })?;
 
-    let migration = resolve_migration(&migrations_dir, &state_dir, &migrations)?;
+    let migration =
+        resolve_migration(&migrations_dir, &state_dir, &migrations, config.sql_dialect)?;
 
     if !migration.up.is_empty() {
         let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A change to an object, as recorded in the `audit_log` table.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditLogEntry {
+    /// The user that made the change, or `None` if it was made outside of a user request.
+    pub actor_id: Option<Uuid>,
+    /// The operation performed: `create`, `update`, `delete`, `restore`, or `hard_delete`.
+    pub operation: String,
+    /// For a create this is `None`. For an update, only the fields that changed. For a delete, the
+    /// whole object.
+    pub data_before: Option<serde_json::Value>,
+    /// For a delete this is `None`. For an update, only the fields that changed. For a create, the
+    /// whole object.
+    pub data_after: Option<serde_json::Value>,
+    /// The ID of the HTTP request that made the change, if any
+    pub request_id: Option<Uuid>,
+    /// When the change happened
+    pub created_at: DateTime<Utc>,
+}
+
+/// Query string parameters for browsing the audit log
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct AuditLogQuery {
+    /// The page to fetch, starting from 0
+    pub page: Option<u32>,
+    /// The number of entries per page
+    pub per_page: Option<u32>,
+}
+
+impl AuditLogQuery {
+    const DEFAULT_PER_PAGE: u32 = 50;
+    const MAX_PER_PAGE: u32 = 200;
+
+    /// The LIMIT and OFFSET to use in the query
+    pub fn limit_offset(&self) -> (i64, i64) {
+        let per_page = self
+            .per_page
+            .unwrap_or(Self::DEFAULT_PER_PAGE)
+            .clamp(1, Self::MAX_PER_PAGE) as i64;
+        let offset = self.page.unwrap_or(0) as i64 * per_page;
+        (per_page, offset)
+    }
+}
@@ -36,6 +36,9 @@ pub struct ApiKey {
     pub active: bool,
     /// When the key will expire
     pub expires_at: DateTime<Utc>,
+    /// Whether this key is for an identity provider calling the SCIM endpoints. SCIM keys can
+    /// not be used with the normal API, and other keys can not be used for SCIM.
+    pub scim: bool,
 }
 
 /// A submission to update an API key
@@ -104,7 +107,7 @@ pub fn decode_key(key: &str) -> Result<(Uuid, Vec<u8>), AuthError> {
 #[cfg(feature = "local_auth")]
 /// Lookup an API token given the bearer token form that the user provides.
 pub async fn lookup_api_key_from_bearer_token(
-    pool: &sqlx::PgPool,
+    pool: &crate::db::DbPool,
     key: &str,
 ) -> Result<ApiKey, error_stack::Report<AuthError>> {
     let (api_key_id, hash) = decode_key(key)?;
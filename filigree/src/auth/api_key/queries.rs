@@ -1,19 +1,22 @@
 use error_stack::{Report, ResultExt};
-use sqlx::PgExecutor;
 use uuid::Uuid;
 
 use super::ApiKey;
-use crate::auth::{AuthError, OrganizationId, UserId};
+use crate::{
+    auth::{AuthError, OrganizationId, UserId},
+    db::DbExecutor,
+};
 
 /// Retrieve an API key, making sure that the hash matches and that the key is valid
 /// In most cases you will prefer to call [lookup_api_key_from_bearer_token] instead, which
 /// calls this after decoding the token.
 pub async fn lookup_api_key_for_auth(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     api_key_id: &Uuid,
     hash: &[u8],
 ) -> Result<ApiKey, Report<AuthError>> {
-    sqlx::query_as!(
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query_as!(
         ApiKey,
         r##"SELECT api_key_id,
             organization_id,
@@ -21,7 +24,8 @@ pub async fn lookup_api_key_for_auth(
             inherits_user_permissions,
             description,
             active,
-            expires_at
+            expires_at,
+            scim
             FROM api_keys
             WHERE
                 api_key_id = $1
@@ -32,18 +36,43 @@ pub async fn lookup_api_key_for_auth(
         hash
     )
     .fetch_optional(pool)
-    .await
-    .change_context(AuthError::Db)?
-    .ok_or_else(|| Report::new(AuthError::InvalidApiKey))
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query_as::<_, ApiKey>(
+        r##"SELECT api_key_id,
+            organization_id,
+            user_id,
+            inherits_user_permissions,
+            description,
+            active,
+            expires_at,
+            scim
+            FROM api_keys
+            WHERE
+                api_key_id = ?1
+                AND hash = ?2
+                AND active
+                AND expires_at > unixepoch()"##,
+    )
+    .bind(api_key_id)
+    .bind(hash)
+    .fetch_optional(pool)
+    .await;
+
+    result
+        .change_context(AuthError::Db)?
+        .ok_or_else(|| Report::new(AuthError::InvalidApiKey))
 }
 
 /// List the API keys for a user
 pub async fn list_api_keys(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: Option<UserId>,
 ) -> Result<Vec<ApiKey>, sqlx::Error> {
-    sqlx::query_as!(
+    #[cfg(not(feature = "sqlite"))]
+    return sqlx::query_as!(
         ApiKey,
         r##"SELECT api_key_id,
             organization_id,
@@ -51,24 +80,46 @@ pub async fn list_api_keys(
             inherits_user_permissions,
             description,
             active,
-            expires_at
+            expires_at,
+            scim
             FROM api_keys
             WHERE
                 organization_id = $1
                 AND user_id IS NOT DISTINCT FROM $2"##,
-        user_id.as_ref().map(|id| id.as_uuid()),
-        organization_id.as_uuid()
+        organization_id.as_uuid(),
+        user_id.as_ref().map(|id| id.as_uuid())
     )
     .fetch_all(pool)
-    .await
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    return sqlx::query_as::<_, ApiKey>(
+        r##"SELECT api_key_id,
+            organization_id,
+            user_id,
+            inherits_user_permissions,
+            description,
+            active,
+            expires_at,
+            scim
+            FROM api_keys
+            WHERE
+                organization_id = ?1
+                AND user_id IS ?2"##,
+    )
+    .bind(organization_id.as_uuid())
+    .bind(user_id.as_ref().map(|id| id.as_uuid()))
+    .fetch_all(pool)
+    .await;
 }
 
 /// Add a newly created API key into the database
 pub async fn add_api_key(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     key: &ApiKey,
     hash: &[u8],
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         r##"INSERT INTO api_keys
             (api_key_id,
@@ -78,9 +129,10 @@ pub async fn add_api_key(
             inherits_user_permissions,
             description,
             active,
-            expires_at)
+            expires_at,
+            scim)
             VALUES
-            ($1, $2, $3, $4, $5, $6, $7, $8)"##,
+            ($1, $2, $3, $4, $5, $6, $7, $8, $9)"##,
         key.api_key_id,
         key.organization_id.as_uuid(),
         key.user_id.as_ref().map(|id| id.as_uuid()),
@@ -89,20 +141,49 @@ pub async fn add_api_key(
         key.description,
         key.active,
         key.expires_at,
+        key.scim,
     )
     .execute(pool)
     .await?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        r##"INSERT INTO api_keys
+            (api_key_id,
+            organization_id,
+            user_id,
+            hash,
+            inherits_user_permissions,
+            description,
+            active,
+            expires_at,
+            scim)
+            VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"##,
+    )
+    .bind(key.api_key_id)
+    .bind(key.organization_id.as_uuid())
+    .bind(key.user_id.as_ref().map(|id| id.as_uuid()))
+    .bind(hash)
+    .bind(key.inherits_user_permissions)
+    .bind(&key.description)
+    .bind(key.active)
+    .bind(key.expires_at.timestamp())
+    .bind(key.scim)
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
 /// Update an existing API key
 pub async fn update_api_key(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: Option<UserId>,
     api_key_id: &Uuid,
     body: &super::ApiKeyUpdateBody,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         r##"
         UPDATE api_keys
@@ -123,16 +204,38 @@ pub async fn update_api_key(
     .execute(pool)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        r##"
+        UPDATE api_keys
+        SET
+            description = COALESCE(?4, description),
+            active = COALESCE(?5, active)
+        WHERE
+            api_key_id = ?1
+            AND organization_id = ?2
+            AND user_id IS ?3
+        "##,
+    )
+    .bind(api_key_id)
+    .bind(organization_id.as_uuid())
+    .bind(user_id.as_ref().map(|id| id.as_uuid()))
+    .bind(&body.description)
+    .bind(body.active)
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
 
 /// Set an API key enabled or disabled
 pub async fn set_api_key_enabled(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     api_key_id: &Uuid,
     enabled: bool,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         r##"UPDATE api_keys
             SET active = $3
@@ -144,15 +247,29 @@ pub async fn set_api_key_enabled(
     )
     .execute(pool)
     .await?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        r##"UPDATE api_keys
+            SET active = ?3
+            WHERE api_key_id = ?1
+            AND organization_id = ?2"##,
+    )
+    .bind(api_key_id)
+    .bind(organization_id.as_uuid())
+    .bind(enabled)
+    .execute(pool)
+    .await?;
     Ok(())
 }
 
 /// Delete an API key
 pub async fn delete_api_key(
-    pool: impl PgExecutor<'_>,
+    pool: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     api_key_id: &Uuid,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         r##"DELETE FROM api_keys
             WHERE
@@ -164,5 +281,17 @@ pub async fn delete_api_key(
     .execute(pool)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        r##"DELETE FROM api_keys
+            WHERE
+                api_key_id = ?1
+                AND organization_id = ?2"##,
+    )
+    .bind(api_key_id)
+    .bind(organization_id.as_uuid())
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
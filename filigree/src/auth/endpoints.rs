@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
 use axum::{
-    extract::{FromRef, State},
+    extract::{FromRef, Path, State},
     response::IntoResponse,
     routing::Router,
 };
@@ -12,20 +12,197 @@ use serde::{Deserialize, Serialize};
 use tower_cookies::Cookies;
 use uuid::Uuid;
 
-use super::{password::login_with_password, AuthError, EmailAndPassword, SessionError};
+use super::{
+    mfa::{verify_second_factor, LoginStatus},
+    passkeys::{self, PasskeyAssertion, PasskeyRegistration},
+    password::login_with_password,
+    saml::sso_required,
+    AuthError, EmailAndPassword, SessionError, UserId,
+};
 use crate::{errors::WrapReport, extract::FormOrJson, server::FiligreeState, Message};
 
+/// The response to a password login
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PasswordLoginResponse {
+    /// A message to show to the user
+    pub message: Cow<'static, str>,
+    /// If true, the user must submit a two-factor authentication code to `/auth/mfa/verify` to
+    /// finish logging in.
+    pub mfa_required: bool,
+}
+
 /// Try to log in with a username and password, and create a session if successful.
 async fn password_login(
     State(state): State<Arc<FiligreeState>>,
     cookies: Cookies,
     FormOrJson(body): FormOrJson<EmailAndPassword>,
 ) -> Result<impl IntoResponse, WrapReport<AuthError>> {
-    login_with_password(&state.session_backend, &cookies, body).await?;
+    let status = login_with_password(&state.session_backend, &cookies, body).await?;
+
+    let response = match status {
+        LoginStatus::LoggedIn => PasswordLoginResponse {
+            message: Cow::Borrowed("Logged in"),
+            mfa_required: false,
+        },
+        LoginStatus::MfaRequired => PasswordLoginResponse {
+            message: Cow::Borrowed("Two-factor authentication required"),
+            mfa_required: true,
+        },
+    };
+
+    Ok(Json(response))
+}
+
+/// A two-factor authentication code
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct MfaCodeRequest {
+    /// A code from the user's authenticator app, or one of their recovery codes
+    // Enforced through the JSON schema when the body is extracted with `FormOrJson`.
+    #[validate(length(min = 1))]
+    pub code: String,
+}
+
+/// Finish logging in by checking the second factor for a partially-authenticated session.
+async fn verify_mfa_login(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+    FormOrJson(body): FormOrJson<MfaCodeRequest>,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = state
+        .session_backend
+        .get_mfa_pending_user(&cookies)
+        .await
+        .change_context(AuthError::SessionBackend)?
+        .ok_or(AuthError::Unauthenticated)?;
+
+    let result = verify_second_factor(&state.db, &user_id, &body.code).await;
+    if result
+        .as_ref()
+        .is_err_and(|e| matches!(e.current_context(), AuthError::InvalidMfaCode))
+    {
+        state
+            .session_backend
+            .record_failed_mfa_attempt(&cookies)
+            .await
+            .change_context(AuthError::SessionBackend)?;
+    }
+    result?;
+
+    // Replace the pending session with a new, fully-authenticated one.
+    state
+        .session_backend
+        .delete_session(&cookies)
+        .await
+        .change_context(AuthError::SessionBackend)?;
+    state
+        .session_backend
+        .create_session(&cookies, &user_id)
+        .await
+        .change_context(AuthError::SessionBackend)?;
 
     Ok(Json(Message::new("Logged in")))
 }
 
+/// Get the user from the current session, for the passkey management endpoints.
+async fn require_session_user(
+    state: &FiligreeState,
+    cookies: &Cookies,
+) -> Result<UserId, WrapReport<AuthError>> {
+    let user_id = state
+        .session_backend
+        .get_session_user(cookies)
+        .await
+        .change_context(AuthError::SessionBackend)?
+        .ok_or(AuthError::Unauthenticated)?;
+    Ok(user_id)
+}
+
+/// Begin registering a passkey for the logged-in user.
+async fn start_passkey_registration(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = require_session_user(&state, &cookies).await?;
+    let options =
+        passkeys::start_passkey_registration(&state.db, &state.passkeys, &user_id).await?;
+    Ok(Json(options))
+}
+
+/// Finish registering a passkey for the logged-in user.
+async fn finish_passkey_registration(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+    FormOrJson(body): FormOrJson<PasskeyRegistration>,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = require_session_user(&state, &cookies).await?;
+    let passkey =
+        passkeys::finish_passkey_registration(&state.db, &state.passkeys, &user_id, &body).await?;
+    Ok(Json(passkey))
+}
+
+/// Start a passkey login
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct PasskeyLoginRequest {
+    /// If provided, limit the login to passkeys registered by the user with this email.
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// Begin logging in with a passkey.
+async fn start_passkey_login(
+    State(state): State<Arc<FiligreeState>>,
+    FormOrJson(body): FormOrJson<PasskeyLoginRequest>,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let email = body.email.as_deref().filter(|email| !email.is_empty());
+    let options = passkeys::start_passkey_login(&state.db, &state.passkeys, email).await?;
+    Ok(Json(options))
+}
+
+/// Finish logging in with a passkey, and create a session if successful. Passkeys are already
+/// resistant to phishing and usually verify the user with biometrics or a PIN, so this does not
+/// ask for a second factor even if the user has enabled one. Users whose organization requires
+/// single sign-on must still log in through SAML.
+async fn finish_passkey_login(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+    FormOrJson(body): FormOrJson<PasskeyAssertion>,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = passkeys::finish_passkey_login(&state.db, &state.passkeys, &body).await?;
+
+    if sso_required(&state.db, &user_id).await? {
+        return Err(AuthError::SsoRequired.into());
+    }
+
+    state
+        .session_backend
+        .create_session(&cookies, &user_id)
+        .await
+        .change_context(AuthError::SessionBackend)?;
+
+    Ok(Json(Message::new("Logged in")))
+}
+
+/// List the logged-in user's passkeys
+async fn list_passkeys(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = require_session_user(&state, &cookies).await?;
+    let passkeys = passkeys::list_passkeys(&state.db, &user_id).await?;
+    Ok(Json(passkeys))
+}
+
+/// Remove one of the logged-in user's passkeys
+async fn delete_passkey(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, WrapReport<AuthError>> {
+    let user_id = require_session_user(&state, &cookies).await?;
+    passkeys::delete_passkey(&state.db, &user_id, &id).await?;
+    Ok(Json(Message::new("Passkey removed")))
+}
+
 /// Remove the current user's session
 async fn logout(
     State(state): State<Arc<FiligreeState>>,
@@ -62,7 +239,8 @@ async fn update_password(
 
     let hashed = super::password::new_hash(request.password).await?;
 
-    let result = sqlx::query!(
+    #[cfg(not(feature = "sqlite"))]
+    let rows_affected = sqlx::query!(
         "WITH sel AS (
             SELECT user_id, (reset_token IS NOT DISTINCT FROM $2 AND reset_expires_at > now()) AS matches
             FROM email_logins
@@ -84,9 +262,53 @@ async fn update_password(
     )
     .execute(&state.db)
     .await
-    .change_context(AuthError::Db)?;
+    .change_context(AuthError::Db)?
+    .rows_affected();
+
+    // SQLite doesn't support modifying data inside a CTE, so run the steps in a transaction instead.
+    #[cfg(feature = "sqlite")]
+    let rows_affected = {
+        let mut tx = state.db.begin().await.change_context(AuthError::Db)?;
+        let user = sqlx::query_as::<_, (Uuid, bool)>(
+            "SELECT user_id, (reset_token IS ?2 AND reset_expires_at > unixepoch()) AS matches
+            FROM email_logins
+            WHERE email = ?1",
+        )
+        .bind(&request.email)
+        .bind(request.token)
+        .fetch_optional(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+        // Always clear the token
+        sqlx::query(
+            "UPDATE email_logins
+            SET reset_token = null, reset_expires_at = null
+            WHERE email = ?1 AND reset_token IS NOT NULL",
+        )
+        .bind(&request.email)
+        .execute(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+        let rows_affected = match user {
+            Some((user_id, true)) => {
+                sqlx::query("UPDATE users SET password_hash = ?2 WHERE id = ?1")
+                    .bind(user_id)
+                    .bind(&hashed.0)
+                    .execute(&mut *tx)
+                    .await
+                    .change_context(AuthError::Db)?
+                    .rows_affected()
+            }
+            _ => 0,
+        };
+
+        tx.commit().await.change_context(AuthError::Db)?;
+        rows_affected
+    };
 
-    if result.rows_affected() == 0 {
+    if rows_affected == 0 {
         return Err(WrapReport::from(AuthError::InvalidToken));
     }
 
@@ -105,5 +327,24 @@ where
             axum::routing::post(update_password),
         )
         .route("/auth/login", axum::routing::post(password_login))
+        .route("/auth/mfa/verify", axum::routing::post(verify_mfa_login))
+        .route("/auth/passkeys", axum::routing::get(list_passkeys))
+        .route("/auth/passkeys/:id", axum::routing::delete(delete_passkey))
+        .route(
+            "/auth/passkeys/register/start",
+            axum::routing::post(start_passkey_registration),
+        )
+        .route(
+            "/auth/passkeys/register/finish",
+            axum::routing::post(finish_passkey_registration),
+        )
+        .route(
+            "/auth/passkeys/login/start",
+            axum::routing::post(start_passkey_login),
+        )
+        .route(
+            "/auth/passkeys/login/finish",
+            axum::routing::post(finish_passkey_login),
+        )
         .route("/auth/logout", axum::routing::post(logout))
 }
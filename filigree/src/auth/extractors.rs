@@ -44,18 +44,62 @@ where
     }
 }
 
+/// Extract authentication info like [Authed], but also allow users whose organization requires
+/// two-factor authentication that they have not set up yet. This should only be used for the
+/// endpoints that enroll the user in two-factor authentication, and so it also rejects anonymous
+/// users.
+pub struct AuthedForMfaSetup<T: AuthInfo>(Arc<T>);
+
+impl<T> Deref for AuthedForMfaSetup<T>
+where
+    T: AuthInfo,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S, T: AuthInfo + 'static> FromRequestParts<S> for AuthedForMfaSetup<T>
+where
+    S: Send + Sync,
+{
+    type Rejection = WrapReport<AuthError>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let auth_info = lookup_auth_info_from_parts::<T>(parts).await?;
+        if auth_info.is_anonymous() {
+            return Err(AuthError::Unauthenticated.into());
+        }
+
+        match auth_info.check_valid() {
+            Ok(()) | Err(AuthError::MfaSetupRequired) => Ok(AuthedForMfaSetup(auth_info)),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
 /// Extract the AuthInfo from Request [Parts]
 pub async fn get_auth_info_from_parts<T: AuthInfo>(
     parts: &mut Parts,
+) -> Result<Arc<T>, Report<AuthError>> {
+    let auth_info = lookup_auth_info_from_parts::<T>(parts).await?;
+    auth_info.check_valid()?;
+    Ok(auth_info)
+}
+
+/// Look up the AuthInfo without checking if it is valid
+async fn lookup_auth_info_from_parts<T: AuthInfo>(
+    parts: &mut Parts,
 ) -> Result<Arc<T>, Report<AuthError>> {
     let auth_lookup = parts
         .extensions
         .get::<Arc<AuthLookup<T>>>()
         .cloned()
         .ok_or(AuthError::Unauthenticated)?;
-    let auth_info = auth_lookup.get_auth_info(parts).await?;
-    auth_info.check_valid()?;
-    Ok(auth_info)
+    auth_lookup.get_auth_info(parts).await
 }
 
 /// Extract the AuthInfo from a [Request]
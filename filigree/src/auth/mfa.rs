@@ -0,0 +1,346 @@
+//! Two-factor authentication using time-based one-time passwords (TOTP), as described in RFC 6238.
+//!
+//! A user enrolls by scanning an `otpauth://` URL into an authenticator app and then confirming
+//! a code from the app. Confirming the enrollment also generates a set of one-time recovery codes,
+//! which can be used in place of a TOTP code if the user loses their authenticator. Only the
+//! hashes of the recovery codes are stored, so they can only be shown to the user once.
+
+mod queries;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use error_stack::{Report, ResultExt};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+pub use queries::*;
+use rand::{Rng, RngCore};
+use sha3::Digest;
+use tower_cookies::Cookies;
+
+use super::{sessions::SessionBackend, AuthError, UserId};
+
+/// The number of digits in a TOTP code
+const TOTP_DIGITS: u32 = 6;
+/// The number of seconds that each TOTP code is valid for
+const TOTP_PERIOD: u64 = 30;
+/// Accept codes from this many periods before or after the current one, to allow for clock drift
+/// between the server and the user's device.
+const TOTP_SKEW: u64 = 1;
+/// The length of a generated TOTP secret, in bytes
+const TOTP_SECRET_LEN: usize = 20;
+
+/// The number of recovery codes generated for a user
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// The number of random characters in a recovery code, not counting the separator.
+const RECOVERY_CODE_LEN: usize = 10;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Characters to escape in the label and issuer of an `otpauth://` URL
+const OTPAUTH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// The outcome of a successful first login step, such as a password or an email link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStatus {
+    /// The user is now logged in
+    LoggedIn,
+    /// The user has two-factor authentication enabled and must submit a code before the login is
+    /// complete.
+    MfaRequired,
+}
+
+/// Start a session for a user who has completed the first step of logging in. If the user has
+/// enabled two-factor authentication, the session is only partially authenticated until the
+/// second factor is verified, and this returns [LoginStatus::MfaRequired].
+pub async fn create_login_session(
+    session_backend: &SessionBackend,
+    cookies: &Cookies,
+    user_id: &UserId,
+) -> Result<LoginStatus, Report<AuthError>> {
+    if mfa_enabled(&session_backend.db, user_id).await? {
+        session_backend
+            .create_mfa_pending_session(cookies, user_id)
+            .await
+            .change_context(AuthError::SessionBackend)?;
+        return Ok(LoginStatus::MfaRequired);
+    }
+
+    session_backend
+        .create_session(cookies, user_id)
+        .await
+        .change_context(AuthError::SessionBackend)?;
+    Ok(LoginStatus::LoggedIn)
+}
+
+/// A shared secret used to generate TOTP codes
+#[derive(Clone, PartialEq, Eq)]
+pub struct TotpSecret(Vec<u8>);
+
+impl TotpSecret {
+    /// Generate a new random secret
+    pub fn generate() -> Self {
+        let mut secret = vec![0u8; TOTP_SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self(secret)
+    }
+
+    /// Decode a secret from its base32 form. Returns `None` if the string is not valid base32.
+    pub fn from_base32(s: &str) -> Option<Self> {
+        base32_decode(s).map(Self)
+    }
+
+    /// Encode the secret as base32, the form that authenticator apps expect.
+    pub fn to_base32(&self) -> String {
+        base32_encode(&self.0)
+    }
+
+    /// Create an `otpauth://` URL that authenticator apps can import, usually by showing it to
+    /// the user as a QR code.
+    pub fn otpauth_url(&self, issuer: &str, account_name: &str) -> String {
+        let issuer = utf8_percent_encode(issuer, OTPAUTH_ENCODE_SET);
+        let account_name = utf8_percent_encode(account_name, OTPAUTH_ENCODE_SET);
+        format!(
+            "otpauth://totp/{issuer}:{account_name}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_PERIOD}",
+            secret = self.to_base32(),
+        )
+    }
+
+    /// Generate the code for a particular time step
+    fn code_at_step(&self, step: u64) -> u32 {
+        hotp(&self.0, step, TOTP_DIGITS)
+    }
+
+    /// Generate the code that an authenticator app would show at the given time. This is mostly
+    /// useful for tests.
+    pub fn code_at(&self, unix_time: u64) -> String {
+        format!(
+            "{:0width$}",
+            self.code_at_step(unix_time / TOTP_PERIOD),
+            width = TOTP_DIGITS as usize
+        )
+    }
+
+    /// Check a code against the given time, and return the time step that it matched.
+    pub fn verify_at(&self, code: &str, unix_time: u64) -> Option<u64> {
+        let code = parse_totp_code(code)?;
+        let current = unix_time / TOTP_PERIOD;
+
+        (current.saturating_sub(TOTP_SKEW)..=current + TOTP_SKEW)
+            .find(|step| self.code_at_step(*step) == code)
+    }
+
+    /// Check a code against the current time, and return the time step that it matched.
+    pub fn verify(&self, code: &str) -> Option<u64> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.verify_at(code, now)
+    }
+}
+
+impl std::fmt::Debug for TotpSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TotpSecret(..)")
+    }
+}
+
+/// Compute an HOTP value as described in RFC 4226
+fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac =
+        Hmac::<sha1::Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    value % 10u32.pow(digits)
+}
+
+/// Parse a TOTP code, ignoring any spaces that the user may have entered.
+fn parse_totp_code(code: &str) -> Option<u32> {
+    let code = code.replace(' ', "");
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    code.parse().ok()
+}
+
+/// Return true if the code looks like a TOTP code instead of a recovery code
+fn is_totp_code(code: &str) -> bool {
+    parse_totp_code(code).is_some()
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut output = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    output
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in s.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+/// Generate a new set of recovery codes
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars = (0..RECOVERY_CODE_LEN)
+                .map(|_| BASE32_ALPHABET[rng.gen_range(0..32)].to_ascii_lowercase() as char)
+                .collect::<String>();
+            let (first, second) = chars.split_at(RECOVERY_CODE_LEN / 2);
+            format!("{first}-{second}")
+        })
+        .collect()
+}
+
+/// Hash a recovery code for storage. The code is normalized first, so that the user can enter it
+/// without the separator or in a different case.
+fn hash_recovery_code(code: &str) -> Vec<u8> {
+    let normalized = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<String>();
+
+    let mut hasher = sha3::Sha3_256::default();
+    hasher.update(normalized.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA1 secret from the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc6238_vectors() {
+        let vectors = [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ];
+
+        for (time, expected) in vectors {
+            assert_eq!(
+                hotp(RFC_SECRET, time / TOTP_PERIOD, 8),
+                expected,
+                "time {time}"
+            );
+        }
+    }
+
+    #[test]
+    fn verify_code() {
+        let secret = TotpSecret(RFC_SECRET.to_vec());
+        assert_eq!(secret.code_at(1111111109), "081804");
+        assert_eq!(secret.verify_at("287082", 59), Some(1));
+        assert_eq!(secret.verify_at("287 082", 59), Some(1));
+        // Codes from adjacent periods are accepted to allow for clock drift
+        assert_eq!(secret.verify_at("287082", 59 + TOTP_PERIOD), Some(1));
+        assert_eq!(secret.verify_at("287082", 59 + 2 * TOTP_PERIOD), None);
+        assert_eq!(secret.verify_at("287083", 59), None);
+        assert_eq!(secret.verify_at("28708", 59), None);
+        assert_eq!(secret.verify_at("abcdef", 59), None);
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        // Test vectors from RFC 4648
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+
+        for (input, expected) in vectors {
+            assert_eq!(base32_encode(input.as_bytes()), expected);
+            assert_eq!(base32_decode(expected).unwrap(), input.as_bytes());
+        }
+
+        assert_eq!(base32_decode("MZXW6YQ=").unwrap(), b"foob");
+        assert_eq!(base32_decode("mzxw6yq").unwrap(), b"foob");
+        assert!(base32_decode("MZXW1").is_none());
+
+        let secret = TotpSecret::generate();
+        assert_eq!(TotpSecret::from_base32(&secret.to_base32()), Some(secret));
+    }
+
+    #[test]
+    fn otpauth_url() {
+        let secret = TotpSecret(b"foobar".to_vec());
+        assert_eq!(
+            secret.otpauth_url("My App", "user@example.com"),
+            "otpauth://totp/My%20App:user%40example.com?secret=MZXW6YTBOI&issuer=My%20App&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), RECOVERY_CODE_LEN + 1);
+            assert!(!is_totp_code(code));
+        }
+
+        let code = &codes[0];
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&code.replace('-', "").to_uppercase())
+        );
+        assert_ne!(hash_recovery_code(code), hash_recovery_code(&codes[1]));
+    }
+}
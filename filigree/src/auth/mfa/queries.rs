@@ -0,0 +1,453 @@
+use error_stack::{Report, ResultExt};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::{generate_recovery_codes, hash_recovery_code, is_totp_code, TotpSecret};
+use crate::{
+    auth::{AuthError, UserId},
+    db::{DbConnection, DbPool},
+};
+
+/// A user's two-factor authentication settings
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MfaStatus {
+    /// If two-factor authentication is enabled for the user
+    pub enabled: bool,
+    /// The number of unused recovery codes that the user has left
+    pub recovery_codes_remaining: i64,
+}
+
+/// The information needed to add a new TOTP secret to an authenticator app
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TotpEnrollment {
+    /// The secret, encoded as base32, for users who need to enter it manually
+    pub secret: String,
+    /// An `otpauth://` URL containing the secret, suitable for display as a QR code
+    pub otpauth_url: String,
+}
+
+/// Return true if the user has enabled two-factor authentication
+pub async fn mfa_enabled(db: &DbPool, user_id: &UserId) -> Result<bool, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled
+        ) AS "enabled!""#,
+        user_id.as_uuid()
+    )
+    .fetch_one(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let enabled = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM user_mfa WHERE user_id = ?1 AND enabled)",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_one(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    Ok(enabled)
+}
+
+/// Get the two-factor authentication settings for a user
+pub async fn get_mfa_status(db: &DbPool, user_id: &UserId) -> Result<MfaStatus, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let status = sqlx::query_as!(
+        MfaStatus,
+        r#"SELECT
+            EXISTS(SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled) AS "enabled!",
+            (SELECT COUNT(*) FROM user_mfa_recovery_codes WHERE user_id = $1 AND used_at IS NULL)
+                AS "recovery_codes_remaining!""#,
+        user_id.as_uuid()
+    )
+    .fetch_one(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let status = {
+        let (enabled, recovery_codes_remaining) = sqlx::query_as::<_, (bool, i64)>(
+            "SELECT
+                EXISTS(SELECT 1 FROM user_mfa WHERE user_id = ?1 AND enabled),
+                (SELECT COUNT(*) FROM user_mfa_recovery_codes WHERE user_id = ?1 AND used_at IS NULL)",
+        )
+        .bind(user_id.as_uuid())
+        .fetch_one(db)
+        .await
+        .change_context(AuthError::Db)?;
+
+        MfaStatus {
+            enabled,
+            recovery_codes_remaining,
+        }
+    };
+
+    Ok(status)
+}
+
+/// Generate a new TOTP secret for the user. Two-factor authentication is not enabled until the
+/// user confirms the enrollment with [confirm_totp_enrollment]. Starting a new enrollment replaces
+/// any unconfirmed secret from a previous attempt.
+///
+/// `issuer` and `account_name` are shown to the user in their authenticator app, and are usually
+/// the name of the application and the user's email address.
+pub async fn start_totp_enrollment(
+    db: &DbPool,
+    user_id: &UserId,
+    issuer: &str,
+    account_name: &str,
+) -> Result<TotpEnrollment, Report<AuthError>> {
+    let secret = TotpSecret::generate();
+    let encoded = secret.to_base32();
+
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "INSERT INTO user_mfa (user_id, totp_secret) VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+            SET totp_secret = EXCLUDED.totp_secret, last_used_step = NULL
+            WHERE NOT user_mfa.enabled",
+        user_id.as_uuid(),
+        &encoded
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "INSERT INTO user_mfa (user_id, totp_secret) VALUES (?1, ?2)
+        ON CONFLICT (user_id) DO UPDATE
+            SET totp_secret = excluded.totp_secret, last_used_step = NULL
+            WHERE NOT user_mfa.enabled",
+    )
+    .bind(user_id.as_uuid())
+    .bind(&encoded)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(AuthError::MfaAlreadyEnabled));
+    }
+
+    Ok(TotpEnrollment {
+        otpauth_url: secret.otpauth_url(issuer, account_name),
+        secret: encoded,
+    })
+}
+
+/// Enable two-factor authentication after checking a code from the user's authenticator app, and
+/// return a new set of recovery codes to show to the user.
+pub async fn confirm_totp_enrollment(
+    db: &DbPool,
+    user_id: &UserId,
+    code: &str,
+) -> Result<Vec<String>, Report<AuthError>> {
+    let mut tx = db.begin().await.change_context(AuthError::Db)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let enrollment = sqlx::query_as!(
+        TotpInfo,
+        "SELECT totp_secret, enabled, last_used_step FROM user_mfa WHERE user_id = $1 FOR UPDATE",
+        user_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let enrollment = sqlx::query_as::<_, TotpInfo>(
+        "SELECT totp_secret, enabled, last_used_step FROM user_mfa WHERE user_id = ?1",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(AuthError::Db)?;
+
+    let enrollment = enrollment.ok_or(AuthError::MfaNotEnabled)?;
+    if enrollment.enabled {
+        return Err(Report::new(AuthError::MfaAlreadyEnabled));
+    }
+
+    let step = enrollment.verify(code)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    sqlx::query!(
+        "UPDATE user_mfa SET enabled = true, last_used_step = $2 WHERE user_id = $1",
+        user_id.as_uuid(),
+        step
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query("UPDATE user_mfa SET enabled = true, last_used_step = ?2 WHERE user_id = ?1")
+        .bind(user_id.as_uuid())
+        .bind(step)
+        .execute(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+    let codes = replace_recovery_codes(&mut tx, user_id).await?;
+    tx.commit().await.change_context(AuthError::Db)?;
+
+    Ok(codes)
+}
+
+/// Check a second factor for a user who has two-factor authentication enabled. The code can be
+/// either a code from the user's authenticator app or one of their recovery codes. Each
+/// code can only be used once.
+pub async fn verify_second_factor(
+    db: &DbPool,
+    user_id: &UserId,
+    code: &str,
+) -> Result<(), Report<AuthError>> {
+    if !is_totp_code(code) {
+        return use_recovery_code(db, user_id, code).await;
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    let info = sqlx::query_as!(
+        TotpInfo,
+        "SELECT totp_secret, enabled, last_used_step FROM user_mfa WHERE user_id = $1 AND enabled",
+        user_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let info = sqlx::query_as::<_, TotpInfo>(
+        "SELECT totp_secret, enabled, last_used_step FROM user_mfa WHERE user_id = ?1 AND enabled",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    let step = info.ok_or(AuthError::MfaNotEnabled)?.verify(code)?;
+
+    // Record the step so that the same code can't be used again. The condition makes this safe
+    // against two requests racing to use the same code.
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "UPDATE user_mfa SET last_used_step = $2
+        WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)",
+        user_id.as_uuid(),
+        step
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "UPDATE user_mfa SET last_used_step = ?2
+        WHERE user_id = ?1 AND (last_used_step IS NULL OR last_used_step < ?2)",
+    )
+    .bind(user_id.as_uuid())
+    .bind(step)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(AuthError::InvalidMfaCode));
+    }
+
+    Ok(())
+}
+
+async fn use_recovery_code(
+    db: &DbPool,
+    user_id: &UserId,
+    code: &str,
+) -> Result<(), Report<AuthError>> {
+    let hash = hash_recovery_code(code);
+
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "UPDATE user_mfa_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1
+            AND code_hash = $2
+            AND used_at IS NULL
+            AND EXISTS(SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled)",
+        user_id.as_uuid(),
+        &hash
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "UPDATE user_mfa_recovery_codes
+        SET used_at = unixepoch()
+        WHERE user_id = ?1
+            AND code_hash = ?2
+            AND used_at IS NULL
+            AND EXISTS(SELECT 1 FROM user_mfa WHERE user_id = ?1 AND enabled)",
+    )
+    .bind(user_id.as_uuid())
+    .bind(&hash)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(AuthError::InvalidMfaCode));
+    }
+
+    Ok(())
+}
+
+/// Replace the user's recovery codes with a new set, and return the new codes.
+pub async fn regenerate_recovery_codes(
+    db: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<String>, Report<AuthError>> {
+    let mut tx = db.begin().await.change_context(AuthError::Db)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let enabled = sqlx::query_scalar!(
+        "SELECT enabled FROM user_mfa WHERE user_id = $1 FOR UPDATE",
+        user_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let enabled = sqlx::query_scalar::<_, bool>("SELECT enabled FROM user_mfa WHERE user_id = ?1")
+        .bind(user_id.as_uuid())
+        .fetch_optional(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+    if enabled != Some(true) {
+        return Err(Report::new(AuthError::MfaNotEnabled));
+    }
+
+    let codes = replace_recovery_codes(&mut tx, user_id).await?;
+    tx.commit().await.change_context(AuthError::Db)?;
+
+    Ok(codes)
+}
+
+/// Turn off two-factor authentication for a user, and remove their secret and recovery codes.
+pub async fn disable_mfa(db: &DbPool, user_id: &UserId) -> Result<(), Report<AuthError>> {
+    let mut tx = db.begin().await.change_context(AuthError::Db)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    {
+        sqlx::query!("DELETE FROM user_mfa WHERE user_id = $1", user_id.as_uuid())
+            .execute(&mut *tx)
+            .await
+            .change_context(AuthError::Db)?;
+
+        sqlx::query!(
+            "DELETE FROM user_mfa_recovery_codes WHERE user_id = $1",
+            user_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        sqlx::query("DELETE FROM user_mfa WHERE user_id = ?1")
+            .bind(user_id.as_uuid())
+            .execute(&mut *tx)
+            .await
+            .change_context(AuthError::Db)?;
+
+        sqlx::query("DELETE FROM user_mfa_recovery_codes WHERE user_id = ?1")
+            .bind(user_id.as_uuid())
+            .execute(&mut *tx)
+            .await
+            .change_context(AuthError::Db)?;
+    }
+
+    tx.commit().await.change_context(AuthError::Db)?;
+    Ok(())
+}
+
+async fn replace_recovery_codes(
+    tx: &mut DbConnection,
+    user_id: &UserId,
+) -> Result<Vec<String>, Report<AuthError>> {
+    let codes = generate_recovery_codes();
+    let hashes = codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect::<Vec<_>>();
+
+    #[cfg(not(feature = "sqlite"))]
+    {
+        sqlx::query!(
+            "DELETE FROM user_mfa_recovery_codes WHERE user_id = $1",
+            user_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+        sqlx::query!(
+            "INSERT INTO user_mfa_recovery_codes (user_id, code_hash)
+            SELECT $1, UNNEST($2::bytea[])",
+            user_id.as_uuid(),
+            &hashes
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        sqlx::query("DELETE FROM user_mfa_recovery_codes WHERE user_id = ?1")
+            .bind(user_id.as_uuid())
+            .execute(&mut *tx)
+            .await
+            .change_context(AuthError::Db)?;
+
+        for hash in &hashes {
+            sqlx::query("INSERT INTO user_mfa_recovery_codes (user_id, code_hash) VALUES (?1, ?2)")
+                .bind(user_id.as_uuid())
+                .bind(hash)
+                .execute(&mut *tx)
+                .await
+                .change_context(AuthError::Db)?;
+        }
+    }
+
+    Ok(codes)
+}
+
+/// The TOTP information stored for a user
+#[cfg_attr(feature = "sqlite", derive(sqlx::FromRow))]
+struct TotpInfo {
+    totp_secret: String,
+    enabled: bool,
+    last_used_step: Option<i64>,
+}
+
+impl TotpInfo {
+    /// Check a code against the secret, and return the time step that it matched.
+    fn verify(&self, code: &str) -> Result<i64, AuthError> {
+        let secret = TotpSecret::from_base32(&self.totp_secret).ok_or(AuthError::InvalidMfaCode)?;
+        let step = secret.verify(code).ok_or(AuthError::InvalidMfaCode)? as i64;
+
+        if self.last_used_step.is_some_and(|last| step <= last) {
+            // This code, or a later one, was already used.
+            return Err(AuthError::InvalidMfaCode);
+        }
+
+        Ok(step)
+    }
+}
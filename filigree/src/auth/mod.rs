@@ -7,17 +7,26 @@ pub mod endpoints;
 mod extractors;
 /// A Request extension for lazy lookup of user auth info
 pub mod lookup;
+#[cfg(feature = "local_auth")]
+/// Two-factor authentication
+pub mod mfa;
 /// Authentication middleware
 pub mod middleware;
 #[cfg(feature = "local_auth")]
 /// OAuth Functionality
 pub mod oauth;
 #[cfg(feature = "local_auth")]
+/// Passkey login using WebAuthn
+pub mod passkeys;
+#[cfg(feature = "local_auth")]
 /// Functions for generating and verifying password hashes
 pub mod password;
 #[cfg(feature = "local_auth")]
 /// Functionalty for passwordless email-based login.
 pub mod passwordless_email_login;
+#[cfg(feature = "local_auth")]
+/// SAML single sign-on
+pub mod saml;
 mod sessions;
 
 use std::borrow::Cow;
@@ -95,6 +104,28 @@ pub enum AuthError {
     /// Password and confirmation value do not match when updating password
     #[error("Passwords do not match")]
     PasswordConfirmMismatch,
+    /// The two-factor authentication code was incorrect or was already used
+    #[error("Invalid two-factor authentication code")]
+    InvalidMfaCode,
+    /// Two-factor authentication was already enabled when trying to enroll
+    #[error("Two-factor authentication is already enabled")]
+    MfaAlreadyEnabled,
+    /// The user has not enabled two-factor authentication
+    #[error("Two-factor authentication is not enabled")]
+    MfaNotEnabled,
+    /// The user's organization requires two-factor authentication, but the user has not set it up.
+    #[error("Organization requires two-factor authentication")]
+    MfaSetupRequired,
+    /// A passkey registration or login response was malformed, did not match the challenge, or
+    /// had an invalid signature.
+    #[error("Invalid passkey: {0}")]
+    InvalidPasskey(Cow<'static, str>),
+    /// The requested passkey does not exist
+    #[error("Passkey not found")]
+    PasskeyNotFound,
+    /// The user's organization requires logging in through its SAML identity provider
+    #[error("Organization requires single sign-on")]
+    SsoRequired,
 }
 
 impl AuthError {
@@ -118,13 +149,19 @@ impl HttpError for AuthError {
         match self {
             Self::InvalidApiKey
             | Self::InvalidToken
+            | Self::InvalidMfaCode
+            | Self::InvalidPasskey(_)
             | Self::Unauthenticated
             | Self::UserNotFound
             | Self::IncorrectPassword => StatusCode::UNAUTHORIZED,
             Self::NotVerified
             | Self::Disabled
+            | Self::MfaSetupRequired
+            | Self::SsoRequired
             | Self::MissingPermission(_)
             | Self::FailedPredicate(_) => StatusCode::FORBIDDEN,
+            Self::MfaAlreadyEnabled | Self::MfaNotEnabled => StatusCode::CONFLICT,
+            Self::PasskeyNotFound => StatusCode::NOT_FOUND,
             Self::ApiKeyFormat | Self::PasswordConfirmMismatch => StatusCode::BAD_REQUEST,
             Self::Db
             | Self::EmailSendFailure
@@ -153,6 +190,13 @@ impl HttpError for AuthError {
             Self::Disabled => ErrorKind::Disabled,
             Self::ApiKeyFormat => ErrorKind::ApiKeyFormat,
             Self::PasswordConfirmMismatch => ErrorKind::PasswordConfirmMismatch,
+            Self::InvalidMfaCode => ErrorKind::InvalidMfaCode,
+            Self::MfaAlreadyEnabled => ErrorKind::MfaAlreadyEnabled,
+            Self::MfaNotEnabled => ErrorKind::MfaNotEnabled,
+            Self::MfaSetupRequired => ErrorKind::MfaSetupRequired,
+            Self::InvalidPasskey(_) => ErrorKind::InvalidPasskey,
+            Self::PasskeyNotFound => ErrorKind::NotFound,
+            Self::SsoRequired => ErrorKind::SsoRequired,
             Self::MissingPermission(_) => ErrorKind::MissingPermission,
             Self::FailedPredicate(_) => ErrorKind::FailedPredicate,
             Self::Db => ErrorKind::Database,
@@ -291,6 +335,15 @@ impl ObjectPermission {
     }
 }
 
+/// A permission that an actor (a user or role) has been granted on a specific object
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct ObjectPermissionGrant {
+    /// The user or role that has the permission
+    pub actor_id: Uuid,
+    /// The permission level granted on the object
+    pub permission: ObjectPermission,
+}
+
 /// An email and password to attempt login
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct EmailAndPassword {
@@ -309,6 +362,9 @@ pub struct LoginResult {
     pub message: Cow<'static, str>,
     /// Where to go next
     pub redirect_to: Option<String>,
+    /// If true, the user must submit a two-factor authentication code to `/auth/mfa/verify` to
+    /// finish logging in.
+    pub mfa_required: bool,
 }
 
 /// Cross-origin Resource Sharing (CORS) configuration
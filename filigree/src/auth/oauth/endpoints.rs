@@ -3,15 +3,18 @@ use std::sync::Arc;
 use axum::{
     extract::{FromRef, Path, Query, State},
     response::IntoResponse,
-    routing, Router,
+    routing, Json, Router,
 };
-use hyper::StatusCode;
 use serde::Deserialize;
 use tower_cookies::Cookies;
 use tracing::instrument;
 
 use super::{handle_login_code, start_oauth_login, OAuthError};
-use crate::{errors::WrapReport, server::FiligreeState};
+use crate::{
+    auth::{mfa::LoginStatus, LoginResult},
+    errors::WrapReport,
+    server::FiligreeState,
+};
 
 /// Start an OAuth2 login
 #[instrument(skip(state, cookies))]
@@ -40,11 +43,24 @@ pub async fn callback(
     Path(provider_name): Path<String>,
     Query(query): Query<OAuthCallbackQuery>,
 ) -> Result<impl IntoResponse, WrapReport<OAuthError>> {
-    handle_login_code(&state, &cookies, &provider_name, query.state, query.code)
+    let response = handle_login_code(&state, &cookies, &provider_name, query.state, query.code)
         .await
         .map_err(WrapReport::from)?;
 
-    Ok(StatusCode::OK)
+    let result = match response.status {
+        LoginStatus::LoggedIn => LoginResult {
+            message: "Logged in".into(),
+            redirect_to: response.redirect_to,
+            mfa_required: false,
+        },
+        LoginStatus::MfaRequired => LoginResult {
+            message: "Two-factor authentication required".into(),
+            redirect_to: response.redirect_to,
+            mfa_required: true,
+        },
+    };
+
+    Ok(Json(result))
 }
 
 /// Create a default set of OAuth endpoints.
@@ -2,16 +2,19 @@ use axum::response::{IntoResponse, Redirect};
 use base64::Engine;
 use error_stack::{Report, ResultExt};
 use hyper::StatusCode;
-use oauth2::TokenResponse;
 use sha3::{Digest, Sha3_256};
-use sqlx::PgExecutor;
 use thiserror::Error;
 use tower_cookies::{Cookie, Cookies};
 use tracing::{event, Level};
 
 use self::providers::{AuthorizeUrl, OAuthUserDetails};
-use super::UserId;
+use super::{
+    mfa::{create_login_session, LoginStatus},
+    saml::sso_required,
+    UserId,
+};
 use crate::{
+    db::DbExecutor,
     errors::{ErrorKind, ForceObfuscate, HttpError, WrapReport},
     server::FiligreeState,
     users::users::{add_user_email_login, CreateUserDetails},
@@ -70,6 +73,15 @@ pub enum OAuthError {
     /// Attempted to login with an unsupported OAuth provider
     #[error("OAuth provider not supported")]
     ProviderNotSupported,
+    /// Failed to load the configuration or signing keys for an OpenID Connect provider
+    #[error("Failed to load OpenID Connect configuration")]
+    Discovery,
+    /// The ID token returned by an OpenID Connect provider was missing or could not be verified
+    #[error("Invalid ID token")]
+    InvalidIdToken,
+    /// The user's organization requires logging in through its SAML identity provider
+    #[error("Organization requires single sign-on")]
+    SsoRequired,
 }
 
 impl HttpError for OAuthError {
@@ -77,10 +89,12 @@ impl HttpError for OAuthError {
 
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Db | Self::ExchangeError | Self::FetchUserDetails | Self::UserCreation => {
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
-            Self::PublicSignupDisabled => StatusCode::FORBIDDEN,
+            Self::Db
+            | Self::ExchangeError
+            | Self::FetchUserDetails
+            | Self::UserCreation
+            | Self::Discovery => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PublicSignupDisabled | Self::SsoRequired => StatusCode::FORBIDDEN,
             Self::ProviderNotSupported => StatusCode::NOT_IMPLEMENTED,
             _ => StatusCode::UNAUTHORIZED,
         }
@@ -110,6 +124,9 @@ impl HttpError for OAuthError {
             Self::ExchangeError => ErrorKind::OAuthExchangeError,
             Self::UserCreation => ErrorKind::UserCreationError,
             Self::ProviderNotSupported => ErrorKind::OAuthProviderNotSupported,
+            Self::Discovery => ErrorKind::OidcDiscovery,
+            Self::InvalidIdToken => ErrorKind::InvalidIdToken,
+            Self::SsoRequired => ErrorKind::SsoRequired,
         }
         .as_str()
     }
@@ -137,6 +154,7 @@ pub async fn start_oauth_login(
 
     event!(Level::DEBUG, %url, "Generated OAuth URL");
 
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "INSERT INTO oauth_authorization_sessions
             (key, provider, add_to_user_id, redirect_to, pkce_verifier, expires_at)
@@ -152,6 +170,22 @@ pub async fn start_oauth_login(
     .await
     .change_context(OAuthError::Db)?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "INSERT INTO oauth_authorization_sessions
+            (key, provider, add_to_user_id, redirect_to, pkce_verifier, expires_at)
+            VALUES
+            (?1, ?2, ?3, ?4, ?5, unixepoch() + 600)",
+    )
+    .bind(key.secret())
+    .bind(provider.name())
+    .bind(link_account.map(|u| u.0))
+    .bind(redirect_to)
+    .bind(pkce_verifier.as_ref().map(|p| p.secret()))
+    .execute(&state.db)
+    .await
+    .change_context(OAuthError::Db)?;
+
     cookies.add(
         // Store a hash of the state code on the client so we can verify that the same client is
         // sending the response. Sending a hash instead of the state value prevents malicious
@@ -165,6 +199,17 @@ pub async fn start_oauth_login(
     Ok(Redirect::to(url.as_str()))
 }
 
+/// A pending OAuth login, as stored in the database
+#[cfg(feature = "sqlite")]
+#[derive(sqlx::FromRow)]
+struct OAuthLoginSession {
+    provider: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    pkce_verifier: Option<String>,
+    add_to_user_id: Option<uuid::Uuid>,
+    redirect_to: Option<String>,
+}
+
 /// A successful OAuth login response
 pub struct OAuthLoginResponse {
     /// The user ID for the user, if the user already exists. If this is None, you should
@@ -174,15 +219,18 @@ pub struct OAuthLoginResponse {
     pub user_details: OAuthUserDetails,
     /// The URL to redirect the user to after login
     pub redirect_to: Option<String>,
+    /// Whether the user is fully logged in, or must still submit a two-factor authentication code
+    pub status: LoginStatus,
 }
 
 /// Link an OAuth login to a user
 pub async fn add_oauth_login(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     user_id: UserId,
     oauth_provider_name: &str,
     oauth_account_id: &str,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "INSERT INTO oauth_logins
             (user_id, oauth_provider, oauth_account_id)
@@ -195,6 +243,19 @@ pub async fn add_oauth_login(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "INSERT INTO oauth_logins
+            (user_id, oauth_provider, oauth_account_id)
+            VALUES
+            (?1, ?2, ?3)",
+    )
+    .bind(user_id)
+    .bind(oauth_provider_name)
+    .bind(oauth_account_id)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
 
@@ -231,6 +292,7 @@ pub async fn handle_login_code(
     };
 
     let provider_name = provider.name();
+    #[cfg(not(feature = "sqlite"))]
     let oauth_login_session = sqlx::query!(
         "DELETE FROM oauth_authorization_sessions
         WHERE key = $1
@@ -242,28 +304,36 @@ pub async fn handle_login_code(
     .change_context(OAuthError::Db)?
     .ok_or(OAuthError::SessionNotFound)?;
 
+    #[cfg(feature = "sqlite")]
+    let oauth_login_session = sqlx::query_as::<_, OAuthLoginSession>(
+        "DELETE FROM oauth_authorization_sessions
+        WHERE key = ?1
+        RETURNING provider, expires_at, pkce_verifier, add_to_user_id, redirect_to",
+    )
+    .bind(&state_code)
+    .fetch_optional(&state.db)
+    .await
+    .change_context(OAuthError::Db)?
+    .ok_or(OAuthError::SessionNotFound)?;
+
     if oauth_login_session.expires_at < chrono::Utc::now()
         || oauth_login_session.provider != provider_name
     {
         return Err(Report::new(OAuthError::SessionExpired).into());
     }
 
-    let token_response = provider
-        .fetch_access_token(
+    let user_details = provider
+        .fetch_login_details(
+            state.http_client.clone(),
             authorization_code,
             oauth_login_session.pkce_verifier.unwrap_or_default(),
         )
         .await?;
-    let access_token = token_response.access_token();
-
-    let user_details = provider
-        .fetch_user_details(state.http_client.clone(), access_token.secret())
-        .await
-        .change_context(OAuthError::FetchUserDetails)?;
 
     let mut tx = state.db.begin().await.change_context(OAuthError::Db)?;
     let (existing_user, oauth_login_exists, known_email) =
         if let Some(email) = user_details.email.as_ref() {
+            #[cfg(not(feature = "sqlite"))]
             let result = sqlx::query!(
                 r##"WITH
             email_lookup AS (
@@ -287,12 +357,38 @@ pub async fn handle_login_code(
             )
             .fetch_optional(&mut *tx)
             .await
+            .change_context(OAuthError::Db)?
+            .map(|r| (r.user_id, r.email_exists, r.oauth_exists));
+
+            #[cfg(feature = "sqlite")]
+            let result = sqlx::query_as::<_, (Option<uuid::Uuid>, bool, bool)>(
+                r##"WITH
+            email_lookup AS (
+                SELECT user_id
+                FROM email_logins
+                WHERE email = ?1
+            ),
+            oauth_lookup AS (
+                SELECT user_id
+                FROM oauth_logins
+                WHERE oauth_provider = ?2 AND oauth_account_id = ?3
+            )
+            SELECT COALESCE(email_lookup.user_id, oauth_lookup.user_id) AS user_id,
+                email_lookup.user_id IS NOT NULL AS email_exists,
+                oauth_lookup.user_id IS NOT NULL AS oauth_exists
+            FROM email_lookup
+            FULL JOIN oauth_lookup USING (user_id)"##,
+            )
+            .bind(email)
+            .bind(provider_name)
+            .bind(&user_details.login_id)
+            .fetch_optional(&mut *tx)
+            .await
             .change_context(OAuthError::Db)?;
 
-            result
-                .map(|r| (r.user_id, r.email_exists, r.oauth_exists))
-                .unwrap_or_default()
+            result.unwrap_or_default()
         } else {
+            #[cfg(not(feature = "sqlite"))]
             let existing_user = sqlx::query_scalar!(
                 "SELECT user_id FROM oauth_logins
         WHERE oauth_provider = $1 AND oauth_account_id = $2",
@@ -303,6 +399,17 @@ pub async fn handle_login_code(
             .await
             .change_context(OAuthError::Db)?;
 
+            #[cfg(feature = "sqlite")]
+            let existing_user = sqlx::query_scalar::<_, uuid::Uuid>(
+                "SELECT user_id FROM oauth_logins
+        WHERE oauth_provider = ?1 AND oauth_account_id = ?2",
+            )
+            .bind(provider_name)
+            .bind(&user_details.login_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .change_context(OAuthError::Db)?;
+
             (existing_user, existing_user.is_some(), false)
         };
 
@@ -355,11 +462,16 @@ pub async fn handle_login_code(
         user_id
     };
 
+    if sso_required(&state.db, &user_id)
+        .await
+        .change_context(OAuthError::Db)?
+    {
+        return Err(Report::new(OAuthError::SsoRequired).into());
+    }
+
     tx.commit().await.change_context(OAuthError::Db)?;
 
-    state
-        .session_backend
-        .create_session(cookies, &user_id)
+    let status = create_login_session(&state.session_backend, cookies, &user_id)
         .await
         .change_context(OAuthError::SessionBackend)?;
 
@@ -367,5 +479,6 @@ pub async fn handle_login_code(
         user_id,
         redirect_to: oauth_login_session.redirect_to,
         user_details,
+        status,
     })
 }
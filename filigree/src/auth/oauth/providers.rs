@@ -3,7 +3,7 @@ use error_stack::{Report, ResultExt};
 use oauth2::{
     basic::{BasicClient, BasicTokenResponse},
     reqwest::async_http_client,
-    AuthorizationCode, CsrfToken, PkceCodeVerifier,
+    AuthorizationCode, CsrfToken, PkceCodeVerifier, TokenResponse,
 };
 use url::Url;
 
@@ -12,10 +12,12 @@ use crate::config::prefixed_env_var;
 
 mod github;
 mod google;
+mod oidc;
 mod twitter;
 
 pub use github::*;
 pub use google::*;
+pub use oidc::*;
 pub use twitter::*;
 
 /// User information from an OAuth provider
@@ -70,6 +72,25 @@ pub trait OAuthProvider: Send + Sync + 'static {
         client: reqwest::Client,
         access_token: &str,
     ) -> Result<OAuthUserDetails, reqwest::Error>;
+
+    /// Exchange the authorization code and get the user info for the user who logged in.
+    ///
+    /// The default implementation calls [OAuthProvider::fetch_access_token] and then
+    /// [OAuthProvider::fetch_user_details]. Providers which return the user's identity along with
+    /// the access token, such as OpenID Connect providers, can override this.
+    async fn fetch_login_details(
+        &self,
+        client: reqwest::Client,
+        authorization_code: String,
+        pkce_verifier: String,
+    ) -> Result<OAuthUserDetails, Report<OAuthError>> {
+        let token_response = self
+            .fetch_access_token(authorization_code, pkce_verifier)
+            .await?;
+        self.fetch_user_details(client, token_response.access_token().secret())
+            .await
+            .change_context(OAuthError::FetchUserDetails)
+    }
 }
 
 /// Helper function to exchange an authorization token for an access token, without PKCE.
@@ -151,3 +172,38 @@ pub fn create_supported_providers(
         .flatten()
         .collect()
 }
+
+/// Create an OpenID Connect provider if the `OAUTH_OIDC_ISSUER_URL`, `OAUTH_OIDC_CLIENT_ID`, and
+/// `OAUTH_OIDC_CLIENT_SECRET` environment variables are set. `OAUTH_OIDC_SCOPES` can optionally
+/// contain a space-separated list of scopes to request instead of the default `email profile`.
+///
+/// Unlike the other providers, this needs to fetch the issuer's configuration, so it is separate
+/// from [create_supported_providers].
+pub async fn create_oidc_provider(
+    env_prefix: &str,
+    redirect_base_url: &str,
+    http_client: &reqwest::Client,
+) -> Result<Option<Box<dyn OAuthProvider>>, Report<OAuthError>> {
+    let (Ok(issuer_url), Ok(client_id), Ok(client_secret)) = (
+        prefixed_env_var(env_prefix, "OAUTH_OIDC_ISSUER_URL"),
+        prefixed_env_var(env_prefix, "OAUTH_OIDC_CLIENT_ID"),
+        prefixed_env_var(env_prefix, "OAUTH_OIDC_CLIENT_SECRET"),
+    ) else {
+        return Ok(None);
+    };
+
+    let mut provider = OidcOAuthProvider::discover(
+        http_client,
+        &issuer_url,
+        client_id,
+        client_secret,
+        redirect_base_url,
+    )
+    .await?;
+
+    if let Ok(scopes) = prefixed_env_var(env_prefix, "OAUTH_OIDC_SCOPES") {
+        provider = provider.with_scopes(scopes.split_whitespace().map(String::from));
+    }
+
+    Ok(Some(Box::new(provider)))
+}
@@ -0,0 +1,904 @@
+//! A generic OpenID Connect provider, configured from the issuer's discovery document. This works
+//! with most enterprise identity providers, such as Okta, Keycloak, Azure AD, and Auth0.
+
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use error_stack::{Report, ResultExt};
+use oauth2::{
+    basic::{
+        BasicClient, BasicErrorResponse, BasicRevocationErrorResponse,
+        BasicTokenIntrospectionResponse, BasicTokenResponse, BasicTokenType,
+    },
+    reqwest::async_http_client,
+    AuthType, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, ExtraTokenFields,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, StandardRevocableToken,
+    StandardTokenResponse, TokenResponse, TokenUrl,
+};
+use ring::signature;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use thiserror::Error;
+use url::Url;
+
+use super::{build_redirect_url, AuthorizeUrl, OAuthProvider, OAuthUserDetails};
+use crate::auth::oauth::OAuthError;
+
+/// How far the ID token's expiration time may be in the past, to allow for clock drift between
+/// the server and the identity provider.
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// The token response from an OpenID Connect provider, which includes the ID token.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct IdTokenFields {
+    id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+type OidcTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+type OidcClient = oauth2::Client<
+    BasicErrorResponse,
+    OidcTokenResponse,
+    BasicTokenType,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+>;
+
+/// The fields that we use from an issuer's `.well-known/openid-configuration` document.
+#[derive(Debug, Deserialize)]
+pub struct OidcDiscoveryDocument {
+    /// The issuer identifier, which must match the `iss` claim in ID tokens
+    pub issuer: String,
+    /// The URL to send the user to for login
+    pub authorization_endpoint: String,
+    /// The URL used to exchange an authorization code for tokens
+    pub token_endpoint: String,
+    /// The URL used to fetch claims about the user with an access token
+    pub userinfo_endpoint: String,
+    /// The URL of the issuer's JSON Web Key Set, used to verify ID token signatures
+    pub jwks_uri: String,
+    /// The ways that the client can authenticate at the token endpoint
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+/// A public key from an issuer's JSON Web Key Set
+#[derive(Clone, Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    /// RSA modulus
+    n: Option<String>,
+    /// RSA exponent
+    e: Option<String>,
+    /// Elliptic curve name
+    crv: Option<String>,
+    /// Elliptic curve point X coordinate
+    x: Option<String>,
+    /// Elliptic curve point Y coordinate
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Some providers send boolean claims as strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BoolClaim {
+    Bool(bool),
+    String(String),
+}
+
+impl BoolClaim {
+    fn value(&self) -> bool {
+        match self {
+            Self::Bool(b) => *b,
+            Self::String(s) => s == "true",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Self::One(aud) => aud == client_id,
+            Self::Many(aud) => aud.iter().any(|a| a == client_id),
+        }
+    }
+}
+
+/// Standard claims about the user, as returned in the ID token or from the userinfo endpoint.
+#[derive(Deserialize)]
+struct UserClaims {
+    sub: String,
+    email: Option<String>,
+    email_verified: Option<BoolClaim>,
+    name: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    preferred_username: Option<String>,
+    picture: Option<String>,
+}
+
+impl UserClaims {
+    fn into_user_details(self) -> OAuthUserDetails {
+        // Emails are used to link logins to existing accounts, so don't trust an email that the
+        // provider says is unverified. Many providers omit the claim entirely, in which case
+        // we assume that the provider has verified it.
+        let email_verified = self
+            .email_verified
+            .as_ref()
+            .map(|v| v.value())
+            .unwrap_or(true);
+
+        let name = self.name.or_else(|| {
+            let full_name = [self.given_name, self.family_name]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" ");
+            (!full_name.is_empty()).then_some(full_name)
+        });
+
+        OAuthUserDetails {
+            login_id: self.sub,
+            name: name.or(self.preferred_username),
+            email: self.email.filter(|_| email_verified),
+            avatar_url: self.picture.and_then(|p| Url::parse(&p).ok()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    azp: Option<String>,
+    exp: i64,
+    nonce: Option<String>,
+    #[serde(flatten)]
+    user: UserClaims,
+}
+
+/// A reason that an ID token was rejected
+#[derive(Debug, Error)]
+enum IdTokenError {
+    #[error("ID token is malformed")]
+    Malformed,
+    #[error("Unsupported signing algorithm {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("No signing key found for ID token")]
+    UnknownKey,
+    #[error("ID token signature is invalid")]
+    BadSignature,
+    #[error("ID token issuer does not match")]
+    Issuer,
+    #[error("ID token audience does not match")]
+    Audience,
+    #[error("ID token has expired")]
+    Expired,
+    #[error("ID token nonce does not match")]
+    Nonce,
+}
+
+/// OAuth provider for any OpenID Connect identity provider
+pub struct OidcOAuthProvider {
+    client: BasicClient,
+    token_client: OidcClient,
+    issuer: String,
+    client_id: String,
+    userinfo_endpoint: String,
+    jwks_uri: String,
+    keys: RwLock<Vec<Jwk>>,
+    scopes: Vec<String>,
+}
+
+impl OidcOAuthProvider {
+    /// Set up the OpenID Connect provider by fetching the issuer's discovery document and
+    /// signing keys.
+    ///
+    /// `issuer_url` must be the exact issuer identifier, such as
+    /// `https://login.microsoftonline.com/{tenant}/v2.0` for Azure AD.
+    pub async fn discover(
+        http_client: &reqwest::Client,
+        issuer_url: &str,
+        client_id: String,
+        client_secret: String,
+        redirect_base_url: &str,
+    ) -> Result<Self, Report<OAuthError>> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer_url.trim_end_matches('/')
+        );
+
+        let document = http_client
+            .get(&discovery_url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .change_context(OAuthError::Discovery)
+            .attach_printable_lazy(|| discovery_url.clone())?
+            .json::<OidcDiscoveryDocument>()
+            .await
+            .change_context(OAuthError::Discovery)
+            .attach_printable_lazy(|| discovery_url.clone())?;
+
+        if document.issuer.trim_end_matches('/') != issuer_url.trim_end_matches('/') {
+            return Err(Report::new(OAuthError::Discovery)).attach_printable(format!(
+                "Discovery document issuer {} does not match {issuer_url}",
+                document.issuer
+            ));
+        }
+
+        let keys = fetch_jwks(http_client, &document.jwks_uri).await?;
+        Self::new(document, keys, client_id, client_secret, redirect_base_url)
+    }
+
+    fn new(
+        document: OidcDiscoveryDocument,
+        keys: Vec<Jwk>,
+        client_id: String,
+        client_secret: String,
+        redirect_base_url: &str,
+    ) -> Result<Self, Report<OAuthError>> {
+        let redirect_url = RedirectUrl::new(build_redirect_url(redirect_base_url, "oidc"))
+            .change_context(OAuthError::Discovery)?;
+        let auth_url = AuthUrl::new(document.authorization_endpoint)
+            .change_context(OAuthError::Discovery)
+            .attach_printable("Invalid authorization_endpoint")?;
+        let token_url = TokenUrl::new(document.token_endpoint)
+            .change_context(OAuthError::Discovery)
+            .attach_printable("Invalid token_endpoint")?;
+
+        // Per the spec, providers that don't list their supported methods support HTTP Basic auth.
+        let methods = &document.token_endpoint_auth_methods_supported;
+        let auth_type = if !methods.is_empty()
+            && !methods.iter().any(|m| m == "client_secret_basic")
+            && methods.iter().any(|m| m == "client_secret_post")
+        {
+            AuthType::RequestBody
+        } else {
+            AuthType::BasicAuth
+        };
+
+        let client = BasicClient::new(
+            ClientId::new(client_id.clone()),
+            Some(ClientSecret::new(client_secret.clone())),
+            auth_url.clone(),
+            Some(token_url.clone()),
+        )
+        .set_auth_type(auth_type.clone())
+        .set_redirect_uri(redirect_url.clone());
+
+        let token_client = OidcClient::new(
+            ClientId::new(client_id.clone()),
+            Some(ClientSecret::new(client_secret)),
+            auth_url,
+            Some(token_url),
+        )
+        .set_auth_type(auth_type)
+        .set_redirect_uri(redirect_url);
+
+        Ok(Self {
+            client,
+            token_client,
+            issuer: document.issuer,
+            client_id,
+            userinfo_endpoint: document.userinfo_endpoint,
+            jwks_uri: document.jwks_uri,
+            keys: RwLock::new(keys),
+            scopes: vec!["openid".into(), "email".into(), "profile".into()],
+        })
+    }
+
+    /// Replace the scopes requested during login. The `openid` scope is always requested.
+    pub fn with_scopes(mut self, scopes: impl IntoIterator<Item = String>) -> Self {
+        self.scopes = std::iter::once("openid".to_string())
+            .chain(scopes.into_iter().filter(|s| s != "openid"))
+            .collect();
+        self
+    }
+
+    /// Verify an ID token, refetching the issuer's keys if the token was signed by a key that we
+    /// haven't seen yet.
+    async fn verify_id_token(
+        &self,
+        http_client: &reqwest::Client,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, Report<OAuthError>> {
+        let now = chrono::Utc::now().timestamp();
+        let result = {
+            let keys = self.keys.read().unwrap();
+            validate_id_token(id_token, &keys, &self.issuer, &self.client_id, nonce, now)
+        };
+
+        let result = match result {
+            Err(IdTokenError::UnknownKey) => {
+                // The issuer may have rotated its keys since we last fetched them.
+                let keys = fetch_jwks(http_client, &self.jwks_uri).await?;
+                let result =
+                    validate_id_token(id_token, &keys, &self.issuer, &self.client_id, nonce, now);
+                *self.keys.write().unwrap() = keys;
+                result
+            }
+            result => result,
+        };
+
+        result.map_err(|e| Report::new(e).change_context(OAuthError::InvalidIdToken))
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for OidcOAuthProvider {
+    fn name(&self) -> &'static str {
+        "oidc"
+    }
+
+    fn client(&self) -> &BasicClient {
+        &self.client
+    }
+
+    fn authorize_url(&self) -> AuthorizeUrl {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, state) = self
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(self.scopes.iter().cloned().map(Scope::new))
+            .add_extra_param("nonce", nonce_for_verifier(pkce_verifier.secret()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        AuthorizeUrl {
+            url,
+            state,
+            pkce_verifier: Some(pkce_verifier),
+        }
+    }
+
+    async fn fetch_access_token(
+        &self,
+        authorization_code: String,
+        pkce_verifier: String,
+    ) -> Result<BasicTokenResponse, Report<OAuthError>> {
+        super::fetch_access_token_with_pkce(&self.client, authorization_code, pkce_verifier).await
+    }
+
+    async fn fetch_user_details(
+        &self,
+        client: reqwest::Client,
+        access_token: &str,
+    ) -> Result<OAuthUserDetails, reqwest::Error> {
+        let claims = client
+            .get(&self.userinfo_endpoint)
+            .bearer_auth(access_token)
+            .header("Accept", "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<UserClaims>()
+            .await?;
+
+        Ok(claims.into_user_details())
+    }
+
+    async fn fetch_login_details(
+        &self,
+        client: reqwest::Client,
+        authorization_code: String,
+        pkce_verifier: String,
+    ) -> Result<OAuthUserDetails, Report<OAuthError>> {
+        let nonce = nonce_for_verifier(&pkce_verifier);
+        let token_response = self
+            .token_client
+            .exchange_code(AuthorizationCode::new(authorization_code))
+            .set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier))
+            .request_async(async_http_client)
+            .await
+            .change_context(OAuthError::ExchangeError)?;
+
+        let id_token = token_response
+            .extra_fields()
+            .id_token
+            .as_deref()
+            .ok_or(OAuthError::InvalidIdToken)
+            .attach_printable("Token response did not include an ID token")?;
+        let claims = self.verify_id_token(&client, id_token, &nonce).await?;
+        let mut details = claims.user.into_user_details();
+
+        // Some providers only include a subset of the user's claims in the ID token.
+        if details.email.is_none() || details.name.is_none() {
+            let userinfo = self
+                .fetch_user_details(client, token_response.access_token().secret())
+                .await
+                .change_context(OAuthError::FetchUserDetails)?;
+
+            // The userinfo response must be for the same user as the ID token.
+            if userinfo.login_id != details.login_id {
+                return Err(Report::new(OAuthError::InvalidIdToken))
+                    .attach_printable("userinfo subject does not match ID token");
+            }
+
+            details.email = details.email.or(userinfo.email);
+            details.name = details.name.or(userinfo.name);
+            details.avatar_url = details.avatar_url.or(userinfo.avatar_url);
+        }
+
+        Ok(details)
+    }
+}
+
+/// Derive the nonce for a login from its PKCE verifier. The verifier is stored with the login
+/// session, so this ties the ID token to the session without needing to store anything else.
+fn nonce_for_verifier(pkce_verifier: &str) -> String {
+    let mut hasher = Sha3_256::new();
+    hasher.update(pkce_verifier.as_bytes());
+    URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+async fn fetch_jwks(
+    http_client: &reqwest::Client,
+    jwks_uri: &str,
+) -> Result<Vec<Jwk>, Report<OAuthError>> {
+    let jwks = http_client
+        .get(jwks_uri)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .change_context(OAuthError::Discovery)
+        .attach_printable_lazy(|| jwks_uri.to_string())?
+        .json::<JwkSet>()
+        .await
+        .change_context(OAuthError::Discovery)
+        .attach_printable_lazy(|| jwks_uri.to_string())?;
+
+    Ok(jwks
+        .keys
+        .into_iter()
+        .filter(|k| k.key_use.as_deref().unwrap_or("sig") == "sig")
+        .collect())
+}
+
+fn decode_b64(value: &str) -> Result<Vec<u8>, IdTokenError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| IdTokenError::Malformed)
+}
+
+fn decode_key_param(value: &Option<String>) -> Result<Vec<u8>, IdTokenError> {
+    value
+        .as_deref()
+        .map(decode_b64)
+        .unwrap_or(Err(IdTokenError::UnknownKey))
+}
+
+/// Check a JWT signature against a key
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], sig: &[u8]) -> Result<(), IdTokenError> {
+    let rsa_alg: Option<&'static signature::RsaParameters> = match alg {
+        "RS256" => Some(&signature::RSA_PKCS1_2048_8192_SHA256),
+        "RS384" => Some(&signature::RSA_PKCS1_2048_8192_SHA384),
+        "RS512" => Some(&signature::RSA_PKCS1_2048_8192_SHA512),
+        "PS256" => Some(&signature::RSA_PSS_2048_8192_SHA256),
+        "PS384" => Some(&signature::RSA_PSS_2048_8192_SHA384),
+        "PS512" => Some(&signature::RSA_PSS_2048_8192_SHA512),
+        _ => None,
+    };
+
+    if let Some(rsa_alg) = rsa_alg {
+        let n = decode_key_param(&key.n)?;
+        let e = decode_key_param(&key.e)?;
+        return signature::RsaPublicKeyComponents { n: &n, e: &e }
+            .verify(rsa_alg, message, sig)
+            .map_err(|_| IdTokenError::BadSignature);
+    }
+
+    let (ec_alg, crv): (&'static signature::EcdsaVerificationAlgorithm, _) = match alg {
+        "ES256" => (&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+        "ES384" => (&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+        _ => return Err(IdTokenError::UnsupportedAlgorithm(alg.to_string())),
+    };
+
+    if key.crv.as_deref() != Some(crv) {
+        return Err(IdTokenError::UnknownKey);
+    }
+
+    let mut point = vec![0x04];
+    point.extend(decode_key_param(&key.x)?);
+    point.extend(decode_key_param(&key.y)?);
+    signature::UnparsedPublicKey::new(ec_alg, point)
+        .verify(message, sig)
+        .map_err(|_| IdTokenError::BadSignature)
+}
+
+/// Verify the signature and standard claims of an ID token, as described in section 3.1.3.7 of
+/// the OpenID Connect Core spec.
+fn validate_id_token(
+    token: &str,
+    keys: &[Jwk],
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<IdTokenClaims, IdTokenError> {
+    let mut parts = token.split('.');
+    let (Some(header_b64), Some(claims_b64), Some(sig_b64), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(IdTokenError::Malformed);
+    };
+
+    let header: JwtHeader =
+        serde_json::from_slice(&decode_b64(header_b64)?).map_err(|_| IdTokenError::Malformed)?;
+    let key_type = match header.alg.get(0..2) {
+        Some("RS") | Some("PS") => "RSA",
+        Some("ES") => "EC",
+        // This also rejects "none" and the HMAC algorithms, which would let anyone who knows the
+        // client secret forge a token.
+        _ => return Err(IdTokenError::UnsupportedAlgorithm(header.alg)),
+    };
+
+    let key = keys
+        .iter()
+        .filter(|k| k.kty == key_type)
+        .filter(|k| k.alg.as_deref().map(|a| a == header.alg).unwrap_or(true))
+        .find(|k| header.kid.is_none() || k.kid == header.kid)
+        .ok_or(IdTokenError::UnknownKey)?;
+
+    let message = &token[..header_b64.len() + 1 + claims_b64.len()];
+    verify_signature(&header.alg, key, message.as_bytes(), &decode_b64(sig_b64)?)?;
+
+    let claims: IdTokenClaims =
+        serde_json::from_slice(&decode_b64(claims_b64)?).map_err(|_| IdTokenError::Malformed)?;
+
+    if claims.iss != issuer {
+        return Err(IdTokenError::Issuer);
+    }
+
+    if !claims.aud.contains(client_id) {
+        return Err(IdTokenError::Audience);
+    }
+
+    if matches!(&claims.aud, Audience::Many(aud) if aud.len() > 1)
+        && claims.azp.as_deref().is_some_and(|azp| azp != client_id)
+    {
+        return Err(IdTokenError::Audience);
+    }
+
+    if claims.exp + CLOCK_SKEW_SECS < now {
+        return Err(IdTokenError::Expired);
+    }
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(IdTokenError::Nonce);
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use axum::{
+        extract::{Form, State},
+        routing::{get, post},
+        Json, Router,
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    struct TestIssuer {
+        key: EcdsaKeyPair,
+        issuer: String,
+    }
+
+    impl TestIssuer {
+        fn new(issuer: &str) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+
+            Self {
+                key,
+                issuer: issuer.to_string(),
+            }
+        }
+
+        fn jwks(&self) -> serde_json::Value {
+            let point = self.key.public_key().as_ref();
+            json!({
+                "keys": [{
+                    "kty": "EC",
+                    "kid": "key-1",
+                    "use": "sig",
+                    "crv": "P-256",
+                    "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                    "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+                }]
+            })
+        }
+
+        fn keys(&self) -> Vec<Jwk> {
+            serde_json::from_value::<JwkSet>(self.jwks()).unwrap().keys
+        }
+
+        fn sign(&self, claims: serde_json::Value) -> String {
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","kid":"key-1"}"#);
+            let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let message = format!("{header}.{claims}");
+            let sig = self
+                .key
+                .sign(&SystemRandom::new(), message.as_bytes())
+                .unwrap();
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref()))
+        }
+
+        fn claims(&self, nonce: &str) -> serde_json::Value {
+            json!({
+                "iss": self.issuer,
+                "aud": "the-client",
+                "sub": "user-1",
+                "exp": chrono::Utc::now().timestamp() + 300,
+                "nonce": nonce,
+                "email": "user@example.com",
+                "given_name": "Test",
+                "family_name": "User",
+            })
+        }
+    }
+
+    fn validate(issuer: &TestIssuer, token: &str) -> Result<IdTokenClaims, IdTokenError> {
+        validate_id_token(
+            token,
+            &issuer.keys(),
+            "https://idp.example.com",
+            "the-client",
+            "the-nonce",
+            chrono::Utc::now().timestamp(),
+        )
+    }
+
+    #[test]
+    fn valid_id_token() {
+        let issuer = TestIssuer::new("https://idp.example.com");
+        let token = issuer.sign(issuer.claims("the-nonce"));
+        let details = validate(&issuer, &token).unwrap().user.into_user_details();
+
+        assert_eq!(details.login_id, "user-1");
+        assert_eq!(details.email.as_deref(), Some("user@example.com"));
+        assert_eq!(details.name.as_deref(), Some("Test User"));
+    }
+
+    #[test]
+    fn rejected_id_tokens() {
+        let issuer = TestIssuer::new("https://idp.example.com");
+
+        let mut claims = issuer.claims("the-nonce");
+        claims["aud"] = json!(["another-client", "the-client"]);
+        claims["azp"] = json!("another-client");
+        assert!(matches!(
+            validate(&issuer, &issuer.sign(claims)),
+            Err(IdTokenError::Audience)
+        ));
+
+        let mut claims = issuer.claims("the-nonce");
+        claims["iss"] = json!("https://evil.example.com");
+        assert!(matches!(
+            validate(&issuer, &issuer.sign(claims)),
+            Err(IdTokenError::Issuer)
+        ));
+
+        let mut claims = issuer.claims("the-nonce");
+        claims["exp"] = json!(chrono::Utc::now().timestamp() - 600);
+        assert!(matches!(
+            validate(&issuer, &issuer.sign(claims)),
+            Err(IdTokenError::Expired)
+        ));
+
+        assert!(matches!(
+            validate(&issuer, &issuer.sign(issuer.claims("other-nonce"))),
+            Err(IdTokenError::Nonce)
+        ));
+
+        // Signed by a key that isn't in the issuer's key set
+        let other_issuer = TestIssuer::new("https://idp.example.com");
+        let token = other_issuer.sign(other_issuer.claims("the-nonce"));
+        assert!(matches!(
+            validate(&issuer, &token),
+            Err(IdTokenError::BadSignature)
+        ));
+
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            URL_SAFE_NO_PAD.encode(issuer.claims("the-nonce").to_string())
+        );
+        assert!(matches!(
+            validate(&issuer, &unsigned),
+            Err(IdTokenError::UnsupportedAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn unverified_email() {
+        let issuer = TestIssuer::new("https://idp.example.com");
+        let mut claims = issuer.claims("the-nonce");
+        claims["email_verified"] = json!("false");
+        claims["name"] = json!("Full Name");
+        let details = validate(&issuer, &issuer.sign(claims))
+            .unwrap()
+            .user
+            .into_user_details();
+
+        assert_eq!(details.email, None);
+        assert_eq!(details.name.as_deref(), Some("Full Name"));
+    }
+
+    #[derive(Clone)]
+    struct FakeIdp {
+        issuer: Arc<TestIssuer>,
+        nonce: Arc<std::sync::Mutex<Option<String>>>,
+    }
+
+    #[derive(Deserialize)]
+    struct TokenRequest {
+        code: String,
+        code_verifier: String,
+    }
+
+    async fn start_fake_idp() -> (String, FakeIdp) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let idp = FakeIdp {
+            issuer: Arc::new(TestIssuer::new(&base)),
+            nonce: Default::default(),
+        };
+
+        let discovery = |State(idp): State<FakeIdp>| async move {
+            let base = &idp.issuer.issuer;
+            Json(json!({
+                "issuer": base,
+                "authorization_endpoint": format!("{base}/authorize"),
+                "token_endpoint": format!("{base}/token"),
+                "userinfo_endpoint": format!("{base}/userinfo"),
+                "jwks_uri": format!("{base}/jwks"),
+                "token_endpoint_auth_methods_supported": ["client_secret_post"],
+            }))
+        };
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            // Serves the same document, which has an issuer that doesn't match this path.
+            .route("/other/.well-known/openid-configuration", get(discovery))
+            .route(
+                "/jwks",
+                get(|State(idp): State<FakeIdp>| async move { Json(idp.issuer.jwks()) }),
+            )
+            .route(
+                "/token",
+                post(
+                    |State(idp): State<FakeIdp>, Form(body): Form<TokenRequest>| async move {
+                        assert_eq!(body.code, "the-code");
+                        let nonce = nonce_for_verifier(&body.code_verifier);
+                        assert_eq!(idp.nonce.lock().unwrap().as_deref(), Some(nonce.as_str()));
+
+                        let mut claims = idp.issuer.claims(&nonce);
+                        claims.as_object_mut().unwrap().remove("email");
+                        Json(json!({
+                            "access_token": "the-access-token",
+                            "token_type": "Bearer",
+                            "id_token": idp.issuer.sign(claims),
+                        }))
+                    },
+                ),
+            )
+            .route(
+                "/userinfo",
+                get(|| async {
+                    Json(json!({
+                        "sub": "user-1",
+                        "email": "userinfo@example.com",
+                        "picture": "https://example.com/avatar.png",
+                    }))
+                }),
+            )
+            .with_state(idp.clone());
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, idp)
+    }
+
+    #[tokio::test]
+    async fn discover_and_login() {
+        let (base, idp) = start_fake_idp().await;
+        let http_client = reqwest::Client::new();
+        let provider = OidcOAuthProvider::discover(
+            &http_client,
+            &format!("{base}/"),
+            "the-client".to_string(),
+            "the-secret".to_string(),
+            "https://app.example.com/auth/oauth/login",
+        )
+        .await
+        .unwrap();
+
+        let AuthorizeUrl {
+            url, pkce_verifier, ..
+        } = provider.authorize_url();
+        assert!(url.as_str().starts_with(&format!("{base}/authorize")));
+        let params = url
+            .query_pairs()
+            .collect::<std::collections::HashMap<_, _>>();
+        assert_eq!(params["scope"], "openid email profile");
+        assert_eq!(
+            params["redirect_uri"],
+            "https://app.example.com/auth/oauth/login/oidc/callback"
+        );
+        *idp.nonce.lock().unwrap() = Some(params["nonce"].to_string());
+
+        let details = provider
+            .fetch_login_details(
+                http_client,
+                "the-code".to_string(),
+                pkce_verifier.unwrap().secret().to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(details.login_id, "user-1");
+        assert_eq!(details.name.as_deref(), Some("Test User"));
+        // Filled in from the userinfo endpoint, since the ID token didn't have it.
+        assert_eq!(details.email.as_deref(), Some("userinfo@example.com"));
+        assert_eq!(
+            details.avatar_url.unwrap().as_str(),
+            "https://example.com/avatar.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn discovery_issuer_mismatch() {
+        let (base, _) = start_fake_idp().await;
+        let result = OidcOAuthProvider::discover(
+            &reqwest::Client::new(),
+            &format!("{base}/other"),
+            "the-client".to_string(),
+            "the-secret".to_string(),
+            "https://app.example.com/auth/oauth/login",
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+}
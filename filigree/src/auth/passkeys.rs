@@ -0,0 +1,752 @@
+//! Passkey login using the WebAuthn standard.
+//!
+//! A logged-in user registers a passkey by requesting creation options, passing them to
+//! `navigator.credentials.create` in the browser, and sending the result back to be verified. The
+//! user can then log in by requesting assertion options, passing them to
+//! `navigator.credentials.get`, and sending back the signed assertion.
+//!
+//! Only the "none" attestation conveyance is supported, so the authenticator's make and model are
+//! not verified. Credentials using ES256 and RS256 keys are supported, which covers all common
+//! platform authenticators and security keys.
+
+mod queries;
+
+use std::borrow::Cow;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::Value;
+pub use queries::*;
+use rand::RngCore;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::AuthError;
+
+/// The COSE algorithm identifier for ECDSA with P-256 and SHA-256
+pub const COSE_ALG_ES256: i64 = -7;
+/// The COSE algorithm identifier for RSASSA-PKCS1-v1_5 with SHA-256
+pub const COSE_ALG_RS256: i64 = -257;
+
+/// The length of a generated challenge, in bytes
+const CHALLENGE_LEN: usize = 32;
+/// How long the user has to finish a registration or login
+const CEREMONY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Authenticator data flag: the user was present
+const FLAG_USER_PRESENT: u8 = 0x01;
+/// Authenticator data flag: the data includes an attested credential
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+const COSE_KEY_TYPE_EC2: i128 = 2;
+const COSE_KEY_TYPE_RSA: i128 = 3;
+const COSE_CURVE_P256: i128 = 1;
+
+/// Settings for the WebAuthn relying party, which is the site that passkeys are registered to.
+#[derive(Debug, Clone)]
+pub struct PasskeyConfig {
+    /// The relying party ID. This is the domain of the site, and passkeys registered with one
+    /// relying party ID can not be used with any other.
+    pub rp_id: String,
+    /// The name of the site, which the browser may show to the user.
+    pub rp_name: String,
+    /// The origins that are allowed to perform WebAuthn ceremonies, such as
+    /// `https://app.example.com`.
+    pub origins: Vec<String>,
+}
+
+impl PasskeyConfig {
+    /// Create a configuration for a site hosted at `origin`. The relying party ID is the host
+    /// portion of the origin.
+    pub fn new(rp_name: impl Into<String>, origin: &str) -> Result<Self, url::ParseError> {
+        let url = url::Url::parse(origin)?;
+        let rp_id = url
+            .host_str()
+            .ok_or(url::ParseError::EmptyHost)?
+            .to_string();
+
+        Ok(Self {
+            rp_id,
+            rp_name: rp_name.into(),
+            origins: vec![url.origin().ascii_serialization()],
+        })
+    }
+
+    /// Allow an additional origin, such as a subdomain of the relying party ID.
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.push(origin.into());
+        self
+    }
+
+    fn rp_id_hash(&self) -> ring::digest::Digest {
+        ring::digest::digest(&ring::digest::SHA256, self.rp_id.as_bytes())
+    }
+}
+
+/// The relying party, as sent to the browser
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RelyingParty {
+    /// The relying party ID
+    pub id: String,
+    /// The name of the site
+    pub name: String,
+}
+
+/// The user that a passkey is being registered for
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUser {
+    /// The user ID, base64url-encoded. Authenticators return this as the user handle when
+    /// logging in.
+    pub id: String,
+    /// The name of the account, usually an email address
+    pub name: String,
+    /// A friendly name for the account
+    pub display_name: String,
+}
+
+/// A public key algorithm that the server accepts
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CredentialParameters {
+    /// Always "public-key"
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The COSE algorithm identifier
+    pub alg: i64,
+}
+
+/// A reference to an existing credential
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CredentialDescriptor {
+    /// Always "public-key"
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The credential ID, base64url-encoded
+    pub id: String,
+}
+
+impl CredentialDescriptor {
+    fn new(credential_id: &[u8]) -> Self {
+        Self {
+            type_: "public-key".to_string(),
+            id: URL_SAFE_NO_PAD.encode(credential_id),
+        }
+    }
+}
+
+/// Requirements for the authenticator used to register a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    /// Whether the credential should be discoverable, so that the user can log in without
+    /// entering their email first.
+    pub resident_key: String,
+    /// Whether the authenticator should verify the user with a PIN or biometrics
+    pub user_verification: String,
+}
+
+/// Options for `navigator.credentials.create`, to register a new passkey. Binary values are
+/// base64url-encoded, and must be decoded before passing them to the browser.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    /// The challenge that the authenticator signs
+    pub challenge: String,
+    /// The relying party
+    pub rp: RelyingParty,
+    /// The user registering the passkey
+    pub user: PasskeyUser,
+    /// The accepted key algorithms, in order of preference
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    /// How long the ceremony may take, in milliseconds
+    pub timeout: u64,
+    /// Credentials that the user has already registered, so the same authenticator isn't
+    /// registered twice.
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    /// Requirements for the authenticator
+    pub authenticator_selection: AuthenticatorSelection,
+    /// The attestation conveyance preference. This is always "none".
+    pub attestation: String,
+}
+
+/// Options for `navigator.credentials.get`, to log in with a passkey. Binary values are
+/// base64url-encoded, and must be decoded before passing them to the browser.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    /// The challenge that the authenticator signs
+    pub challenge: String,
+    /// The relying party ID
+    pub rp_id: String,
+    /// How long the ceremony may take, in milliseconds
+    pub timeout: u64,
+    /// The credentials that may be used. If empty, the browser lets the user pick any
+    /// discoverable credential for this site.
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    /// Whether the authenticator should verify the user with a PIN or biometrics
+    pub user_verification: String,
+}
+
+/// The authenticator's response when registering a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AttestationResponse {
+    /// The client data JSON, base64url-encoded
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    /// The CBOR attestation object, base64url-encoded
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// The credential returned by `navigator.credentials.create`, with binary values
+/// base64url-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PasskeyRegistration {
+    /// The credential ID, base64url-encoded
+    pub id: String,
+    /// The authenticator's response
+    pub response: AttestationResponse,
+    /// A name for the passkey, to help the user tell their passkeys apart.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// The authenticator's response when logging in with a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    /// The client data JSON, base64url-encoded
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    /// The authenticator data, base64url-encoded
+    pub authenticator_data: String,
+    /// The signature over the authenticator data and client data hash, base64url-encoded
+    pub signature: String,
+    /// The user ID that the passkey was registered with, base64url-encoded
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+/// The credential returned by `navigator.credentials.get`, with binary values
+/// base64url-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PasskeyAssertion {
+    /// The credential ID, base64url-encoded
+    pub id: String,
+    /// The authenticator's response
+    pub response: AssertionResponse,
+}
+
+/// A public key from a registered credential
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoseKey {
+    /// An ECDSA P-256 key
+    Es256 {
+        /// The x coordinate
+        x: Vec<u8>,
+        /// The y coordinate
+        y: Vec<u8>,
+    },
+    /// An RSA key
+    Rs256 {
+        /// The modulus
+        n: Vec<u8>,
+        /// The public exponent
+        e: Vec<u8>,
+    },
+}
+
+impl CoseKey {
+    /// Parse a key in the COSE_Key format.
+    pub fn from_cbor(data: &[u8]) -> Result<Self, AuthError> {
+        let value: Value =
+            ciborium::de::from_reader(data).map_err(|_| invalid("public key is not valid CBOR"))?;
+        let map = value
+            .as_map()
+            .ok_or_else(|| invalid("public key is not a map"))?;
+
+        let key_type = cose_int(map, 1).ok_or_else(|| invalid("missing key type"))?;
+        let alg = cose_int(map, 3).ok_or_else(|| invalid("missing key algorithm"))?;
+
+        match (key_type, alg as i64) {
+            (COSE_KEY_TYPE_EC2, COSE_ALG_ES256) => {
+                if cose_int(map, -1) != Some(COSE_CURVE_P256) {
+                    return Err(invalid("unsupported curve"));
+                }
+
+                let x = cose_bytes(map, -2).filter(|x| x.len() == 32);
+                let y = cose_bytes(map, -3).filter(|y| y.len() == 32);
+                match (x, y) {
+                    (Some(x), Some(y)) => Ok(CoseKey::Es256 {
+                        x: x.to_vec(),
+                        y: y.to_vec(),
+                    }),
+                    _ => Err(invalid("invalid EC2 key coordinates")),
+                }
+            }
+            (COSE_KEY_TYPE_RSA, COSE_ALG_RS256) => {
+                let n = cose_bytes(map, -1).ok_or_else(|| invalid("missing RSA modulus"))?;
+                let e = cose_bytes(map, -2).ok_or_else(|| invalid("missing RSA exponent"))?;
+                Ok(CoseKey::Rs256 {
+                    n: n.to_vec(),
+                    e: e.to_vec(),
+                })
+            }
+            _ => Err(invalid("unsupported key algorithm")),
+        }
+    }
+
+    /// Encode the key in the COSE_Key format.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let int = |i: i64| Value::Integer(i.into());
+        let entries = match self {
+            CoseKey::Es256 { x, y } => vec![
+                (
+                    int(1),
+                    Value::Integer(COSE_KEY_TYPE_EC2.try_into().unwrap()),
+                ),
+                (int(3), int(COSE_ALG_ES256)),
+                (int(-1), Value::Integer(COSE_CURVE_P256.try_into().unwrap())),
+                (int(-2), Value::Bytes(x.clone())),
+                (int(-3), Value::Bytes(y.clone())),
+            ],
+            CoseKey::Rs256 { n, e } => vec![
+                (
+                    int(1),
+                    Value::Integer(COSE_KEY_TYPE_RSA.try_into().unwrap()),
+                ),
+                (int(3), int(COSE_ALG_RS256)),
+                (int(-1), Value::Bytes(n.clone())),
+                (int(-2), Value::Bytes(e.clone())),
+            ],
+        };
+
+        let mut output = Vec::new();
+        ciborium::ser::into_writer(&Value::Map(entries), &mut output)
+            .expect("writing to a Vec can not fail");
+        output
+    }
+
+    /// The COSE algorithm identifier for the key
+    pub fn algorithm(&self) -> i64 {
+        match self {
+            CoseKey::Es256 { .. } => COSE_ALG_ES256,
+            CoseKey::Rs256 { .. } => COSE_ALG_RS256,
+        }
+    }
+
+    /// Check a signature made with this key
+    pub fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match self {
+            CoseKey::Es256 { x, y } => {
+                let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+            CoseKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+                .is_ok(),
+        }
+    }
+}
+
+fn cose_value(map: &[(Value, Value)], key: i128) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn cose_int(map: &[(Value, Value)], key: i128) -> Option<i128> {
+    cose_value(map, key)?.as_integer().map(i128::from)
+}
+
+fn cose_bytes(map: &[(Value, Value)], key: i128) -> Option<&[u8]> {
+    cose_value(map, key)?.as_bytes().map(|b| b.as_slice())
+}
+
+/// A credential included in the authenticator data during registration
+#[derive(Debug, Clone)]
+pub struct AttestedCredential {
+    /// The credential ID
+    pub credential_id: Vec<u8>,
+    /// The credential's public key, in COSE format
+    pub public_key: Vec<u8>,
+}
+
+/// The authenticator data structure, which the authenticator signs
+#[derive(Debug, Clone)]
+pub struct AuthenticatorData {
+    /// The SHA-256 hash of the relying party ID
+    pub rp_id_hash: [u8; 32],
+    /// Flags describing the ceremony
+    pub flags: u8,
+    /// The authenticator's signature counter
+    pub sign_count: u32,
+    /// The new credential, when registering a passkey
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+    /// Parse the authenticator data.
+    pub fn parse(data: &[u8]) -> Result<Self, AuthError> {
+        if data.len() < 37 {
+            return Err(invalid("authenticator data is too short"));
+        }
+
+        let rp_id_hash = data[0..32].try_into().unwrap();
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes(data[33..37].try_into().unwrap());
+
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // Skip the 16-byte AAGUID
+            let rest = data
+                .get(53..)
+                .ok_or_else(|| invalid("authenticator data is too short"))?;
+            let id_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let credential_id = rest
+                .get(2..2 + id_len)
+                .ok_or_else(|| invalid("authenticator data is too short"))?
+                .to_vec();
+
+            // The public key is followed by optional extension data, so parse it to find where
+            // it ends.
+            let key_data = &rest[2 + id_len..];
+            let mut reader = key_data;
+            ciborium::de::from_reader::<Value, _>(&mut reader)
+                .map_err(|_| invalid("public key is not valid CBOR"))?;
+            let public_key = key_data[..key_data.len() - reader.len()].to_vec();
+
+            Some(AttestedCredential {
+                credential_id,
+                public_key,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    /// Check that the data is for this relying party and that the user was present.
+    fn check(&self, config: &PasskeyConfig) -> Result<(), AuthError> {
+        if self.rp_id_hash != config.rp_id_hash().as_ref() {
+            return Err(invalid("relying party ID does not match"));
+        }
+
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(invalid("user was not present"));
+        }
+
+        Ok(())
+    }
+}
+
+/// The client data that the browser passes to the authenticator
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    type_: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Check the client data and return the challenge from it.
+fn verify_client_data(
+    config: &PasskeyConfig,
+    client_data_json: &[u8],
+    expected_type: &str,
+) -> Result<Vec<u8>, AuthError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_| invalid("client data is not valid JSON"))?;
+
+    if client_data.type_ != expected_type {
+        return Err(invalid("wrong client data type"));
+    }
+
+    if !config.origins.iter().any(|o| o == &client_data.origin) {
+        return Err(invalid("origin is not allowed"));
+    }
+
+    decode(&client_data.challenge)
+}
+
+/// A passkey registration that passed verification
+#[derive(Debug, Clone)]
+pub struct VerifiedRegistration {
+    /// The challenge that was signed
+    pub challenge: Vec<u8>,
+    /// The new credential's ID
+    pub credential_id: Vec<u8>,
+    /// The new credential's public key, in COSE format
+    pub public_key: Vec<u8>,
+    /// The COSE algorithm identifier for the public key
+    pub algorithm: i64,
+    /// The authenticator's initial signature counter
+    pub sign_count: u32,
+}
+
+/// Verify the response from `navigator.credentials.create`. This does not check the challenge,
+/// which the caller must do using the returned value.
+pub fn verify_registration(
+    config: &PasskeyConfig,
+    registration: &PasskeyRegistration,
+) -> Result<VerifiedRegistration, AuthError> {
+    let client_data_json = decode(&registration.response.client_data_json)?;
+    let challenge = verify_client_data(config, &client_data_json, "webauthn.create")?;
+
+    let attestation_object = decode(&registration.response.attestation_object)?;
+    let attestation: Value = ciborium::de::from_reader(attestation_object.as_slice())
+        .map_err(|_| invalid("attestation object is not valid CBOR"))?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some("authData"))
+                .and_then(|(_, v)| v.as_bytes())
+        })
+        .ok_or_else(|| invalid("attestation object is missing authenticator data"))?;
+
+    let auth_data = AuthenticatorData::parse(auth_data)?;
+    auth_data.check(config)?;
+
+    let credential = auth_data
+        .attested_credential
+        .ok_or_else(|| invalid("missing credential data"))?;
+    if credential.credential_id != decode(&registration.id)? {
+        return Err(invalid("credential ID does not match"));
+    }
+
+    let key = CoseKey::from_cbor(&credential.public_key)?;
+
+    Ok(VerifiedRegistration {
+        challenge,
+        credential_id: credential.credential_id,
+        public_key: credential.public_key,
+        algorithm: key.algorithm(),
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// A passkey login that passed verification
+#[derive(Debug, Clone)]
+pub struct VerifiedAssertion {
+    /// The challenge that was signed
+    pub challenge: Vec<u8>,
+    /// The authenticator's new signature counter
+    pub sign_count: u32,
+}
+
+/// Verify the response from `navigator.credentials.get` against a stored credential. This does
+/// not check the challenge, which the caller must do using the returned value.
+///
+/// `stored_sign_count` is the last signature counter seen for the credential. If the
+/// authenticator supports counters, a counter that has not increased indicates that the
+/// credential may have been cloned, and the login is rejected.
+pub fn verify_assertion(
+    config: &PasskeyConfig,
+    assertion: &PasskeyAssertion,
+    public_key: &[u8],
+    stored_sign_count: u32,
+) -> Result<VerifiedAssertion, AuthError> {
+    let client_data_json = decode(&assertion.response.client_data_json)?;
+    let challenge = verify_client_data(config, &client_data_json, "webauthn.get")?;
+
+    let auth_data_bytes = decode(&assertion.response.authenticator_data)?;
+    let auth_data = AuthenticatorData::parse(&auth_data_bytes)?;
+    auth_data.check(config)?;
+
+    let key = CoseKey::from_cbor(public_key)?;
+    let client_data_hash = ring::digest::digest(&ring::digest::SHA256, &client_data_json);
+    let message = [auth_data_bytes.as_slice(), client_data_hash.as_ref()].concat();
+    let signature = decode(&assertion.response.signature)?;
+    if !key.verify(&message, &signature) {
+        return Err(invalid("signature does not match"));
+    }
+
+    if (auth_data.sign_count != 0 || stored_sign_count != 0)
+        && auth_data.sign_count <= stored_sign_count
+    {
+        return Err(invalid("signature counter did not increase"));
+    }
+
+    Ok(VerifiedAssertion {
+        challenge,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// The key algorithms that the server accepts, in order of preference
+fn supported_algorithms() -> Vec<CredentialParameters> {
+    [COSE_ALG_ES256, COSE_ALG_RS256]
+        .into_iter()
+        .map(|alg| CredentialParameters {
+            type_: "public-key".to_string(),
+            alg,
+        })
+        .collect()
+}
+
+fn default_authenticator_selection() -> AuthenticatorSelection {
+    AuthenticatorSelection {
+        resident_key: "preferred".to_string(),
+        user_verification: "preferred".to_string(),
+    }
+}
+
+fn generate_challenge() -> Vec<u8> {
+    let mut challenge = vec![0u8; CHALLENGE_LEN];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    challenge
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AuthError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid("invalid base64url value"))
+}
+
+fn invalid(message: &'static str) -> AuthError {
+    AuthError::InvalidPasskey(Cow::Borrowed(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SoftwareAuthenticator;
+
+    fn config() -> PasskeyConfig {
+        PasskeyConfig::new("Test", "https://example.com").unwrap()
+    }
+
+    fn creation_options(config: &PasskeyConfig, challenge: &[u8]) -> PasskeyCreationOptions {
+        PasskeyCreationOptions {
+            challenge: URL_SAFE_NO_PAD.encode(challenge),
+            rp: RelyingParty {
+                id: config.rp_id.clone(),
+                name: config.rp_name.clone(),
+            },
+            user: PasskeyUser {
+                id: URL_SAFE_NO_PAD.encode(b"user"),
+                name: "user@example.com".to_string(),
+                display_name: "User".to_string(),
+            },
+            pub_key_cred_params: supported_algorithms(),
+            timeout: 60000,
+            exclude_credentials: vec![],
+            authenticator_selection: default_authenticator_selection(),
+            attestation: "none".to_string(),
+        }
+    }
+
+    fn request_options(config: &PasskeyConfig, challenge: &[u8]) -> PasskeyRequestOptions {
+        PasskeyRequestOptions {
+            challenge: URL_SAFE_NO_PAD.encode(challenge),
+            rp_id: config.rp_id.clone(),
+            timeout: 60000,
+            allow_credentials: vec![],
+            user_verification: "preferred".to_string(),
+        }
+    }
+
+    #[test]
+    fn config_from_origin() {
+        let config = PasskeyConfig::new("App", "http://localhost:5173/some/path").unwrap();
+        assert_eq!(config.rp_id, "localhost");
+        assert_eq!(config.origins, vec!["http://localhost:5173".to_string()]);
+    }
+
+    #[test]
+    fn cose_key_round_trip() {
+        let key = CoseKey::Es256 {
+            x: vec![1; 32],
+            y: vec![2; 32],
+        };
+        assert_eq!(CoseKey::from_cbor(&key.to_cbor()).unwrap(), key);
+
+        let key = CoseKey::Rs256 {
+            n: vec![3; 256],
+            e: vec![1, 0, 1],
+        };
+        assert_eq!(CoseKey::from_cbor(&key.to_cbor()).unwrap(), key);
+
+        let bad_key = CoseKey::Es256 {
+            x: vec![1; 31],
+            y: vec![2; 32],
+        };
+        assert!(CoseKey::from_cbor(&bad_key.to_cbor()).is_err());
+    }
+
+    #[test]
+    fn register_and_login() {
+        let config = config();
+        let mut authenticator = SoftwareAuthenticator::new("https://example.com");
+
+        let registration = authenticator.register(&creation_options(&config, b"challenge-1"));
+        let verified = verify_registration(&config, &registration).unwrap();
+        assert_eq!(verified.challenge, b"challenge-1");
+        assert_eq!(verified.algorithm, COSE_ALG_ES256);
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(&verified.credential_id),
+            authenticator.credential_id()
+        );
+
+        let assertion = authenticator.login(&request_options(&config, b"challenge-2"));
+        let result = verify_assertion(
+            &config,
+            &assertion,
+            &verified.public_key,
+            verified.sign_count,
+        )
+        .unwrap();
+        assert_eq!(result.challenge, b"challenge-2");
+        assert!(result.sign_count > verified.sign_count);
+
+        // Replaying the same assertion fails the signature counter check
+        let err = verify_assertion(&config, &assertion, &verified.public_key, result.sign_count)
+            .unwrap_err();
+        assert!(matches!(err, AuthError::InvalidPasskey(_)), "{err:?}");
+
+        // Tampering with the client data invalidates the signature
+        let mut tampered = authenticator.login(&request_options(&config, b"challenge-3"));
+        tampered.response.client_data_json = URL_SAFE_NO_PAD.encode(
+            serde_json::json!({
+                "type": "webauthn.get",
+                "challenge": URL_SAFE_NO_PAD.encode(b"other-challenge"),
+                "origin": "https://example.com",
+            })
+            .to_string(),
+        );
+        assert!(
+            verify_assertion(&config, &tampered, &verified.public_key, result.sign_count).is_err()
+        );
+    }
+
+    #[test]
+    fn wrong_origin() {
+        let config = config();
+        let mut authenticator = SoftwareAuthenticator::new("https://evil.example.net");
+        let mut options = creation_options(&config, b"challenge");
+        options.rp.id = "evil.example.net".to_string();
+
+        let registration = authenticator.register(&options);
+        let err = verify_registration(&config, &registration).unwrap_err();
+        assert!(
+            matches!(err, AuthError::InvalidPasskey(ref message) if message.contains("origin")),
+            "{err:?}"
+        );
+
+        // The origin matches, but the passkey is for a different relying party
+        let config = config.with_origin("https://evil.example.net");
+        let err = verify_registration(&config, &registration).unwrap_err();
+        assert!(
+            matches!(err, AuthError::InvalidPasskey(ref message) if message.contains("relying party")),
+            "{err:?}"
+        );
+    }
+}
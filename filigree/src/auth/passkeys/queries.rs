@@ -0,0 +1,486 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use error_stack::{Report, ResultExt};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::{
+    decode, default_authenticator_selection, generate_challenge, invalid, supported_algorithms,
+    verify_assertion, verify_registration, CredentialDescriptor, PasskeyAssertion, PasskeyConfig,
+    PasskeyCreationOptions, PasskeyRegistration, PasskeyRequestOptions, PasskeyUser, RelyingParty,
+    CEREMONY_TIMEOUT,
+};
+use crate::{
+    auth::{AuthError, UserId},
+    db::DbPool,
+};
+
+const PURPOSE_REGISTER: &str = "register";
+const PURPOSE_LOGIN: &str = "login";
+
+/// A passkey registered by a user
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PasskeyInfo {
+    /// The credential ID, base64url-encoded
+    pub id: String,
+    /// The name of the passkey
+    pub name: String,
+    /// When the passkey was registered
+    pub created_at: DateTime<Utc>,
+    /// When the passkey was last used to log in
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct PasskeyRow {
+    id: Vec<u8>,
+    name: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<PasskeyRow> for PasskeyInfo {
+    fn from(row: PasskeyRow) -> Self {
+        Self {
+            id: URL_SAFE_NO_PAD.encode(row.id),
+            name: row.name,
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredCredential {
+    user_id: UserId,
+    public_key: Vec<u8>,
+    sign_count: i64,
+}
+
+/// Store a new challenge, and remove any expired ones while we're at it.
+async fn save_challenge(
+    db: &DbPool,
+    challenge: &[u8],
+    user_id: Option<&UserId>,
+    purpose: &str,
+) -> Result<(), Report<AuthError>> {
+    let user_id = user_id.map(|id| *id.as_uuid());
+
+    #[cfg(not(feature = "sqlite"))]
+    {
+        sqlx::query!("DELETE FROM webauthn_challenges WHERE expires_at < now()")
+            .execute(db)
+            .await
+            .change_context(AuthError::Db)?;
+
+        sqlx::query!(
+            "INSERT INTO webauthn_challenges (challenge, user_id, purpose, expires_at)
+            VALUES ($1, $2, $3, now() + $4)",
+            challenge,
+            user_id,
+            purpose,
+            CEREMONY_TIMEOUT as _
+        )
+        .execute(db)
+        .await
+        .change_context(AuthError::Db)?;
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        sqlx::query("DELETE FROM webauthn_challenges WHERE expires_at < unixepoch()")
+            .execute(db)
+            .await
+            .change_context(AuthError::Db)?;
+
+        sqlx::query(
+            "INSERT INTO webauthn_challenges (challenge, user_id, purpose, expires_at)
+            VALUES (?1, ?2, ?3, unixepoch() + ?4)",
+        )
+        .bind(challenge)
+        .bind(user_id)
+        .bind(purpose)
+        .bind(CEREMONY_TIMEOUT.as_secs() as i64)
+        .execute(db)
+        .await
+        .change_context(AuthError::Db)?;
+    }
+
+    Ok(())
+}
+
+/// Remove a challenge so that it can not be used again, and return an error if it does not exist
+/// or has expired.
+async fn take_challenge(
+    db: &DbPool,
+    challenge: &[u8],
+    user_id: Option<&UserId>,
+    purpose: &str,
+) -> Result<(), Report<AuthError>> {
+    let user_id = user_id.map(|id| *id.as_uuid());
+
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "DELETE FROM webauthn_challenges
+        WHERE challenge = $1
+            AND user_id IS NOT DISTINCT FROM $2
+            AND purpose = $3
+            AND expires_at > now()",
+        challenge,
+        user_id,
+        purpose
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "DELETE FROM webauthn_challenges
+        WHERE challenge = ?1
+            AND user_id IS ?2
+            AND purpose = ?3
+            AND expires_at > unixepoch()",
+    )
+    .bind(challenge)
+    .bind(user_id)
+    .bind(purpose)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(invalid("challenge is invalid or expired")));
+    }
+
+    Ok(())
+}
+
+/// The IDs of the credentials that a user has registered
+async fn user_credential_ids(
+    db: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<Vec<u8>>, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM webauthn_credentials WHERE user_id = $1",
+        user_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let ids =
+        sqlx::query_scalar::<_, Vec<u8>>("SELECT id FROM webauthn_credentials WHERE user_id = ?1")
+            .bind(user_id.as_uuid())
+            .fetch_all(db)
+            .await
+            .change_context(AuthError::Db)?;
+
+    Ok(ids)
+}
+
+/// Begin registering a new passkey for a user, and return the options to pass to
+/// `navigator.credentials.create`.
+pub async fn start_passkey_registration(
+    db: &DbPool,
+    config: &PasskeyConfig,
+    user_id: &UserId,
+) -> Result<PasskeyCreationOptions, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let email = sqlx::query_scalar!(
+        "SELECT email FROM email_logins WHERE user_id = $1 ORDER BY verified DESC, email LIMIT 1",
+        user_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let email = sqlx::query_scalar::<_, String>(
+        "SELECT email FROM email_logins WHERE user_id = ?1 ORDER BY verified DESC, email LIMIT 1",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    let account_name = email.unwrap_or_else(|| user_id.to_string());
+    let existing = user_credential_ids(db, user_id).await?;
+
+    let challenge = generate_challenge();
+    save_challenge(db, &challenge, Some(user_id), PURPOSE_REGISTER).await?;
+
+    Ok(PasskeyCreationOptions {
+        challenge: URL_SAFE_NO_PAD.encode(&challenge),
+        rp: RelyingParty {
+            id: config.rp_id.clone(),
+            name: config.rp_name.clone(),
+        },
+        user: PasskeyUser {
+            id: URL_SAFE_NO_PAD.encode(user_id.as_uuid().as_bytes()),
+            display_name: account_name.clone(),
+            name: account_name,
+        },
+        pub_key_cred_params: supported_algorithms(),
+        timeout: CEREMONY_TIMEOUT.as_millis() as u64,
+        exclude_credentials: existing
+            .iter()
+            .map(|id| CredentialDescriptor::new(id))
+            .collect(),
+        authenticator_selection: default_authenticator_selection(),
+        attestation: "none".to_string(),
+    })
+}
+
+/// Verify the response from `navigator.credentials.create` and save the new passkey.
+pub async fn finish_passkey_registration(
+    db: &DbPool,
+    config: &PasskeyConfig,
+    user_id: &UserId,
+    registration: &PasskeyRegistration,
+) -> Result<PasskeyInfo, Report<AuthError>> {
+    let verified = verify_registration(config, registration)?;
+    take_challenge(db, &verified.challenge, Some(user_id), PURPOSE_REGISTER).await?;
+
+    let name = registration
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("Passkey");
+
+    #[cfg(not(feature = "sqlite"))]
+    let row = sqlx::query_as!(
+        PasskeyRow,
+        "INSERT INTO webauthn_credentials (id, user_id, public_key, algorithm, sign_count, name)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id) DO NOTHING
+        RETURNING id, name, created_at, last_used_at",
+        &verified.credential_id,
+        user_id.as_uuid(),
+        &verified.public_key,
+        verified.algorithm as i32,
+        verified.sign_count as i64,
+        name
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let row = sqlx::query_as::<_, PasskeyRow>(
+        "INSERT INTO webauthn_credentials (id, user_id, public_key, algorithm, sign_count, name)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT (id) DO NOTHING
+        RETURNING id, name, created_at, last_used_at",
+    )
+    .bind(&verified.credential_id)
+    .bind(user_id.as_uuid())
+    .bind(&verified.public_key)
+    .bind(verified.algorithm as i32)
+    .bind(verified.sign_count as i64)
+    .bind(name)
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    let row = row.ok_or_else(|| invalid("passkey is already registered"))?;
+    Ok(row.into())
+}
+
+/// Begin logging in with a passkey, and return the options to pass to
+/// `navigator.credentials.get`.
+///
+/// If an email is provided, the browser is asked to use one of that user's passkeys. Otherwise
+/// the user can choose from any discoverable passkey that they have registered for the site.
+pub async fn start_passkey_login(
+    db: &DbPool,
+    config: &PasskeyConfig,
+    email: Option<&str>,
+) -> Result<PasskeyRequestOptions, Report<AuthError>> {
+    let allow_credentials = match email {
+        Some(email) => {
+            #[cfg(not(feature = "sqlite"))]
+            let ids = sqlx::query_scalar!(
+                "SELECT wc.id FROM webauthn_credentials wc
+                JOIN email_logins el ON el.user_id = wc.user_id
+                WHERE el.email = $1",
+                email
+            )
+            .fetch_all(db)
+            .await
+            .change_context(AuthError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            let ids = sqlx::query_scalar::<_, Vec<u8>>(
+                "SELECT wc.id FROM webauthn_credentials wc
+                JOIN email_logins el ON el.user_id = wc.user_id
+                WHERE el.email = ?1",
+            )
+            .bind(email)
+            .fetch_all(db)
+            .await
+            .change_context(AuthError::Db)?;
+
+            ids.iter().map(|id| CredentialDescriptor::new(id)).collect()
+        }
+        None => Vec::new(),
+    };
+
+    let challenge = generate_challenge();
+    save_challenge(db, &challenge, None, PURPOSE_LOGIN).await?;
+
+    Ok(PasskeyRequestOptions {
+        challenge: URL_SAFE_NO_PAD.encode(&challenge),
+        rp_id: config.rp_id.clone(),
+        timeout: CEREMONY_TIMEOUT.as_millis() as u64,
+        allow_credentials,
+        user_verification: "preferred".to_string(),
+    })
+}
+
+/// Verify the response from `navigator.credentials.get`, and return the user that owns the
+/// passkey.
+pub async fn finish_passkey_login(
+    db: &DbPool,
+    config: &PasskeyConfig,
+    assertion: &PasskeyAssertion,
+) -> Result<UserId, Report<AuthError>> {
+    let credential_id = decode(&assertion.id)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let credential = sqlx::query_as!(
+        StoredCredential,
+        r#"SELECT user_id AS "user_id: UserId", public_key, sign_count
+        FROM webauthn_credentials
+        WHERE id = $1"#,
+        &credential_id
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let credential = sqlx::query_as::<_, StoredCredential>(
+        "SELECT user_id, public_key, sign_count FROM webauthn_credentials WHERE id = ?1",
+    )
+    .bind(&credential_id)
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    let credential = credential.ok_or_else(|| invalid("unknown passkey"))?;
+
+    if let Some(user_handle) = &assertion.response.user_handle {
+        if decode(user_handle)? != credential.user_id.as_uuid().as_bytes() {
+            return Err(Report::new(invalid("user handle does not match")));
+        }
+    }
+
+    let verified = verify_assertion(
+        config,
+        assertion,
+        &credential.public_key,
+        credential.sign_count as u32,
+    )?;
+    take_challenge(db, &verified.challenge, None, PURPOSE_LOGIN).await?;
+
+    // The condition guards against two logins racing with the same counter value.
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "UPDATE webauthn_credentials
+        SET sign_count = $2, last_used_at = now()
+        WHERE id = $1 AND (sign_count = 0 OR sign_count < $2)",
+        &credential_id,
+        verified.sign_count as i64
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "UPDATE webauthn_credentials
+        SET sign_count = ?2, last_used_at = unixepoch()
+        WHERE id = ?1 AND (sign_count = 0 OR sign_count < ?2)",
+    )
+    .bind(&credential_id)
+    .bind(verified.sign_count as i64)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(invalid("signature counter did not increase")));
+    }
+
+    Ok(credential.user_id)
+}
+
+/// List the passkeys that a user has registered
+pub async fn list_passkeys(
+    db: &DbPool,
+    user_id: &UserId,
+) -> Result<Vec<PasskeyInfo>, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let rows = sqlx::query_as!(
+        PasskeyRow,
+        "SELECT id, name, created_at, last_used_at
+        FROM webauthn_credentials
+        WHERE user_id = $1
+        ORDER BY created_at",
+        user_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let rows = sqlx::query_as::<_, PasskeyRow>(
+        "SELECT id, name, created_at, last_used_at
+        FROM webauthn_credentials
+        WHERE user_id = ?1
+        ORDER BY created_at",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_all(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    Ok(rows.into_iter().map(PasskeyInfo::from).collect())
+}
+
+/// Remove one of a user's passkeys. `id` is the base64url-encoded credential ID.
+pub async fn delete_passkey(
+    db: &DbPool,
+    user_id: &UserId,
+    id: &str,
+) -> Result<(), Report<AuthError>> {
+    let credential_id = decode(id).map_err(|_| AuthError::PasskeyNotFound)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2",
+        &credential_id,
+        user_id.as_uuid()
+    )
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query("DELETE FROM webauthn_credentials WHERE id = ?1 AND user_id = ?2")
+        .bind(&credential_id)
+        .bind(user_id.as_uuid())
+        .execute(db)
+        .await
+        .change_context(AuthError::Db)?;
+
+    if result.rows_affected() == 0 {
+        return Err(Report::new(AuthError::PasskeyNotFound));
+    }
+
+    Ok(())
+}
@@ -6,13 +6,17 @@ use argon2::{
 };
 use error_stack::{Report, ResultExt};
 use serde_json::json;
-use sqlx::PgPool;
 use tower_cookies::Cookies;
 use tracing::instrument;
 use uuid::Uuid;
 
-use super::{sessions::SessionBackend, AuthError, EmailAndPassword, UserId};
-use crate::errors::FormDataResponse;
+use super::{
+    mfa::{create_login_session, LoginStatus},
+    saml::sso_required,
+    sessions::SessionBackend,
+    AuthError, EmailAndPassword, UserId,
+};
+use crate::{db::DbPool, errors::FormDataResponse};
 
 /// A wrapper around a hashed password, to help avoid passing a plaintext password where a hashed
 /// password is expected.
@@ -67,9 +71,18 @@ pub async fn verify_password(password: String, hash_str: HashedPassword) -> Resu
     Ok(())
 }
 
+/// The login information for a user, as stored in the database
+#[cfg(feature = "sqlite")]
+#[derive(sqlx::FromRow)]
+struct UserPasswordInfo {
+    user_id: UserId,
+    password_hash: Option<String>,
+    verified: bool,
+}
+
 /// Look up a user and verify the password, and check that the user is verified.
 pub async fn lookup_user_from_email_and_password(
-    db: &PgPool,
+    db: &DbPool,
     email_and_password: EmailAndPassword,
 ) -> Result<UserId, Report<AuthError>> {
     if email_and_password.password.is_empty() {
@@ -78,6 +91,7 @@ pub async fn lookup_user_from_email_and_password(
         Err(AuthError::Unauthenticated)?;
     }
 
+    #[cfg(not(feature = "sqlite"))]
     let user_info = sqlx::query!(
         r#"SELECT user_id as "user_id: UserId", password_hash, email_logins.verified
         FROM email_logins
@@ -90,6 +104,19 @@ pub async fn lookup_user_from_email_and_password(
     .change_context(AuthError::Db)?
     .ok_or(AuthError::UserNotFound)?;
 
+    #[cfg(feature = "sqlite")]
+    let user_info = sqlx::query_as::<_, UserPasswordInfo>(
+        r#"SELECT user_id, password_hash, email_logins.verified
+        FROM email_logins
+        JOIN users ON users.id = email_logins.user_id
+        WHERE email_logins.email = ?1"#,
+    )
+    .bind(&email_and_password.email)
+    .fetch_optional(db)
+    .await
+    .change_context(AuthError::Db)?
+    .ok_or(AuthError::UserNotFound)?;
+
     let password_hash = HashedPassword(user_info.password_hash.unwrap_or_default());
 
     verify_password(email_and_password.password, password_hash).await?;
@@ -102,13 +129,17 @@ pub async fn lookup_user_from_email_and_password(
 }
 
 /// Lookup a user based on the email/password, and create a new session.
-/// This returns an error if the email is not found, the password is incorrect, or if the user is
-/// not verified.
+/// This returns an error if the email is not found, the password is incorrect, if the user is
+/// not verified, or if the user's organization requires single sign-on.
+///
+/// If the user has enabled two-factor authentication, the session is only partially
+/// authenticated until the second factor is verified, and this returns
+/// [LoginStatus::MfaRequired].
 pub async fn login_with_password(
     session_backend: &SessionBackend,
     cookies: &Cookies,
     email_and_password: EmailAndPassword,
-) -> Result<(), Report<AuthError>> {
+) -> Result<LoginStatus, Report<AuthError>> {
     let user_id =
         lookup_user_from_email_and_password(&session_backend.db, email_and_password.clone())
             .await
@@ -116,17 +147,18 @@ pub async fn login_with_password(
                 FormDataResponse::new(Arc::new(json!({ "email": email_and_password.email })))
             })?;
 
-    session_backend
-        .create_session(&cookies, &user_id)
-        .await
-        .change_context(AuthError::SessionBackend)?;
-    Ok(())
+    if sso_required(&session_backend.db, &user_id).await? {
+        return Err(Report::new(AuthError::SsoRequired));
+    }
+
+    create_login_session(session_backend, cookies, &user_id).await
 }
 
 /// Create a password reset token
-pub async fn create_reset_token(db: &sqlx::PgPool, email: &str) -> Result<Uuid, Report<AuthError>> {
+pub async fn create_reset_token(db: &DbPool, email: &str) -> Result<Uuid, Report<AuthError>> {
     let token = Uuid::new_v4();
 
+    #[cfg(not(feature = "sqlite"))]
     let result = sqlx::query!(
         "UPDATE email_logins
         SET reset_token = $2,
@@ -139,6 +171,19 @@ pub async fn create_reset_token(db: &sqlx::PgPool, email: &str) -> Result<Uuid,
     .await
     .change_context(AuthError::Db)?;
 
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "UPDATE email_logins
+        SET reset_token = ?2,
+            reset_expires_at = unixepoch() + 3600
+        WHERE email = ?1",
+    )
+    .bind(email)
+    .bind(token)
+    .execute(db)
+    .await
+    .change_context(AuthError::Db)?;
+
     if result.rows_affected() == 0 {
         return Err(Report::new(AuthError::Unauthenticated));
     }
@@ -2,7 +2,11 @@ use error_stack::{Report, ResultExt};
 use tower_cookies::Cookies;
 use uuid::Uuid;
 
-use super::{AuthError, UserId};
+use super::{
+    mfa::{create_login_session, LoginStatus},
+    saml::sso_required,
+    AuthError, UserId,
+};
 use crate::server::FiligreeState;
 
 /// A successful result of creating a login token
@@ -23,6 +27,7 @@ pub async fn setup_passwordless_login(
 
     let found_email = {
         // TODO get the user name here too if we have it
+        #[cfg(not(feature = "sqlite"))]
         let result = sqlx::query!(
             "UPDATE email_logins
             SET passwordless_login_token = $2,
@@ -35,6 +40,19 @@ pub async fn setup_passwordless_login(
         .await
         .change_context(AuthError::Db)?;
 
+        #[cfg(feature = "sqlite")]
+        let result = sqlx::query(
+            "UPDATE email_logins
+            SET passwordless_login_token = ?2,
+                passwordless_login_expires_at = unixepoch() + 3600
+            WHERE email = ?1",
+        )
+        .bind(&email)
+        .bind(token)
+        .execute(&state.db)
+        .await
+        .change_context(AuthError::Db)?;
+
         result.rows_affected() > 0
     };
 
@@ -44,6 +62,7 @@ pub async fn setup_passwordless_login(
             new_user: false,
         })
     } else if state.new_user_flags.allow_public_signup {
+        #[cfg(not(feature = "sqlite"))]
         sqlx::query!(
             "INSERT INTO user_invites (email, organization_id, token, token_expires_at)
                 VALUES ($1, NULL, $2, now() + interval '1 hour')
@@ -58,6 +77,21 @@ pub async fn setup_passwordless_login(
         .await
         .change_context(AuthError::Db)?;
 
+        #[cfg(feature = "sqlite")]
+        sqlx::query(
+            "INSERT INTO user_invites (email, organization_id, token, token_expires_at)
+                VALUES (?1, NULL, ?2, unixepoch() + 3600)
+                ON CONFLICT(email, ifnull(organization_id, ''))
+                DO UPDATE SET invite_sent_at = unixepoch(),
+                    token = ?2,
+                    token_expires_at = unixepoch() + 3600",
+        )
+        .bind(&email)
+        .bind(token)
+        .execute(&state.db)
+        .await
+        .change_context(AuthError::Db)?;
+
         Ok(PasswordlessLoginRequestAnswer {
             token,
             new_user: true,
@@ -68,13 +102,18 @@ pub async fn setup_passwordless_login(
 }
 
 /// Given a token from an email, log in the user.
+///
+/// If the user has enabled two-factor authentication, the session is only partially
+/// authenticated until the second factor is verified, and this returns
+/// [LoginStatus::MfaRequired].
 pub async fn perform_passwordless_login(
     state: &FiligreeState,
     cookies: &Cookies,
     email: String,
     token: Uuid,
-) -> Result<(), Report<AuthError>> {
+) -> Result<LoginStatus, Report<AuthError>> {
     // Get the token, and unconditionally clear it.
+    #[cfg(not(feature = "sqlite"))]
     let result = sqlx::query!(
         r##"
         UPDATE email_logins upd
@@ -97,22 +136,69 @@ pub async fn perform_passwordless_login(
     .await
     .change_context(AuthError::Db)?;
 
+    #[cfg(not(feature = "sqlite"))]
     let user = result
         .as_ref()
         .filter(|r| r.valid.unwrap_or(false))
         .map(|r| r.user_id);
 
+    // SQLite's RETURNING can't see the old row, so look up the token first.
+    #[cfg(feature = "sqlite")]
+    let user = {
+        let mut tx = state.db.begin().await.change_context(AuthError::Db)?;
+        let result = sqlx::query_as::<_, (UserId, bool)>(
+            r##"
+            SELECT user_id,
+                (passwordless_login_token = ?2 AND passwordless_login_expires_at > unixepoch()) AS valid
+            FROM email_logins
+            WHERE email = ?1 AND passwordless_login_token IS NOT NULL
+            "##,
+        )
+        .bind(&email)
+        .bind(token)
+        .fetch_optional(&mut *tx)
+        .await
+        .change_context(AuthError::Db)?;
+
+        if let Some((_, valid)) = result {
+            sqlx::query(
+                "UPDATE email_logins
+                SET passwordless_login_token = null,
+                    passwordless_login_expires_at = null,
+                    verified = verified OR ?2
+                WHERE email = ?1",
+            )
+            .bind(&email)
+            .bind(valid)
+            .execute(&mut *tx)
+            .await
+            .change_context(AuthError::Db)?;
+        }
+
+        tx.commit().await.change_context(AuthError::Db)?;
+
+        result
+            .filter(|(_, valid)| *valid)
+            .map(|(user_id, _)| user_id)
+    };
+
     let Some(user_id) = user else {
         return Err(Report::new(AuthError::InvalidToken));
     };
 
-    state
-        .session_backend
-        .create_session(cookies, &user_id)
-        .await
-        .change_context(AuthError::SessionBackend)?;
+    if sso_required(&state.db, &user_id).await? {
+        return Err(Report::new(AuthError::SsoRequired));
+    }
 
-    Ok(())
+    create_login_session(&state.session_backend, cookies, &user_id).await
+}
+
+/// The token for an invite, as stored in the database
+#[cfg(feature = "sqlite")]
+#[derive(sqlx::FromRow)]
+struct UserInvite {
+    token: Uuid,
+    token_expires_at: chrono::DateTime<chrono::Utc>,
 }
 
 /// Accept a signup request. This only verifies the invite, and doesn't actually add the
@@ -122,6 +208,7 @@ pub async fn check_signup_request(
     email: &str,
     token: Uuid,
 ) -> Result<(), Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
     let result = sqlx::query!(
         "DELETE FROM user_invites
         WHERE email=$1 AND organization_id IS NULL
@@ -133,6 +220,18 @@ pub async fn check_signup_request(
     .change_context(AuthError::Db)?
     .ok_or(AuthError::InvalidToken)?;
 
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query_as::<_, UserInvite>(
+        "DELETE FROM user_invites
+        WHERE email=?1 AND organization_id IS NULL
+        RETURNING token, token_expires_at",
+    )
+    .bind(email)
+    .fetch_optional(&state.db)
+    .await
+    .change_context(AuthError::Db)?
+    .ok_or(AuthError::InvalidToken)?;
+
     if result.token != token || result.token_expires_at < chrono::Utc::now() {
         return Err(Report::new(AuthError::InvalidToken));
     }
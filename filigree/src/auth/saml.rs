@@ -0,0 +1,851 @@
+//! SAML 2.0 single sign-on, configured separately for each organization.
+//!
+//! Each organization can register an identity provider (IdP) by uploading its metadata, or by
+//! entering the entity ID, SSO URL, and signing certificate directly. The app acts as the service
+//! provider (SP) with an entity ID and Assertion Consumer Service (ACS) URL that are unique to the
+//! organization, so that each organization's IdP only ever sees its own SP.
+//!
+//! Logins are always started by the service provider, which sends an `AuthnRequest` using the
+//! HTTP-Redirect binding. The IdP sends the response back using the HTTP-POST binding. The
+//! response or the assertion inside it must be signed with the configured certificate, and
+//! encrypted assertions are not supported.
+
+mod queries;
+mod signature;
+mod xml;
+
+/// HTTP endpoints for SAML login
+pub mod endpoints;
+
+use std::io::Write;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use error_stack::{Report, ResultExt};
+use flate2::{write::DeflateEncoder, Compression};
+use hyper::StatusCode;
+use quick_xml::escape::escape;
+use thiserror::Error;
+
+pub use endpoints::create_routes;
+pub use queries::*;
+
+pub(crate) use self::signature::sign_enveloped;
+use self::{signature::decode_base64, xml::Element};
+use super::OrganizationId;
+use crate::errors::{ErrorKind, ForceObfuscate, HttpError};
+
+const SAMLP_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const SAML_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const MD_NS: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+const BINDING_REDIRECT: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+const BINDING_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const CONFIRMATION_BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+
+/// How far the IdP's clock can differ from ours when checking validity periods
+const CLOCK_SKEW: chrono::Duration = chrono::Duration::seconds(60);
+
+/// An error related to SAML login or configuration
+#[derive(Error, Debug)]
+pub enum SamlError {
+    /// The identity provider metadata or certificate could not be used
+    #[error("Invalid SAML identity provider configuration")]
+    InvalidConfig,
+    /// The SAML response was malformed, expired, not signed correctly, or not intended for this
+    /// service provider. In production this will appear as a generic 401 error.
+    #[error("Invalid SAML response")]
+    InvalidResponse,
+    /// The SAML response did not match a pending login request. In production this will appear
+    /// as a generic 401 error.
+    #[error("SAML login request not found")]
+    RequestNotFound,
+    /// The organization does not have SAML login configured
+    #[error("SAML is not configured for this organization")]
+    NotConfigured,
+    /// The email from the identity provider belongs to a user outside the organization
+    #[error("Email is already in use by another account")]
+    EmailInUse,
+    /// The database returned an error
+    #[error("Database error")]
+    Db,
+    /// Error while trying to create a new user after a SAML login
+    #[error("Failed to create user")]
+    UserCreation,
+    /// Some error from the session backend
+    #[error("Session backend error")]
+    SessionBackend,
+}
+
+impl HttpError for SamlError {
+    type Detail = ();
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidConfig => StatusCode::BAD_REQUEST,
+            Self::InvalidResponse | Self::RequestNotFound => StatusCode::UNAUTHORIZED,
+            Self::NotConfigured => StatusCode::NOT_FOUND,
+            Self::EmailInUse => StatusCode::CONFLICT,
+            Self::Db | Self::UserCreation | Self::SessionBackend => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_detail(&self) -> Self::Detail {}
+
+    fn obfuscate(&self) -> Option<ForceObfuscate> {
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            Some(ForceObfuscate::unauthenticated())
+        } else {
+            None
+        }
+    }
+
+    fn error_kind(&self) -> &'static str {
+        match self {
+            Self::InvalidConfig => ErrorKind::InvalidSamlConfig,
+            Self::InvalidResponse | Self::RequestNotFound => ErrorKind::InvalidSamlResponse,
+            Self::NotConfigured => ErrorKind::NotFound,
+            Self::EmailInUse => ErrorKind::EmailInUse,
+            Self::Db => ErrorKind::Database,
+            Self::UserCreation => ErrorKind::UserCreationError,
+            Self::SessionBackend => ErrorKind::SessionBackend,
+        }
+        .as_str()
+    }
+}
+
+/// Service provider settings shared by all organizations
+#[derive(Debug, Clone)]
+pub struct SamlConfig {
+    /// The URL that the SAML endpoints are mounted under, such as `https://app.example.com/api`
+    pub base_url: String,
+}
+
+impl SamlConfig {
+    /// Create a configuration for SAML endpoints mounted under `base_url`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The service provider entity ID for an organization. This is also the URL of its metadata.
+    pub fn entity_id(&self, organization_id: OrganizationId) -> String {
+        format!("{}/auth/saml/{organization_id}/metadata", self.base_url)
+    }
+
+    /// The Assertion Consumer Service URL for an organization, which receives SAML responses
+    pub fn acs_url(&self, organization_id: OrganizationId) -> String {
+        format!("{}/auth/saml/{organization_id}/acs", self.base_url)
+    }
+
+    /// The URL which starts a SAML login for an organization
+    pub fn login_url(&self, organization_id: OrganizationId) -> String {
+        format!("{}/auth/saml/{organization_id}/login", self.base_url)
+    }
+
+    /// Generate the service provider metadata for an organization, to be uploaded to its
+    /// identity provider.
+    pub fn metadata_xml(&self, organization_id: OrganizationId) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<md:EntityDescriptor xmlns:md="{MD_NS}" entityID="{entity_id}">
+  <md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" protocolSupportEnumeration="{SAMLP_NS}">
+    <md:AssertionConsumerService Binding="{BINDING_POST}" Location="{acs_url}" index="0" isDefault="true"/>
+  </md:SPSSODescriptor>
+</md:EntityDescriptor>
+"#,
+            entity_id = escape(&self.entity_id(organization_id)),
+            acs_url = escape(&self.acs_url(organization_id)),
+        )
+    }
+}
+
+/// The configuration for an organization's identity provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamlIdpConfig {
+    /// The IdP's entity ID, which must match the Issuer of its responses
+    pub entity_id: String,
+    /// The URL to send login requests to, using the HTTP-Redirect binding
+    pub sso_url: String,
+    /// The DER-encoded X.509 certificate that the IdP signs responses with
+    pub certificate: Vec<u8>,
+}
+
+impl SamlIdpConfig {
+    /// Create a configuration from its parts. The certificate can be PEM-encoded or just the
+    /// base64 data inside the PEM markers, as often shown in IdP admin consoles.
+    pub fn new(
+        entity_id: impl Into<String>,
+        sso_url: impl Into<String>,
+        certificate: &str,
+    ) -> Result<Self, Report<SamlError>> {
+        let certificate = certificate
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        let certificate = decode_base64(&certificate)
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Certificate is not valid base64")?;
+
+        let config = Self {
+            entity_id: entity_id.into(),
+            sso_url: sso_url.into(),
+            certificate,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read the configuration from an IdP's metadata XML
+    pub fn from_metadata(metadata: &str) -> Result<Self, Report<SamlError>> {
+        let root = xml::parse(metadata).change_context(SamlError::InvalidConfig)?;
+        let entity = if root.is(MD_NS, "EntityDescriptor") {
+            &root
+        } else if root.is(MD_NS, "EntitiesDescriptor") {
+            root.child(MD_NS, "EntityDescriptor")
+                .ok_or(SamlError::InvalidConfig)
+                .attach_printable("Metadata has no EntityDescriptor")?
+        } else {
+            return Err(Report::new(SamlError::InvalidConfig))
+                .attach_printable("Metadata has no EntityDescriptor");
+        };
+
+        let entity_id = entity
+            .attr("entityID")
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Metadata has no entityID")?;
+        let idp = entity
+            .child(MD_NS, "IDPSSODescriptor")
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Metadata has no IDPSSODescriptor")?;
+
+        let sso_url = idp
+            .children_named(MD_NS, "SingleSignOnService")
+            .find(|e| e.attr("Binding") == Some(BINDING_REDIRECT))
+            .and_then(|e| e.attr("Location"))
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable(
+                "Metadata has no SingleSignOnService with the HTTP-Redirect binding",
+            )?;
+
+        let certificate = idp
+            .children_named(MD_NS, "KeyDescriptor")
+            .filter(|e| matches!(e.attr("use"), None | Some("signing")))
+            .find_map(|e| e.descendant(DSIG_NS, "X509Certificate"))
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Metadata has no signing certificate")?;
+        let certificate = decode_base64(&certificate.text())
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Certificate is not valid base64")?;
+
+        let config = Self {
+            entity_id: entity_id.to_string(),
+            sso_url: sso_url.to_string(),
+            certificate,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The certificate, PEM-encoded
+    pub fn certificate_pem(&self) -> String {
+        let encoded = STANDARD.encode(&self.certificate);
+        let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+        // Base64 is ASCII, so splitting on any byte boundary is safe.
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+        pem
+    }
+
+    fn validate(&self) -> Result<(), Report<SamlError>> {
+        if self.entity_id.is_empty() {
+            return Err(Report::new(SamlError::InvalidConfig))
+                .attach_printable("Entity ID is empty");
+        }
+
+        url::Url::parse(&self.sso_url)
+            .change_context(SamlError::InvalidConfig)
+            .attach_printable("Invalid SSO URL")?;
+        self.public_key()?;
+        Ok(())
+    }
+
+    fn public_key(&self) -> Result<signature::PublicKey, Report<SamlError>> {
+        signature::certificate_public_key(&self.certificate)
+            .ok_or(SamlError::InvalidConfig)
+            .attach_printable("Certificate must contain an RSA, P-256, or P-384 public key")
+    }
+
+    /// Build the URL that sends the user to the IdP with a login request
+    pub fn authn_request_url(
+        &self,
+        sp_entity_id: &str,
+        acs_url: &str,
+        request_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<url::Url, Report<SamlError>> {
+        let request = format!(
+            r#"<samlp:AuthnRequest xmlns:samlp="{SAMLP_NS}" xmlns:saml="{SAML_NS}" ID="{id}" Version="2.0" IssueInstant="{instant}" Destination="{destination}" AssertionConsumerServiceURL="{acs_url}" ProtocolBinding="{BINDING_POST}"><saml:Issuer>{issuer}</saml:Issuer></samlp:AuthnRequest>"#,
+            id = escape(request_id),
+            instant = now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            destination = escape(&self.sso_url),
+            acs_url = escape(acs_url),
+            issuer = escape(sp_entity_id),
+        );
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(request.as_bytes())
+            .change_context(SamlError::InvalidConfig)?;
+        let deflated = encoder.finish().change_context(SamlError::InvalidConfig)?;
+
+        let mut url = url::Url::parse(&self.sso_url).change_context(SamlError::InvalidConfig)?;
+        url.query_pairs_mut()
+            .append_pair("SAMLRequest", &STANDARD.encode(deflated));
+        Ok(url)
+    }
+}
+
+/// The parts of an `AuthnRequest` that an identity provider needs to respond to it
+#[derive(Debug, Clone)]
+pub(crate) struct AuthnRequestInfo {
+    pub id: String,
+    pub issuer: String,
+    pub acs_url: String,
+}
+
+/// Read the `AuthnRequest` from a URL generated by [SamlIdpConfig::authn_request_url]. This is
+/// used by the test identity provider in [crate::testing].
+pub(crate) fn read_authn_request(url: &url::Url) -> Option<AuthnRequestInfo> {
+    use std::io::Read;
+
+    let (_, encoded) = url.query_pairs().find(|(key, _)| key == "SAMLRequest")?;
+    let deflated = STANDARD.decode(encoded.as_bytes()).ok()?;
+    let mut request = String::new();
+    flate2::read::DeflateDecoder::new(deflated.as_slice())
+        .read_to_string(&mut request)
+        .ok()?;
+
+    let request = xml::parse(&request).ok()?;
+    if !request.is(SAMLP_NS, "AuthnRequest") {
+        return None;
+    }
+
+    Some(AuthnRequestInfo {
+        id: request.attr("ID")?.to_string(),
+        issuer: request.child(SAML_NS, "Issuer")?.text(),
+        acs_url: request.attr("AssertionConsumerServiceURL")?.to_string(),
+    })
+}
+
+/// An attribute from a SAML assertion
+#[derive(Debug, Clone)]
+pub struct SamlAttribute {
+    /// The name of the attribute, often a URI
+    pub name: String,
+    /// A human-readable name for the attribute
+    pub friendly_name: Option<String>,
+    /// The values of the attribute
+    pub values: Vec<String>,
+}
+
+/// The information from a verified SAML assertion
+#[derive(Debug, Clone)]
+pub struct SamlAssertion {
+    /// The ID of the AuthnRequest that this is a response to
+    pub in_response_to: String,
+    /// The subject's name identifier, which is stable for a user at a particular IdP
+    pub name_id: String,
+    /// The format of `name_id`
+    pub name_id_format: Option<String>,
+    /// The attributes sent by the IdP
+    pub attributes: Vec<SamlAttribute>,
+}
+
+const EMAIL_ATTRIBUTES: &[&str] = &[
+    "email",
+    "mail",
+    "emailaddress",
+    "urn:oid:0.9.2342.19200300.100.1.3",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+];
+
+const NAME_ATTRIBUTES: &[&str] = &[
+    "displayName",
+    "name",
+    "urn:oid:2.16.840.1.113730.3.1.241",
+    "http://schemas.microsoft.com/identity/claims/displayname",
+];
+
+const GIVEN_NAME_ATTRIBUTES: &[&str] = &[
+    "givenName",
+    "firstName",
+    "urn:oid:2.5.4.42",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/givenname",
+];
+
+const SURNAME_ATTRIBUTES: &[&str] = &[
+    "sn",
+    "surname",
+    "lastName",
+    "urn:oid:2.5.4.4",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/surname",
+];
+
+impl SamlAssertion {
+    /// Return the first value of the first attribute matching one of `names`. Names are compared
+    /// case-insensitively against both the name and friendly name of each attribute.
+    pub fn attribute(&self, names: &[&str]) -> Option<&str> {
+        names.iter().find_map(|name| {
+            self.attributes
+                .iter()
+                .find(|attr| {
+                    attr.name.eq_ignore_ascii_case(name)
+                        || attr
+                            .friendly_name
+                            .as_deref()
+                            .is_some_and(|f| f.eq_ignore_ascii_case(name))
+                })
+                .and_then(|attr| attr.values.first())
+                .map(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+        })
+    }
+
+    /// The user's email address, from the common email attributes or the NameID if it looks like
+    /// an email address.
+    pub fn email(&self) -> Option<String> {
+        self.attribute(EMAIL_ATTRIBUTES)
+            .or_else(|| self.name_id.contains('@').then_some(self.name_id.as_str()))
+            .map(|email| email.to_string())
+    }
+
+    /// The user's name, from the common display name attributes or the given name and surname.
+    pub fn name(&self) -> Option<String> {
+        if let Some(name) = self.attribute(NAME_ATTRIBUTES) {
+            return Some(name.to_string());
+        }
+
+        match (
+            self.attribute(GIVEN_NAME_ATTRIBUTES),
+            self.attribute(SURNAME_ATTRIBUTES),
+        ) {
+            (Some(given), Some(surname)) => Some(format!("{given} {surname}")),
+            (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+            (None, None) => None,
+        }
+    }
+}
+
+fn invalid(message: &'static str) -> Report<SamlError> {
+    Report::new(SamlError::InvalidResponse).attach_printable(message)
+}
+
+fn parse_time(value: Option<&str>) -> Result<Option<DateTime<Utc>>, Report<SamlError>> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|_| invalid("Invalid timestamp"))
+        })
+        .transpose()
+}
+
+/// Verify a base64-encoded SAML response sent to the ACS URL, and return the assertion inside it.
+///
+/// The caller must also check that `in_response_to` on the result matches a login request that
+/// was started by this service provider for the same organization.
+pub fn validate_response(
+    encoded_response: &str,
+    idp: &SamlIdpConfig,
+    sp_entity_id: &str,
+    acs_url: &str,
+    now: DateTime<Utc>,
+) -> Result<SamlAssertion, Report<SamlError>> {
+    let key = idp.public_key()?;
+    let response = decode_base64(encoded_response)
+        .and_then(|r| String::from_utf8(r).ok())
+        .ok_or_else(|| invalid("Response is not valid base64"))?;
+    let response = xml::parse(&response).change_context(SamlError::InvalidResponse)?;
+
+    if !response.is(SAMLP_NS, "Response") {
+        return Err(invalid("Root element is not a Response"));
+    }
+
+    let status = response
+        .child(SAMLP_NS, "Status")
+        .and_then(|s| s.child(SAMLP_NS, "StatusCode"))
+        .and_then(|s| s.attr("Value"));
+    if status != Some(STATUS_SUCCESS) {
+        return Err(invalid("Response status is not Success"))
+            .attach_printable(status.unwrap_or_default().to_string());
+    }
+
+    if response
+        .attr("Destination")
+        .is_some_and(|dest| dest != acs_url)
+    {
+        return Err(invalid("Response Destination does not match the ACS URL"));
+    }
+
+    if response
+        .child(SAML_NS, "Issuer")
+        .is_some_and(|issuer| issuer.text() != idp.entity_id)
+    {
+        return Err(invalid("Response Issuer does not match the IdP"));
+    }
+
+    let in_response_to = response
+        .attr("InResponseTo")
+        .ok_or_else(|| invalid("Unsolicited responses are not supported"))?;
+
+    if response.child(SAML_NS, "EncryptedAssertion").is_some() {
+        return Err(invalid("Encrypted assertions are not supported"));
+    }
+
+    let mut assertions = response.children_named(SAML_NS, "Assertion");
+    let (Some(assertion), None) = (assertions.next(), assertions.next()) else {
+        return Err(invalid("Response must contain exactly one assertion"));
+    };
+
+    // Checking the signatures before reading anything else from the assertion ensures that only
+    // signed data is used.
+    let response_signed = signature::verify_enveloped_signature(&response, &key)?;
+    let assertion_signed = signature::verify_enveloped_signature(assertion, &key)?;
+    if !response_signed && !assertion_signed {
+        return Err(invalid("Neither the response nor the assertion is signed"));
+    }
+
+    read_assertion(assertion, idp, sp_entity_id, acs_url, in_response_to, now)
+}
+
+fn read_assertion(
+    assertion: &Element,
+    idp: &SamlIdpConfig,
+    sp_entity_id: &str,
+    acs_url: &str,
+    in_response_to: &str,
+    now: DateTime<Utc>,
+) -> Result<SamlAssertion, Report<SamlError>> {
+    let issuer = assertion
+        .child(SAML_NS, "Issuer")
+        .ok_or_else(|| invalid("Assertion has no Issuer"))?;
+    if issuer.text() != idp.entity_id {
+        return Err(invalid("Assertion Issuer does not match the IdP"));
+    }
+
+    let conditions = assertion
+        .child(SAML_NS, "Conditions")
+        .ok_or_else(|| invalid("Assertion has no Conditions"))?;
+    if parse_time(conditions.attr("NotBefore"))?.is_some_and(|t| now + CLOCK_SKEW < t) {
+        return Err(invalid("Assertion is not yet valid"));
+    }
+    if parse_time(conditions.attr("NotOnOrAfter"))?.is_some_and(|t| now - CLOCK_SKEW >= t) {
+        return Err(invalid("Assertion has expired"));
+    }
+
+    let mut audience_restrictions = conditions
+        .children_named(SAML_NS, "AudienceRestriction")
+        .peekable();
+    if audience_restrictions.peek().is_none() {
+        return Err(invalid("Assertion has no AudienceRestriction"));
+    }
+    for restriction in audience_restrictions {
+        if !restriction
+            .children_named(SAML_NS, "Audience")
+            .any(|audience| audience.text() == sp_entity_id)
+        {
+            return Err(invalid(
+                "Assertion is not intended for this service provider",
+            ));
+        }
+    }
+
+    let subject = assertion
+        .child(SAML_NS, "Subject")
+        .ok_or_else(|| invalid("Assertion has no Subject"))?;
+
+    let mut confirmed = false;
+    for confirmation in subject.children_named(SAML_NS, "SubjectConfirmation") {
+        if confirmation.attr("Method") != Some(CONFIRMATION_BEARER) {
+            continue;
+        }
+
+        let Some(data) = confirmation.child(SAML_NS, "SubjectConfirmationData") else {
+            continue;
+        };
+
+        // InResponseTo is required here, and not just on the response, since the response itself
+        // may not be signed.
+        let not_on_or_after = parse_time(data.attr("NotOnOrAfter"))?;
+        confirmed = data.attr("Recipient") == Some(acs_url)
+            && data.attr("InResponseTo") == Some(in_response_to)
+            && not_on_or_after.is_some_and(|t| now - CLOCK_SKEW < t);
+        if confirmed {
+            break;
+        }
+    }
+
+    if !confirmed {
+        return Err(invalid("Assertion has no valid bearer SubjectConfirmation"));
+    }
+
+    let name_id = subject
+        .child(SAML_NS, "NameID")
+        .ok_or_else(|| invalid("Assertion has no NameID"))?;
+    let name_id_value = name_id.text();
+    if name_id_value.is_empty() {
+        return Err(invalid("Assertion has an empty NameID"));
+    }
+
+    if assertion.child(SAML_NS, "AuthnStatement").is_none() {
+        return Err(invalid("Assertion has no AuthnStatement"));
+    }
+
+    let attributes = assertion
+        .children_named(SAML_NS, "AttributeStatement")
+        .flat_map(|statement| statement.children_named(SAML_NS, "Attribute"))
+        .filter_map(|attr| {
+            Some(SamlAttribute {
+                name: attr.attr("Name")?.to_string(),
+                friendly_name: attr.attr("FriendlyName").map(String::from),
+                values: attr
+                    .children_named(SAML_NS, "AttributeValue")
+                    .map(|value| value.text())
+                    .collect(),
+            })
+        })
+        .collect();
+
+    Ok(SamlAssertion {
+        in_response_to: in_response_to.to_string(),
+        name_id: name_id_value,
+        name_id_format: name_id.attr("Format").map(String::from),
+        attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestSamlIdp;
+
+    const RSA_RESPONSE: &str = include_str!("saml/fixtures/rsa_response.xml");
+    const IDP_METADATA: &str = include_str!("saml/fixtures/idp_metadata.xml");
+    const SP_ENTITY_ID: &str = "https://sp.example.com/saml/metadata";
+    const ACS_URL: &str = "https://sp.example.com/saml/acs";
+
+    fn fixture_time() -> DateTime<Utc> {
+        "2024-01-01T00:01:00Z".parse().unwrap()
+    }
+
+    fn validate_fixture(
+        response: &str,
+        now: DateTime<Utc>,
+    ) -> Result<SamlAssertion, Report<SamlError>> {
+        let idp = SamlIdpConfig::from_metadata(IDP_METADATA).unwrap();
+        validate_response(&STANDARD.encode(response), &idp, SP_ENTITY_ID, ACS_URL, now)
+    }
+
+    #[test]
+    fn idp_metadata() {
+        let idp = SamlIdpConfig::from_metadata(IDP_METADATA).unwrap();
+        assert_eq!(idp.entity_id, "https://idp.example.com/metadata");
+        assert_eq!(idp.sso_url, "https://idp.example.com/sso/redirect");
+
+        let pem = IDP_METADATA
+            .split("<ds:X509Certificate>")
+            .nth(1)
+            .and_then(|s| s.split("</ds:X509Certificate>").next())
+            .unwrap();
+        let from_parts = SamlIdpConfig::new(
+            "https://idp.example.com/metadata",
+            "https://idp.example.com/sso/redirect",
+            &format!("-----BEGIN CERTIFICATE-----\n{pem}\n-----END CERTIFICATE-----"),
+        )
+        .unwrap();
+        assert_eq!(from_parts, idp);
+
+        let round_trip =
+            SamlIdpConfig::new(&idp.entity_id, &idp.sso_url, &idp.certificate_pem()).unwrap();
+        assert_eq!(round_trip, idp);
+
+        let err = SamlIdpConfig::new("https://idp.example.com", "not a url", pem).unwrap_err();
+        assert!(matches!(err.current_context(), SamlError::InvalidConfig));
+        let err = SamlIdpConfig::new("https://idp.example.com", "https://idp.example.com", "AAAA")
+            .unwrap_err();
+        assert!(matches!(err.current_context(), SamlError::InvalidConfig));
+    }
+
+    #[test]
+    fn valid_rsa_response() {
+        let assertion = validate_fixture(RSA_RESPONSE, fixture_time()).unwrap();
+        assert_eq!(assertion.in_response_to, "_request1");
+        assert_eq!(assertion.name_id, "user-1234");
+        assert_eq!(
+            assertion.name_id_format.as_deref(),
+            Some("urn:oasis:names:tc:SAML:2.0:nameid-format:persistent")
+        );
+        assert_eq!(assertion.email().as_deref(), Some("ada@example.com"));
+        assert_eq!(assertion.name().as_deref(), Some("Ada Lovelace"));
+    }
+
+    #[test]
+    fn rejected_responses() {
+        let now = fixture_time();
+        let cases = [
+            (
+                "tampered",
+                RSA_RESPONSE.replace("ada@example.com", "eve@example.com"),
+                now,
+            ),
+            (
+                "expired",
+                RSA_RESPONSE.to_string(),
+                "2024-01-01T00:10:00Z".parse().unwrap(),
+            ),
+            (
+                "not yet valid",
+                RSA_RESPONSE.to_string(),
+                "2023-12-31T23:50:00Z".parse().unwrap(),
+            ),
+            (
+                "wrong destination",
+                RSA_RESPONSE.replace(
+                    r#"Destination="https://sp.example.com/saml/acs""#,
+                    r#"Destination="https://other.example.com/saml/acs""#,
+                ),
+                now,
+            ),
+            (
+                "unsigned",
+                RSA_RESPONSE
+                    .split("<ds:Signature")
+                    .next()
+                    .map(|start| {
+                        let end = RSA_RESPONSE.split("</ds:Signature>").nth(1).unwrap();
+                        format!("{start}{end}")
+                    })
+                    .unwrap(),
+                now,
+            ),
+            (
+                "failed status",
+                RSA_RESPONSE.replace("status:Success", "status:Responder"),
+                now,
+            ),
+            (
+                "extra assertion",
+                RSA_RESPONSE.replace(
+                    "</samlp:Status>",
+                    r#"</samlp:Status><saml:Assertion ID="_evil"><saml:Issuer>https://idp.example.com/metadata</saml:Issuer></saml:Assertion>"#,
+                ),
+                now,
+            ),
+        ];
+
+        for (desc, response, now) in cases {
+            let err = validate_fixture(&response, now).expect_err(desc);
+            assert!(
+                matches!(err.current_context(), SamlError::InvalidResponse),
+                "{desc}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_service_provider() {
+        let idp = SamlIdpConfig::from_metadata(IDP_METADATA).unwrap();
+        let other_idp = SamlIdpConfig {
+            entity_id: "https://other-idp.example.com".to_string(),
+            ..idp.clone()
+        };
+        let other_key =
+            SamlIdpConfig::from_metadata(&TestSamlIdp::new(&idp.entity_id).metadata()).unwrap();
+
+        let cases = [
+            (
+                "wrong audience",
+                &idp,
+                "https://other.example.com/saml/metadata",
+                ACS_URL,
+            ),
+            (
+                "wrong ACS URL",
+                &idp,
+                SP_ENTITY_ID,
+                "https://other.example.com/saml/acs",
+            ),
+            ("wrong issuer", &other_idp, SP_ENTITY_ID, ACS_URL),
+            ("wrong certificate", &other_key, SP_ENTITY_ID, ACS_URL),
+        ];
+
+        let response = STANDARD.encode(RSA_RESPONSE);
+        for (desc, idp, sp_entity_id, acs_url) in cases {
+            let err = validate_response(&response, idp, sp_entity_id, acs_url, fixture_time())
+                .expect_err(desc);
+            assert!(
+                matches!(err.current_context(), SamlError::InvalidResponse),
+                "{desc}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_idp_round_trip() {
+        let test_idp = TestSamlIdp::new("https://idp.example.com");
+        let idp = SamlIdpConfig::from_metadata(&test_idp.metadata()).unwrap();
+        assert_eq!(idp.sso_url, TestSamlIdp::SSO_URL);
+
+        let url = idp
+            .authn_request_url(SP_ENTITY_ID, ACS_URL, "_request2", Utc::now())
+            .unwrap();
+        let request = read_authn_request(&url).unwrap();
+        assert_eq!(request.id, "_request2");
+        assert_eq!(request.issuer, SP_ENTITY_ID);
+        assert_eq!(request.acs_url, ACS_URL);
+
+        let response = test_idp.respond(
+            url.as_str(),
+            "user-5678",
+            &[("givenName", "Grace"), ("sn", "Hopper")],
+        );
+        let assertion =
+            validate_response(&response, &idp, SP_ENTITY_ID, ACS_URL, Utc::now()).unwrap();
+        assert_eq!(assertion.in_response_to, "_request2");
+        assert_eq!(assertion.name_id, "user-5678");
+        assert_eq!(assertion.email(), None);
+        assert_eq!(assertion.name().as_deref(), Some("Grace Hopper"));
+    }
+
+    #[test]
+    fn sp_metadata() {
+        let config = SamlConfig::new("https://app.example.com/api/");
+        let org = OrganizationId::new();
+        let metadata = xml::parse(&config.metadata_xml(org)).unwrap();
+        assert_eq!(
+            metadata.attr("entityID"),
+            Some(config.entity_id(org).as_str())
+        );
+        assert_eq!(
+            config.entity_id(org),
+            format!("https://app.example.com/api/auth/saml/{org}/metadata")
+        );
+
+        let acs = metadata
+            .descendant(MD_NS, "AssertionConsumerService")
+            .unwrap();
+        assert_eq!(acs.attr("Location"), Some(config.acs_url(org).as_str()));
+        assert_eq!(acs.attr("Binding"), Some(BINDING_POST));
+    }
+}
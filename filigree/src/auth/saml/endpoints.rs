@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    response::{Html, IntoResponse, Redirect},
+    routing, Form, Router,
+};
+use http::header::CONTENT_TYPE;
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use tower_cookies::Cookies;
+use tracing::instrument;
+
+use super::{finish_saml_login, start_saml_login, SamlError};
+use crate::{auth::OrganizationId, errors::WrapReport, server::FiligreeState};
+
+/// Return the service provider metadata for an organization
+#[instrument(skip(state))]
+pub async fn metadata(
+    State(state): State<Arc<FiligreeState>>,
+    Path(organization_id): Path<OrganizationId>,
+) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "application/samlmetadata+xml")],
+        state.saml.metadata_xml(organization_id),
+    )
+}
+
+/// Query string for starting a SAML login
+#[derive(Debug, Deserialize)]
+pub struct SamlLoginQuery {
+    redirect_to: Option<String>,
+}
+
+/// Start a SAML login by redirecting to the organization's identity provider
+#[instrument(skip(state))]
+pub async fn login(
+    State(state): State<Arc<FiligreeState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(query): Query<SamlLoginQuery>,
+) -> Result<impl IntoResponse, WrapReport<SamlError>> {
+    let url = start_saml_login(&state, organization_id, query.redirect_to).await?;
+    Ok(Redirect::to(url.as_str()))
+}
+
+/// The form posted by the identity provider to the ACS URL. The redirect after login is stored
+/// with the request, so `RelayState` is not used.
+#[derive(Debug, Deserialize)]
+pub struct AcsForm {
+    #[serde(rename = "SAMLResponse")]
+    saml_response: String,
+}
+
+/// Receive a SAML response from the identity provider and log in the user
+#[instrument(skip(state, cookies, form))]
+pub async fn acs(
+    State(state): State<Arc<FiligreeState>>,
+    cookies: Cookies,
+    Path(organization_id): Path<OrganizationId>,
+    Form(form): Form<AcsForm>,
+) -> Result<impl IntoResponse, WrapReport<SamlError>> {
+    let response =
+        finish_saml_login(&state, &cookies, organization_id, &form.saml_response).await?;
+
+    // This request is a cross-site POST from the IdP, so a normal redirect would not carry a
+    // SameSite=Strict session cookie. Redirecting from a page on this site avoids that.
+    let redirect_to = response.redirect_to.unwrap_or_else(|| "/".to_string());
+    Ok(Html(format!(
+        r#"<!DOCTYPE html><html><head><meta http-equiv="refresh" content="0;url={0}"></head><body><a href="{0}">Continue</a></body></html>"#,
+        escape(&redirect_to)
+    )))
+}
+
+/// Create the SAML endpoints
+///
+/// - GET /auth/saml/:organization_id/metadata returns the service provider metadata
+/// - GET /auth/saml/:organization_id/login wraps `start_saml_login`
+/// - POST /auth/saml/:organization_id/acs wraps `finish_saml_login`
+pub fn create_routes<T>() -> Router<T>
+where
+    Arc<FiligreeState>: FromRef<T> + Clone,
+    T: Send + Sync + Clone + 'static,
+{
+    Router::new()
+        .route(
+            "/auth/saml/:organization_id/metadata",
+            routing::get(metadata),
+        )
+        .route("/auth/saml/:organization_id/login", routing::get(login))
+        .route("/auth/saml/:organization_id/acs", routing::post(acs))
+}
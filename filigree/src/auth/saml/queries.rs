@@ -0,0 +1,425 @@
+use chrono::Utc;
+use error_stack::{Report, ResultExt};
+use tower_cookies::Cookies;
+
+use super::{validate_response, SamlAssertion, SamlError, SamlIdpConfig};
+use crate::{
+    auth::{oauth::add_oauth_login, AuthError, OrganizationId, UserId},
+    db::DbPool,
+    server::FiligreeState,
+    users::users::CreateUserDetails,
+};
+
+/// How long the user has to finish logging in at the identity provider
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+#[derive(sqlx::FromRow)]
+struct SamlProviderRow {
+    idp_entity_id: String,
+    idp_sso_url: String,
+    idp_certificate: Vec<u8>,
+}
+
+/// The key used to link a SAML NameID to a user in the `oauth_logins` table. NameIDs are only
+/// unique within an IdP, so this is specific to the organization.
+fn login_provider_name(organization_id: OrganizationId) -> String {
+    format!("saml:{}", organization_id.as_uuid())
+}
+
+/// Get the SAML identity provider configured for an organization
+pub async fn get_saml_provider(
+    db: &DbPool,
+    organization_id: OrganizationId,
+) -> Result<Option<SamlIdpConfig>, Report<SamlError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let row = sqlx::query_as!(
+        SamlProviderRow,
+        "SELECT idp_entity_id, idp_sso_url, idp_certificate
+        FROM organization_saml_providers
+        WHERE organization_id = $1",
+        organization_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let row = sqlx::query_as::<_, SamlProviderRow>(
+        "SELECT idp_entity_id, idp_sso_url, idp_certificate
+        FROM organization_saml_providers
+        WHERE organization_id = ?1",
+    )
+    .bind(organization_id.as_uuid())
+    .fetch_optional(db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    Ok(row.map(|row| SamlIdpConfig {
+        entity_id: row.idp_entity_id,
+        sso_url: row.idp_sso_url,
+        certificate: row.idp_certificate,
+    }))
+}
+
+/// Set the SAML identity provider for an organization, replacing any existing configuration
+pub async fn save_saml_provider(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    config: &SamlIdpConfig,
+) -> Result<(), Report<SamlError>> {
+    #[cfg(not(feature = "sqlite"))]
+    sqlx::query!(
+        "INSERT INTO organization_saml_providers
+            (organization_id, idp_entity_id, idp_sso_url, idp_certificate)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (organization_id) DO UPDATE SET
+            idp_entity_id = EXCLUDED.idp_entity_id,
+            idp_sso_url = EXCLUDED.idp_sso_url,
+            idp_certificate = EXCLUDED.idp_certificate,
+            updated_at = now()",
+        organization_id.as_uuid(),
+        &config.entity_id,
+        &config.sso_url,
+        &config.certificate,
+    )
+    .execute(db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "INSERT INTO organization_saml_providers
+            (organization_id, idp_entity_id, idp_sso_url, idp_certificate)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (organization_id) DO UPDATE SET
+            idp_entity_id = EXCLUDED.idp_entity_id,
+            idp_sso_url = EXCLUDED.idp_sso_url,
+            idp_certificate = EXCLUDED.idp_certificate,
+            updated_at = unixepoch()",
+    )
+    .bind(organization_id.as_uuid())
+    .bind(&config.entity_id)
+    .bind(&config.sso_url)
+    .bind(&config.certificate)
+    .execute(db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    Ok(())
+}
+
+/// Remove the SAML identity provider from an organization. Returns false if the organization
+/// did not have one.
+pub async fn delete_saml_provider(
+    db: &DbPool,
+    organization_id: OrganizationId,
+) -> Result<bool, Report<SamlError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "DELETE FROM organization_saml_providers WHERE organization_id = $1",
+        organization_id.as_uuid()
+    )
+    .execute(db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query("DELETE FROM organization_saml_providers WHERE organization_id = ?1")
+        .bind(organization_id.as_uuid())
+        .execute(db)
+        .await
+        .change_context(SamlError::Db)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Return true if the user belongs to an organization that requires SAML login, and so can not
+/// log in any other way. The requirement only applies once the organization has an identity
+/// provider configured.
+pub async fn sso_required(db: &DbPool, user_id: &UserId) -> Result<bool, Report<AuthError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let required = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1
+            FROM organization_members om
+            JOIN organizations o ON o.id = om.organization_id
+            JOIN organization_saml_providers sp ON sp.organization_id = om.organization_id
+            WHERE om.user_id = $1 AND om.active AND o.require_sso
+        ) AS "required!""#,
+        user_id.as_uuid()
+    )
+    .fetch_one(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let required = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(
+            SELECT 1
+            FROM organization_members om
+            JOIN organizations o ON o.id = om.organization_id
+            JOIN organization_saml_providers sp ON sp.organization_id = om.organization_id
+            WHERE om.user_id = ?1 AND om.active AND o.require_sso
+        )",
+    )
+    .bind(user_id.as_uuid())
+    .fetch_one(db)
+    .await
+    .change_context(AuthError::Db)?;
+
+    Ok(required)
+}
+
+/// Only allow redirects to paths on this site after login.
+fn is_local_redirect(redirect_to: &str) -> bool {
+    redirect_to.starts_with('/') && !redirect_to.starts_with("//") && !redirect_to.contains('\\')
+}
+
+/// Start a SAML login for an organization, and return the URL of the identity provider to send
+/// the user to. After logging in, the user will be sent to `redirect_to`, which must be a path
+/// on this site.
+pub async fn start_saml_login(
+    state: &FiligreeState,
+    organization_id: OrganizationId,
+    redirect_to: Option<String>,
+) -> Result<url::Url, Report<SamlError>> {
+    let idp = get_saml_provider(&state.db, organization_id)
+        .await?
+        .ok_or(SamlError::NotConfigured)?;
+
+    let redirect_to = redirect_to.filter(|r| is_local_redirect(r));
+    // IDs must start with a letter or underscore
+    let request_id = format!("_{}", uuid::Uuid::new_v4().simple());
+    let url = idp.authn_request_url(
+        &state.saml.entity_id(organization_id),
+        &state.saml.acs_url(organization_id),
+        &request_id,
+        Utc::now(),
+    )?;
+
+    #[cfg(not(feature = "sqlite"))]
+    {
+        sqlx::query!("DELETE FROM saml_requests WHERE expires_at < now()")
+            .execute(&state.db)
+            .await
+            .change_context(SamlError::Db)?;
+
+        sqlx::query!(
+            "INSERT INTO saml_requests (id, organization_id, redirect_to, expires_at)
+            VALUES ($1, $2, $3, now() + $4)",
+            &request_id,
+            organization_id.as_uuid(),
+            redirect_to,
+            REQUEST_TIMEOUT as _
+        )
+        .execute(&state.db)
+        .await
+        .change_context(SamlError::Db)?;
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        sqlx::query("DELETE FROM saml_requests WHERE expires_at < unixepoch()")
+            .execute(&state.db)
+            .await
+            .change_context(SamlError::Db)?;
+
+        sqlx::query(
+            "INSERT INTO saml_requests (id, organization_id, redirect_to, expires_at)
+            VALUES (?1, ?2, ?3, unixepoch() + ?4)",
+        )
+        .bind(&request_id)
+        .bind(organization_id.as_uuid())
+        .bind(redirect_to)
+        .bind(REQUEST_TIMEOUT.as_secs() as i64)
+        .execute(&state.db)
+        .await
+        .change_context(SamlError::Db)?;
+    }
+
+    Ok(url)
+}
+
+/// A successful SAML login
+pub struct SamlLoginResponse {
+    /// The user that logged in
+    pub user_id: UserId,
+    /// True if the user was created by this login
+    pub new_user: bool,
+    /// The verified assertion from the identity provider
+    pub assertion: SamlAssertion,
+    /// The URL to redirect the user to after login
+    pub redirect_to: Option<String>,
+}
+
+/// Handle a SAML response posted to the ACS URL, and log in the user.
+///
+/// The user is found by the NameID that the IdP sent, or by email address if they have not used
+/// SAML before. Users are only matched by email if they are already members of the organization.
+/// If no user matches, a new user is created in the organization using the [UserCreator](crate::users::users::UserCreator).
+/// This happens even when public signups are disabled, since the organization's IdP controls who
+/// can log in.
+pub async fn finish_saml_login(
+    state: &FiligreeState,
+    cookies: &Cookies,
+    organization_id: OrganizationId,
+    saml_response: &str,
+) -> Result<SamlLoginResponse, Report<SamlError>> {
+    let idp = get_saml_provider(&state.db, organization_id)
+        .await?
+        .ok_or(SamlError::NotConfigured)?;
+
+    let assertion = validate_response(
+        saml_response,
+        &idp,
+        &state.saml.entity_id(organization_id),
+        &state.saml.acs_url(organization_id),
+        Utc::now(),
+    )?;
+
+    // Consume the request so that the response can not be replayed.
+    #[cfg(not(feature = "sqlite"))]
+    let request = sqlx::query!(
+        "DELETE FROM saml_requests
+        WHERE id = $1 AND organization_id = $2
+        RETURNING redirect_to, expires_at",
+        &assertion.in_response_to,
+        organization_id.as_uuid()
+    )
+    .fetch_optional(&state.db)
+    .await
+    .change_context(SamlError::Db)?
+    .map(|r| (r.redirect_to, r.expires_at));
+
+    #[cfg(feature = "sqlite")]
+    let request = sqlx::query_as::<_, (Option<String>, chrono::DateTime<Utc>)>(
+        "DELETE FROM saml_requests
+        WHERE id = ?1 AND organization_id = ?2
+        RETURNING redirect_to, expires_at",
+    )
+    .bind(&assertion.in_response_to)
+    .bind(organization_id.as_uuid())
+    .fetch_optional(&state.db)
+    .await
+    .change_context(SamlError::Db)?;
+
+    let redirect_to = match request {
+        Some((redirect_to, expires_at)) if expires_at > Utc::now() => redirect_to,
+        _ => return Err(Report::new(SamlError::RequestNotFound)),
+    };
+
+    let provider_name = login_provider_name(organization_id);
+    let email = assertion.email();
+
+    let mut tx = state.db.begin().await.change_context(SamlError::Db)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let existing_user = sqlx::query_scalar!(
+        r#"SELECT user_id AS "user_id: UserId" FROM oauth_logins
+        WHERE oauth_provider = $1 AND oauth_account_id = $2"#,
+        &provider_name,
+        &assertion.name_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(SamlError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    let existing_user = sqlx::query_scalar::<_, UserId>(
+        "SELECT user_id FROM oauth_logins
+        WHERE oauth_provider = ?1 AND oauth_account_id = ?2",
+    )
+    .bind(&provider_name)
+    .bind(&assertion.name_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(SamlError::Db)?;
+
+    let email_user = match (existing_user, email.as_ref()) {
+        (None, Some(email)) => {
+            #[cfg(not(feature = "sqlite"))]
+            let result = sqlx::query!(
+                r#"SELECT el.user_id AS "user_id: UserId",
+                    EXISTS(
+                        SELECT 1 FROM organization_members om
+                        WHERE om.user_id = el.user_id AND om.organization_id = $2
+                    ) AS "is_member!"
+                FROM email_logins el
+                WHERE el.email = $1"#,
+                email,
+                organization_id.as_uuid()
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .change_context(SamlError::Db)?
+            .map(|r| (r.user_id, r.is_member));
+
+            #[cfg(feature = "sqlite")]
+            let result = sqlx::query_as::<_, (UserId, bool)>(
+                "SELECT el.user_id,
+                    EXISTS(
+                        SELECT 1 FROM organization_members om
+                        WHERE om.user_id = el.user_id AND om.organization_id = ?2
+                    )
+                FROM email_logins el
+                WHERE el.email = ?1",
+            )
+            .bind(email)
+            .bind(organization_id.as_uuid())
+            .fetch_optional(&mut *tx)
+            .await
+            .change_context(SamlError::Db)?;
+
+            result
+        }
+        _ => None,
+    };
+
+    let (user_id, new_user) = match (existing_user, email_user) {
+        (Some(user_id), _) => (user_id, false),
+        (None, Some((user_id, true))) => {
+            // A member of the organization logging in with SAML for the first time
+            add_oauth_login(&mut *tx, user_id, &provider_name, &assertion.name_id)
+                .await
+                .change_context(SamlError::Db)?;
+            (user_id, false)
+        }
+        (None, Some((_, false))) => {
+            // Don't let an organization's IdP take over accounts outside the organization.
+            return Err(Report::new(SamlError::EmailInUse));
+        }
+        (None, None) => {
+            let details = CreateUserDetails {
+                email,
+                name: assertion.name(),
+                avatar_url: None,
+                password_plaintext: None,
+            };
+
+            let user_id = state
+                .user_creator
+                .create_user(&mut tx, Some(organization_id), details)
+                .await
+                .change_context(SamlError::UserCreation)?;
+            add_oauth_login(&mut *tx, user_id, &provider_name, &assertion.name_id)
+                .await
+                .change_context(SamlError::Db)?;
+            (user_id, true)
+        }
+    };
+
+    tx.commit().await.change_context(SamlError::Db)?;
+
+    state
+        .session_backend
+        .create_session(cookies, &user_id)
+        .await
+        .change_context(SamlError::SessionBackend)?;
+
+    Ok(SamlLoginResponse {
+        user_id,
+        new_user,
+        assertion,
+        redirect_to,
+    })
+}
@@ -0,0 +1,321 @@
+//! Verification of enveloped XML signatures, as used in SAML responses.
+//!
+//! Signatures are only checked against the certificate configured for the identity provider. Any
+//! certificate or key included in the signature itself is ignored.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use error_stack::{Report, ResultExt};
+use ring::{digest, signature};
+
+use super::{
+    xml::{canonicalize, parse, Canonicalization, Element},
+    SamlError, DSIG_NS,
+};
+
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const C14N: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+/// DER-encoded OID for rsaEncryption
+const OID_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// DER-encoded OID for id-ecPublicKey
+const OID_EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// DER-encoded OID for the P-256 curve
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+/// DER-encoded OID for the P-384 curve
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyType {
+    Rsa,
+    EcP256,
+    EcP384,
+}
+
+/// The public key from an X.509 certificate
+#[derive(Debug, Clone)]
+pub(super) struct PublicKey {
+    key_type: KeyType,
+    /// The contents of the certificate's subjectPublicKey field
+    key: Vec<u8>,
+}
+
+fn invalid(message: &'static str) -> Report<SamlError> {
+    Report::new(SamlError::InvalidResponse).attach_printable(message)
+}
+
+/// Read a DER element, and return its tag, contents, and the remaining input.
+fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&len_byte, mut input) = input.split_first()?;
+
+    let len = if len_byte < 0x80 {
+        len_byte as usize
+    } else {
+        let num_bytes = (len_byte & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > 4 || input.len() < num_bytes {
+            return None;
+        }
+
+        let len = input[..num_bytes]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        input = &input[num_bytes..];
+        len
+    };
+
+    if input.len() < len {
+        return None;
+    }
+
+    Some((tag, &input[..len], &input[len..]))
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+const DER_CONTEXT_0: u8 = 0xa0;
+
+/// Extract the public key from a DER-encoded X.509 certificate
+pub(super) fn certificate_public_key(cert: &[u8]) -> Option<PublicKey> {
+    let (DER_SEQUENCE, cert, _) = der_read(cert)? else {
+        return None;
+    };
+    let (DER_SEQUENCE, tbs, _) = der_read(cert)? else {
+        return None;
+    };
+
+    // Skip the optional version, then the serial number, signature algorithm, issuer, validity,
+    // and subject.
+    let mut rest = tbs;
+    if rest.first() == Some(&DER_CONTEXT_0) {
+        rest = der_read(rest)?.2;
+    }
+    for _ in 0..5 {
+        rest = der_read(rest)?.2;
+    }
+
+    let (DER_SEQUENCE, spki, _) = der_read(rest)? else {
+        return None;
+    };
+    let (DER_SEQUENCE, algorithm, spki_rest) = der_read(spki)? else {
+        return None;
+    };
+    let (DER_BIT_STRING, key, _) = der_read(spki_rest)? else {
+        return None;
+    };
+    // The first byte of a bit string is the number of unused bits, which is always 0 for keys.
+    let (0, key) = key.split_first()? else {
+        return None;
+    };
+
+    let (DER_OID, key_oid, params) = der_read(algorithm)? else {
+        return None;
+    };
+    let key_type = if key_oid == OID_RSA {
+        KeyType::Rsa
+    } else if key_oid == OID_EC {
+        match der_read(params)? {
+            (DER_OID, OID_P256, _) => KeyType::EcP256,
+            (DER_OID, OID_P384, _) => KeyType::EcP384,
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+
+    Some(PublicKey {
+        key_type,
+        key: key.to_vec(),
+    })
+}
+
+/// Decode base64 that may contain line breaks and other whitespace
+pub(super) fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    let value = value
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>();
+    STANDARD.decode(value).ok()
+}
+
+fn digest_algorithm(uri: &str) -> Result<&'static digest::Algorithm, Report<SamlError>> {
+    match uri {
+        "http://www.w3.org/2001/04/xmlenc#sha256" => Ok(&digest::SHA256),
+        "http://www.w3.org/2001/04/xmldsig-more#sha384" => Ok(&digest::SHA384),
+        "http://www.w3.org/2001/04/xmlenc#sha512" => Ok(&digest::SHA512),
+        _ => Err(invalid("Unsupported digest algorithm")).attach_printable(uri.to_string()),
+    }
+}
+
+fn verification_algorithm(
+    uri: &str,
+    key_type: KeyType,
+) -> Result<&'static dyn signature::VerificationAlgorithm, Report<SamlError>> {
+    let alg: &'static dyn signature::VerificationAlgorithm = match (uri, key_type) {
+        ("http://www.w3.org/2001/04/xmldsig-more#rsa-sha256", KeyType::Rsa) => {
+            &signature::RSA_PKCS1_2048_8192_SHA256
+        }
+        ("http://www.w3.org/2001/04/xmldsig-more#rsa-sha384", KeyType::Rsa) => {
+            &signature::RSA_PKCS1_2048_8192_SHA384
+        }
+        ("http://www.w3.org/2001/04/xmldsig-more#rsa-sha512", KeyType::Rsa) => {
+            &signature::RSA_PKCS1_2048_8192_SHA512
+        }
+        ("http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256", KeyType::EcP256) => {
+            &signature::ECDSA_P256_SHA256_FIXED
+        }
+        ("http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha384", KeyType::EcP384) => {
+            &signature::ECDSA_P384_SHA384_FIXED
+        }
+        _ => {
+            return Err(invalid(
+                "Unsupported signature algorithm for this certificate",
+            ))
+            .attach_printable(uri.to_string())
+        }
+    };
+
+    Ok(alg)
+}
+
+fn canonicalization(element: &Element) -> Result<Canonicalization, Report<SamlError>> {
+    match element.attr("Algorithm") {
+        Some(EXC_C14N) => {
+            let prefixes = element
+                .child(EXC_C14N, "InclusiveNamespaces")
+                .and_then(|e| e.attr("PrefixList"))
+                .map(|list| list.split_whitespace().map(String::from).collect())
+                .unwrap_or_default();
+            Ok(Canonicalization::Exclusive(prefixes))
+        }
+        Some(C14N) => Ok(Canonicalization::Inclusive),
+        other => Err(invalid("Unsupported canonicalization algorithm"))
+            .attach_printable(other.unwrap_or_default().to_string()),
+    }
+}
+
+fn required_child<'a>(
+    element: &'a Element,
+    local: &'static str,
+) -> Result<&'a Element, Report<SamlError>> {
+    element
+        .child(DSIG_NS, local)
+        .ok_or_else(|| invalid("Signature is missing an element").attach_printable(local))
+}
+
+/// Verify the signature that is a direct child of `element`, and check that it covers all of
+/// `element`. Returns `Ok(false)` if the element is not signed.
+pub(super) fn verify_enveloped_signature(
+    element: &Element,
+    key: &PublicKey,
+) -> Result<bool, Report<SamlError>> {
+    let Some(signature) = element.child(DSIG_NS, "Signature") else {
+        return Ok(false);
+    };
+
+    if element.children_named(DSIG_NS, "Signature").count() > 1 {
+        return Err(invalid("Multiple signatures on one element"));
+    }
+
+    let signed_info = required_child(signature, "SignedInfo")?;
+    let signed_info_c14n =
+        canonicalization(required_child(signed_info, "CanonicalizationMethod")?)?;
+    let signature_method = required_child(signed_info, "SignatureMethod")?
+        .attr("Algorithm")
+        .unwrap_or_default();
+
+    let mut references = signed_info.children_named(DSIG_NS, "Reference");
+    let (Some(reference), None) = (references.next(), references.next()) else {
+        return Err(invalid("Signature must have exactly one reference"));
+    };
+
+    // The signature must refer to the element that contains it. This, along with only reading
+    // data from the element that was verified, prevents signature wrapping attacks.
+    let id = element
+        .attr("ID")
+        .ok_or_else(|| invalid("Signed element has no ID"))?;
+    if reference.attr("URI") != Some(&format!("#{id}")) {
+        return Err(invalid("Signature does not refer to the signed element"));
+    }
+
+    let mut enveloped = false;
+    let mut reference_c14n = Canonicalization::Inclusive;
+    if let Some(transforms) = reference.child(DSIG_NS, "Transforms") {
+        for transform in transforms.children_named(DSIG_NS, "Transform") {
+            if transform.attr("Algorithm") == Some(ENVELOPED_SIGNATURE) {
+                enveloped = true;
+            } else {
+                reference_c14n = canonicalization(transform)?;
+            }
+        }
+    }
+
+    if !enveloped {
+        return Err(invalid("Signature is not an enveloped signature"));
+    }
+
+    let digest_alg = digest_algorithm(
+        required_child(reference, "DigestMethod")?
+            .attr("Algorithm")
+            .unwrap_or_default(),
+    )?;
+    let expected_digest = decode_base64(&required_child(reference, "DigestValue")?.text())
+        .ok_or_else(|| invalid("Invalid digest value"))?;
+
+    let canonical = canonicalize(element, &reference_c14n, Some(signature));
+    let digest = digest::digest(digest_alg, canonical.as_bytes());
+    if digest.as_ref() != expected_digest.as_slice() {
+        return Err(invalid("Digest does not match"));
+    }
+
+    let signature_value = decode_base64(&required_child(signature, "SignatureValue")?.text())
+        .ok_or_else(|| invalid("Invalid signature value"))?;
+    let alg = verification_algorithm(signature_method, key.key_type)?;
+    let canonical_signed_info = canonicalize(signed_info, &signed_info_c14n, None);
+    signature::UnparsedPublicKey::new(alg, &key.key)
+        .verify(canonical_signed_info.as_bytes(), &signature_value)
+        .map_err(|_| invalid("Signature does not match"))?;
+
+    Ok(true)
+}
+
+/// Add an enveloped ECDSA P-256 signature to the root element of `xml`, which must have an `ID`
+/// attribute and a `saml:Issuer` child. The signature is placed after the Issuer, as required by
+/// the SAML schema. This is used by the test identity provider in [crate::testing].
+pub(crate) fn sign_enveloped(xml: &str, key: &signature::EcdsaKeyPair) -> String {
+    let element = parse(xml).expect("parsing XML to sign");
+    let id = element.attr("ID").expect("signed element must have an ID");
+    let c14n = Canonicalization::Exclusive(Vec::new());
+    let digest = digest::digest(
+        &digest::SHA256,
+        canonicalize(&element, &c14n, None).as_bytes(),
+    );
+
+    let signed_info = format!(
+        r##"<ds:SignedInfo xmlns:ds="{DSIG_NS}"><ds:CanonicalizationMethod Algorithm="{EXC_C14N}"/><ds:SignatureMethod Algorithm="http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256"/><ds:Reference URI="#{id}"><ds:Transforms><ds:Transform Algorithm="{ENVELOPED_SIGNATURE}"/><ds:Transform Algorithm="{EXC_C14N}"/></ds:Transforms><ds:DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/><ds:DigestValue>{digest}</ds:DigestValue></ds:Reference></ds:SignedInfo>"##,
+        digest = STANDARD.encode(digest.as_ref()),
+    );
+    let canonical_signed_info = canonicalize(
+        &parse(&signed_info).expect("parsing SignedInfo"),
+        &c14n,
+        None,
+    );
+    let signature_value = key
+        .sign(
+            &ring::rand::SystemRandom::new(),
+            canonical_signed_info.as_bytes(),
+        )
+        .expect("signing");
+
+    let signature = format!(
+        r#"<ds:Signature xmlns:ds="{DSIG_NS}">{signed_info}<ds:SignatureValue>{}</ds:SignatureValue></ds:Signature>"#,
+        STANDARD.encode(signature_value.as_ref())
+    );
+
+    let issuer_end = xml
+        .find("</saml:Issuer>")
+        .expect("signed element must have an Issuer")
+        + "</saml:Issuer>".len();
+    format!("{}{signature}{}", &xml[..issuer_end], &xml[issuer_end..])
+}
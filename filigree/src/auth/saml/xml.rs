@@ -0,0 +1,468 @@
+//! A minimal XML tree and the canonicalization needed to verify XML signatures.
+//!
+//! This only supports what is needed to process SAML messages. Notably, documents with a DOCTYPE
+//! are rejected, which also prevents entity expansion attacks.
+
+use std::collections::BTreeMap;
+
+use quick_xml::{events::Event, Reader};
+use thiserror::Error;
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// An XML element
+#[derive(Debug)]
+pub(super) struct Element {
+    pub prefix: Option<String>,
+    pub local: String,
+    /// The namespace URI of this element
+    pub ns: String,
+    /// All the namespaces in scope for this element, including those declared on ancestors.
+    /// The default namespace uses an empty prefix.
+    pub namespaces: BTreeMap<String, String>,
+    /// Attributes other than namespace declarations, as (qualified name, value)
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug)]
+pub(super) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// An error encountered while parsing XML
+#[derive(Debug, Error)]
+#[error("Invalid XML: {0}")]
+pub(super) struct XmlError(&'static str);
+
+fn malformed(message: &'static str) -> XmlError {
+    XmlError(message)
+}
+
+fn split_qname(qname: &str) -> (Option<&str>, &str) {
+    match qname.split_once(':') {
+        Some((prefix, local)) => (Some(prefix), local),
+        None => (None, qname),
+    }
+}
+
+fn normalize_newlines(s: &str) -> String {
+    s.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn unescape(s: &str) -> Result<String, XmlError> {
+    quick_xml::escape::unescape(s)
+        .map(|s| s.into_owned())
+        .map_err(|_| malformed("invalid character reference"))
+}
+
+fn start_element(
+    start: &quick_xml::events::BytesStart,
+    parent: Option<&Element>,
+) -> Result<Element, XmlError> {
+    let qname = std::str::from_utf8(start.name().as_ref())
+        .map_err(|_| malformed("invalid UTF-8"))?
+        .to_string();
+    let mut namespaces = parent.map(|p| p.namespaces.clone()).unwrap_or_default();
+    let mut attrs = Vec::new();
+
+    for attr in start.attributes() {
+        let attr = attr.map_err(|_| malformed("invalid attribute"))?;
+        let key = std::str::from_utf8(attr.key.as_ref()).map_err(|_| malformed("invalid UTF-8"))?;
+        let raw = std::str::from_utf8(&attr.value).map_err(|_| malformed("invalid UTF-8"))?;
+        // Attribute value normalization, as described in section 3.3.3 of the XML spec.
+        let value = unescape(&normalize_newlines(raw).replace(['\t', '\n'], " "))?;
+
+        if key == "xmlns" {
+            namespaces.insert(String::new(), value);
+        } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+            namespaces.insert(prefix.to_string(), value);
+        } else {
+            attrs.push((key.to_string(), value));
+        }
+    }
+
+    let (prefix, local) = split_qname(&qname);
+    let ns = match prefix {
+        Some("xml") => XML_NS.to_string(),
+        Some(prefix) => namespaces
+            .get(prefix)
+            .cloned()
+            .ok_or(malformed("undeclared namespace prefix"))?,
+        None => namespaces.get("").cloned().unwrap_or_default(),
+    };
+
+    Ok(Element {
+        prefix: prefix.map(String::from),
+        local: local.to_string(),
+        ns,
+        namespaces,
+        attrs,
+        children: Vec::new(),
+    })
+}
+
+/// Parse an XML document and return its root element
+pub(super) fn parse(xml: &str) -> Result<Element, XmlError> {
+    let mut reader = Reader::from_str(xml);
+    reader.check_end_names(true);
+
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    let mut add_element = |stack: &mut Vec<Element>, element: Element| {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(Node::Element(element)),
+            None if root.is_none() => root = Some(element),
+            None => return Err(malformed("multiple root elements")),
+        }
+        Ok(())
+    };
+
+    loop {
+        match reader
+            .read_event()
+            .map_err(|_| malformed("could not parse XML"))?
+        {
+            Event::Start(start) => {
+                let element = start_element(&start, stack.last())?;
+                stack.push(element);
+            }
+            Event::Empty(start) => {
+                let element = start_element(&start, stack.last())?;
+                add_element(&mut stack, element)?;
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or(malformed("unexpected end tag"))?;
+                add_element(&mut stack, element)?;
+            }
+            Event::Text(text) => {
+                let raw = std::str::from_utf8(&text).map_err(|_| malformed("invalid UTF-8"))?;
+                match stack.last_mut() {
+                    Some(parent) => parent
+                        .children
+                        .push(Node::Text(unescape(&normalize_newlines(raw))?)),
+                    None if raw.trim().is_empty() => {}
+                    None => return Err(malformed("text outside of the root element")),
+                }
+            }
+            Event::CData(text) => {
+                let raw = std::str::from_utf8(&text).map_err(|_| malformed("invalid UTF-8"))?;
+                let parent = stack.last_mut().ok_or(malformed("unexpected CDATA"))?;
+                parent.children.push(Node::Text(normalize_newlines(raw)));
+            }
+            Event::DocType(_) => return Err(malformed("DOCTYPE is not allowed")),
+            // Comments are not part of the canonical form. Processing instructions are, but
+            // nothing uses them in SAML messages, so a signature covering one will fail to
+            // validate.
+            Event::Comment(_) | Event::Decl(_) | Event::PI(_) => {}
+            Event::Eof => break,
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(malformed("unclosed element"));
+    }
+
+    root.ok_or(malformed("document is empty"))
+}
+
+impl Element {
+    /// Return true if this element has the given namespace and local name
+    pub fn is(&self, ns: &str, local: &str) -> bool {
+        self.ns == ns && self.local == local
+    }
+
+    /// Get an unqualified attribute
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the child elements
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// Find the first child element with the given name
+    pub fn child(&self, ns: &str, local: &str) -> Option<&Element> {
+        self.elements().find(|e| e.is(ns, local))
+    }
+
+    /// Find all child elements with the given name
+    pub fn children_named<'a>(
+        &'a self,
+        ns: &'a str,
+        local: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.elements().filter(move |e| e.is(ns, local))
+    }
+
+    /// The text content directly inside this element, with surrounding whitespace removed.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                Node::Text(t) => Some(t.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Find the first descendant element, searching depth-first, with the given name
+    pub fn descendant(&self, ns: &str, local: &str) -> Option<&Element> {
+        self.elements().find_map(|e| {
+            if e.is(ns, local) {
+                Some(e)
+            } else {
+                e.descendant(ns, local)
+            }
+        })
+    }
+
+    fn qname(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{prefix}:{}", self.local),
+            None => self.local.clone(),
+        }
+    }
+}
+
+/// The XML canonicalization algorithms that can be used in signatures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Canonicalization {
+    /// Canonical XML 1.0, without comments
+    Inclusive,
+    /// Exclusive XML Canonicalization 1.0, without comments. The list contains namespace
+    /// prefixes which should be treated as in the inclusive algorithm, with `#default` meaning
+    /// the default namespace.
+    Exclusive(Vec<String>),
+}
+
+fn escape_text(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_attr(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Canonicalize an element and its descendants. If `exclude` is set, that element is omitted
+/// from the output, as done by the enveloped signature transform.
+pub(super) fn canonicalize(
+    element: &Element,
+    method: &Canonicalization,
+    exclude: Option<&Element>,
+) -> String {
+    let mut out = String::new();
+    write_canonical(element, method, exclude, &BTreeMap::new(), &mut out);
+    out
+}
+
+fn write_canonical(
+    element: &Element,
+    method: &Canonicalization,
+    exclude: Option<&Element>,
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    let prefixes: Vec<&str> = match method {
+        Canonicalization::Inclusive => element.namespaces.keys().map(|p| p.as_str()).collect(),
+        Canonicalization::Exclusive(inclusive_prefixes) => {
+            // Only the namespaces which are visibly used by this element
+            let mut prefixes = vec![element.prefix.as_deref().unwrap_or("")];
+            prefixes.extend(
+                element
+                    .attrs
+                    .iter()
+                    .filter_map(|(key, _)| split_qname(key).0)
+                    .filter(|prefix| *prefix != "xml"),
+            );
+            prefixes.extend(
+                inclusive_prefixes
+                    .iter()
+                    .map(|p| if p == "#default" { "" } else { p.as_str() })
+                    .filter(|p| element.namespaces.contains_key(*p)),
+            );
+            prefixes
+        }
+    };
+
+    let mut rendered = rendered.clone();
+    let mut ns_decls = BTreeMap::new();
+    for prefix in prefixes {
+        let uri = element
+            .namespaces
+            .get(prefix)
+            .map(|u| u.as_str())
+            .unwrap_or("");
+        let already_rendered = match rendered.get(prefix) {
+            Some(r) => r == uri,
+            // An empty default namespace only needs to be declared to undo a non-empty one.
+            None => prefix.is_empty() && uri.is_empty(),
+        };
+
+        if !already_rendered {
+            ns_decls.insert(prefix, uri);
+            rendered.insert(prefix.to_string(), uri.to_string());
+        }
+    }
+
+    let mut attrs = element
+        .attrs
+        .iter()
+        .map(|(key, value)| {
+            let (prefix, local) = split_qname(key);
+            let ns = match prefix {
+                Some("xml") => XML_NS,
+                Some(prefix) => element
+                    .namespaces
+                    .get(prefix)
+                    .map(|u| u.as_str())
+                    .unwrap_or(""),
+                None => "",
+            };
+            ((ns, local), key, value)
+        })
+        .collect::<Vec<_>>();
+    attrs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let qname = element.qname();
+    out.push('<');
+    out.push_str(&qname);
+    for (prefix, uri) in ns_decls {
+        if prefix.is_empty() {
+            out.push_str(" xmlns=\"");
+        } else {
+            out.push_str(" xmlns:");
+            out.push_str(prefix);
+            out.push_str("=\"");
+        }
+        escape_attr(uri, out);
+        out.push('"');
+    }
+
+    for (_, key, value) in attrs {
+        out.push(' ');
+        out.push_str(key);
+        out.push_str("=\"");
+        escape_attr(value, out);
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in &element.children {
+        match child {
+            Node::Text(text) => escape_text(text, out),
+            Node::Element(child) => {
+                if !exclude.is_some_and(|e| std::ptr::eq(e, child)) {
+                    write_canonical(child, method, exclude, &rendered, out);
+                }
+            }
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&qname);
+    out.push('>');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exc_c14n(xml: &str) -> String {
+        canonicalize(
+            &parse(xml).unwrap(),
+            &Canonicalization::Exclusive(vec![]),
+            None,
+        )
+    }
+
+    #[test]
+    fn exclusive_canonicalization() {
+        let xml = r#"<?xml version="1.0"?>
+<!-- comment -->
+<a:Root xmlns:a="urn:a" xmlns:b="urn:b" xmlns:unused="urn:unused" b:z="1" y='two &amp; "three"' x="&#x9;tab">
+  <a:Empty/>
+  <b:Child xmlns:a="urn:a" attr="&lt;&gt;">Text &amp; &lt;more&gt; <![CDATA[<cdata>]]></b:Child>
+  <Plain xmlns="urn:default"><Inner/></Plain>
+</a:Root>"#;
+
+        // Verified against `xmllint --exc-c14n`
+        assert_eq!(
+            exc_c14n(xml),
+            "<a:Root xmlns:a=\"urn:a\" xmlns:b=\"urn:b\" x=\"&#x9;tab\" y=\"two &amp; &quot;three&quot;\" b:z=\"1\">\n  \
+            <a:Empty></a:Empty>\n  \
+            <b:Child attr=\"&lt;>\">Text &amp; &lt;more&gt; &lt;cdata&gt;</b:Child>\n  \
+            <Plain xmlns=\"urn:default\"><Inner></Inner></Plain>\n\
+            </a:Root>"
+        );
+    }
+
+    #[test]
+    fn inclusive_canonicalization() {
+        let root = parse(
+            r#"<a:Root xmlns:a="urn:a" xmlns:unused="urn:unused"><a:Child xmlns:c="urn:c"/></a:Root>"#,
+        )
+        .unwrap();
+
+        let child = root.elements().next().unwrap();
+        assert_eq!(
+            canonicalize(child, &Canonicalization::Inclusive, None),
+            r#"<a:Child xmlns:a="urn:a" xmlns:c="urn:c" xmlns:unused="urn:unused"></a:Child>"#
+        );
+        assert_eq!(
+            canonicalize(child, &Canonicalization::Exclusive(vec![]), None),
+            r#"<a:Child xmlns:a="urn:a"></a:Child>"#
+        );
+        assert_eq!(
+            canonicalize(
+                child,
+                &Canonicalization::Exclusive(vec!["unused".to_string()]),
+                None
+            ),
+            r#"<a:Child xmlns:a="urn:a" xmlns:unused="urn:unused"></a:Child>"#
+        );
+    }
+
+    #[test]
+    fn rejects_doctype() {
+        let xml = r#"<!DOCTYPE a [<!ENTITY x "y">]><a>&x;</a>"#;
+        assert!(parse(xml).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse("<p:a/>").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -202,18 +202,22 @@ mod session_backend {
     use std::str::FromStr;
 
     use error_stack::{Report, ResultExt};
-    use sqlx::PgPool;
     use tower_cookies::{Cookie, Cookies};
     use uuid::Uuid;
 
     use super::{ExpiryStyle, SessionCookieBuilder, SessionError, SessionId, SessionKey};
-    use crate::auth::UserId;
+    use crate::{auth::UserId, db::DbPool};
+
+    /// How long a user has to enter their second factor after entering their password
+    const MFA_PENDING_SESSION_EXPIRY: std::time::Duration = std::time::Duration::from_secs(600);
+    /// How many wrong second factor codes a pending session can submit before it is deleted
+    const MAX_MFA_ATTEMPTS: i32 = 5;
 
     /// The backend for storing and retrieving session information.
     #[derive(Clone)]
     pub struct SessionBackend {
         /// The database connection pool
-        pub db: PgPool,
+        pub db: DbPool,
         /// Session cookie managemeent
         pub cookies: SessionCookieBuilder,
         /// How to calculate the expiration date for a session
@@ -222,7 +226,7 @@ mod session_backend {
 
     impl SessionBackend {
         /// Create the [SessionBackend]
-        pub fn new(db: PgPool, cookies: SessionCookieBuilder, expiry_style: ExpiryStyle) -> Self {
+        pub fn new(db: DbPool, cookies: SessionCookieBuilder, expiry_style: ExpiryStyle) -> Self {
             Self {
                 db,
                 cookies,
@@ -252,32 +256,174 @@ mod session_backend {
             &self,
             cookies: &Cookies,
             user_id: &UserId,
+        ) -> Result<(), Report<SessionError>> {
+            self.add_session(cookies, user_id, false, self.expiry_style.expiry_duration())
+                .await
+        }
+
+        /// Create a session for a user who has entered their password but still needs to provide
+        /// their second factor. This session can not be used to authenticate requests, only to
+        /// finish the login by calling [SessionBackend::get_mfa_pending_user].
+        pub async fn create_mfa_pending_session(
+            &self,
+            cookies: &Cookies,
+            user_id: &UserId,
+        ) -> Result<(), Report<SessionError>> {
+            self.add_session(cookies, user_id, true, MFA_PENDING_SESSION_EXPIRY)
+                .await
+        }
+
+        async fn add_session(
+            &self,
+            cookies: &Cookies,
+            user_id: &UserId,
+            mfa_pending: bool,
+            expiry: std::time::Duration,
         ) -> Result<(), Report<SessionError>> {
             let session_id = SessionId::new();
             let hash = Uuid::new_v4();
 
+            #[cfg(not(feature = "sqlite"))]
             sqlx::query!(
                 "
-            INSERT INTO user_sessions (id, user_id, hash, expires_at) VALUES
-            ($1, $2, $3, now() + $4)",
+            INSERT INTO user_sessions (id, user_id, hash, expires_at, mfa_pending) VALUES
+            ($1, $2, $3, now() + $4, $5)",
                 session_id.as_uuid(),
                 user_id.as_uuid(),
                 &hash,
-                self.expiry_style.expiry_duration() as _
+                expiry as _,
+                mfa_pending
+            )
+            .execute(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            sqlx::query(
+                "INSERT INTO user_sessions (id, user_id, hash, expires_at, mfa_pending) VALUES
+                (?1, ?2, ?3, unixepoch() + ?4, ?5)",
             )
+            .bind(session_id.as_uuid())
+            .bind(user_id.as_uuid())
+            .bind(hash)
+            .bind(expiry.as_secs() as i64)
+            .bind(mfa_pending)
             .execute(&self.db)
             .await
             .change_context(SessionError::Db)?;
 
-            let cookie = self.cookies.create_cookie(
-                &SessionKey::new(session_id, hash),
-                self.expiry_style.expiry_duration(),
-            );
+            let cookie = self
+                .cookies
+                .create_cookie(&SessionKey::new(session_id, hash), expiry);
 
             cookies.add(cookie);
             Ok(())
         }
 
+        /// Look up the user for a session created by [SessionBackend::create_mfa_pending_session].
+        /// Returns `None` if there is no session cookie or it does not refer to a pending session.
+        pub async fn get_mfa_pending_user(
+            &self,
+            cookies: &Cookies,
+        ) -> Result<Option<UserId>, Report<SessionError>> {
+            self.session_user(cookies, true).await
+        }
+
+        /// Record a wrong second factor code for the current pending session. Once the session has
+        /// failed too many times it is deleted, and the user has to log in again.
+        pub async fn record_failed_mfa_attempt(
+            &self,
+            cookies: &Cookies,
+        ) -> Result<(), Report<SessionError>> {
+            let Some(key) = cookies
+                .get("sid")
+                .and_then(|cookie| SessionKey::from_str(cookie.value()).ok())
+            else {
+                return Ok(());
+            };
+
+            #[cfg(not(feature = "sqlite"))]
+            let attempts = sqlx::query_scalar!(
+                "UPDATE user_sessions SET mfa_failed_attempts = mfa_failed_attempts + 1
+                WHERE id = $1 AND hash = $2 AND mfa_pending
+                RETURNING mfa_failed_attempts",
+                key.session_id.as_uuid(),
+                &key.hash
+            )
+            .fetch_optional(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            let attempts = sqlx::query_scalar::<_, i32>(
+                "UPDATE user_sessions SET mfa_failed_attempts = mfa_failed_attempts + 1
+                WHERE id = ?1 AND hash = ?2 AND mfa_pending
+                RETURNING mfa_failed_attempts",
+            )
+            .bind(key.session_id.as_uuid())
+            .bind(key.hash)
+            .fetch_optional(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
+            if attempts.unwrap_or(0) >= MAX_MFA_ATTEMPTS {
+                self.delete_session(cookies).await?;
+            }
+
+            Ok(())
+        }
+
+        /// Look up the user for the current session. Returns `None` if there is no session cookie,
+        /// or if the session has expired or is still waiting for a second factor.
+        ///
+        /// This is for the few endpoints that need to know the user but can't use the
+        /// application's authentication extractors. Most handlers should use those instead.
+        pub async fn get_session_user(
+            &self,
+            cookies: &Cookies,
+        ) -> Result<Option<UserId>, Report<SessionError>> {
+            self.session_user(cookies, false).await
+        }
+
+        async fn session_user(
+            &self,
+            cookies: &Cookies,
+            mfa_pending: bool,
+        ) -> Result<Option<UserId>, Report<SessionError>> {
+            let Some(key) = cookies
+                .get("sid")
+                .and_then(|cookie| SessionKey::from_str(cookie.value()).ok())
+            else {
+                return Ok(None);
+            };
+
+            #[cfg(not(feature = "sqlite"))]
+            let user_id = sqlx::query_scalar!(
+                r#"SELECT user_id AS "user_id: UserId" FROM user_sessions
+                WHERE id = $1 AND hash = $2 AND mfa_pending = $3 AND expires_at > now()"#,
+                key.session_id.as_uuid(),
+                &key.hash,
+                mfa_pending
+            )
+            .fetch_optional(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            let user_id = sqlx::query_scalar::<_, UserId>(
+                "SELECT user_id FROM user_sessions
+                WHERE id = ?1 AND hash = ?2 AND mfa_pending = ?3 AND expires_at > unixepoch()",
+            )
+            .bind(key.session_id.as_uuid())
+            .bind(key.hash)
+            .bind(mfa_pending)
+            .fetch_optional(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
+            Ok(user_id)
+        }
+
         /// Update a session with the new expiry time. This usually is not called directly since it is
         /// part of the query that retrieves the actual user as well.
         pub async fn touch_session(
@@ -289,6 +435,7 @@ mod session_backend {
                 return Ok(());
             };
 
+            #[cfg(not(feature = "sqlite"))]
             let updated = sqlx::query!(
                 "UPDATE user_sessions
                 SET expires_at = now() + $1
@@ -303,6 +450,21 @@ mod session_backend {
             .await
             .change_context(SessionError::Db)?;
 
+            #[cfg(feature = "sqlite")]
+            let updated = sqlx::query(
+                "UPDATE user_sessions
+                SET expires_at = unixepoch() + ?1
+                WHERE id=?2 and hash=?3
+                -- Prevent unnecessary updates
+                AND (expires_at < unixepoch() + ?1 - 60)",
+            )
+            .bind(duration.as_secs() as i64)
+            .bind(key.session_id)
+            .bind(key.hash)
+            .execute(&self.db)
+            .await
+            .change_context(SessionError::Db)?;
+
             if updated.rows_affected() > 0 {
                 cookies.add(self.cookies.create_cookie(&key, duration));
             }
@@ -312,10 +474,18 @@ mod session_backend {
 
         /// Delete all sessions for a user
         pub async fn delete_for_user(&self, id: UserId) -> Result<(), Report<SessionError>> {
+            #[cfg(not(feature = "sqlite"))]
             sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1", id.as_uuid())
                 .execute(&self.db)
                 .await
                 .change_context(SessionError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            sqlx::query("DELETE FROM user_sessions WHERE user_id = ?1")
+                .bind(id.as_uuid())
+                .execute(&self.db)
+                .await
+                .change_context(SessionError::Db)?;
             Ok(())
         }
 
@@ -333,6 +503,7 @@ mod session_backend {
 
             let key = SessionKey::from_str(cookie.value())?;
 
+            #[cfg(not(feature = "sqlite"))]
             sqlx::query!(
                 "DELETE FROM user_sessions WHERE id = $1 and hash = $2",
                 key.session_id.as_uuid(),
@@ -342,15 +513,30 @@ mod session_backend {
             .await
             .change_context(SessionError::Db)?;
 
+            #[cfg(feature = "sqlite")]
+            sqlx::query("DELETE FROM user_sessions WHERE id = ?1 and hash = ?2")
+                .bind(key.session_id.as_uuid())
+                .bind(key.hash)
+                .execute(&self.db)
+                .await
+                .change_context(SessionError::Db)?;
+
             Ok(())
         }
 
         /// Sweep the session table and remove any expired sessions.
         pub async fn delete_expired_sessions(&self) -> Result<(), Report<SessionError>> {
+            #[cfg(not(feature = "sqlite"))]
             sqlx::query!("DELETE FROM user_sessions WHERE expires_at < now()")
                 .execute(&self.db)
                 .await
                 .change_context(SessionError::Db)?;
+
+            #[cfg(feature = "sqlite")]
+            sqlx::query("DELETE FROM user_sessions WHERE expires_at < unixepoch()")
+                .execute(&self.db)
+                .await
+                .change_context(SessionError::Db)?;
             Ok(())
         }
     }
@@ -0,0 +1,19 @@
+//! The database used by Filigree's built-in queries. This is Postgres by default, and SQLite when
+//! the `sqlite` feature is enabled.
+
+/// The database that Filigree's built-in queries run against
+#[cfg(not(feature = "sqlite"))]
+pub type Db = sqlx::Postgres;
+/// The database that Filigree's built-in queries run against
+#[cfg(feature = "sqlite")]
+pub type Db = sqlx::Sqlite;
+
+/// A connection pool for [Db]
+pub type DbPool = sqlx::Pool<Db>;
+
+/// A single connection to [Db]
+pub type DbConnection = <Db as sqlx::Database>::Connection;
+
+/// An [sqlx::Executor] for [Db], similar to [sqlx::PgExecutor].
+pub trait DbExecutor<'c>: sqlx::Executor<'c, Database = Db> {}
+impl<'c, T: sqlx::Executor<'c, Database = Db>> DbExecutor<'c> for T {}
@@ -0,0 +1,240 @@
+use axum::extract::{FromRequest, Request, State};
+use axum_extra::extract::Multipart;
+use error_stack::{Report, ResultExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{
+    check_timestamp, deliver, split_addresses, InboundAttachment, InboundEmail, InboundEmailError,
+    InboundStateRef, MAX_BODY_SIZE,
+};
+use crate::errors::WrapReport;
+
+/// The fields of a form posted by a Mailgun route
+#[derive(Debug, Default)]
+pub struct MailgunForm {
+    /// The text fields of the form
+    pub fields: Vec<(String, String)>,
+    /// The files in the form, which are the email's attachments
+    pub attachments: Vec<InboundAttachment>,
+}
+
+impl MailgunForm {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Read the form from a request. Mailgun sends a multipart form when the email has
+    /// attachments, and a URL-encoded form otherwise.
+    async fn from_request(request: Request) -> Result<Self, Report<InboundEmailError>> {
+        let multipart = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.starts_with("multipart/form-data"))
+            .unwrap_or(false);
+
+        if !multipart {
+            let body = axum::body::to_bytes(request.into_body(), MAX_BODY_SIZE)
+                .await
+                .change_context(InboundEmailError::Parse)?;
+            let fields = form_urlencoded::parse(&body).into_owned().collect();
+            return Ok(Self {
+                fields,
+                attachments: Vec::new(),
+            });
+        }
+
+        let mut multipart = Multipart::from_request(request, &())
+            .await
+            .change_context(InboundEmailError::Parse)?;
+        let mut form = Self::default();
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .change_context(InboundEmailError::Parse)?
+        {
+            let name = field.name().unwrap_or_default().to_string();
+            match field.file_name() {
+                Some(filename) => {
+                    let filename = filename.to_string();
+                    let content_type = field
+                        .content_type()
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+                    let content = field
+                        .bytes()
+                        .await
+                        .change_context(InboundEmailError::Parse)?;
+                    form.attachments.push(InboundAttachment {
+                        filename,
+                        content_type,
+                        content,
+                    });
+                }
+                None => {
+                    let value = field
+                        .text()
+                        .await
+                        .change_context(InboundEmailError::Parse)?;
+                    form.fields.push((name, value));
+                }
+            }
+        }
+
+        Ok(form)
+    }
+}
+
+/// Verify the `timestamp`, `token`, and `signature` fields that Mailgun adds to the form,
+/// using the account's HTTP webhook signing key.
+pub fn verify_signature(
+    signing_key: &str,
+    form: &MailgunForm,
+) -> Result<(), Report<InboundEmailError>> {
+    let timestamp = form.field("timestamp").unwrap_or_default();
+    let token = form.field("token").unwrap_or_default();
+    let signature = form
+        .field("signature")
+        .and_then(|s| hex::decode(s).ok())
+        .ok_or(InboundEmailError::InvalidSignature)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_key.as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(token.as_bytes());
+    mac.verify_slice(&signature)
+        .change_context(InboundEmailError::InvalidSignature)?;
+
+    let timestamp = timestamp
+        .parse::<i64>()
+        .change_context(InboundEmailError::InvalidSignature)?;
+    check_timestamp(timestamp)
+}
+
+/// Parse the form that a Mailgun route's `forward()` action sends.
+pub fn parse(form: MailgunForm) -> Result<InboundEmail, Report<InboundEmailError>> {
+    let headers = match form.field("message-headers") {
+        Some(headers) => serde_json::from_str::<Vec<(String, String)>>(headers)
+            .change_context(InboundEmailError::Parse)
+            .attach_printable("Invalid message-headers field")?,
+        None => Vec::new(),
+    };
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    };
+
+    let from = form
+        .field("from")
+        .or_else(|| form.field("sender"))
+        .ok_or(InboundEmailError::Parse)
+        .attach_printable("Missing from field")?
+        .to_string();
+    let to = header("To")
+        .or_else(|| form.field("recipient"))
+        .map(split_addresses)
+        .unwrap_or_default();
+    let cc = header("Cc").map(split_addresses).unwrap_or_default();
+
+    Ok(InboundEmail {
+        from,
+        to,
+        cc,
+        subject: form.field("subject").unwrap_or_default().to_string(),
+        text: form.field("body-plain").unwrap_or_default().to_string(),
+        html: form.field("body-html").unwrap_or_default().to_string(),
+        headers,
+        attachments: form.attachments,
+        ..Default::default()
+    })
+}
+
+pub(super) async fn handler(
+    State(state): InboundStateRef,
+    request: Request,
+) -> Result<http::StatusCode, WrapReport<InboundEmailError>> {
+    let form = MailgunForm::from_request(request).await?;
+    let signing_key = state
+        .config
+        .mailgun_signing_key
+        .as_deref()
+        .unwrap_or_default();
+    verify_signature(signing_key, &form)?;
+    let email = parse(form)?;
+    deliver(&state, email).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn form(signing_key: &str, timestamp: i64) -> MailgunForm {
+        let token = "a8ce0edb2dd8301dee6c2405235584e45aa91d1e9f979f3de0";
+        let mut mac = Hmac::<Sha256>::new_from_slice(signing_key.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}{token}").as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let headers = serde_json::json!([
+            ["To", "Support <reply+abc@example.com>"],
+            ["Message-Id", "<reply@example.com>"],
+            ["In-Reply-To", "<original@example.com>"]
+        ]);
+
+        MailgunForm {
+            fields: [
+                ("timestamp", timestamp.to_string()),
+                ("token", token.to_string()),
+                ("signature", signature),
+                ("recipient", "reply+abc@example.com".to_string()),
+                ("sender", "someone@example.com".to_string()),
+                ("from", "Someone <someone@example.com>".to_string()),
+                ("subject", "Re: Hello".to_string()),
+                ("body-plain", "Sounds good".to_string()),
+                ("message-headers", headers.to_string()),
+            ]
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v))
+            .collect(),
+            attachments: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_form() {
+        let mut email = parse(form("key", 0)).unwrap();
+        email.fill_from_headers();
+
+        assert_eq!(email.from, "Someone <someone@example.com>");
+        assert_eq!(email.to, vec!["Support <reply+abc@example.com>"]);
+        assert_eq!(email.subject, "Re: Hello");
+        assert_eq!(email.text, "Sounds good");
+        assert_eq!(email.message_id.as_deref(), Some("reply@example.com"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("original@example.com"));
+        assert_eq!(email.recipient_tag("reply"), Some("abc"));
+    }
+
+    #[test]
+    fn signature() {
+        let now = chrono::Utc::now().timestamp();
+        verify_signature("key", &form("key", now)).expect("valid signature");
+
+        let err = verify_signature("other-key", &form("key", now)).unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            InboundEmailError::InvalidSignature
+        ));
+
+        let err = verify_signature("key", &form("key", now - 3600)).unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            InboundEmailError::InvalidSignature
+        ));
+    }
+}
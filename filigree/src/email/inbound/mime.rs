@@ -0,0 +1,523 @@
+use std::borrow::Cow;
+
+use axum::{extract::State, http::HeaderMap};
+use base64::Engine;
+use bytes::Bytes;
+use error_stack::Report;
+
+use super::{
+    deliver, split_addresses, verify_basic_auth, InboundAttachment, InboundEmail,
+    InboundEmailError, InboundStateRef,
+};
+use crate::errors::WrapReport;
+
+/// Multipart messages nested deeper than this are treated as attachments instead of being
+/// parsed further.
+const MAX_DEPTH: usize = 10;
+
+/// A parsed `Content-Type` or `Content-Disposition` header
+struct HeaderParams {
+    value: String,
+    params: Vec<(String, String)>,
+}
+
+impl HeaderParams {
+    fn parse(header: &str) -> Self {
+        let mut parts = split_params(header).into_iter();
+        let value = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let params = parts
+            .filter_map(|part| {
+                let (name, value) = part.split_once('=')?;
+                let name = name.trim().to_ascii_lowercase();
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .map(|v| v.replace("\\\"", "\"").replace("\\\\", "\\"))
+                    .unwrap_or_else(|| value.to_string());
+
+                // RFC 2231 extended values look like `filename*=utf-8''file%20name.txt`
+                match name.strip_suffix('*') {
+                    Some(name) => {
+                        let encoded = value.splitn(3, '\'').last().unwrap_or_default();
+                        let decoded = percent_encoding::percent_decode_str(encoded)
+                            .decode_utf8_lossy()
+                            .into_owned();
+                        Some((name.to_string(), decoded))
+                    }
+                    None => Some((name, decode_words(&value))),
+                }
+            })
+            .collect();
+
+        Self { value, params }
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Split a header on semicolons that aren't inside quotes
+fn split_params(header: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in header.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                parts.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&header[start..]);
+    parts
+}
+
+/// Decode text in the given charset, replacing any invalid sequences
+fn decode_charset(charset: Option<&str>, data: &[u8]) -> String {
+    let encoding = charset
+        .and_then(|c| encoding_rs::Encoding::for_label(c.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode_without_bom_handling(data).0.into_owned()
+}
+
+fn decode_quoted_printable(data: &[u8], header: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'=' => {
+                let rest = &data[i + 1..];
+                if rest.starts_with(b"\r\n") {
+                    // Soft line break
+                    i += 3;
+                } else if rest.starts_with(b"\n") {
+                    i += 2;
+                } else if let Some(byte) = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    output.push(byte);
+                    i += 3;
+                } else {
+                    output.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if header => {
+                output.push(b' ');
+                i += 1;
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+fn decode_base64(data: &[u8]) -> Vec<u8> {
+    let cleaned = data
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+    base64::engine::general_purpose::STANDARD
+        .decode(&cleaned)
+        .or_else(|_| base64::engine::general_purpose::STANDARD_NO_PAD.decode(&cleaned))
+        .unwrap_or_default()
+}
+
+/// Decode one RFC 2047 encoded word, like `=?utf-8?B?SGVsbG8=?=`
+fn decode_word(word: &str) -> Option<String> {
+    let inner = word.strip_prefix("=?")?.strip_suffix("?=")?;
+    let mut parts = inner.splitn(3, '?');
+    let charset = parts.next()?;
+    // Strip an RFC 2231 language suffix, as in `utf-8*en`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    let encoding = parts.next()?;
+    let text = parts.next()?;
+
+    let data = match encoding {
+        "B" | "b" => base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .or_else(|_| base64::engine::general_purpose::STANDARD_NO_PAD.decode(text))
+            .ok()?,
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+
+    Some(decode_charset(Some(charset), &data))
+}
+
+/// The length of the encoded word at the start of `s`, which has the form
+/// `=?charset?encoding?text?=`
+fn encoded_word_len(s: &str) -> Option<usize> {
+    let after_prefix = s.strip_prefix("=?")?;
+    let charset_len = after_prefix.find('?')?;
+    let after_charset = &after_prefix[charset_len + 1..];
+    let encoding_len = after_charset.find('?')?;
+    let text_len = after_charset[encoding_len + 1..].find("?=")?;
+    Some(2 + charset_len + 1 + encoding_len + 1 + text_len + 2)
+}
+
+/// Decode the RFC 2047 encoded words in a header value
+fn decode_words(value: &str) -> String {
+    if !value.contains("=?") {
+        return value.to_string();
+    }
+
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut last_was_word = false;
+    while let Some(start) = rest.find("=?") {
+        let before = &rest[..start];
+        let Some(len) = encoded_word_len(&rest[start..]) else {
+            break;
+        };
+        let end = start + len;
+
+        match decode_word(&rest[start..end]) {
+            Some(decoded) => {
+                // Whitespace between adjacent encoded words is not part of the text
+                if !(last_was_word && before.trim().is_empty()) {
+                    output.push_str(before);
+                }
+                output.push_str(&decoded);
+                last_was_word = true;
+            }
+            None => {
+                output.push_str(before);
+                output.push_str(&rest[start..end]);
+                last_was_word = false;
+            }
+        }
+
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Split a message into the header and body sections
+fn split_header(data: &[u8]) -> (&[u8], &[u8]) {
+    let crlf = data.windows(4).position(|w| w == b"\r\n\r\n");
+    let lf = data.windows(2).position(|w| w == b"\n\n");
+    match (crlf, lf) {
+        (Some(c), Some(l)) if l < c => (&data[..l], &data[l + 2..]),
+        (Some(c), _) => (&data[..c], &data[c + 4..]),
+        (None, Some(l)) => (&data[..l], &data[l + 2..]),
+        // A message with no body
+        (None, None) => (data, &[]),
+    }
+}
+
+/// Parse a header section, unfolding continuation lines
+fn parse_headers(data: &[u8]) -> Vec<(String, String)> {
+    let text = String::from_utf8_lossy(data);
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    headers
+        .into_iter()
+        .map(|(name, value)| {
+            let value = decode_words(&value);
+            (name, value)
+        })
+        .collect()
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Split a multipart body into its parts
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+
+    let mut parts = Vec::new();
+    let mut part_start: Option<usize> = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|p| line_start + p + 1)
+            .unwrap_or(body.len());
+        let line = &body[line_start..line_end];
+        let trimmed = line.trim_ascii_end();
+
+        if trimmed.starts_with(delimiter) {
+            if let Some(start) = part_start {
+                // The line break before the delimiter belongs to the delimiter.
+                let mut end = line_start;
+                if end > start && body[end - 1] == b'\n' {
+                    end -= 1;
+                    if end > start && body[end - 1] == b'\r' {
+                        end -= 1;
+                    }
+                }
+                parts.push(&body[start..end]);
+            }
+
+            if trimmed[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+
+            part_start = Some(line_end);
+        }
+
+        line_start = line_end;
+    }
+
+    // The closing delimiter is missing, so take what we have.
+    if let Some(start) = part_start {
+        parts.push(&body[start..]);
+    }
+
+    parts
+}
+
+fn decode_body<'a>(headers: &[(String, String)], body: &'a [u8]) -> Cow<'a, [u8]> {
+    let encoding = find_header(headers, "Content-Transfer-Encoding")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match encoding.as_str() {
+        "base64" => Cow::Owned(decode_base64(body)),
+        "quoted-printable" => Cow::Owned(decode_quoted_printable(body, false)),
+        _ => Cow::Borrowed(body),
+    }
+}
+
+/// Add a part and any parts it contains to the email
+fn add_part(email: &mut InboundEmail, data: &[u8], depth: usize) {
+    let (header, body) = split_header(data);
+    let headers = parse_headers(header);
+    add_part_with_headers(email, &headers, body, depth);
+}
+
+fn add_part_with_headers(
+    email: &mut InboundEmail,
+    headers: &[(String, String)],
+    body: &[u8],
+    depth: usize,
+) {
+    let content_type = HeaderParams::parse(
+        find_header(headers, "Content-Type").unwrap_or("text/plain; charset=us-ascii"),
+    );
+    let disposition = find_header(headers, "Content-Disposition").map(HeaderParams::parse);
+
+    if content_type.value.starts_with("multipart/") && depth < MAX_DEPTH {
+        if let Some(boundary) = content_type.param("boundary") {
+            for part in split_multipart(body, boundary) {
+                add_part(email, part, depth + 1);
+            }
+            return;
+        }
+    }
+
+    let filename = disposition
+        .as_ref()
+        .and_then(|d| d.param("filename"))
+        .or_else(|| content_type.param("name"));
+    let is_attachment = disposition
+        .as_ref()
+        .map(|d| d.value == "attachment")
+        .unwrap_or(false)
+        || filename.is_some();
+
+    let content = decode_body(headers, body);
+    let text_field = match content_type.value.as_str() {
+        "text/plain" if !is_attachment => Some(&mut email.text),
+        "text/html" if !is_attachment => Some(&mut email.html),
+        _ => None,
+    };
+
+    match text_field {
+        Some(field) => {
+            let text = decode_charset(content_type.param("charset"), &content);
+            // Some clients split the body into several parts around inline attachments.
+            if !field.is_empty() {
+                field.push('\n');
+            }
+            field.push_str(&text);
+        }
+        None => {
+            let filename = filename.map(|f| f.to_string()).unwrap_or_else(|| {
+                if content_type.value == "message/rfc822" {
+                    "message.eml".to_string()
+                } else {
+                    "attachment".to_string()
+                }
+            });
+
+            email.attachments.push(InboundAttachment {
+                filename,
+                content_type: content_type.value,
+                content: Bytes::copy_from_slice(&content),
+            });
+        }
+    }
+}
+
+/// Parse a raw MIME message
+pub fn parse(data: &[u8]) -> Result<InboundEmail, Report<InboundEmailError>> {
+    let (header, body) = split_header(data);
+    let headers = parse_headers(header);
+    if headers.is_empty() {
+        return Err(
+            Report::new(InboundEmailError::Parse).attach_printable("Message has no headers")
+        );
+    }
+
+    let address_list = |name: &str| {
+        find_header(&headers, name)
+            .map(split_addresses)
+            .unwrap_or_default()
+    };
+
+    let mut email = InboundEmail {
+        from: find_header(&headers, "From")
+            .unwrap_or_default()
+            .to_string(),
+        to: address_list("To"),
+        cc: address_list("Cc"),
+        subject: find_header(&headers, "Subject")
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    };
+
+    add_part_with_headers(&mut email, &headers, body, 0);
+    email.headers = headers;
+    email.fill_from_headers();
+
+    Ok(email)
+}
+
+pub(super) async fn handler(
+    State(state): InboundStateRef,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<http::StatusCode, WrapReport<InboundEmailError>> {
+    let credentials = state.config.mime_basic_auth.as_deref().unwrap_or_default();
+    verify_basic_auth(&headers, credentials)?;
+    let email = parse(&body)?;
+    deliver(&state, email).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MESSAGE: &str = "From: =?utf-8?Q?Jos=C3=A9?= <jose@example.com>\r
+To: \"Support, Team\" <support@example.com>, reply+abc@example.com\r
+Subject: =?utf-8?B?UmU6IEhlbGxv?=\r
+ =?utf-8?B?IHRoZXJl?=\r
+Message-ID: <reply-1@example.com>\r
+In-Reply-To: <original@example.com>\r
+References: <root@example.com>\r
+ <original@example.com>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"outer\"\r
+\r
+This is a multipart message.\r
+--outer\r
+Content-Type: multipart/alternative; boundary=inner\r
+\r
+--inner\r
+Content-Type: text/plain; charset=iso-8859-1\r
+Content-Transfer-Encoding: quoted-printable\r
+\r
+Caf=E9 at noon? This is a long line that is wrapped with a soft =\r
+line break.\r
+--inner\r
+Content-Type: text/html; charset=utf-8\r
+Content-Transfer-Encoding: base64\r
+\r
+PHA+Q2Fmw6kgYXQgbm9vbj88L3A+\r
+--inner--\r
+--outer\r
+Content-Type: application/pdf\r
+Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.pdf\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjQ=\r
+--outer--\r
+";
+
+    #[test]
+    fn parse_message() {
+        let email = parse(MESSAGE.as_bytes()).unwrap();
+        assert_eq!(email.from, "José <jose@example.com>");
+        assert_eq!(
+            email.to,
+            vec![
+                "\"Support, Team\" <support@example.com>",
+                "reply+abc@example.com"
+            ]
+        );
+        assert_eq!(email.subject, "Re: Hello there");
+        assert_eq!(
+            email.text,
+            "Café at noon? This is a long line that is wrapped with a soft line break."
+        );
+        assert_eq!(email.html, "<p>Café at noon?</p>");
+        assert_eq!(email.message_id.as_deref(), Some("reply-1@example.com"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("original@example.com"));
+        assert_eq!(
+            email.references,
+            vec!["root@example.com", "original@example.com"]
+        );
+        assert_eq!(email.recipient_tag("reply"), Some("abc"));
+
+        assert_eq!(email.attachments.len(), 1);
+        let attachment = &email.attachments[0];
+        assert_eq!(attachment.filename, "résumé.pdf");
+        assert_eq!(attachment.content_type, "application/pdf");
+        assert_eq!(attachment.content.as_ref(), b"%PDF-1.4");
+    }
+
+    #[test]
+    fn parse_simple_message() {
+        let email = parse(b"From: a@example.com\nSubject: Hi\n\nHello\n").unwrap();
+        assert_eq!(email.from, "a@example.com");
+        assert_eq!(email.subject, "Hi");
+        assert_eq!(email.text, "Hello\n");
+        assert!(email.attachments.is_empty());
+    }
+
+    #[test]
+    fn reject_non_message() {
+        let err = parse(b"").unwrap_err();
+        assert!(matches!(err.current_context(), InboundEmailError::Parse));
+    }
+}
@@ -0,0 +1,155 @@
+use axum::{extract::State, http::HeaderMap};
+use base64::Engine;
+use bytes::Bytes;
+use error_stack::{Report, ResultExt};
+use serde::Deserialize;
+
+use super::{
+    deliver, split_addresses, verify_basic_auth, InboundAttachment, InboundEmail,
+    InboundEmailError, InboundStateRef,
+};
+use crate::errors::WrapReport;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Payload {
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(default)]
+    cc: String,
+    #[serde(default)]
+    reply_to: String,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    text_body: String,
+    #[serde(default)]
+    html_body: String,
+    #[serde(default)]
+    headers: Vec<Header>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Attachment {
+    name: String,
+    content: String,
+    content_type: String,
+}
+
+/// Parse the body of a Postmark inbound webhook.
+pub fn parse(body: &[u8]) -> Result<InboundEmail, Report<InboundEmailError>> {
+    let payload: Payload = serde_json::from_slice(body).change_context(InboundEmailError::Parse)?;
+
+    let attachments = payload
+        .attachments
+        .into_iter()
+        .map(|a| {
+            let content = base64::engine::general_purpose::STANDARD
+                .decode(&a.content)
+                .change_context(InboundEmailError::Parse)
+                .attach_printable_lazy(|| format!("Invalid content for attachment {}", a.name))?;
+            Ok(InboundAttachment {
+                filename: a.name,
+                content_type: a.content_type,
+                content: Bytes::from(content),
+            })
+        })
+        .collect::<Result<Vec<_>, Report<InboundEmailError>>>()?;
+
+    Ok(InboundEmail {
+        from: payload.from,
+        to: split_addresses(&payload.to),
+        cc: split_addresses(&payload.cc),
+        reply_to: Some(payload.reply_to).filter(|r| !r.is_empty()),
+        subject: payload.subject,
+        text: payload.text_body,
+        html: payload.html_body,
+        headers: payload
+            .headers
+            .into_iter()
+            .map(|h| (h.name, h.value))
+            .collect(),
+        attachments,
+        ..Default::default()
+    })
+}
+
+pub(super) async fn handler(
+    State(state): InboundStateRef,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<http::StatusCode, WrapReport<InboundEmailError>> {
+    let credentials = state
+        .config
+        .postmark_basic_auth
+        .as_deref()
+        .unwrap_or_default();
+    verify_basic_auth(&headers, credentials)?;
+    let email = parse(&body)?;
+    deliver(&state, email).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_payload() {
+        let body = serde_json::json!({
+            "FromName": "Someone",
+            "MessageStream": "inbound",
+            "From": "someone@example.com",
+            "FromFull": { "Email": "someone@example.com", "Name": "Someone" },
+            "To": "\"Support\" <reply+abc@example.com>, other@example.com",
+            "Cc": "",
+            "ReplyTo": "",
+            "Subject": "Re: Hello",
+            "MessageID": "22c74902-a0c1-4511-804f-341342852c90",
+            "TextBody": "Sounds good",
+            "HtmlBody": "<p>Sounds good</p>",
+            "StrippedTextReply": "Sounds good",
+            "Headers": [
+                { "Name": "Message-ID", "Value": "<reply@example.com>" },
+                { "Name": "In-Reply-To", "Value": "<original@example.com>" }
+            ],
+            "Attachments": [
+                {
+                    "Name": "notes.txt",
+                    "Content": "bm90ZXM=",
+                    "ContentType": "text/plain",
+                    "ContentLength": 5
+                }
+            ]
+        });
+
+        let mut email = parse(body.to_string().as_bytes()).unwrap();
+        email.fill_from_headers();
+
+        assert_eq!(email.from, "someone@example.com");
+        assert_eq!(
+            email.to,
+            vec!["\"Support\" <reply+abc@example.com>", "other@example.com"]
+        );
+        assert!(email.cc.is_empty());
+        assert_eq!(email.reply_to, None);
+        assert_eq!(email.subject, "Re: Hello");
+        assert_eq!(email.text, "Sounds good");
+        assert_eq!(email.html, "<p>Sounds good</p>");
+        assert_eq!(email.message_id.as_deref(), Some("reply@example.com"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("original@example.com"));
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].filename, "notes.txt");
+        assert_eq!(email.attachments[0].content.as_ref(), b"notes");
+    }
+}
@@ -1,15 +1,20 @@
 use error_stack::Report;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use self::services::{EmailError, EmailSender};
 
+/// Receiving email from inbound webhooks
+#[cfg(feature = "inbound_email")]
+pub mod inbound;
+/// Development routes to preview email templates
+pub mod preview;
 /// Email sending services
 pub mod services;
 /// Email template helpers
 pub mod templates;
 
 /// An email to be sent
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Email {
     /// Sender of the email
     pub from: String,
@@ -33,9 +38,17 @@ pub struct Email {
     pub tags: Vec<String>,
 }
 
+/// Get the address from a string like `Name <user@example.com>`
+pub fn bare_address(address: &str) -> &str {
+    match (address.rfind('<'), address.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &address[start + 1..end],
+        _ => address.trim(),
+    }
+}
+
 /// An attachment to an email
 #[cfg_attr(test, derive(PartialEq, Eq))]
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct EmailAttachment {
     /// The name of the attachment
     pub filename: String,
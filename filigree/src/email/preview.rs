@@ -0,0 +1,277 @@
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{OriginalUri, Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use error_stack::Report;
+
+use super::{
+    services::{noop_service::NoopEmailService, EmailError, EmailSender},
+    templates::EmailTemplate,
+    Email,
+};
+
+/// A template with sample data, to be rendered in the preview
+pub type PreviewTemplate = Box<dyn EmailTemplate + Send + Sync>;
+
+/// Render email templates with sample data, so they can be viewed in a browser while working on
+/// them. This is meant for development, and should not be enabled in production.
+///
+/// The templates are reloaded on every render, so when they are embedded with [rust_embed] in
+/// a debug build, changes to the template files show up without restarting the server.
+pub struct EmailPreviews {
+    create_templates: fn() -> tera::Tera,
+    templates: Vec<(String, PreviewTemplate)>,
+}
+
+impl EmailPreviews {
+    /// Create a new set of previews, using `create_templates` to load the templates.
+    pub fn new(create_templates: fn() -> tera::Tera) -> Self {
+        Self {
+            create_templates,
+            templates: Vec::new(),
+        }
+    }
+
+    /// Add a template, with the sample data to render it with.
+    pub fn add(
+        mut self,
+        name: impl Into<String>,
+        template: impl EmailTemplate + Send + Sync + 'static,
+    ) -> Self {
+        self.templates.push((name.into(), Box::new(template)));
+        self
+    }
+
+    /// The names of the templates, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.templates.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Render a template the same way that [EmailSender] does when sending it, including CSS
+    /// inlining. Returns `None` if there is no template with this name.
+    pub fn render(&self, name: &str) -> Option<Result<Email, Report<EmailError>>> {
+        let (_, template) = self.templates.iter().find(|(n, _)| n == name)?;
+        let sender = EmailSender::new(
+            "sender@example.com".to_string(),
+            (self.create_templates)(),
+            Box::new(NoopEmailService {}),
+        );
+        Some(sender.render_template("recipient@example.com".to_string(), template.as_ref()))
+    }
+
+    /// Create routes to view the previews.
+    ///
+    /// - `/` lists the templates
+    /// - `/{name}` shows the HTML and text versions side by side, and reloads when they change
+    /// - `/{name}/html` and `/{name}/text` return the rendered content
+    pub fn create_routes<S: Clone + Send + Sync + 'static>(self) -> Router<S> {
+        Router::new()
+            .route("/", get(list_templates))
+            .route("/:name", get(preview_template))
+            .route("/:name/html", get(template_html))
+            .route("/:name/text", get(template_text))
+            .route("/:name/version", get(template_version))
+            .with_state(Arc::new(self))
+    }
+}
+
+type PreviewState = State<Arc<EmailPreviews>>;
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_response(
+    previews: &EmailPreviews,
+    name: &str,
+    respond: impl FnOnce(Email) -> Response,
+) -> Response {
+    match previews.render(name) {
+        Some(Ok(email)) => respond(email),
+        Some(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")).into_response(),
+        None => (StatusCode::NOT_FOUND, "Template not found").into_response(),
+    }
+}
+
+/// The full path of the request, without a trailing slash. The routes may be nested anywhere, so
+/// links are built from this.
+fn base_path(uri: &OriginalUri) -> String {
+    escape_html(uri.path().trim_end_matches('/'))
+}
+
+async fn list_templates(State(previews): PreviewState, uri: OriginalUri) -> Html<String> {
+    let base = base_path(&uri);
+    let items = previews
+        .names()
+        .map(|name| {
+            let name = escape_html(name);
+            format!(r#"<li><a href="{base}/{name}">{name}</a></li>"#)
+        })
+        .collect::<String>();
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><title>Email Templates</title></head>
+<body><h1>Email Templates</h1><ul>{items}</ul></body>
+</html>"#
+    ))
+}
+
+async fn preview_template(
+    State(previews): PreviewState,
+    Path(name): Path<String>,
+    uri: OriginalUri,
+) -> Response {
+    let Some(result) = previews.render(&name) else {
+        return (StatusCode::NOT_FOUND, "Template not found").into_response();
+    };
+
+    let base = base_path(&uri);
+    let index = base
+        .rsplit_once('/')
+        .map(|(index, _)| index)
+        .unwrap_or_default();
+    let version = preview_version(&result);
+    let (title, content) = match result {
+        Ok(email) => (
+            escape_html(&email.subject),
+            format!(
+                r#"<iframe src="{base}/html" title="HTML"></iframe><pre>{}</pre>"#,
+                escape_html(&email.text)
+            ),
+        ),
+        // Show the error in the page so that it keeps checking for changes.
+        Err(e) => (
+            "Render error".to_string(),
+            format!("<pre>{}</pre>", escape_html(&format!("{e:?}"))),
+        ),
+    };
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<title>{name}</title>
+<style>
+  body {{ font-family: sans-serif; margin: 1rem; }}
+  main {{ display: flex; gap: 1rem; height: calc(100vh - 8rem); }}
+  iframe, pre {{ flex: 1; border: 1px solid #ccc; margin: 0; overflow: auto; }}
+  pre {{ padding: 0.5rem; white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p><a href="{index}">All templates</a></p>
+<main>{content}</main>
+<script>
+  setInterval(async () => {{
+    try {{
+      const res = await fetch('{base}/version');
+      if (res.ok && (await res.text()) !== '{version}') location.reload();
+    }} catch (e) {{}}
+  }}, 1000);
+</script>
+</body>
+</html>"#,
+        name = escape_html(&name),
+    ))
+    .into_response()
+}
+
+async fn template_html(State(previews): PreviewState, Path(name): Path<String>) -> Response {
+    render_response(&previews, &name, |email| Html(email.html).into_response())
+}
+
+async fn template_text(State(previews): PreviewState, Path(name): Path<String>) -> Response {
+    render_response(&previews, &name, |email| {
+        (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            email.text,
+        )
+            .into_response()
+    })
+}
+
+/// A hash of the rendered content, which the preview page polls to know when to reload.
+async fn template_version(State(previews): PreviewState, Path(name): Path<String>) -> Response {
+    match previews.render(&name) {
+        Some(result) => preview_version(&result).into_response(),
+        None => (StatusCode::NOT_FOUND, "Template not found").into_response(),
+    }
+}
+
+fn preview_version(result: &Result<Email, Report<EmailError>>) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    match result {
+        Ok(email) => {
+            email.subject.hash(&mut hasher);
+            email.html.hash(&mut hasher);
+            email.text.hash(&mut hasher);
+        }
+        Err(e) => format!("{e:?}").hash(&mut hasher),
+    }
+    format!("{:x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::templates::{render_template_pair_with_context, EmailContent, TeraError};
+
+    struct SampleTemplate;
+
+    impl EmailTemplate for SampleTemplate {
+        fn subject(&self) -> String {
+            "Sample".to_string()
+        }
+
+        fn render(&self, renderer: &tera::Tera) -> Result<EmailContent, TeraError> {
+            let mut context = tera::Context::new();
+            context.insert("name", "Someone");
+            render_template_pair_with_context(renderer, &context, "sample.html", "sample.txt")
+        }
+    }
+
+    fn create_templates() -> tera::Tera {
+        let mut tera = tera::Tera::default();
+        tera.add_raw_templates([
+            (
+                "sample.html",
+                "<html><head><style>p { color: red; }</style></head><body><p>Hi {{name}}</p></body></html>",
+            ),
+            ("sample.txt", "Hi {{name}}"),
+        ])
+        .unwrap();
+        tera
+    }
+
+    #[test]
+    fn render_preview() {
+        let previews = EmailPreviews::new(create_templates).add("sample", SampleTemplate);
+
+        assert!(previews.render("missing").is_none());
+
+        let email = previews.render("sample").unwrap().unwrap();
+        assert_eq!(email.subject, "Sample");
+        assert_eq!(email.text, "Hi Someone");
+        assert!(
+            email
+                .html
+                .contains(r#"<p style="color: red;">Hi Someone</p>"#),
+            "CSS should be inlined: {}",
+            email.html
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use error_stack::{Report, ResultExt};
+use reqwest::multipart::{Form, Part};
+use thiserror::Error;
+
+use super::{attachment_content_type, send_request, EmailError, EmailService, SentEmail};
+use crate::email::Email;
+
+/// Errors from configuring [MailgunEmailService]
+#[derive(Debug, Error)]
+pub enum MailgunConfigError {
+    /// The API key was not in the expected format
+    #[error("Expected an API key in the form domain:api_key or eu:domain:api_key")]
+    InvalidApiKey,
+}
+
+/// Sends email using Mailgun (mailgun.com)
+pub struct MailgunEmailService {
+    base_url: String,
+    domain: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for MailgunEmailService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MailgunEmailService")
+            .field("base_url", &self.base_url)
+            .field("domain", &self.domain)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MailgunEmailService {
+    /// Create a new MailgunEmailService for a sending domain, using Mailgun's US region
+    pub fn new(domain: String, api_key: String) -> Self {
+        Self::new_with_base_url("https://api.mailgun.net".to_string(), domain, api_key)
+    }
+
+    /// Create a new MailgunEmailService, overriding the base URL. Use
+    /// `https://api.eu.mailgun.net` for domains in Mailgun's EU region.
+    pub fn new_with_base_url(base_url: String, domain: String, api_key: String) -> Self {
+        Self {
+            base_url,
+            domain,
+            api_key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Create a new MailgunEmailService from an API key in the form `domain:api_key`, or
+    /// `eu:domain:api_key` for domains in Mailgun's EU region.
+    pub fn from_api_key(api_key: &str) -> Result<Self, MailgunConfigError> {
+        let parts = api_key.split(':').collect::<Vec<_>>();
+        let (base_url, domain, key) = match parts.as_slice() {
+            [domain, key] => ("https://api.mailgun.net", *domain, *key),
+            ["eu", domain, key] => ("https://api.eu.mailgun.net", *domain, *key),
+            ["us", domain, key] => ("https://api.mailgun.net", *domain, *key),
+            _ => return Err(MailgunConfigError::InvalidApiKey),
+        };
+
+        if domain.is_empty() || key.is_empty() {
+            return Err(MailgunConfigError::InvalidApiKey);
+        }
+
+        Ok(Self::new_with_base_url(
+            base_url.to_string(),
+            domain.to_string(),
+            key.to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl EmailService for MailgunEmailService {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
+        let mut form = Form::new()
+            .text("from", email.from)
+            .text("subject", email.subject);
+
+        for (field, addresses) in [("to", email.to), ("cc", email.cc), ("bcc", email.bcc)] {
+            for address in addresses {
+                form = form.text(field, address);
+            }
+        }
+
+        if let Some(reply_to) = email.reply_to {
+            form = form.text("h:Reply-To", reply_to);
+        }
+
+        if !email.text.is_empty() {
+            form = form.text("text", email.text);
+        }
+
+        if !email.html.is_empty() {
+            form = form.text("html", email.html);
+        }
+
+        for tag in email.tags {
+            form = form.text("o:tag", tag);
+        }
+
+        for attachment in email.attachments {
+            let content_type = attachment_content_type(&attachment);
+            let part = Part::bytes(attachment.content)
+                .file_name(attachment.filename)
+                .mime_str(&content_type)
+                .change_context(EmailError::Failed)
+                .attach_printable("Mailgun")?;
+            form = form.part("attachment", part);
+        }
+
+        let request = self
+            .client
+            .post(format!("{}/v3/{}/messages", self.base_url, self.domain))
+            .basic_auth("api", Some(&self.api_key))
+            .multipart(form);
+
+        let response = send_request("Mailgun", request, |_| false).await?;
+        // Mailgun returns the Message-ID header value, which is wrapped in angle brackets.
+        let message_id = response
+            .json_field("id")
+            .map(|id| id.trim_matches(['<', '>']).to_string());
+        Ok(SentEmail { message_id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+
+    use super::*;
+    use crate::email::{services::test_server, EmailAttachment, EmailBuilder};
+
+    #[test]
+    fn api_key() {
+        let service = MailgunEmailService::from_api_key("mg.example.com:key-abc").unwrap();
+        assert_eq!(service.base_url, "https://api.mailgun.net");
+        assert_eq!(service.domain, "mg.example.com");
+        assert_eq!(service.api_key, "key-abc");
+
+        let service = MailgunEmailService::from_api_key("eu:mg.example.com:key-abc").unwrap();
+        assert_eq!(service.base_url, "https://api.eu.mailgun.net");
+
+        assert!(MailgunEmailService::from_api_key("key-abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email() {
+        let (url, received) = test_server::start(
+            200,
+            r#"{"id":"<20240101.1@mg.example.com>","message":"Queued"}"#,
+        )
+        .await;
+        let service = MailgunEmailService::new_with_base_url(
+            url,
+            "mg.example.com".to_string(),
+            "key-abc".to_string(),
+        );
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .from("sender@example.com")
+            .to("another@example.com")
+            .cc("manager@example.com")
+            .reply_to("replies@example.com")
+            .text("Hi")
+            .tag("welcome")
+            .attachment(EmailAttachment {
+                filename: "hello.txt".to_string(),
+                content: b"attached".to_vec(),
+            })
+            .build();
+        let sent = service.send(email).await.unwrap();
+        assert_eq!(
+            sent.message_id.as_deref(),
+            Some("20240101.1@mg.example.com")
+        );
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.path, "/v3/mg.example.com/messages");
+        let auth = base64::engine::general_purpose::STANDARD.encode("api:key-abc");
+        assert_eq!(
+            request.headers["authorization"].to_str().unwrap(),
+            format!("Basic {auth}")
+        );
+
+        let body = String::from_utf8_lossy(&request.body);
+        let field = |name: &str, value: &str| {
+            format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n")
+        };
+        assert!(body.contains(&field("to", "someone@example.com")));
+        assert!(body.contains(&field("to", "another@example.com")));
+        assert!(body.contains(&field("cc", "manager@example.com")));
+        assert!(body.contains(&field("h:Reply-To", "replies@example.com")));
+        assert!(body.contains(&field("o:tag", "welcome")));
+        assert!(body.contains(
+            "name=\"attachment\"; filename=\"hello.txt\"\r\nContent-Type: text/plain\r\n\r\nattached"
+        ));
+    }
+}
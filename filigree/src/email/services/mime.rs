@@ -0,0 +1,119 @@
+//! Rendering emails into MIME messages, for services that send raw messages
+
+use base64::Engine;
+
+use super::attachment_content_type;
+use crate::email::Email;
+
+/// Remove line breaks from a header value, so that it can't add extra headers
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Encode a header value using RFC 2047 if it contains characters that aren't allowed in headers
+fn encoded_header_value(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        value.to_string()
+    } else {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(header_value(value));
+        format!("=?utf-8?B?{encoded}?=")
+    }
+}
+
+/// Encode content as base64, wrapped to the line length that email allows
+fn base64_lines(content: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+    let mut output = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        // base64 output is always ASCII
+        output.push_str(std::str::from_utf8(chunk).unwrap());
+        output.push_str("\r\n");
+    }
+    output
+}
+
+fn text_part(content_type: &str, content: &str) -> String {
+    format!(
+        "Content-Type: {content_type}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+        base64_lines(content.as_bytes())
+    )
+}
+
+fn multipart(subtype: &str, parts: &[String]) -> String {
+    let boundary = format!("=_{}", uuid::Uuid::now_v7().simple());
+    let mut output = format!("Content-Type: multipart/{subtype}; boundary=\"{boundary}\"\r\n\r\n");
+    for part in parts {
+        output.push_str(&format!("--{boundary}\r\n{part}\r\n"));
+    }
+    output.push_str(&format!("--{boundary}--\r\n"));
+    output
+}
+
+/// Generate a unique Message-ID, without the surrounding angle brackets
+pub(super) fn message_id(domain: &str) -> String {
+    format!("{}@{domain}", uuid::Uuid::now_v7())
+}
+
+/// Render an email into a MIME message. BCC recipients are left out of the headers.
+pub(super) fn build_message(email: &Email, message_id: &str) -> String {
+    let mut headers = vec![
+        format!("Date: {}", chrono::Utc::now().to_rfc2822()),
+        format!("Message-ID: <{message_id}>"),
+        format!("From: {}", header_value(&email.from)),
+        format!("To: {}", header_value(&email.to.join(", "))),
+    ];
+
+    if !email.cc.is_empty() {
+        headers.push(format!("Cc: {}", header_value(&email.cc.join(", "))));
+    }
+
+    if let Some(reply_to) = &email.reply_to {
+        headers.push(format!("Reply-To: {}", header_value(reply_to)));
+    }
+
+    headers.push(format!("Subject: {}", encoded_header_value(&email.subject)));
+    headers.push("MIME-Version: 1.0".to_string());
+
+    let text = (!email.text.is_empty()).then(|| text_part("text/plain", &email.text));
+    let html = (!email.html.is_empty()).then(|| text_part("text/html", &email.html));
+    let content = match (text, html) {
+        (Some(text), Some(html)) => multipart("alternative", &[text, html]),
+        (Some(part), None) | (None, Some(part)) => part,
+        (None, None) => text_part("text/plain", ""),
+    };
+
+    let body = if email.attachments.is_empty() {
+        content
+    } else {
+        let parts = std::iter::once(content)
+            .chain(email.attachments.iter().map(|attachment| {
+                let filename = header_value(&attachment.filename).replace('"', "'");
+                let content_type = attachment_content_type(attachment);
+                format!(
+                    "Content-Type: {content_type}; name=\"{filename}\"\r\nContent-Disposition: attachment; filename=\"{filename}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+                    base64_lines(&attachment.content)
+                )
+            }))
+            .collect::<Vec<_>>();
+        multipart("mixed", &parts)
+    };
+
+    format!("{}\r\n{body}", headers.join("\r\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::EmailBuilder;
+
+    #[test]
+    fn message_line_endings() {
+        let email = EmailBuilder::new("someone@example.com", ".Subject")
+            .from("sender@example.com")
+            .text("Hi")
+            .build();
+        let message = build_message(&email, &message_id("example.com"));
+        assert!(message.contains("Subject: .Subject\r\n"));
+        assert!(!message.replace("\r\n", "").contains('\n'));
+    }
+}
@@ -1,8 +1,25 @@
+/// Mailgun email service support
+#[cfg(feature = "mailgun")]
+pub mod mailgun;
+#[cfg(any(feature = "smtp", feature = "ses"))]
+mod mime;
 /// An email service that does nothing, for testing and early development
 pub mod noop_service;
+/// Postmark email service support
+#[cfg(feature = "postmark")]
+pub mod postmark;
 /// ReSend email service support
 #[cfg(feature = "resend")]
 pub mod resend;
+/// SendGrid email service support
+#[cfg(feature = "sendgrid")]
+pub mod sendgrid;
+/// Amazon SES email service support
+#[cfg(feature = "ses")]
+pub mod ses;
+/// SMTP email service support
+#[cfg(feature = "smtp")]
+pub mod smtp;
 /// An email sender for tests, which doesn't send the emails but does save the content for later
 /// inspection.
 pub mod test_service;
@@ -27,11 +44,105 @@ pub enum EmailError {
     TooLarge,
 }
 
+/// Information about an email that a service accepted for delivery
+#[derive(Debug, Default, Clone)]
+pub struct SentEmail {
+    /// The ID that the service assigned to the message, for services that return one
+    pub message_id: Option<String>,
+}
+
 /// A service that can send an email
 #[async_trait]
 pub trait EmailService: Send + Sync {
     /// Send an email
-    async fn send(&self, email: Email) -> Result<(), EmailError>;
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>>;
+}
+
+/// Stores emails so that a background worker can send them later, with retries. This keeps a
+/// provider outage from failing the request that sent the email.
+#[async_trait]
+pub trait EmailOutbox: Send + Sync {
+    /// Save an email to be sent
+    async fn enqueue(&self, email: Email) -> Result<(), Report<EmailError>>;
+}
+
+/// Guess the MIME type of an attachment from its filename
+#[cfg(any(
+    feature = "mailgun",
+    feature = "postmark",
+    feature = "sendgrid",
+    feature = "ses",
+    feature = "smtp"
+))]
+fn attachment_content_type(attachment: &super::EmailAttachment) -> String {
+    mime_guess::from_path(&attachment.filename)
+        .first_or_octet_stream()
+        .to_string()
+}
+
+/// A successful response from an email provider's HTTP API
+#[cfg(any(
+    feature = "mailgun",
+    feature = "postmark",
+    feature = "resend",
+    feature = "sendgrid",
+    feature = "ses"
+))]
+struct ProviderResponse {
+    headers: reqwest::header::HeaderMap,
+    body: String,
+}
+
+#[cfg(any(
+    feature = "mailgun",
+    feature = "postmark",
+    feature = "resend",
+    feature = "sendgrid",
+    feature = "ses"
+))]
+impl ProviderResponse {
+    /// Read a string field from the JSON response body
+    fn json_field(&self, field: &str) -> Option<String> {
+        let body = serde_json::from_str::<serde_json::Value>(&self.body).ok()?;
+        body.get(field)?.as_str().map(|s| s.to_string())
+    }
+}
+
+/// Send a request to an email provider's HTTP API. Error responses are returned as
+/// [EmailError::TooLarge] if the status is 413 or `too_large` returns true for the response body,
+/// and [EmailError::Failed] otherwise, with the status and body attached.
+#[cfg(any(
+    feature = "mailgun",
+    feature = "postmark",
+    feature = "resend",
+    feature = "sendgrid",
+    feature = "ses"
+))]
+async fn send_request(
+    provider: &'static str,
+    request: reqwest::RequestBuilder,
+    too_large: impl Fn(&str) -> bool,
+) -> Result<ProviderResponse, Report<EmailError>> {
+    let response = request
+        .send()
+        .await
+        .change_context(EmailError::Failed)
+        .attach_printable(provider)?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        return Ok(ProviderResponse { headers, body });
+    }
+
+    let error = if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE || too_large(&body) {
+        EmailError::TooLarge
+    } else {
+        EmailError::Failed
+    };
+
+    Err(Report::new(error).attach_printable(format!("{provider} returned {status}: {body}")))
 }
 
 /// A service that manages email sending
@@ -39,6 +150,7 @@ pub struct EmailSender {
     default_from: String,
     templates: tera::Tera,
     service: Box<dyn EmailService>,
+    outbox: Option<Box<dyn EmailOutbox>>,
 }
 
 impl EmailSender {
@@ -52,11 +164,43 @@ impl EmailSender {
             default_from,
             templates,
             service,
+            outbox: None,
+        }
+    }
+
+    /// Save emails to an outbox instead of sending them immediately. A background worker should
+    /// then send them using [EmailSender::send_now].
+    pub fn with_outbox(mut self, outbox: Box<dyn EmailOutbox>) -> Self {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Return true if this sender saves emails to an outbox
+    pub fn has_outbox(&self) -> bool {
+        self.outbox.is_some()
+    }
+
+    /// Send an email, filling in any unset fields that have a default. If there is an outbox,
+    /// the email is saved there to be sent later.
+    pub async fn send(&self, email: Email) -> Result<(), Report<EmailError>> {
+        let email = self.prepare(email)?;
+        self.deliver(email).await
+    }
+
+    async fn deliver(&self, email: Email) -> Result<(), Report<EmailError>> {
+        match &self.outbox {
+            Some(outbox) => outbox.enqueue(email).await,
+            None => self.service.send(email).await.map(|_| ()),
         }
     }
 
-    /// Send an email, filling in any unset fields that have a default
-    pub async fn send(&self, mut email: Email) -> Result<(), Report<EmailError>> {
+    /// Send an email immediately through the email service, bypassing the outbox.
+    pub async fn send_now(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
+        let email = self.prepare(email)?;
+        self.service.send(email).await
+    }
+
+    fn prepare(&self, mut email: Email) -> Result<Email, Report<EmailError>> {
         if email.from.is_empty() {
             email.from = self.default_from.clone();
         }
@@ -70,22 +214,31 @@ impl EmailSender {
                 .change_context(EmailError::Rendering)?;
         }
 
-        self.service.send(email).await?;
-        Ok(())
+        Ok(email)
     }
 
-    /// Render an email template and send the email.
-    pub async fn send_template(
+    /// Render an email template into an email, exactly as [EmailSender::send_template] would
+    /// send it, without sending it.
+    pub fn render_template(
         &self,
         to: String,
-        template: impl EmailTemplate,
-    ) -> Result<(), Report<EmailError>> {
+        template: &(impl EmailTemplate + ?Sized),
+    ) -> Result<Email, Report<EmailError>> {
         let email = template
             .into_email(&self.templates, to)?
             .from(self.default_from.clone())
             .build();
-        self.send(email).await?;
-        Ok(())
+        self.prepare(email)
+    }
+
+    /// Render an email template and send the email.
+    pub async fn send_template(
+        &self,
+        to: String,
+        template: impl EmailTemplate,
+    ) -> Result<(), Report<EmailError>> {
+        let email = self.render_template(to, &template)?;
+        self.deliver(email).await
     }
 }
 
@@ -96,6 +249,114 @@ pub fn email_service_from_name(name: &str, api_key: String) -> Box<dyn EmailServ
         "none" => Box::new(noop_service::NoopEmailService {}),
         #[cfg(feature = "resend")]
         "resend" => Box::new(resend::ResendEmailService::new(api_key)),
+        #[cfg(feature = "postmark")]
+        "postmark" => Box::new(postmark::PostmarkEmailService::new(api_key)),
+        #[cfg(feature = "sendgrid")]
+        "sendgrid" => Box::new(sendgrid::SendgridEmailService::new(api_key)),
+        #[cfg(feature = "ses")]
+        "ses" => Box::new(
+            ses::SesEmailService::from_api_key(&api_key)
+                .unwrap_or_else(|e| panic!("Invalid SES configuration: {e}")),
+        ),
+        #[cfg(feature = "mailgun")]
+        "mailgun" => Box::new(
+            mailgun::MailgunEmailService::from_api_key(&api_key)
+                .unwrap_or_else(|e| panic!("Invalid Mailgun configuration: {e}")),
+        ),
+        #[cfg(feature = "smtp")]
+        "smtp" => Box::new(
+            smtp::SmtpEmailService::from_url(&api_key)
+                .unwrap_or_else(|e| panic!("Invalid SMTP configuration: {e}")),
+        ),
         _ => panic!("Unknown email service: {}", name),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{test_service::TestEmailService, EmailSender};
+    use crate::email::EmailBuilder;
+
+    #[tokio::test]
+    async fn outbox() {
+        let service = TestEmailService::new();
+        let sent = service.emails.clone();
+        let queued = service.queued.clone();
+        let outbox = service.outbox();
+        let sender = EmailSender::new(
+            "default@example.com".to_string(),
+            tera::Tera::default(),
+            Box::new(service),
+        )
+        .with_outbox(Box::new(outbox));
+        assert!(sender.has_outbox());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .text("Hi")
+            .build();
+        sender.send(email).await.unwrap();
+        assert!(sent.lock().unwrap().is_empty());
+
+        // The worker takes the queued email and sends it.
+        let email = queued.lock().unwrap().pop().unwrap();
+        assert_eq!(email.from, "default@example.com");
+        let result = sender.send_now(email).await.unwrap();
+        assert_eq!(result.message_id.as_deref(), Some("test-1"));
+        assert_eq!(sent.lock().unwrap()[0].subject, "Hello");
+    }
+}
+
+/// A local HTTP server that records the requests it receives, for testing the provider APIs
+#[cfg(all(
+    test,
+    any(
+        feature = "mailgun",
+        feature = "postmark",
+        feature = "resend",
+        feature = "sendgrid",
+        feature = "ses"
+    )
+))]
+mod test_server {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::{OriginalUri, State},
+        http::{HeaderMap, StatusCode},
+        Router,
+    };
+
+    #[derive(Debug)]
+    pub struct Request {
+        pub path: String,
+        pub headers: HeaderMap,
+        pub body: bytes::Bytes,
+    }
+
+    pub type Received = Arc<Mutex<Vec<Request>>>;
+
+    /// Start a server that responds to every request with `status` and `body`, returning its base
+    /// URL and the requests it receives.
+    pub async fn start(status: u16, body: &'static str) -> (String, Received) {
+        let received = Received::default();
+        let status = StatusCode::from_u16(status).unwrap();
+        let handler = move |State(received): State<Received>,
+                            OriginalUri(uri): OriginalUri,
+                            headers: HeaderMap,
+                            request_body: bytes::Bytes| async move {
+            received.lock().unwrap().push(Request {
+                path: uri.path().to_string(),
+                headers,
+                body: request_body,
+            });
+            (status, body)
+        };
+        let app = Router::new().fallback(handler).with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{addr}"), received)
+    }
+}
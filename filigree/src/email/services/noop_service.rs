@@ -1,6 +1,7 @@
 use async_trait::async_trait;
+use error_stack::Report;
 
-use super::{EmailError, EmailService};
+use super::{EmailError, EmailService, SentEmail};
 use crate::email::Email;
 
 /// An email service that doesn't send emails. Can be useful when first starting out a project.
@@ -8,7 +9,7 @@ pub struct NoopEmailService {}
 
 #[async_trait]
 impl EmailService for NoopEmailService {
-    async fn send(&self, _email: Email) -> Result<(), EmailError> {
-        Ok(())
+    async fn send(&self, _email: Email) -> Result<SentEmail, Report<EmailError>> {
+        Ok(SentEmail::default())
     }
 }
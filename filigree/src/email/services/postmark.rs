@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use base64::Engine;
+use error_stack::Report;
+use serde::Serialize;
+
+use super::{attachment_content_type, send_request, EmailError, EmailService, SentEmail};
+use crate::email::Email;
+
+/// Sends email using Postmark (postmarkapp.com)
+pub struct PostmarkEmailService {
+    base_url: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for PostmarkEmailService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostmarkEmailService")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PostmarkEmailService {
+    /// Create a new PostmarkEmailService at the normal base URL, using a server API token
+    pub fn new(token: String) -> Self {
+        Self::new_with_base_url("https://api.postmarkapp.com".to_string(), token)
+    }
+
+    /// Create a new PostmarkEmailService, overriding the base URL
+    pub fn new_with_base_url(base_url: String, token: String) -> Self {
+        Self {
+            base_url,
+            token,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailService for PostmarkEmailService {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
+        // Postmark only supports a single tag, so any others are passed along as metadata.
+        let mut tags = email.tags.into_iter();
+        let tag = tags.next();
+        let mut metadata = HashMap::new();
+        let other_tags = tags.collect::<Vec<_>>();
+        if !other_tags.is_empty() {
+            metadata.insert("tags", other_tags.join(","));
+        }
+
+        let body = PostmarkEmailBody {
+            from: email.from,
+            to: email.to.join(","),
+            cc: email.cc.join(","),
+            bcc: email.bcc.join(","),
+            reply_to: email.reply_to,
+            subject: email.subject,
+            text_body: email.text,
+            html_body: email.html,
+            tag,
+            metadata,
+            attachments: email
+                .attachments
+                .iter()
+                .map(|attachment| PostmarkAttachment {
+                    content_type: attachment_content_type(attachment),
+                    content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+                    name: attachment.filename.clone(),
+                })
+                .collect(),
+        };
+
+        let request = self
+            .client
+            .post(format!("{}/email", self.base_url))
+            .header("X-Postmark-Server-Token", &self.token)
+            .header("Accept", "application/json")
+            .json(&body);
+
+        let response = send_request("Postmark", request, |_| false).await?;
+        Ok(SentEmail {
+            message_id: response.json_field("MessageID"),
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEmailBody {
+    from: String,
+    to: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    cc: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    bcc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<String>,
+    subject: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    text_body: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    html_body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<&'static str, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment {
+    name: String,
+    content: String,
+    content_type: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::{services::test_server, EmailAttachment, EmailBuilder};
+
+    #[tokio::test]
+    async fn send_email() {
+        let (url, received) =
+            test_server::start(200, r#"{"ErrorCode":0,"MessageID":"b7bc2f4a"}"#).await;
+        let service = PostmarkEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .from("sender@example.com")
+            .to("another@example.com")
+            .bcc("archive@example.com")
+            .reply_to("replies@example.com")
+            .html("<p>Hi</p>")
+            .tag("welcome")
+            .tag("onboarding")
+            .attachment(EmailAttachment {
+                filename: "hello.txt".to_string(),
+                content: b"attached".to_vec(),
+            })
+            .build();
+        let sent = service.send(email).await.unwrap();
+        assert_eq!(sent.message_id.as_deref(), Some("b7bc2f4a"));
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.path, "/email");
+        assert_eq!(request.headers["x-postmark-server-token"], "the-token");
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "From": "sender@example.com",
+                "To": "someone@example.com,another@example.com",
+                "Bcc": "archive@example.com",
+                "ReplyTo": "replies@example.com",
+                "Subject": "Hello",
+                "HtmlBody": "<p>Hi</p>",
+                "Tag": "welcome",
+                "Metadata": { "tags": "onboarding" },
+                "Attachments": [{
+                    "Name": "hello.txt",
+                    "Content": "YXR0YWNoZWQ=",
+                    "ContentType": "text/plain",
+                }],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn error_details() {
+        let (url, _) = test_server::start(
+            422,
+            r#"{"ErrorCode":300,"Message":"Invalid 'From' address"}"#,
+        )
+        .await;
+        let service = PostmarkEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .text("Hi")
+            .build();
+        let err = service.send(email).await.unwrap_err();
+        assert!(matches!(err.current_context(), EmailError::Failed));
+        assert!(format!("{err:?}").contains("Invalid 'From' address"));
+    }
+}
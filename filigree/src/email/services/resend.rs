@@ -1,7 +1,8 @@
 use async_trait::async_trait;
+use error_stack::Report;
 use serde::Serialize;
 
-use super::{EmailError, EmailService};
+use super::{send_request, EmailError, EmailService, SentEmail};
 use crate::email::{Email, EmailAttachment};
 
 /// Sends email using Resend (resend.com)
@@ -41,7 +42,7 @@ impl ResendEmailService {
 
 #[async_trait]
 impl EmailService for ResendEmailService {
-    async fn send(&self, email: Email) -> Result<(), EmailError> {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
         let body = ResendEmailBody {
             from: email.from,
             to: email.to,
@@ -59,18 +60,16 @@ impl EmailService for ResendEmailService {
                 .collect(),
         };
 
-        self.client
+        let request = self
+            .client
             .post(format!("{}/emails", self.base_url))
             .header("Authorization", format!("Bearer {}", self.token))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|_| EmailError::Failed)?
-            .error_for_status()
-            // TODO better error decoding -- https://resend.com/docs/api-reference/errors
-            .map_err(|_| EmailError::Failed)?;
-
-        Ok(())
+            .json(&body);
+
+        let response = send_request("Resend", request, |_| false).await?;
+        Ok(SentEmail {
+            message_id: response.json_field("id"),
+        })
     }
 }
 
@@ -99,3 +98,50 @@ struct ResendEmailBody {
 struct Tag {
     name: String,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::{services::test_server, EmailBuilder};
+
+    #[tokio::test]
+    async fn send_email() {
+        let (url, received) = test_server::start(200, r#"{"id":"abc"}"#).await;
+        let service = ResendEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .from("sender@example.com")
+            .reply_to("replies@example.com")
+            .text("Hi")
+            .tag("welcome")
+            .build();
+        let sent = service.send(email).await.unwrap();
+        assert_eq!(sent.message_id.as_deref(), Some("abc"));
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.path, "/emails");
+        assert_eq!(request.headers["authorization"], "Bearer the-token");
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["reply_to"], "replies@example.com");
+        assert_eq!(body["tags"], serde_json::json!([{ "name": "welcome" }]));
+    }
+
+    #[tokio::test]
+    async fn error_details() {
+        let (url, _) = test_server::start(
+            422,
+            r#"{"name":"validation_error","message":"Invalid `to` field."}"#,
+        )
+        .await;
+        let service = ResendEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("not an address", "Hello")
+            .text("Hi")
+            .build();
+        let err = service.send(email).await.unwrap_err();
+        assert!(matches!(err.current_context(), EmailError::Failed));
+        assert!(format!("{err:?}").contains("Invalid `to` field."));
+    }
+}
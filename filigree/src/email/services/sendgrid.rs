@@ -0,0 +1,227 @@
+use async_trait::async_trait;
+use base64::Engine;
+use error_stack::Report;
+use serde::Serialize;
+
+use super::{attachment_content_type, send_request, EmailError, EmailService, SentEmail};
+use crate::email::Email;
+
+/// Sends email using SendGrid (sendgrid.com)
+pub struct SendgridEmailService {
+    base_url: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for SendgridEmailService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendgridEmailService")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SendgridEmailService {
+    /// Create a new SendgridEmailService at the normal base URL
+    pub fn new(token: String) -> Self {
+        Self::new_with_base_url("https://api.sendgrid.com".to_string(), token)
+    }
+
+    /// Create a new SendgridEmailService, overriding the base URL
+    pub fn new_with_base_url(base_url: String, token: String) -> Self {
+        Self {
+            base_url,
+            token,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailService for SendgridEmailService {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
+        // SendGrid requires the plain text content to come first.
+        let content = [("text/plain", email.text), ("text/html", email.html)]
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(content_type, value)| Content {
+                content_type,
+                value,
+            })
+            .collect();
+
+        let body = SendgridEmailBody {
+            personalizations: [Personalization {
+                to: addresses(&email.to),
+                cc: addresses(&email.cc),
+                bcc: addresses(&email.bcc),
+            }],
+            from: Address::parse(&email.from),
+            reply_to: email.reply_to.as_deref().map(Address::parse),
+            subject: email.subject,
+            content,
+            attachments: email
+                .attachments
+                .iter()
+                .map(|attachment| Attachment {
+                    content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+                    content_type: attachment_content_type(attachment),
+                    filename: attachment.filename.clone(),
+                    disposition: "attachment",
+                })
+                .collect(),
+            categories: email.tags,
+        };
+
+        let request = self
+            .client
+            .post(format!("{}/v3/mail/send", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .json(&body);
+
+        let response = send_request("SendGrid", request, |_| false).await?;
+        let message_id = response
+            .headers
+            .get("x-message-id")
+            .and_then(|id| id.to_str().ok())
+            .map(|id| id.to_string());
+        Ok(SentEmail { message_id })
+    }
+}
+
+fn addresses(addresses: &[String]) -> Vec<Address> {
+    addresses.iter().map(|a| Address::parse(a)).collect()
+}
+
+#[derive(Serialize)]
+struct SendgridEmailBody {
+    personalizations: [Personalization; 1],
+    from: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<Address>,
+    subject: String,
+    content: Vec<Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    categories: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Personalization {
+    to: Vec<Address>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc: Vec<Address>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc: Vec<Address>,
+}
+
+#[derive(Serialize)]
+struct Address {
+    email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl Address {
+    /// Parse an address that may be in the form `Name <user@example.com>`
+    fn parse(address: &str) -> Self {
+        match (address.rfind('<'), address.rfind('>')) {
+            (Some(start), Some(end)) if start < end => {
+                let name = address[..start].trim().trim_matches('"');
+                Self {
+                    email: address[start + 1..end].to_string(),
+                    name: (!name.is_empty()).then(|| name.to_string()),
+                }
+            }
+            _ => Self {
+                email: address.trim().to_string(),
+                name: None,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Content {
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct Attachment {
+    content: String,
+    #[serde(rename = "type")]
+    content_type: String,
+    filename: String,
+    disposition: &'static str,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::{services::test_server, EmailAttachment, EmailBuilder};
+
+    #[tokio::test]
+    async fn send_email() {
+        let (url, received) = test_server::start(202, "").await;
+        let service = SendgridEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("Someone <someone@example.com>", "Hello")
+            .from("\"The Sender\" <sender@example.com>")
+            .cc("manager@example.com")
+            .reply_to("replies@example.com")
+            .text("Hi")
+            .html("<p>Hi</p>")
+            .tag("welcome")
+            .attachment(EmailAttachment {
+                filename: "hello.txt".to_string(),
+                content: b"attached".to_vec(),
+            })
+            .build();
+        service.send(email).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.path, "/v3/mail/send");
+        assert_eq!(request.headers["authorization"], "Bearer the-token");
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "personalizations": [{
+                    "to": [{ "email": "someone@example.com", "name": "Someone" }],
+                    "cc": [{ "email": "manager@example.com" }],
+                }],
+                "from": { "email": "sender@example.com", "name": "The Sender" },
+                "reply_to": { "email": "replies@example.com" },
+                "subject": "Hello",
+                "content": [
+                    { "type": "text/plain", "value": "Hi" },
+                    { "type": "text/html", "value": "<p>Hi</p>" },
+                ],
+                "attachments": [{
+                    "content": "YXR0YWNoZWQ=",
+                    "type": "text/plain",
+                    "filename": "hello.txt",
+                    "disposition": "attachment",
+                }],
+                "categories": ["welcome"],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        let (url, _) = test_server::start(413, "Payload Too Large").await;
+        let service = SendgridEmailService::new_with_base_url(url, "the-token".to_string());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .text("Hi")
+            .build();
+        let err = service.send(email).await.unwrap_err();
+        assert!(matches!(err.current_context(), EmailError::TooLarge));
+    }
+}
@@ -0,0 +1,509 @@
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::{
+    mime::{build_message, message_id},
+    send_request, EmailError, EmailService, SentEmail,
+};
+use crate::email::{bare_address, Email};
+
+/// AWS credentials for sending email through SES
+#[derive(Clone)]
+pub struct SesCredentials {
+    /// The access key ID
+    pub access_key_id: String,
+    /// The secret access key
+    pub secret_access_key: String,
+    /// The session token, when using temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for SesCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SesCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Errors from configuring [SesEmailService]
+#[derive(Debug, Error)]
+pub enum SesConfigError {
+    /// A required environment variable was not set
+    #[error("Missing environment variable {0}")]
+    MissingEnv(&'static str),
+    /// The API key was not in the expected format
+    #[error("Expected an API key in the form region:access_key_id:secret_access_key")]
+    InvalidApiKey,
+}
+
+/// Sends email using the Amazon SES v2 API
+pub struct SesEmailService {
+    base_url: String,
+    region: String,
+    credentials: SesCredentials,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for SesEmailService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SesEmailService")
+            .field("base_url", &self.base_url)
+            .field("region", &self.region)
+            .field("credentials", &self.credentials)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SesEmailService {
+    /// Create a new SesEmailService for the given AWS region
+    pub fn new(region: String, credentials: SesCredentials) -> Self {
+        Self::new_with_base_url(
+            format!("https://email.{region}.amazonaws.com"),
+            region,
+            credentials,
+        )
+    }
+
+    /// Create a new SesEmailService, overriding the base URL
+    pub fn new_with_base_url(
+        base_url: String,
+        region: String,
+        credentials: SesCredentials,
+    ) -> Self {
+        Self {
+            base_url,
+            region,
+            credentials,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Create a new SesEmailService from the standard `AWS_REGION`, `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY`, and `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env() -> Result<Self, SesConfigError> {
+        let var = |name: &'static str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .ok_or(SesConfigError::MissingEnv("AWS_REGION"))?;
+        let credentials = SesCredentials {
+            access_key_id: var("AWS_ACCESS_KEY_ID")
+                .ok_or(SesConfigError::MissingEnv("AWS_ACCESS_KEY_ID"))?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")
+                .ok_or(SesConfigError::MissingEnv("AWS_SECRET_ACCESS_KEY"))?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        };
+
+        Ok(Self::new(region, credentials))
+    }
+
+    /// Create a new SesEmailService from an API key in the form
+    /// `region:access_key_id:secret_access_key`. If the key is empty, the configuration is read
+    /// from the environment as in [SesEmailService::from_env].
+    pub fn from_api_key(api_key: &str) -> Result<Self, SesConfigError> {
+        if api_key.is_empty() {
+            return Self::from_env();
+        }
+
+        let mut parts = api_key.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(region), Some(access_key_id), Some(secret_access_key))
+                if !region.is_empty() && !access_key_id.is_empty() =>
+            {
+                Ok(Self::new(
+                    region.to_string(),
+                    SesCredentials {
+                        access_key_id: access_key_id.to_string(),
+                        secret_access_key: secret_access_key.to_string(),
+                        session_token: None,
+                    },
+                ))
+            }
+            _ => Err(SesConfigError::InvalidApiKey),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailService for SesEmailService {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
+        // Simple messages don't support attachments, so send a raw MIME message when there are any.
+        let content = if email.attachments.is_empty() {
+            let text = |data: &str| {
+                (!data.is_empty()).then(|| SesText {
+                    data: data.to_string(),
+                    charset: "UTF-8",
+                })
+            };
+
+            SesContent::Simple(SimpleMessage {
+                subject: SesText {
+                    data: email.subject.clone(),
+                    charset: "UTF-8",
+                },
+                body: SimpleBody {
+                    text: text(&email.text),
+                    html: text(&email.html),
+                },
+            })
+        } else {
+            let domain = bare_address(&email.from)
+                .rsplit_once('@')
+                .map(|(_, domain)| domain)
+                .unwrap_or("localhost");
+            let message = build_message(&email, &message_id(domain));
+            SesContent::Raw(RawMessage {
+                data: base64::engine::general_purpose::STANDARD.encode(message),
+            })
+        };
+
+        let body = SesEmailBody {
+            from_email_address: email.from,
+            destination: Destination {
+                to_addresses: email.to,
+                cc_addresses: email.cc,
+                bcc_addresses: email.bcc,
+            },
+            reply_to_addresses: email.reply_to.into_iter().collect(),
+            content,
+            email_tags: email.tags.iter().map(|tag| SesTag::from_tag(tag)).collect(),
+        };
+
+        let url = format!("{}/v2/email/outbound-emails", self.base_url);
+        let body = serde_json::to_vec(&body).map_err(|e| {
+            Report::new(e)
+                .change_context(EmailError::Failed)
+                .attach_printable("SES")
+        })?;
+        let headers = sign_request(
+            "POST",
+            &url,
+            &body,
+            &self.credentials,
+            &self.region,
+            "ses",
+            Utc::now(),
+        );
+
+        let mut request = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = send_request("SES", request, |body| body.contains("Message length")).await?;
+        Ok(SentEmail {
+            message_id: response.json_field("MessageId"),
+        })
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Sign a request with AWS Signature Version 4, returning the headers to add to the request.
+fn sign_request(
+    method: &str,
+    url: &str,
+    body: &[u8],
+    credentials: &SesCredentials,
+    region: &str,
+    service: &str,
+    now: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let url = url::Url::parse(url).expect("SES URL is valid");
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut headers = vec![("host", host), ("x-amz-date", amz_date.clone())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+        path = url.path(),
+        query = url.query().unwrap_or_default(),
+        payload_hash = hex::encode(Sha256::digest(body)),
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac(key.as_bytes(), &date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    let key = hmac(&key, "aws4_request");
+    let signature = hex::encode(hmac(&key, &string_to_sign));
+
+    // reqwest adds the Host header itself.
+    headers.retain(|(name, _)| *name != "host");
+    headers.push((
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            credentials.access_key_id
+        ),
+    ));
+
+    headers
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SesEmailBody {
+    from_email_address: String,
+    destination: Destination,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reply_to_addresses: Vec<String>,
+    content: SesContent,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    email_tags: Vec<SesTag>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Destination {
+    to_addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc_addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc_addresses: Vec<String>,
+}
+
+#[derive(Serialize)]
+enum SesContent {
+    Simple(SimpleMessage),
+    Raw(RawMessage),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SimpleMessage {
+    subject: SesText,
+    body: SimpleBody,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SimpleBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<SesText>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<SesText>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SesText {
+    data: String,
+    charset: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawMessage {
+    data: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SesTag {
+    name: String,
+    value: String,
+}
+
+impl SesTag {
+    /// SES tags are name/value pairs, so a tag of `name=value` is split into its parts and any
+    /// other tag is sent with a value of `true`. Characters that SES doesn't allow are replaced
+    /// with underscores.
+    fn from_tag(tag: &str) -> Self {
+        let clean = |s: &str| {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        };
+
+        let (name, value) = tag.split_once('=').unwrap_or((tag, "true"));
+        Self {
+            name: clean(name),
+            value: clean(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::email::{services::test_server, EmailAttachment, EmailBuilder};
+
+    fn credentials() -> SesCredentials {
+        SesCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn signature() {
+        // The get-vanilla case from the AWS Signature Version 4 test suite
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sign_request(
+            "GET",
+            "https://example.amazonaws.com/",
+            b"",
+            &credentials(),
+            "us-east-1",
+            "service",
+            now,
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                ("x-amz-date", "20150830T123600Z".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn api_key() {
+        let service = SesEmailService::from_api_key("us-west-2:AKID:secret:with:colons").unwrap();
+        assert_eq!(service.region, "us-west-2");
+        assert_eq!(service.base_url, "https://email.us-west-2.amazonaws.com");
+        assert_eq!(service.credentials.secret_access_key, "secret:with:colons");
+
+        assert!(SesEmailService::from_api_key("us-west-2").is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email() {
+        let (url, received) = test_server::start(200, r#"{"MessageId":"abc"}"#).await;
+        let service =
+            SesEmailService::new_with_base_url(url, "us-east-1".to_string(), credentials());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .from("sender@example.com")
+            .bcc("archive@example.com")
+            .reply_to("replies@example.com")
+            .text("Hi")
+            .tag("category=welcome")
+            .tag("first email")
+            .build();
+        let sent = service.send(email).await.unwrap();
+        assert_eq!(sent.message_id.as_deref(), Some("abc"));
+
+        let with_attachment = EmailBuilder::new("someone@example.com", "Attached")
+            .from("sender@example.com")
+            .text("See attached")
+            .attachment(EmailAttachment {
+                filename: "hello.txt".to_string(),
+                content: b"attached".to_vec(),
+            })
+            .build();
+        service.send(with_attachment).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.path, "/v2/email/outbound-emails");
+        let auth = request.headers["authorization"].to_str().unwrap();
+        assert!(auth.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(auth.contains("/us-east-1/ses/aws4_request"));
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "FromEmailAddress": "sender@example.com",
+                "Destination": {
+                    "ToAddresses": ["someone@example.com"],
+                    "BccAddresses": ["archive@example.com"],
+                },
+                "ReplyToAddresses": ["replies@example.com"],
+                "Content": {
+                    "Simple": {
+                        "Subject": { "Data": "Hello", "Charset": "UTF-8" },
+                        "Body": { "Text": { "Data": "Hi", "Charset": "UTF-8" } },
+                    }
+                },
+                "EmailTags": [
+                    { "Name": "category", "Value": "welcome" },
+                    { "Name": "first_email", "Value": "true" },
+                ],
+            })
+        );
+
+        let body: serde_json::Value = serde_json::from_slice(&received[1].body).unwrap();
+        let raw = base64::engine::general_purpose::STANDARD
+            .decode(body["Content"]["Raw"]["Data"].as_str().unwrap())
+            .unwrap();
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.contains("Content-Disposition: attachment; filename=\"hello.txt\""));
+        assert!(raw.contains("@example.com>\r\n"));
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        let (url, _) = test_server::start(
+            400,
+            r#"{"message":"Message length is more than 10485760 bytes long: '10485761'."}"#,
+        )
+        .await;
+        let service =
+            SesEmailService::new_with_base_url(url, "us-east-1".to_string(), credentials());
+
+        let email = EmailBuilder::new("someone@example.com", "Hello")
+            .from("sender@example.com")
+            .text("Hi")
+            .build();
+        let err = service.send(email).await.unwrap_err();
+        assert!(matches!(err.current_context(), EmailError::TooLarge));
+        assert!(format!("{err:?}").contains("10485760"));
+    }
+}
@@ -1,9 +1,10 @@
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
+use error_stack::Report;
 use tracing::{event, Level};
 
-use super::{EmailError, EmailService};
+use super::{EmailError, EmailOutbox, EmailService, SentEmail};
 use crate::email::Email;
 
 /// An email service that doesn't send emails, but does save the generated emails for later
@@ -11,6 +12,8 @@ use crate::email::Email;
 pub struct TestEmailService {
     /// The emails that have been sent
     pub emails: Arc<Mutex<Vec<Email>>>,
+    /// The emails that have been saved to this service's [TestEmailOutbox]
+    pub queued: Arc<Mutex<Vec<Email>>>,
 }
 
 impl TestEmailService {
@@ -18,15 +21,43 @@ impl TestEmailService {
     pub fn new() -> Self {
         Self {
             emails: Arc::new(Mutex::new(vec![])),
+            queued: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Create an outbox that saves emails to [TestEmailService::queued], for testing code that
+    /// uses [EmailSender::with_outbox](super::EmailSender::with_outbox).
+    pub fn outbox(&self) -> TestEmailOutbox {
+        TestEmailOutbox {
+            queued: self.queued.clone(),
         }
     }
 }
 
 #[async_trait]
 impl EmailService for TestEmailService {
-    async fn send(&self, email: Email) -> Result<(), EmailError> {
+    async fn send(&self, email: Email) -> Result<SentEmail, Report<EmailError>> {
         event!(Level::INFO, ?email, "Sending email");
-        self.emails.lock().unwrap().push(email);
+        let mut emails = self.emails.lock().unwrap();
+        emails.push(email);
+        Ok(SentEmail {
+            message_id: Some(format!("test-{}", emails.len())),
+        })
+    }
+}
+
+/// An email outbox for tests, which saves the queued emails for later checking instead of
+/// sending them.
+pub struct TestEmailOutbox {
+    /// The emails that have been queued
+    pub queued: Arc<Mutex<Vec<Email>>>,
+}
+
+#[async_trait]
+impl EmailOutbox for TestEmailOutbox {
+    async fn enqueue(&self, email: Email) -> Result<(), Report<EmailError>> {
+        event!(Level::INFO, ?email, "Queueing email");
+        self.queued.lock().unwrap().push(email);
         Ok(())
     }
 }
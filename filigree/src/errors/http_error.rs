@@ -76,24 +76,52 @@ pub enum ErrorKind {
     DatabaseInit,
     /// User or organization is inactive
     Disabled,
+    /// The email address is already used by an account that can not be linked to this login
+    EmailInUse,
     /// Error from the email sending service
     EmailSendFailure,
     /// A permissions predicate failed
     FailedPredicate,
+    /// The requested file was flagged by the virus scanner
+    FileInfected,
     /// An OAuth login seemed to work, but fetching the user's details failed.
     FetchOAuthUserDetails,
+    /// The app failed to process an inbound email
+    InboundEmail,
     /// The password was incorrect. In production this should be obfuscated
     IncorrectPassword,
     /// The API key provided in a request was invalid or disabled
     InvalidApiKey,
     /// The Host header supplied in a request did not match an expected host
     InvalidHostHeader,
+    /// The ID token from an OpenID Connect provider was missing or could not be verified
+    InvalidIdToken,
+    /// The two-factor authentication code was incorrect or was already used
+    InvalidMfaCode,
+    /// A passkey registration or login response could not be verified
+    InvalidPasskey,
+    /// The SAML identity provider configuration supplied for an organization was invalid
+    InvalidSamlConfig,
+    /// A SAML response was malformed, expired, or did not have a valid signature
+    InvalidSamlResponse,
+    /// A SCIM provisioning request was malformed or had invalid values
+    InvalidScimRequest,
+    /// The signature on a webhook request was missing or incorrect
+    InvalidSignature,
     /// The token provided in a reset request was invalid or expired
     InvalidToken,
     /// I/O error
     IO,
+    /// The user tried to enroll in two-factor authentication when it was already enabled
+    MfaAlreadyEnabled,
+    /// The operation requires two-factor authentication, which the user has not enabled
+    MfaNotEnabled,
+    /// The user's organization requires two-factor authentication, but the user has not set it up
+    MfaSetupRequired,
     /// The requested operation requires a permission that the client does not have
     MissingPermission,
+    /// An update to a model with optimistic locking did not specify the version being updated
+    MissingVersion,
     /// The requested object was not found
     NotFound,
     /// The user's account has not yet been verified
@@ -106,6 +134,8 @@ pub enum ErrorKind {
     OAuthSessionExpired,
     /// The OAuth session provided in a request was not found
     OAuthSessionNotFound,
+    /// Failed to load the configuration for an OpenID Connect provider
+    OidcDiscovery,
     /// The client requested a sort order that is not supported for the given model
     OrderBy,
     /// The password and confirmation fields supplied by the client do not match.
@@ -114,10 +144,18 @@ pub enum ErrorKind {
     PasswordHasherError,
     /// Error reading the request body
     RequestRead,
+    /// The requested file has not finished its virus scan
+    ScanPending,
+    /// Error communicating with the virus scanner
+    ScanFailed,
+    /// A SCIM provisioning request conflicted with an existing user or group
+    ScimConflict,
     /// Failed to start the server
     ServerStart,
     /// Internal error with the session backend
     SessionBackend,
+    /// The user's organization requires logging in with single sign-on
+    SsoRequired,
     /// Error while shutting down the server
     Shutdown,
     /// Creation of new user accounts is disabled
@@ -126,6 +164,8 @@ pub enum ErrorKind {
     Storage,
     /// The user is not logged in
     Unauthenticated,
+    /// An uploaded file's content type is not allowed
+    UnsupportedMediaType,
     /// An upload failed in some way not covered by a more specific error message
     UploadFailed,
     /// An uploaded file was larger than the configured limit
@@ -134,6 +174,8 @@ pub enum ErrorKind {
     UserCreationError,
     /// The requested user does not exist
     UserNotFound,
+    /// The object was changed by another request since the client read it
+    VersionMismatch,
 }
 
 impl ErrorKind {
@@ -145,35 +187,56 @@ impl ErrorKind {
             Self::Database => "database",
             Self::DatabaseInit => "db_init",
             Self::Disabled => "disabled",
+            Self::EmailInUse => "email_in_use",
             Self::EmailSendFailure => "email_send_failure",
             Self::FailedPredicate => "failed_authz_condition",
+            Self::FileInfected => "file_infected",
             Self::FetchOAuthUserDetails => "fetch_oauth_user_details",
+            Self::InboundEmail => "inbound_email",
             Self::IncorrectPassword => "incorrect_password",
             Self::InvalidApiKey => "invalid_api_key",
             Self::InvalidHostHeader => "invalid_host_header",
+            Self::InvalidIdToken => "invalid_id_token",
+            Self::InvalidMfaCode => "invalid_mfa_code",
+            Self::InvalidPasskey => "invalid_passkey",
+            Self::InvalidSamlConfig => "invalid_saml_config",
+            Self::InvalidSamlResponse => "invalid_saml_response",
+            Self::InvalidScimRequest => "invalid_scim_request",
+            Self::InvalidSignature => "invalid_signature",
             Self::InvalidToken => "invalid_token",
             Self::IO => "io_error",
+            Self::MfaAlreadyEnabled => "mfa_already_enabled",
+            Self::MfaNotEnabled => "mfa_not_enabled",
+            Self::MfaSetupRequired => "mfa_setup_required",
             Self::MissingPermission => "missing_permission",
+            Self::MissingVersion => "missing_version",
             Self::NotFound => "not_found",
             Self::NotVerified => "not_verified",
             Self::OAuthExchangeError => "oauth_exchange_error",
             Self::OAuthProviderNotSupported => "oauth_provider_not_supported",
             Self::OAuthSessionExpired => "oauth_session_expired",
             Self::OAuthSessionNotFound => "oauth_session_not_found",
+            Self::OidcDiscovery => "oidc_discovery",
             Self::OrderBy => "order_by",
             Self::PasswordConfirmMismatch => "password_mismatch",
             Self::PasswordHasherError => "password_hash_internal",
             Self::RequestRead => "request_read",
+            Self::ScanPending => "scan_pending",
+            Self::ScanFailed => "scan_failed",
+            Self::ScimConflict => "scim_conflict",
             Self::ServerStart => "server",
             Self::SessionBackend => "session_backend",
+            Self::SsoRequired => "sso_required",
             Self::Shutdown => "shutdown",
             Self::SignupDisabled => "signup_disabled",
             Self::Storage => "storage",
             Self::Unauthenticated => "unauthenticated",
+            Self::UnsupportedMediaType => "unsupported_media_type",
             Self::UploadFailed => "upload_failed",
             Self::UploadTooLarge => "upload_too_large",
             Self::UserCreationError => "user_creation_error",
             Self::UserNotFound => "user_not_found",
+            Self::VersionMismatch => "version_mismatch",
         }
     }
 }
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use hyper::header::IF_MATCH;
+
+use super::Rejection;
+
+/// Extract the object version from an `If-Match` header, for models that use optimistic locking.
+/// The header is optional.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfMatchVersion {
+    /// The request had no `If-Match` header
+    Missing,
+    /// `If-Match: *`, which matches any current version of the object
+    Any,
+    /// The request should only succeed if the object is at this version
+    Version(i32),
+    /// The header can never match any version. `If-Match` uses strong comparison, so this is
+    /// returned for weak ETags. The request should fail with 412 Precondition Failed.
+    Never,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfMatchVersion
+where
+    S: Send + Sync,
+{
+    type Rejection = Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(header) = parts.headers.get(IF_MATCH) else {
+            return Ok(IfMatchVersion::Missing);
+        };
+
+        let header = header
+            .to_str()
+            .map_err(|_| Rejection::InvalidIfMatch)?
+            .trim();
+        if header == "*" {
+            return Ok(IfMatchVersion::Any);
+        }
+
+        if header.starts_with("W/") {
+            return Ok(IfMatchVersion::Never);
+        }
+
+        let version = parse_version_etag(header).ok_or(Rejection::InvalidIfMatch)?;
+        Ok(IfMatchVersion::Version(version))
+    }
+}
+
+/// Format an object version as an ETag header value
+pub fn version_etag(version: i32) -> String {
+    format!("\"{version}\"")
+}
+
+/// Parse a version from an ETag created by [version_etag]. Bare numbers are accepted as well.
+/// Weak ETags never match in `If-Match`, so they return `None`.
+pub fn parse_version_etag(etag: &str) -> Option<i32> {
+    let etag = etag.trim();
+    if etag.starts_with("W/") {
+        return None;
+    }
+
+    let etag = etag
+        .strip_prefix('"')
+        .and_then(|e| e.strip_suffix('"'))
+        .unwrap_or(etag);
+    etag.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_etag() {
+        assert_eq!(parse_version_etag(&version_etag(5)), Some(5));
+        assert_eq!(parse_version_etag("W/\"12\""), None);
+        assert_eq!(parse_version_etag("3"), Some(3));
+        assert_eq!(parse_version_etag("\"abc\""), None);
+        assert_eq!(parse_version_etag("*"), None);
+    }
+
+    async fn extract(header: Option<&str>) -> Result<IfMatchVersion, Rejection> {
+        let mut request = axum::http::Request::builder();
+        if let Some(header) = header {
+            request = request.header(IF_MATCH, header);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        IfMatchVersion::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn extract_header() {
+        assert_eq!(extract(None).await.unwrap(), IfMatchVersion::Missing);
+        assert_eq!(extract(Some("*")).await.unwrap(), IfMatchVersion::Any);
+        assert_eq!(
+            extract(Some("\"4\"")).await.unwrap(),
+            IfMatchVersion::Version(4)
+        );
+        assert_eq!(
+            extract(Some("W/\"4\"")).await.unwrap(),
+            IfMatchVersion::Never
+        );
+        assert!(matches!(
+            extract(Some("\"abc\"")).await,
+            Err(Rejection::InvalidIfMatch)
+        ));
+    }
+}
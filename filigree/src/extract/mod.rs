@@ -14,9 +14,11 @@ use crate::{
 };
 
 mod form_or_json;
+mod if_match;
 mod multipart;
 
 pub use form_or_json::*;
+pub use if_match::*;
 pub use multipart::*;
 
 /// Types of errors that the extraction middleware can return
@@ -40,6 +42,8 @@ pub enum Rejection {
     Serde(serde_path_to_error::Error<serde_json::Error>),
     /// The client passed a content-type header which we don't support
     UnsupportedContentType,
+    /// The If-Match header did not contain a valid object version
+    InvalidIfMatch,
 }
 
 impl From<MultipartError> for Rejection {
@@ -127,6 +131,15 @@ impl IntoResponse for Rejection {
                 )),
             )
                 .into_response(),
+            Rejection::InvalidIfMatch => (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponseData::new(
+                    "invalid_if_match",
+                    "Invalid If-Match header",
+                    (),
+                )),
+            )
+                .into_response(),
         }
     }
 }
@@ -0,0 +1,541 @@
+//! Image processing for uploaded files: resizing into variants and stripping metadata.
+
+use std::{io::Cursor, str::FromStr};
+
+use bytes::Bytes;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, metadata::Orientation, DynamicImage,
+    ImageDecoder, ImageFormat, ImageReader, Limits,
+};
+use serde::{Deserialize, Serialize};
+
+/// The largest width or height of an image that [process_image] will decode
+pub const MAX_IMAGE_DIMENSION: u32 = 10_000;
+/// The most memory that [process_image] will allocate to decode an image
+pub const MAX_IMAGE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// An error that may occur while processing an image
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    /// The file is not an image in a supported format
+    #[error("Unsupported image format")]
+    UnsupportedFormat,
+    /// The image could not be decoded
+    #[error("Failed to decode image: {0}")]
+    Decode(image::ImageError),
+    /// The image is larger than [MAX_IMAGE_DIMENSION] or would take more than
+    /// [MAX_IMAGE_ALLOC] bytes to decode
+    #[error("Image is too large to process")]
+    TooLarge,
+    /// A variant could not be encoded
+    #[error("Failed to encode image: {0}")]
+    Encode(image::ImageError),
+    /// A variant specification could not be parsed
+    #[error("Invalid image variant {0:?}, expected a value like \"200x200 cover\"")]
+    InvalidVariant(String),
+}
+
+/// How to fit an image into the dimensions of a variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFit {
+    /// Scale the image to fill the dimensions, cropping whatever doesn't fit.
+    Cover,
+    /// Scale the image to fit inside the dimensions, preserving its aspect ratio.
+    #[default]
+    Contain,
+    /// Stretch the image to exactly the dimensions.
+    Fill,
+}
+
+/// A resized version of an image, written as `WIDTHxHEIGHT FIT`, such as `200x200 cover`.
+/// The fit can be `cover`, `contain`, or `fill`, and defaults to `contain` if omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ImageVariantSpec {
+    /// The width of the variant
+    pub width: u32,
+    /// The height of the variant
+    pub height: u32,
+    /// How to fit the image into the dimensions
+    pub fit: ImageFit,
+}
+
+impl FromStr for ImageVariantSpec {
+    type Err = ImageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ImageError::InvalidVariant(s.to_string());
+        let mut parts = s.split_whitespace();
+        let (width, height) = parts
+            .next()
+            .and_then(|size| size.split_once('x'))
+            .ok_or_else(invalid)?;
+        let width = width.parse::<u32>().map_err(|_| invalid())?;
+        let height = height.parse::<u32>().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+
+        let fit = match parts.next() {
+            None | Some("contain") => ImageFit::Contain,
+            Some("cover") => ImageFit::Cover,
+            Some("fill") => ImageFit::Fill,
+            Some(_) => return Err(invalid()),
+        };
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self { width, height, fit })
+    }
+}
+
+impl TryFrom<String> for ImageVariantSpec {
+    type Error = ImageError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ImageVariantSpec> for String {
+    fn from(value: ImageVariantSpec) -> Self {
+        let fit = match value.fit {
+            ImageFit::Cover => "cover",
+            ImageFit::Contain => "contain",
+            ImageFit::Fill => "fill",
+        };
+        format!("{}x{} {fit}", value.width, value.height)
+    }
+}
+
+impl ImageVariantSpec {
+    #[cfg(feature = "filigree-cli")]
+    /// Recreate the structure in Rust code.
+    pub fn template_text(&self) -> String {
+        format!(
+            "filigree::images::ImageVariantSpec {{ width: {}, height: {}, fit: filigree::images::ImageFit::{:?} }}",
+            self.width, self.height, self.fit
+        )
+    }
+
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match self.fit {
+            ImageFit::Cover => image.resize_to_fill(self.width, self.height, FilterType::Lanczos3),
+            ImageFit::Contain => image.resize(self.width, self.height, FilterType::Lanczos3),
+            ImageFit::Fill => image.resize_exact(self.width, self.height, FilterType::Lanczos3),
+        }
+    }
+}
+
+/// The format in which to write image variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutputFormat {
+    /// JPEG, which does not support transparency
+    #[default]
+    Jpeg,
+    /// PNG
+    Png,
+    /// Lossless WebP
+    Webp,
+}
+
+impl ImageOutputFormat {
+    /// The file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+        }
+    }
+
+    /// The MIME type for this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    fn encode(&self, image: &DynamicImage) -> Result<Bytes, ImageError> {
+        let mut output = Vec::new();
+        match self {
+            Self::Jpeg => {
+                // JPEG has no alpha channel
+                JpegEncoder::new_with_quality(&mut output, 85)
+                    .encode_image(&image.to_rgb8())
+                    .map_err(ImageError::Encode)?;
+            }
+            Self::Png => image
+                .write_to(Cursor::new(&mut output), ImageFormat::Png)
+                .map_err(ImageError::Encode)?,
+            Self::Webp => image
+                .write_to(Cursor::new(&mut output), ImageFormat::WebP)
+                .map_err(ImageError::Encode)?,
+        }
+
+        Ok(Bytes::from(output))
+    }
+}
+
+/// A resized version of an image
+#[derive(Debug)]
+pub struct ImageVariant {
+    /// The name of the variant
+    pub name: String,
+    /// The width of the resized image
+    pub width: u32,
+    /// The height of the resized image
+    pub height: u32,
+    /// The encoded image
+    pub data: Bytes,
+}
+
+/// The results of [process_image]
+#[derive(Debug)]
+pub struct ProcessedImage {
+    /// The width of the original image, after applying its EXIF orientation
+    pub width: u32,
+    /// The height of the original image, after applying its EXIF orientation
+    pub height: u32,
+    /// The original image with its metadata removed, or `None` if it had no metadata to remove.
+    pub stripped: Option<Bytes>,
+    /// The generated variants
+    pub variants: Vec<ImageVariant>,
+}
+
+/// Read an image, remove its EXIF and XMP metadata, and generate resized variants of it.
+///
+/// When possible, metadata is removed without reencoding the image. Images with an EXIF
+/// orientation other than the default are rotated and reencoded instead, so that they
+/// still display correctly once the orientation tag is gone.
+///
+/// This does CPU-intensive work and should be run in a blocking task.
+pub fn process_image(
+    data: &[u8],
+    variants: &[(&str, ImageVariantSpec)],
+    output_format: ImageOutputFormat,
+) -> Result<ProcessedImage, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| ImageError::UnsupportedFormat)?;
+    reader.limits(image_limits());
+    let format = reader.format().ok_or(ImageError::UnsupportedFormat)?;
+    let mut decoder = reader.into_decoder().map_err(decode_error)?;
+    // The decoder only checks the dimensions, so check the size of the decoded image too.
+    image_limits()
+        .reserve(decoder.total_bytes())
+        .map_err(decode_error)?;
+    let orientation = decoder.orientation().map_err(decode_error)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    image.apply_orientation(orientation);
+
+    let stripped = if orientation == Orientation::NoTransforms {
+        strip_metadata(data, format)?.map(Bytes::from)
+    } else {
+        let mut output = Vec::new();
+        match format {
+            ImageFormat::Jpeg => {
+                JpegEncoder::new_with_quality(&mut output, 90)
+                    .encode_image(&image.to_rgb8())
+                    .map_err(ImageError::Encode)?;
+            }
+            _ => image
+                .write_to(Cursor::new(&mut output), format)
+                .map_err(ImageError::Encode)?,
+        }
+        Some(Bytes::from(output))
+    };
+
+    let variants = variants
+        .iter()
+        .map(|(name, spec)| {
+            let resized = spec.apply(&image);
+            Ok(ImageVariant {
+                name: name.to_string(),
+                width: resized.width(),
+                height: resized.height(),
+                data: output_format.encode(&resized)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ImageError>>()?;
+
+    Ok(ProcessedImage {
+        width: image.width(),
+        height: image.height(),
+        stripped,
+        variants,
+    })
+}
+
+fn image_limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_IMAGE_ALLOC);
+    limits
+}
+
+fn decode_error(e: image::ImageError) -> ImageError {
+    match e {
+        image::ImageError::Limits(_) => ImageError::TooLarge,
+        e => ImageError::Decode(e),
+    }
+}
+
+/// Remove EXIF and XMP metadata from an image without reencoding it. Returns `None` if the
+/// image contained no metadata.
+pub fn strip_metadata(data: &[u8], format: ImageFormat) -> Result<Option<Vec<u8>>, ImageError> {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(data),
+        ImageFormat::Png => strip_png(data),
+        ImageFormat::WebP => strip_webp(data),
+        // GIF has no standard place for this metadata
+        ImageFormat::Gif => Ok(None),
+        _ => Err(ImageError::UnsupportedFormat),
+    }
+}
+
+fn truncated() -> ImageError {
+    ImageError::Decode(image::ImageError::IoError(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "Image data is truncated",
+    )))
+}
+
+/// Remove APP1 segments, which contain EXIF and XMP data.
+fn strip_jpeg(data: &[u8]) -> Result<Option<Vec<u8>>, ImageError> {
+    const APP1: u8 = 0xe1;
+    const START_OF_SCAN: u8 = 0xda;
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(data.get(..2).ok_or_else(truncated)?);
+    let mut pos = 2;
+    let mut changed = false;
+
+    loop {
+        let header = data.get(pos..pos + 4).ok_or_else(truncated)?;
+        let marker = header[1];
+        if header[0] != 0xff || marker == START_OF_SCAN {
+            // The rest of the file is image data
+            output.extend_from_slice(&data[pos..]);
+            break;
+        }
+
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let end = pos + 2 + len;
+        let segment = data.get(pos..end).ok_or_else(truncated)?;
+        if marker == APP1 {
+            changed = true;
+        } else {
+            output.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+
+    Ok(changed.then_some(output))
+}
+
+/// Remove `eXIf` chunks and XMP `iTXt` chunks.
+fn strip_png(data: &[u8]) -> Result<Option<Vec<u8>>, ImageError> {
+    const SIGNATURE_LEN: usize = 8;
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(data.get(..SIGNATURE_LEN).ok_or_else(truncated)?);
+    let mut pos = SIGNATURE_LEN;
+    let mut changed = false;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(truncated)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+        // Length, type, data, CRC
+        let end = pos + 12 + len;
+        let chunk = data.get(pos..end).ok_or_else(truncated)?;
+
+        let is_metadata = chunk_type == b"eXIf"
+            || (chunk_type == b"iTXt" && chunk[8..].starts_with(b"XML:com.adobe.xmp\0"));
+        if is_metadata {
+            changed = true;
+        } else {
+            output.extend_from_slice(chunk);
+        }
+        pos = end;
+    }
+
+    Ok(changed.then_some(output))
+}
+
+/// Remove `EXIF` and `XMP ` chunks and clear their flags in the `VP8X` header.
+fn strip_webp(data: &[u8]) -> Result<Option<Vec<u8>>, ImageError> {
+    const RIFF_HEADER_LEN: usize = 12;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(data.get(..RIFF_HEADER_LEN).ok_or_else(truncated)?);
+    let mut pos = RIFF_HEADER_LEN;
+    let mut changed = false;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(truncated)?;
+        let chunk_type = &header[0..4];
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        // Chunks are padded to an even length
+        let end = pos + 8 + len + (len & 1);
+        let chunk = data.get(pos..end.min(data.len())).ok_or_else(truncated)?;
+
+        if chunk_type == b"EXIF" || chunk_type == b"XMP " {
+            changed = true;
+        } else if chunk_type == b"VP8X" && chunk.len() > 8 {
+            let flags_pos = output.len() + 8;
+            output.extend_from_slice(chunk);
+            output[flags_pos] &= !(EXIF_FLAG | XMP_FLAG);
+        } else {
+            output.extend_from_slice(chunk);
+        }
+        pos = end;
+    }
+
+    if changed {
+        let riff_len = (output.len() - 8) as u32;
+        output[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    }
+
+    Ok(changed.then_some(output))
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn test_jpeg() -> Vec<u8> {
+        let image = RgbImage::from_fn(400, 300, |x, y| Rgb([(x % 256) as u8, (y % 256) as u8, 0]));
+        let mut data = Vec::new();
+        JpegEncoder::new(&mut data).encode_image(&image).unwrap();
+        data
+    }
+
+    /// Insert an APP1 EXIF segment after the SOI marker
+    fn add_exif(jpeg: &[u8], exif: &[u8]) -> Vec<u8> {
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(exif);
+        let len = (segment.len() + 2) as u16;
+
+        let mut output = jpeg[..2].to_vec();
+        output.extend_from_slice(&[0xff, 0xe1]);
+        output.extend_from_slice(&len.to_be_bytes());
+        output.extend_from_slice(&segment);
+        output.extend_from_slice(&jpeg[2..]);
+        output
+    }
+
+    /// A minimal little-endian TIFF structure with only an orientation tag
+    fn orientation_exif(orientation: u16) -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        // tag, type SHORT, count 1, value
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&orientation.to_le_bytes());
+        exif.extend_from_slice(&[0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        exif
+    }
+
+    #[test]
+    fn parse_variant_spec() {
+        assert_eq!(
+            "200x100 cover".parse::<ImageVariantSpec>().unwrap(),
+            ImageVariantSpec {
+                width: 200,
+                height: 100,
+                fit: ImageFit::Cover
+            }
+        );
+        assert_eq!(
+            "64x64".parse::<ImageVariantSpec>().unwrap().fit,
+            ImageFit::Contain
+        );
+        assert!("200".parse::<ImageVariantSpec>().is_err());
+        assert!("0x200".parse::<ImageVariantSpec>().is_err());
+        assert!("200x200 stretch".parse::<ImageVariantSpec>().is_err());
+    }
+
+    #[test]
+    fn resize_variants() {
+        let variants = [
+            ("thumb", "100x100 cover".parse().unwrap()),
+            ("small", "200x200 contain".parse().unwrap()),
+            ("wide", "50x10 fill".parse().unwrap()),
+        ];
+        let result = process_image(&test_jpeg(), &variants, ImageOutputFormat::Png).unwrap();
+
+        assert_eq!((result.width, result.height), (400, 300));
+        assert!(result.stripped.is_none());
+
+        let sizes = result
+            .variants
+            .iter()
+            .map(|v| (v.name.as_str(), v.width, v.height))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            vec![("thumb", 100, 100), ("small", 200, 150), ("wide", 50, 10)]
+        );
+
+        let decoded = image::load_from_memory(&result.variants[0].data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 100));
+    }
+
+    #[test]
+    fn strip_exif_without_reencoding() {
+        let original = test_jpeg();
+        let with_exif = add_exif(&original, &orientation_exif(1));
+
+        let result = process_image(&with_exif, &[], ImageOutputFormat::Jpeg).unwrap();
+        assert_eq!(result.stripped.as_deref(), Some(original.as_slice()));
+    }
+
+    #[test]
+    fn apply_orientation_before_stripping() {
+        // Orientation 6 rotates the image 90 degrees clockwise
+        let with_exif = add_exif(&test_jpeg(), &orientation_exif(6));
+
+        let result = process_image(&with_exif, &[], ImageOutputFormat::Jpeg).unwrap();
+        assert_eq!((result.width, result.height), (300, 400));
+
+        let stripped = result.stripped.unwrap();
+        assert!(strip_metadata(&stripped, ImageFormat::Jpeg)
+            .unwrap()
+            .is_none());
+        let decoded = image::load_from_memory(&stripped).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (300, 400));
+    }
+
+    #[test]
+    fn too_large() {
+        let image = RgbImage::new(MAX_IMAGE_DIMENSION + 1, 1);
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        let err = process_image(&data, &[], ImageOutputFormat::Jpeg).unwrap_err();
+        assert!(matches!(err, ImageError::TooLarge), "{err:?}");
+    }
+
+    #[test]
+    fn not_an_image() {
+        let err = process_image(b"just some text", &[], ImageOutputFormat::Jpeg).unwrap_err();
+        assert!(matches!(err, ImageError::UnsupportedFormat));
+    }
+}
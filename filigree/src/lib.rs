@@ -6,9 +6,12 @@ use std::borrow::Cow;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+/// Audit log records of changes to models
+pub mod audit;
 /// Authentication and Authorization
 pub mod auth;
 pub mod config;
+pub mod db;
 /// Email templates and sending
 pub mod email;
 pub mod error_reporting;
@@ -21,6 +24,9 @@ pub mod extract;
 pub mod html;
 #[cfg(feature = "htmx")]
 pub mod htmx;
+#[cfg(feature = "images")]
+/// Resize images and strip their metadata
+pub mod images;
 /// Extension trait to inspect the body of a Reqwest error response
 pub mod inspect_response;
 #[cfg(feature = "maud")]
@@ -56,6 +62,9 @@ pub mod uploads;
 pub mod users;
 #[cfg(feature = "maud")]
 pub mod vite_manifest;
+/// Signed webhook deliveries
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 
 /// A simple structure for sending back a message-only response
 #[derive(Serialize, Debug)]
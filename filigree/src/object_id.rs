@@ -273,6 +273,34 @@ impl<'r, PREFIX: ObjectIdPrefix> sqlx::Decode<'r, sqlx::Postgres> for ObjectId<P
     }
 }
 
+/// Store and retrieve in SQLite as a UUID blob
+#[cfg(feature = "sqlite")]
+impl<PREFIX: ObjectIdPrefix> sqlx::Type<sqlx::Sqlite> for ObjectId<PREFIX> {
+    fn type_info() -> <sqlx::Sqlite as Database>::TypeInfo {
+        <sqlx::types::Uuid as sqlx::Type<sqlx::Sqlite>>::type_info()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<'q, PREFIX: ObjectIdPrefix> sqlx::Encode<'q, sqlx::Sqlite> for ObjectId<PREFIX> {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <sqlx::Sqlite as sqlx::Database>::ArgumentBuffer<'q>,
+    ) -> Result<sqlx::encode::IsNull, Box<dyn std::error::Error + Send + Sync>> {
+        <sqlx::types::Uuid as sqlx::Encode<'_, sqlx::Sqlite>>::encode_by_ref(&self.0, buf)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<'r, PREFIX: ObjectIdPrefix> sqlx::Decode<'r, sqlx::Sqlite> for ObjectId<PREFIX> {
+    fn decode(
+        value: <sqlx::Sqlite as sqlx::Database>::ValueRef<'r>,
+    ) -> Result<Self, sqlx::error::BoxDynError> {
+        let u = <sqlx::types::Uuid as sqlx::Decode<'r, sqlx::Sqlite>>::decode(value)?;
+        Ok(Self(u, PhantomData))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use axum::{extract::Path, response::IntoResponse, Router};
@@ -2,7 +2,7 @@
 
 use std::{borrow::Cow, fmt::Display};
 
-use axum::extract::Request;
+use axum::{extract::Request, middleware::Next, response::Response};
 use tower_http::request_id::{MakeRequestId, RequestId};
 use uuid::Uuid;
 
@@ -53,3 +53,28 @@ impl MakeRequestId for MakeRequestUuidV7 {
         Some(RequestId::new(request_id))
     }
 }
+
+tokio::task_local! {
+    static REQUEST_ID: Uuid;
+}
+
+/// Return the ID of the request currently being handled, if any. This requires the
+/// [scope_request_id] middleware to run inside of the layer that sets the request ID.
+pub fn current_request_id() -> Option<Uuid> {
+    REQUEST_ID.try_with(|id| *id).ok()
+}
+
+/// Middleware that makes the ID generated by [MakeRequestUuidV7] available to the rest of the
+/// request through [current_request_id].
+pub async fn scope_request_id(req: Request, next: Next) -> Response {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .and_then(|id| Uuid::parse_str(id).ok());
+
+    match request_id {
+        Some(id) => REQUEST_ID.scope(id, next.run(req)).await,
+        None => next.run(req).await,
+    }
+}
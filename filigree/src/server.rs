@@ -5,7 +5,9 @@ use tracing::{event, Level};
 
 #[cfg(feature = "local_auth")]
 use crate::{
-    auth::{oauth::providers::OAuthProvider, SessionBackend},
+    auth::{
+        oauth::providers::OAuthProvider, passkeys::PasskeyConfig, saml::SamlConfig, SessionBackend,
+    },
     users::users::UserCreator,
 };
 use crate::{email::services::EmailSender, error_reporting::ErrorReporter};
@@ -13,7 +15,7 @@ use crate::{email::services::EmailSender, error_reporting::ErrorReporter};
 /// Internal state used by the server
 pub struct FiligreeState {
     /// The database connection pool
-    pub db: sqlx::PgPool,
+    pub db: crate::db::DbPool,
 
     #[cfg(feature = "local_auth")]
     /// User session backend
@@ -41,6 +43,14 @@ pub struct FiligreeState {
     /// The enabled OAuth Providers. This can be populated using [create_supported_providers].
     pub oauth_providers: Vec<Box<dyn OAuthProvider>>,
 
+    #[cfg(feature = "local_auth")]
+    /// Relying party settings for passkey login
+    pub passkeys: PasskeyConfig,
+
+    #[cfg(feature = "local_auth")]
+    /// Service provider settings for SAML single sign-on
+    pub saml: SamlConfig,
+
     /// Error reporting
     pub error_reporter: ErrorReporter,
 }
@@ -5,21 +5,63 @@ use std::fmt::{Display, Write};
 pub enum BindingOperator {
     /// Simple equals
     Eq,
-    /// Use = ANY()
+    /// Use = ANY(), or a JSON array lookup in SQLite
     Array,
     /// Greater than or equal
     Gte,
     /// Less than or equal
     Lte,
+    /// Compare a `(sort_field, id)` row against a cursor position. This binds two parameters,
+    /// the sort field value and the ID, which are cast to the given types.
+    After {
+        /// The SQL type of the sort field
+        value_type: &'static str,
+        /// The SQL type of the ID
+        id_type: &'static str,
+        /// If the list is sorted in descending order
+        descending: bool,
+    },
 }
 
+/// The character that starts a numbered query parameter
+#[cfg(not(feature = "sqlite"))]
+const PARAM_PREFIX: char = '$';
+/// The character that starts a numbered query parameter
+#[cfg(feature = "sqlite")]
+const PARAM_PREFIX: char = '?';
+
 impl BindingOperator {
     fn write(&self, f: &mut std::fmt::Formatter<'_>, param: usize) -> std::fmt::Result {
+        let p = PARAM_PREFIX;
+        match self {
+            BindingOperator::Eq => write!(f, "= {p}{param}"),
+            #[cfg(not(feature = "sqlite"))]
+            BindingOperator::Array => write!(f, "= ANY({p}{param})"),
+            // SQLite has no arrays, so the values are bound as a JSON array instead.
+            #[cfg(feature = "sqlite")]
+            BindingOperator::Array => write!(f, "IN (SELECT value FROM json_each({p}{param}))"),
+            BindingOperator::Gte => write!(f, ">= {p}{param}"),
+            BindingOperator::Lte => write!(f, "<= {p}{param}"),
+            BindingOperator::After {
+                value_type,
+                id_type,
+                descending,
+            } => {
+                let op = if *descending { '<' } else { '>' };
+                let id_param = param + 1;
+                write!(
+                    f,
+                    "{op} (CAST({p}{param} AS {value_type}), CAST({p}{id_param} AS {id_type}))"
+                )
+            }
+        }
+    }
+
+    /// The number of query parameters used by this operator
+    fn num_params(&self) -> usize {
         match self {
-            BindingOperator::Eq => write!(f, "= ${param}"),
-            BindingOperator::Array => write!(f, "= ANY(${param})"),
-            BindingOperator::Gte => write!(f, ">= ${param}"),
-            BindingOperator::Lte => write!(f, "<= ${param}"),
+            BindingOperator::After { .. } => 2,
+            _ => 1,
         }
     }
 }
@@ -62,6 +104,30 @@ impl<'a> FilterBuilder<'a> {
 
         self.clauses.push((field, operator));
     }
+
+    /// Filter to rows after a cursor position, if it is `Some`. `fields` should be a row
+    /// expression like `(updated_at, id)`.
+    pub fn add_cursor<T>(
+        &mut self,
+        fields: &'a str,
+        cursor: &Option<T>,
+        value_type: &'static str,
+        id_type: &'static str,
+        descending: bool,
+    ) {
+        if cursor.is_none() {
+            return;
+        }
+
+        self.clauses.push((
+            fields,
+            BindingOperator::After {
+                value_type,
+                id_type,
+                descending,
+            },
+        ));
+    }
 }
 
 impl<'a> Display for FilterBuilder<'a> {
@@ -73,15 +139,16 @@ impl<'a> Display for FilterBuilder<'a> {
         }
 
         f.write_char('(')?;
+        let mut param = self.first_parameter;
         for (i, (field, operator)) in self.clauses.iter().enumerate() {
             if i > 0 {
                 f.write_str(" AND ")?;
             }
 
-            let param = self.first_parameter + i;
             f.write_str(field)?;
             f.write_char(' ')?;
             operator.write(f, param)?;
+            param += operator.num_params();
         }
         f.write_char(')')?;
 
@@ -113,7 +180,7 @@ impl Display for ValuesBuilder {
                     f.write_char(',')?;
                 }
 
-                f.write_char('$')?;
+                f.write_char(PARAM_PREFIX)?;
                 write!(f, "{}", binding)?;
                 binding += 1;
             }
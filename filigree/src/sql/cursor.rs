@@ -0,0 +1,177 @@
+use base64::{engine::GeneralPurpose, Engine};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const B64_ENGINE: GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+/// The name of the column that holds the JSON-encoded value of the sort field in cursor-paginated
+/// queries
+pub const CURSOR_VALUE_COLUMN: &str = "_cursor_value";
+/// The name of the column that holds the text value of the object ID in cursor-paginated queries
+pub const CURSOR_ID_COLUMN: &str = "_cursor_id";
+
+/// An error decoding a list cursor
+#[derive(Debug, Error)]
+pub enum CursorError {
+    /// The cursor could not be decoded
+    #[error("Invalid cursor")]
+    Invalid,
+    /// The cursor was created for a different sort order than the one requested
+    #[error("Cursor does not match the requested order_by")]
+    OrderMismatch,
+}
+
+/// The position in a cursor-paginated list, encoded into an opaque string for the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListCursor {
+    /// The order_by value that the list was sorted with, including the direction prefix
+    #[serde(rename = "o")]
+    pub order_by: String,
+    /// The value of the sort field in the last returned row, encoded as JSON
+    #[serde(rename = "v")]
+    pub value: String,
+    /// The ID of the last returned row, cast to text
+    #[serde(rename = "i")]
+    pub id: String,
+}
+
+impl ListCursor {
+    /// Encode the cursor into an opaque string
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("serializing cursor");
+        B64_ENGINE.encode(json)
+    }
+
+    /// Decode a cursor from a string created by [ListCursor::encode]
+    pub fn decode(cursor: &str) -> Result<Self, CursorError> {
+        let json = B64_ENGINE
+            .decode(cursor)
+            .map_err(|_| CursorError::Invalid)?;
+        serde_json::from_slice(&json).map_err(|_| CursorError::Invalid)
+    }
+
+    /// Decode a cursor and make sure that it was created for the given sort order.
+    pub fn decode_for_order(cursor: &str, order_by: &str) -> Result<Self, CursorError> {
+        let cursor = Self::decode(cursor)?;
+        if cursor.order_by != order_by {
+            return Err(CursorError::OrderMismatch);
+        }
+
+        Ok(cursor)
+    }
+}
+
+/// A row from a cursor-paginated query, with the values needed to build the next cursor.
+pub struct CursorRow<T> {
+    /// The row itself
+    pub row: T,
+    /// The sort field value, from [CURSOR_VALUE_COLUMN]
+    pub cursor_value: String,
+    /// The object ID, from [CURSOR_ID_COLUMN]
+    pub cursor_id: String,
+}
+
+impl<'r, R, T> sqlx::FromRow<'r, R> for CursorRow<T>
+where
+    R: sqlx::Row,
+    T: sqlx::FromRow<'r, R>,
+    &'r str: sqlx::ColumnIndex<R>,
+    String: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+{
+    fn from_row(row: &'r R) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            row: T::from_row(row)?,
+            cursor_value: row.try_get(CURSOR_VALUE_COLUMN)?,
+            cursor_id: row.try_get(CURSOR_ID_COLUMN)?,
+        })
+    }
+}
+
+/// A page of results from a cursor-paginated list
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListPage<T> {
+    /// The objects in this page
+    pub results: Vec<T>,
+    /// Pass this as the `after` parameter to fetch the next page. This is `None` when there are no
+    /// more results.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> ListPage<T> {
+    /// Build a page from rows fetched with a limit of `per_page + 1`. The extra row, if present,
+    /// indicates that there is another page and is not returned.
+    pub fn from_rows(mut rows: Vec<CursorRow<T>>, per_page: usize, order_by: &str) -> Self {
+        let has_more = rows.len() > per_page;
+        rows.truncate(per_page);
+
+        let next_cursor = has_more.then(|| rows.last()).flatten().map(|last| {
+            ListCursor {
+                order_by: order_by.to_string(),
+                value: last.cursor_value.clone(),
+                id: last.cursor_id.clone(),
+            }
+            .encode()
+        });
+
+        ListPage {
+            results: rows.into_iter().map(|r| r.row).collect(),
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn row(value: &str) -> CursorRow<String> {
+        CursorRow {
+            row: value.to_string(),
+            cursor_value: value.to_string(),
+            cursor_id: format!("id-{value}"),
+        }
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = ListCursor {
+            order_by: "-updated_at".to_string(),
+            value: "\"2024-01-01T00:00:00+00:00\"".to_string(),
+            id: "0190a3a3-0000-7000-8000-000000000000".to_string(),
+        };
+
+        let encoded = cursor.encode();
+        assert_eq!(ListCursor::decode(&encoded).unwrap(), cursor);
+        assert_eq!(
+            ListCursor::decode_for_order(&encoded, "-updated_at").unwrap(),
+            cursor
+        );
+        assert!(matches!(
+            ListCursor::decode_for_order(&encoded, "updated_at"),
+            Err(CursorError::OrderMismatch)
+        ));
+        assert!(matches!(
+            ListCursor::decode("not a cursor"),
+            Err(CursorError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn page_with_more_results() {
+        let page = ListPage::from_rows(vec![row("a"), row("b"), row("c")], 2, "name");
+        assert_eq!(page.results, vec!["a", "b"]);
+
+        let cursor = ListCursor::decode(page.next_cursor.as_deref().unwrap()).unwrap();
+        assert_eq!(cursor.order_by, "name");
+        assert_eq!(cursor.value, "b");
+        assert_eq!(cursor.id, "id-b");
+    }
+
+    #[test]
+    fn last_page() {
+        let page = ListPage::from_rows(vec![row("a"), row("b")], 2, "name");
+        assert_eq!(page.results, vec!["a", "b"]);
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -1,3 +1,6 @@
 mod bindings;
+/// Cursor-based pagination for list queries
+pub mod cursor;
 
 pub use bindings::*;
+pub use cursor::{ListCursor, ListPage};
@@ -1,4 +1,6 @@
-use axum::body::Body;
+use std::time::Duration;
+
+use axum::{body::Body, response::IntoResponse};
 use axum_extra::extract::multipart::MultipartError;
 use bytes::Bytes;
 use futures::{Stream, TryStreamExt};
@@ -8,16 +10,22 @@ use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tracing::instrument;
 use url::Url;
 
-use self::in_memory::InMemoryStore;
+use self::{in_memory::InMemoryStore, presigned::LocalSigner};
 use crate::errors::{ErrorKind, HttpError};
 
+#[cfg(feature = "storage_azure")]
+pub mod azure;
 mod config;
+#[cfg(feature = "storage_gcp")]
+pub mod gcs;
 pub(crate) mod in_memory;
 pub mod local;
+mod presigned;
 #[cfg(feature = "storage_aws")]
 pub mod s3;
 
 pub use config::*;
+pub use presigned::LocalPresignedQuery;
 
 /// A filename in a query string
 #[derive(Debug, Deserialize)]
@@ -47,6 +55,12 @@ pub enum StorageError {
     /// Invalid configuration
     #[error("Invalid configuration: {0}")]
     Configuration(&'static str),
+    /// The storage provider does not support this operation
+    #[error("Unsupported operation: {0}")]
+    Unsupported(&'static str),
+    /// A presigned URL had an invalid signature or was expired
+    #[error("Invalid or expired signature")]
+    InvalidSignature,
 }
 
 impl From<object_store::Error> for StorageError {
@@ -64,6 +78,7 @@ impl HttpError for StorageError {
     fn status_code(&self) -> hyper::StatusCode {
         match self {
             Self::NotFound(_) => hyper::StatusCode::NOT_FOUND,
+            Self::InvalidSignature => hyper::StatusCode::FORBIDDEN,
             _ => hyper::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -71,6 +86,7 @@ impl HttpError for StorageError {
     fn error_kind(&self) -> &'static str {
         match self {
             Self::NotFound(_) => ErrorKind::NotFound.as_str(),
+            Self::InvalidSignature => ErrorKind::InvalidSignature.as_str(),
             _ => ErrorKind::Storage.as_str(),
         }
     }
@@ -89,6 +105,7 @@ pub struct Storage {
     /// A public URL where this bucket may be accessed
     pub public_url: Option<Url>,
     store: ObjectStore,
+    local_signer: Option<LocalSigner>,
 }
 
 impl Storage {
@@ -97,6 +114,10 @@ impl Storage {
         match config {
             #[cfg(feature = "storage_aws")]
             StorageConfig::S3(options) => Self::new_s3(options, bucket),
+            #[cfg(feature = "storage_gcp")]
+            StorageConfig::Gcs(options) => Self::new_gcs(options, bucket),
+            #[cfg(feature = "storage_azure")]
+            StorageConfig::Azure(options) => Self::new_azure(options, bucket),
             StorageConfig::Local(options) => Self::new_local(options, bucket),
             StorageConfig::Memory => Ok(Self::new_memory()),
         }
@@ -108,6 +129,16 @@ impl Storage {
         self
     }
 
+    /// Return the public URL of an object, if this bucket has a public URL.
+    pub fn public_url_for(&self, location: &str) -> Option<Url> {
+        let mut url = self.public_url.clone()?;
+        url.path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .extend(location.split('/'));
+        Some(url)
+    }
+
     #[cfg(feature = "storage_aws")]
     /// Create a new Storage for an S3 bucket
     pub fn new_s3(options: &s3::S3StoreConfig, bucket: String) -> Result<Self, StorageError> {
@@ -116,6 +147,34 @@ impl Storage {
             bucket,
             store: ObjectStore::S3(store),
             public_url: None,
+            local_signer: None,
+        })
+    }
+
+    #[cfg(feature = "storage_gcp")]
+    /// Create a new Storage for a Google Cloud Storage bucket
+    pub fn new_gcs(options: &gcs::GcsStoreConfig, bucket: String) -> Result<Self, StorageError> {
+        let store = gcs::create_store(options, &bucket)?;
+        Ok(Self {
+            bucket,
+            store: ObjectStore::Gcs(store),
+            public_url: None,
+            local_signer: None,
+        })
+    }
+
+    #[cfg(feature = "storage_azure")]
+    /// Create a new Storage for an Azure Blob Storage container
+    pub fn new_azure(
+        options: &azure::AzureStoreConfig,
+        bucket: String,
+    ) -> Result<Self, StorageError> {
+        let store = azure::create_store(options, &bucket)?;
+        Ok(Self {
+            bucket,
+            store: ObjectStore::Azure(store),
+            public_url: None,
+            local_signer: None,
         })
     }
 
@@ -130,6 +189,7 @@ impl Storage {
             bucket,
             store: ObjectStore::Local(store),
             public_url: None,
+            local_signer: None,
         })
     }
 
@@ -139,6 +199,7 @@ impl Storage {
             bucket: "memory".to_string(),
             store: ObjectStore::Memory(InMemoryStore::new()),
             public_url: None,
+            local_signer: None,
         }
     }
 
@@ -189,9 +250,97 @@ impl Storage {
         self.store.abort_multipart(&location, id).await
     }
 
-    /// Return true if the storage provider supports presigned upload URLs.
+    /// Set the base URL for presigned URLs to local or in-memory storage. These stores can't
+    /// serve presigned URLs themselves, so the application has to handle them by passing the
+    /// requests to [Storage::serve_local_presigned_url]. The URL for an object is its location
+    /// appended to this base URL.
+    ///
+    /// This has no effect on storage providers that support presigned URLs natively.
+    pub fn with_local_presigned_url_base(mut self, url: Option<Url>) -> Self {
+        self.local_signer = url.map(LocalSigner::new);
+        self
+    }
+
+    /// Return true if presigned URLs can be generated for this storage location.
     pub fn supports_presigned_urls(&self) -> bool {
-        self.store.supports_presigned_urls()
+        match &self.store {
+            ObjectStore::Local(_) | ObjectStore::Memory(_) => self.local_signer.is_some(),
+            store => store.signer().is_some(),
+        }
+    }
+
+    /// Create a URL that can be used to download an object, without any other authentication,
+    /// until `expires_in` has passed.
+    pub async fn presigned_get_url(
+        &self,
+        location: &str,
+        expires_in: Duration,
+    ) -> Result<Url, StorageError> {
+        self.presigned_url(reqwest::Method::GET, location, expires_in)
+            .await
+    }
+
+    /// Create a URL that can be used to upload an object with a `PUT` request, without any other
+    /// authentication, until `expires_in` has passed.
+    pub async fn presigned_put_url(
+        &self,
+        location: &str,
+        expires_in: Duration,
+    ) -> Result<Url, StorageError> {
+        self.presigned_url(reqwest::Method::PUT, location, expires_in)
+            .await
+    }
+
+    #[instrument]
+    async fn presigned_url(
+        &self,
+        method: reqwest::Method,
+        location: &str,
+        expires_in: Duration,
+    ) -> Result<Url, StorageError> {
+        match &self.store {
+            ObjectStore::Local(_) | ObjectStore::Memory(_) => {
+                let signer = self.local_signer.as_ref().ok_or(StorageError::Unsupported(
+                    "Presigned URLs for local storage require a base URL",
+                ))?;
+                Ok(signer.sign(method.as_str(), location, expires_in))
+            }
+            store => {
+                let signer = store.signer().ok_or(StorageError::Unsupported(
+                    "This storage provider does not support presigned URLs",
+                ))?;
+                let url = signer
+                    .signed_url(method, &Path::from(location), expires_in)
+                    .await?;
+                Ok(url)
+            }
+        }
+    }
+
+    /// Handle a request to a URL from [Storage::presigned_get_url] or
+    /// [Storage::presigned_put_url], for local or in-memory storage.
+    pub async fn serve_local_presigned_url(
+        &self,
+        method: &http::Method,
+        location: &str,
+        query: &LocalPresignedQuery,
+        body: Body,
+    ) -> Result<axum::response::Response, StorageError> {
+        let signer = self
+            .local_signer
+            .as_ref()
+            .ok_or(StorageError::InvalidSignature)?;
+        signer.verify(method.as_str(), location, query)?;
+
+        match *method {
+            http::Method::GET => Ok(self.stream_to_client(location).await?.into_response()),
+            http::Method::PUT => {
+                self.save_request_body(location, body.into_data_stream())
+                    .await?;
+                Ok(http::StatusCode::OK.into_response())
+            }
+            _ => Err(StorageError::InvalidSignature),
+        }
     }
 
     /// Stream an object from the store to a [Response]
@@ -256,7 +405,7 @@ impl Storage {
     }
 
     /// Stream a request body into object storage
-    pub async fn save_request_body<STREAMERROR, F>(
+    pub async fn save_request_body<STREAMERROR>(
         &self,
         location: &str,
         body: impl Stream<Item = Result<Bytes, STREAMERROR>> + Unpin,
@@ -323,6 +472,10 @@ enum ObjectStore {
     Local(object_store::local::LocalFileSystem),
     #[cfg(feature = "storage_aws")]
     S3(object_store::aws::AmazonS3),
+    #[cfg(feature = "storage_gcp")]
+    Gcs(object_store::gcp::GoogleCloudStorage),
+    #[cfg(feature = "storage_azure")]
+    Azure(object_store::azure::MicrosoftAzure),
     Memory(InMemoryStore),
 }
 
@@ -332,6 +485,10 @@ impl std::fmt::Debug for ObjectStore {
             Self::Local(_) => f.debug_tuple("Local").finish(),
             #[cfg(feature = "storage_aws")]
             Self::S3(_) => f.debug_tuple("S3").finish(),
+            #[cfg(feature = "storage_gcp")]
+            Self::Gcs(_) => f.debug_tuple("Gcs").finish(),
+            #[cfg(feature = "storage_azure")]
+            Self::Azure(_) => f.debug_tuple("Azure").finish(),
             Self::Memory(_) => f.debug_tuple("Memory").finish(),
         }
     }
@@ -343,6 +500,10 @@ impl ObjectStore {
             ObjectStore::Local(local) => local.get(location).await,
             #[cfg(feature = "storage_aws")]
             ObjectStore::S3(s3) => s3.get(location).await,
+            #[cfg(feature = "storage_gcp")]
+            ObjectStore::Gcs(gcs) => gcs.get(location).await,
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => azure.get(location).await,
             ObjectStore::Memory(mem) => mem.get(location).await,
         }
     }
@@ -352,6 +513,10 @@ impl ObjectStore {
             ObjectStore::Local(local) => local.put(location, data).await,
             #[cfg(feature = "storage_aws")]
             ObjectStore::S3(s3) => s3.put(location, data).await,
+            #[cfg(feature = "storage_gcp")]
+            ObjectStore::Gcs(gcs) => gcs.put(location, data).await,
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => azure.put(location, data).await,
             ObjectStore::Memory(mem) => mem.put(location, data).await,
         }
     }
@@ -364,6 +529,10 @@ impl ObjectStore {
             ObjectStore::Local(local) => local.put_multipart(location).await,
             #[cfg(feature = "storage_aws")]
             ObjectStore::S3(s3) => s3.put_multipart(location).await,
+            #[cfg(feature = "storage_gcp")]
+            ObjectStore::Gcs(gcs) => gcs.put_multipart(location).await,
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => azure.put_multipart(location).await,
             ObjectStore::Memory(mem) => mem.put_multipart(location).await,
         }
     }
@@ -377,6 +546,10 @@ impl ObjectStore {
             ObjectStore::Local(local) => local.abort_multipart(location, id).await,
             #[cfg(feature = "storage_aws")]
             ObjectStore::S3(s3) => s3.abort_multipart(location, id).await,
+            #[cfg(feature = "storage_gcp")]
+            ObjectStore::Gcs(gcs) => gcs.abort_multipart(location, id).await,
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => azure.abort_multipart(location, id).await,
             ObjectStore::Memory(_) => Ok(()),
         }
     }
@@ -386,14 +559,22 @@ impl ObjectStore {
             ObjectStore::Local(local) => local.delete(location).await,
             #[cfg(feature = "storage_aws")]
             ObjectStore::S3(s3) => s3.delete(location).await,
+            #[cfg(feature = "storage_gcp")]
+            ObjectStore::Gcs(gcs) => gcs.delete(location).await,
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => azure.delete(location).await,
             ObjectStore::Memory(mem) => mem.delete(location).await,
         }
     }
 
-    pub fn supports_presigned_urls(&self) -> bool {
-        #[cfg(feature = "storage_aws")]
-        return matches!(&self, ObjectStore::S3(_));
-        #[cfg(not(feature = "storage_aws"))]
-        return false;
+    /// Return the store's presigned URL generator, if it has one.
+    pub fn signer(&self) -> Option<&dyn object_store::signer::Signer> {
+        match self {
+            #[cfg(feature = "storage_aws")]
+            ObjectStore::S3(s3) => Some(s3),
+            #[cfg(feature = "storage_azure")]
+            ObjectStore::Azure(azure) => Some(azure),
+            _ => None,
+        }
     }
 }
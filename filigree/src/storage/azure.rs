@@ -0,0 +1,129 @@
+//! Azure Blob Storage configuration for object_store
+use object_store::azure::MicrosoftAzure;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use url::Url;
+
+use super::StorageError;
+use crate::config::{merge_option_if_set, parse_option, prefixed_env_var};
+
+/// Configuration for an Azure Blob Storage store. The bucket name is used as the container name.
+/// If no access key is set, the store will try to authenticate using a managed identity.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AzureStoreConfig {
+    /// The storage account name
+    pub account: Option<String>,
+    /// The storage account's access key
+    pub access_key: Option<String>,
+    /// The endpoint to use when connecting to the service, if not the default
+    /// `https://{account}.blob.core.windows.net` endpoint.
+    pub endpoint: Option<Url>,
+    /// If true, connect to a local Azurite emulator instead of the real service.
+    pub use_emulator: Option<bool>,
+}
+
+impl AzureStoreConfig {
+    /// Overwrite this configuration's values with environment values, if set.
+    pub fn merge_env(&mut self, prefix: &str) -> Result<(), StorageError> {
+        let from_env = AzureStoreConfig::from_env(prefix)?;
+        merge_option_if_set(&mut self.account, from_env.account);
+        merge_option_if_set(&mut self.access_key, from_env.access_key);
+        merge_option_if_set(&mut self.endpoint, from_env.endpoint);
+        merge_option_if_set(&mut self.use_emulator, from_env.use_emulator);
+
+        Ok(())
+    }
+
+    /// Create a new AzureStoreConfig from environment variables
+    pub fn from_env(prefix: &str) -> Result<Self, StorageError> {
+        Ok(AzureStoreConfig {
+            account: prefixed_env_var(prefix, "AZURE_ACCOUNT").ok(),
+            access_key: prefixed_env_var(prefix, "AZURE_ACCESS_KEY").ok(),
+            endpoint: parse_option(prefixed_env_var(prefix, "AZURE_ENDPOINT").ok())
+                .map_err(|_| StorageError::Configuration("Azure endpoint must be a URI"))?,
+            use_emulator: parse_option(prefixed_env_var(prefix, "AZURE_USE_EMULATOR").ok())
+                .map_err(|_| {
+                    StorageError::Configuration("AZURE_USE_EMULATOR must be true or false")
+                })?,
+        })
+    }
+
+    #[cfg(feature = "filigree-cli")]
+    /// Recreate the structure in Rust code.
+    pub fn template_text(&self) -> String {
+        use crate::templates::{OptionAsStorageUrl, OptionAsString};
+
+        format!(
+            "StorageConfig::Azure(filigree::storage::azure::AzureStoreConfig {{
+                account: {},
+                access_key: {},
+                endpoint: {},
+                use_emulator: {:?},
+            }})",
+            OptionAsString(&self.account),
+            OptionAsString(&self.access_key),
+            OptionAsStorageUrl(&self.endpoint),
+            self.use_emulator,
+        )
+    }
+}
+
+/// Create a new Azure Blob Storage store.
+pub fn create_store(
+    config: &AzureStoreConfig,
+    bucket: &str,
+) -> Result<MicrosoftAzure, StorageError> {
+    let mut builder = object_store::azure::MicrosoftAzureBuilder::new()
+        .with_container_name(bucket)
+        .with_use_emulator(config.use_emulator.unwrap_or(false));
+
+    if let Some(account) = config.account.as_ref() {
+        builder = builder.with_account(account.as_str());
+    }
+
+    if let Some(access_key) = config.access_key.as_ref() {
+        builder = builder.with_access_key(access_key.as_str());
+    }
+
+    if let Some(endpoint) = config.endpoint.as_ref() {
+        event!(Level::DEBUG, %endpoint, "Creating Azure provider with custom endpoint");
+        builder = builder
+            .with_endpoint(endpoint.to_string())
+            .with_allow_http(endpoint.scheme() == "http");
+    }
+
+    let store = builder.build()?;
+    Ok(store)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_env() {
+        std::env::set_var("TEST_AZURE_MERGE_AZURE_ACCOUNT", "from-env");
+        std::env::set_var("TEST_AZURE_MERGE_AZURE_USE_EMULATOR", "true");
+
+        let mut config = AzureStoreConfig {
+            account: Some("from-config".to_string()),
+            access_key: Some("key".to_string()),
+            ..Default::default()
+        };
+        config.merge_env("TEST_AZURE_MERGE_").unwrap();
+
+        assert_eq!(config.account.as_deref(), Some("from-env"));
+        assert_eq!(config.access_key.as_deref(), Some("key"));
+        assert_eq!(config.use_emulator, Some(true));
+    }
+
+    #[test]
+    fn emulator_store() {
+        let config = AzureStoreConfig {
+            use_emulator: Some(true),
+            ..Default::default()
+        };
+
+        create_store(&config, "container").expect("creating store");
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+#[cfg(feature = "storage_azure")]
+use super::azure;
+#[cfg(feature = "storage_gcp")]
+use super::gcs;
 use super::{local, s3, StorageError};
 use crate::config::{merge_option_if_set, parse_option, prefixed_env_var};
 
@@ -62,6 +66,15 @@ pub enum StoragePreset {
         /// The jurisdiction for the bucket that will be accessed via this provider, if any.
         jurisdiction: Option<R2Jurisdiction>,
     },
+    #[cfg(feature = "storage_gcp")]
+    /// Google Cloud Storage
+    GoogleCloudStorage,
+    #[cfg(feature = "storage_azure")]
+    /// Azure Blob Storage
+    AzureBlobStorage {
+        /// The storage account name
+        account: Option<String>,
+    },
 }
 
 impl StoragePreset {
@@ -103,6 +116,15 @@ impl StoragePreset {
                 OptionAsString(account_id),
                 jurisdiction
             ),
+            #[cfg(feature = "storage_gcp")]
+            StoragePreset::GoogleCloudStorage => {
+                "filigree::storage::StoragePreset::GoogleCloudStorage".to_string()
+            }
+            #[cfg(feature = "storage_azure")]
+            StoragePreset::AzureBlobStorage { account } => format!(
+                "filigree::storage::StoragePreset::AzureBlobStorage {{ account: {} }}",
+                OptionAsString(account),
+            ),
         }
     }
 
@@ -131,6 +153,12 @@ impl StoragePreset {
                     parse_option(prefixed_env_var(prefix, "R2_JURISDICTION").ok())?,
                 );
             }
+            #[cfg(feature = "storage_gcp")]
+            StoragePreset::GoogleCloudStorage => {}
+            #[cfg(feature = "storage_azure")]
+            StoragePreset::AzureBlobStorage { account } => {
+                merge_option_if_set(account, prefixed_env_var(prefix, "AZURE_ACCOUNT").ok());
+            }
         };
 
         Ok(())
@@ -200,6 +228,13 @@ impl StoragePreset {
                     ..Default::default()
                 })
             }
+            #[cfg(feature = "storage_gcp")]
+            Self::GoogleCloudStorage => StorageConfig::Gcs(gcs::GcsStoreConfig::default()),
+            #[cfg(feature = "storage_azure")]
+            Self::AzureBlobStorage { account } => StorageConfig::Azure(azure::AzureStoreConfig {
+                account,
+                ..Default::default()
+            }),
         };
 
         Ok(config)
@@ -213,6 +248,12 @@ pub enum StorageConfig {
     #[cfg(feature = "storage_aws")]
     /// S3-compatible storage configuration
     S3(s3::S3StoreConfig),
+    #[cfg(feature = "storage_gcp")]
+    /// Google Cloud Storage configuration
+    Gcs(gcs::GcsStoreConfig),
+    #[cfg(feature = "storage_azure")]
+    /// Azure Blob Storage configuration
+    Azure(azure::AzureStoreConfig),
     /// Local filesystem storage
     Local(local::LocalStoreConfig),
     /// In-memory storage for testing
@@ -236,6 +277,16 @@ impl StorageConfig {
                         StorageConfig::S3(_) => default_settings,
                         _ => StorageConfig::S3(s3::S3StoreConfig::default()),
                     },
+                    #[cfg(feature = "storage_gcp")]
+                    "GCS" => match default_settings {
+                        StorageConfig::Gcs(_) => default_settings,
+                        _ => StorageConfig::Gcs(gcs::GcsStoreConfig::default()),
+                    },
+                    #[cfg(feature = "storage_azure")]
+                    "AZURE" => match default_settings {
+                        StorageConfig::Azure(_) => default_settings,
+                        _ => StorageConfig::Azure(azure::AzureStoreConfig::default()),
+                    },
                     "LOCAL" => match default_settings {
                         StorageConfig::Local(_) => default_settings,
                         _ => StorageConfig::Local(local::LocalStoreConfig::default()),
@@ -258,6 +309,10 @@ impl StorageConfig {
         match self {
             #[cfg(feature = "storage_aws")]
             StorageConfig::S3(options) => options.merge_env(prefix)?,
+            #[cfg(feature = "storage_gcp")]
+            StorageConfig::Gcs(options) => options.merge_env(prefix)?,
+            #[cfg(feature = "storage_azure")]
+            StorageConfig::Azure(options) => options.merge_env(prefix)?,
             StorageConfig::Local(options) => options.merge_env(prefix)?,
             StorageConfig::Memory => {}
         }
@@ -269,6 +324,8 @@ impl StorageConfig {
     pub fn template_text(&self) -> String {
         match self {
             Self::S3(config) => config.template_text(),
+            Self::Gcs(config) => config.template_text(),
+            Self::Azure(config) => config.template_text(),
             Self::Local(config) => config.template_text(),
             Self::Memory => "StorageConfig::Memory".to_string(),
         }
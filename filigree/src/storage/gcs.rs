@@ -0,0 +1,112 @@
+//! Google Cloud Storage configuration for object_store
+use object_store::gcp::GoogleCloudStorage;
+use serde::{Deserialize, Serialize};
+
+use super::StorageError;
+use crate::config::{merge_option_if_set, prefixed_env_var};
+
+/// Configuration for a Google Cloud Storage store. If none of the credential options are set,
+/// the store will fetch credentials from the instance metadata service.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct GcsStoreConfig {
+    /// The path to a service account JSON file
+    pub service_account_path: Option<String>,
+    /// The contents of a service account JSON file
+    pub service_account_key: Option<String>,
+    /// The path to an application credentials file, as created by `gcloud auth application-default login`
+    pub application_credentials: Option<String>,
+}
+
+impl GcsStoreConfig {
+    /// Overwrite this configuration's values with environment values, if set.
+    pub fn merge_env(&mut self, prefix: &str) -> Result<(), StorageError> {
+        let from_env = GcsStoreConfig::from_env(prefix)?;
+        merge_option_if_set(
+            &mut self.service_account_path,
+            from_env.service_account_path,
+        );
+        merge_option_if_set(&mut self.service_account_key, from_env.service_account_key);
+        merge_option_if_set(
+            &mut self.application_credentials,
+            from_env.application_credentials,
+        );
+
+        Ok(())
+    }
+
+    /// Create a new GcsStoreConfig from environment variables
+    pub fn from_env(prefix: &str) -> Result<Self, StorageError> {
+        Ok(GcsStoreConfig {
+            service_account_path: prefixed_env_var(prefix, "GCS_SERVICE_ACCOUNT_PATH").ok(),
+            service_account_key: prefixed_env_var(prefix, "GCS_SERVICE_ACCOUNT_KEY").ok(),
+            application_credentials: prefixed_env_var(prefix, "GCS_APPLICATION_CREDENTIALS").ok(),
+        })
+    }
+
+    #[cfg(feature = "filigree-cli")]
+    /// Recreate the structure in Rust code.
+    pub fn template_text(&self) -> String {
+        use crate::templates::OptionAsString;
+
+        format!(
+            "StorageConfig::Gcs(filigree::storage::gcs::GcsStoreConfig {{
+                service_account_path: {},
+                service_account_key: {},
+                application_credentials: {},
+            }})",
+            OptionAsString(&self.service_account_path),
+            OptionAsString(&self.service_account_key),
+            OptionAsString(&self.application_credentials),
+        )
+    }
+}
+
+/// Create a new Google Cloud Storage store.
+pub fn create_store(
+    config: &GcsStoreConfig,
+    bucket: &str,
+) -> Result<GoogleCloudStorage, StorageError> {
+    let mut builder = object_store::gcp::GoogleCloudStorageBuilder::new().with_bucket_name(bucket);
+
+    match (
+        config.service_account_path.as_ref(),
+        config.service_account_key.as_ref(),
+    ) {
+        (Some(_), Some(_)) => {
+            return Err(StorageError::Configuration(
+                "Must provide at most one of service_account_path and service_account_key",
+            ));
+        }
+        (Some(path), None) => {
+            builder = builder.with_service_account_path(path.as_str());
+        }
+        (None, Some(key)) => {
+            builder = builder.with_service_account_key(key.as_str());
+        }
+        (None, None) => {}
+    }
+
+    if let Some(credentials) = config.application_credentials.as_ref() {
+        builder = builder.with_application_credentials(credentials.as_str());
+    }
+
+    let store = builder.build()?;
+    Ok(store)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conflicting_credentials() {
+        let config = GcsStoreConfig {
+            service_account_path: Some("service-account.json".to_string()),
+            service_account_key: Some("{}".to_string()),
+            ..Default::default()
+        };
+
+        let err = create_store(&config, "bucket").unwrap_err();
+        assert!(matches!(err, StorageError::Configuration(_)));
+    }
+}
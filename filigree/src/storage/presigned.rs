@@ -0,0 +1,202 @@
+//! Presigned URLs for local and in-memory storage, which can't generate them on their own.
+//! The URLs point to routes in the application, which verify the signature and then pass the
+//! request through to the store.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use sha3::{Digest, Sha3_256};
+use url::Url;
+
+use super::StorageError;
+
+/// The query string parameters of a presigned URL for local or in-memory storage
+#[derive(Debug, Deserialize)]
+pub struct LocalPresignedQuery {
+    /// The HTTP method that the URL was signed for
+    pub method: String,
+    /// When the URL expires, as a Unix timestamp
+    pub expires: i64,
+    /// The signature of the other parameters and the object location
+    pub signature: String,
+}
+
+/// Signs and verifies URLs using a random key. The key is not persisted, so URLs stop working
+/// when the server restarts.
+pub(super) struct LocalSigner {
+    base_url: Url,
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalSigner {
+    pub fn new(base_url: Url) -> Self {
+        let mut key = [0u8; 32];
+        key[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        Self { base_url, key }
+    }
+
+    fn signature(&self, method: &str, location: &str, expires: i64) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.key);
+        hasher.update(method.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(location.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(expires.to_string().as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn sign(&self, method: &str, location: &str, expires_in: Duration) -> Url {
+        let expires = chrono::Utc::now().timestamp() + expires_in.as_secs() as i64;
+        let signature = URL_SAFE_NO_PAD.encode(self.signature(method, location, expires));
+
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("presigned URL base must be a valid base URL")
+            .pop_if_empty()
+            .extend(location.split('/'));
+        url.query_pairs_mut()
+            .append_pair("method", method)
+            .append_pair("expires", &expires.to_string())
+            .append_pair("signature", &signature);
+        url
+    }
+
+    pub fn verify(
+        &self,
+        method: &str,
+        location: &str,
+        query: &LocalPresignedQuery,
+    ) -> Result<(), StorageError> {
+        if query.method != method || query.expires < chrono::Utc::now().timestamp() {
+            return Err(StorageError::InvalidSignature);
+        }
+
+        let expected = self.signature(method, location, query.expires);
+        let signature = URL_SAFE_NO_PAD
+            .decode(&query.signature)
+            .map_err(|_| StorageError::InvalidSignature)?;
+
+        // Constant-time comparison
+        let matches = signature.len() == expected.len()
+            && signature
+                .iter()
+                .zip(expected.iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0;
+
+        if matches {
+            Ok(())
+        } else {
+            Err(StorageError::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+
+    use super::*;
+    use crate::storage::Storage;
+
+    fn query(url: &Url) -> LocalPresignedQuery {
+        let pairs = url
+            .query_pairs()
+            .into_owned()
+            .collect::<std::collections::HashMap<_, _>>();
+        LocalPresignedQuery {
+            method: pairs["method"].clone(),
+            expires: pairs["expires"].parse().unwrap(),
+            signature: pairs["signature"].clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_and_download() {
+        let storage = Storage::new_memory().with_local_presigned_url_base(Some(
+            Url::parse("https://example.com/api/storage/files/").unwrap(),
+        ));
+        assert!(storage.supports_presigned_urls());
+
+        let location = "org/a file.txt";
+        let put_url = storage
+            .presigned_put_url(location, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(put_url.path(), "/api/storage/files/org/a%20file.txt");
+
+        storage
+            .serve_local_presigned_url(
+                &http::Method::PUT,
+                location,
+                &query(&put_url),
+                Body::from("contents"),
+            )
+            .await
+            .expect("upload");
+
+        let err = storage
+            .serve_local_presigned_url(
+                &http::Method::GET,
+                location,
+                &query(&put_url),
+                Body::empty(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, StorageError::InvalidSignature));
+
+        let get_url = storage
+            .presigned_get_url(location, Duration::from_secs(60))
+            .await
+            .unwrap();
+        let response = storage
+            .serve_local_presigned_url(
+                &http::Method::GET,
+                location,
+                &query(&get_url),
+                Body::empty(),
+            )
+            .await
+            .expect("download");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), b"contents");
+    }
+
+    #[test]
+    fn reject_modified_urls() {
+        let signer = LocalSigner::new(Url::parse("https://example.com/storage/").unwrap());
+        let url = signer.sign("GET", "a.txt", Duration::from_secs(60));
+        let q = query(&url);
+        signer.verify("GET", "a.txt", &q).expect("valid signature");
+
+        let err = signer.verify("GET", "b.txt", &q).unwrap_err();
+        assert!(matches!(err, StorageError::InvalidSignature));
+
+        let expired = LocalPresignedQuery {
+            expires: q.expires - 120,
+            ..q
+        };
+        let err = signer.verify("GET", "a.txt", &expired).unwrap_err();
+        assert!(matches!(err, StorageError::InvalidSignature));
+
+        let other_signer = LocalSigner::new(Url::parse("https://example.com/storage/").unwrap());
+        let err = other_signer
+            .verify("GET", "a.txt", &query(&url))
+            .unwrap_err();
+        assert!(matches!(err, StorageError::InvalidSignature));
+    }
+}
@@ -16,7 +16,7 @@ impl Display for OptionAsString<'_> {
 }
 
 /// Write an Option<Url> out in a way that will compile to the same thing.
-/// None remains "None", Some("abc") becomes "Some(r##"abc"##.parse()?)
+/// None remains "None", Some("abc") becomes "Some(r##"abc"##.parse().map_err(...)?)"
 pub struct OptionAsStorageUrl<'a>(pub &'a Option<Url>);
 
 impl Display for OptionAsStorageUrl<'_> {
@@ -26,7 +26,7 @@ impl Display for OptionAsStorageUrl<'_> {
             Some(s) => {
                 write!(
                     f,
-                    r###"Some(r##"{}"##.parse().map(StorageError::Configuration("Invalid endpoint URL)))"###,
+                    r###"Some(r##"{}"##.parse().map_err(|_| StorageError::Configuration("Invalid endpoint URL"))?)"###,
                     s.to_string(),
                 )
             }
@@ -71,6 +71,11 @@ impl TestClient {
         self.client.put(format!("{}/{}", self.base, url.as_ref()))
     }
 
+    /// Create a new PATCH request
+    pub fn patch(&self, url: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.client.patch(format!("{}/{}", self.base, url.as_ref()))
+    }
+
     /// Create a new DELETE request
     pub fn delete(&self, url: impl AsRef<str>) -> reqwest::RequestBuilder {
         self.client
@@ -115,3 +120,510 @@ impl ResponseExt for reqwest::Response {
         }
     }
 }
+
+/// Create a PNG image with the given dimensions, for testing image uploads.
+#[cfg(feature = "images")]
+pub fn test_png(width: u32, height: u32) -> Vec<u8> {
+    let image = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+    });
+    let mut data = Vec::new();
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut data),
+            image::ImageFormat::Png,
+        )
+        .expect("encoding test image");
+    data
+}
+
+/// The EICAR test file, which virus scanners detect as a virus without it being harmful.
+#[cfg(feature = "storage")]
+pub const EICAR_TEST_FILE: &[u8] =
+    br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+/// An [UploadScanner](crate::uploads::UploadScanner) for tests, which reports any file
+/// containing [EICAR_TEST_FILE] as infected.
+#[cfg(feature = "storage")]
+#[derive(Debug, Default)]
+pub struct TestUploadScanner;
+
+#[cfg(feature = "storage")]
+#[async_trait]
+impl crate::uploads::UploadScanner for TestUploadScanner {
+    async fn scan(
+        &self,
+        data: futures::stream::BoxStream<'_, Result<bytes::Bytes, crate::storage::StorageError>>,
+    ) -> Result<crate::uploads::ScanResult, crate::uploads::ScanError> {
+        use futures::TryStreamExt;
+
+        let data = data
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map_err(crate::uploads::ScanError::Read)?;
+        let infected = data
+            .windows(EICAR_TEST_FILE.len())
+            .any(|w| w == EICAR_TEST_FILE);
+
+        if infected {
+            Ok(crate::uploads::ScanResult::Infected(
+                "EICAR-Test-File".to_string(),
+            ))
+        } else {
+            Ok(crate::uploads::ScanResult::Clean)
+        }
+    }
+}
+
+/// An OAuth provider for tests, named `test`, which never contacts a real server. The
+/// authorization code passed to the callback is used as the user's email, and `test-{code}` as
+/// their login ID, so a test can log in as any user by completing the callback with their email.
+#[cfg(feature = "local_auth")]
+pub struct TestOAuthProvider {
+    client: oauth2::basic::BasicClient,
+}
+
+#[cfg(feature = "local_auth")]
+impl TestOAuthProvider {
+    /// Create the provider. `redirect_base_url` is the same base URL passed to the other
+    /// providers.
+    pub fn new(redirect_base_url: &str) -> Self {
+        let client = oauth2::basic::BasicClient::new(
+            oauth2::ClientId::new("test-client".to_string()),
+            None,
+            oauth2::AuthUrl::new("https://oauth.example.com/authorize".to_string()).unwrap(),
+            None,
+        )
+        .set_redirect_uri(
+            oauth2::RedirectUrl::new(crate::auth::oauth::providers::build_redirect_url(
+                redirect_base_url,
+                "test",
+            ))
+            .unwrap(),
+        );
+
+        Self { client }
+    }
+}
+
+#[cfg(feature = "local_auth")]
+#[async_trait]
+impl crate::auth::oauth::providers::OAuthProvider for TestOAuthProvider {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn client(&self) -> &oauth2::basic::BasicClient {
+        &self.client
+    }
+
+    fn authorize_url(&self) -> crate::auth::oauth::providers::AuthorizeUrl {
+        let (url, state) = self
+            .client
+            .authorize_url(oauth2::CsrfToken::new_random)
+            .url();
+
+        crate::auth::oauth::providers::AuthorizeUrl {
+            url,
+            state,
+            pkce_verifier: None,
+        }
+    }
+
+    async fn fetch_access_token(
+        &self,
+        _authorization_code: String,
+        _pkce_verifier: String,
+    ) -> Result<
+        oauth2::basic::BasicTokenResponse,
+        error_stack::Report<crate::auth::oauth::OAuthError>,
+    > {
+        Err(error_stack::Report::new(
+            crate::auth::oauth::OAuthError::ExchangeError,
+        ))
+    }
+
+    async fn fetch_user_details(
+        &self,
+        _client: reqwest::Client,
+        access_token: &str,
+    ) -> Result<crate::auth::oauth::providers::OAuthUserDetails, reqwest::Error> {
+        Ok(crate::auth::oauth::providers::OAuthUserDetails {
+            login_id: format!("test-{access_token}"),
+            email: Some(access_token.to_string()),
+            ..Default::default()
+        })
+    }
+
+    async fn fetch_login_details(
+        &self,
+        client: reqwest::Client,
+        authorization_code: String,
+        _pkce_verifier: String,
+    ) -> Result<
+        crate::auth::oauth::providers::OAuthUserDetails,
+        error_stack::Report<crate::auth::oauth::OAuthError>,
+    > {
+        // Skip the token exchange and use the code directly.
+        self.fetch_user_details(client, &authorization_code)
+            .await
+            .map_err(|e| {
+                error_stack::Report::new(e)
+                    .change_context(crate::auth::oauth::OAuthError::FetchUserDetails)
+            })
+    }
+}
+
+/// A software passkey authenticator, for testing passkey registration and login without a
+/// browser or hardware. Each authenticator holds a single ES256 credential.
+///
+/// Cloning the authenticator clones the credential and its signature counter, which can be used
+/// to test detection of cloned authenticators.
+#[cfg(feature = "local_auth")]
+#[derive(Clone)]
+pub struct SoftwareAuthenticator {
+    origin: String,
+    pkcs8: Vec<u8>,
+    credential_id: Vec<u8>,
+    user_handle: Option<Vec<u8>>,
+    sign_count: u32,
+}
+
+#[cfg(feature = "local_auth")]
+impl std::fmt::Debug for SoftwareAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoftwareAuthenticator")
+            .field("origin", &self.origin)
+            .field("sign_count", &self.sign_count)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "local_auth")]
+impl SoftwareAuthenticator {
+    /// Create an authenticator with a new key, which acts as if it is being used from a page at
+    /// `origin`.
+    pub fn new(origin: impl Into<String>) -> Self {
+        use rand::RngCore;
+        use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("generating key");
+        let mut credential_id = vec![0u8; 16];
+        rand::thread_rng().fill_bytes(&mut credential_id);
+
+        Self {
+            origin: origin.into(),
+            pkcs8: pkcs8.as_ref().to_vec(),
+            credential_id,
+            user_handle: None,
+            sign_count: 0,
+        }
+    }
+
+    /// The ID of the authenticator's credential, base64url-encoded
+    pub fn credential_id(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    fn key_pair(&self) -> ring::signature::EcdsaKeyPair {
+        ring::signature::EcdsaKeyPair::from_pkcs8(
+            &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+            &self.pkcs8,
+            &ring::rand::SystemRandom::new(),
+        )
+        .expect("loading key")
+    }
+
+    fn client_data(&self, type_: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": type_,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn authenticator_data(&mut self, rp_id: &str, credential: Option<&[u8]>) -> Vec<u8> {
+        // User present and user verified
+        let mut flags = 0x01 | 0x04;
+        if credential.is_some() {
+            flags |= 0x40;
+        }
+
+        self.sign_count += 1;
+        let mut data = ring::digest::digest(&ring::digest::SHA256, rp_id.as_bytes())
+            .as_ref()
+            .to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+
+        if let Some(public_key) = credential {
+            // An all-zero AAGUID, as used by authenticators that don't provide attestation.
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            data.extend_from_slice(public_key);
+        }
+
+        data
+    }
+
+    /// Respond to a registration request, as `navigator.credentials.create` would.
+    pub fn register(
+        &mut self,
+        options: &crate::auth::passkeys::PasskeyCreationOptions,
+    ) -> crate::auth::passkeys::PasskeyRegistration {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        use ring::signature::KeyPair;
+
+        use crate::auth::passkeys::{AttestationResponse, CoseKey, PasskeyRegistration};
+
+        let key_pair = self.key_pair();
+        let point = key_pair.public_key().as_ref();
+        let public_key = CoseKey::Es256 {
+            x: point[1..33].to_vec(),
+            y: point[33..65].to_vec(),
+        }
+        .to_cbor();
+
+        let auth_data = self.authenticator_data(&options.rp.id, Some(&public_key));
+        let attestation = ciborium::Value::Map(vec![
+            ("fmt".into(), "none".into()),
+            ("attStmt".into(), ciborium::Value::Map(vec![])),
+            ("authData".into(), ciborium::Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(&attestation, &mut attestation_object)
+            .expect("encoding attestation");
+
+        self.user_handle = URL_SAFE_NO_PAD.decode(&options.user.id).ok();
+
+        PasskeyRegistration {
+            id: self.credential_id(),
+            response: AttestationResponse {
+                client_data_json: URL_SAFE_NO_PAD
+                    .encode(self.client_data("webauthn.create", &options.challenge)),
+                attestation_object: URL_SAFE_NO_PAD.encode(attestation_object),
+            },
+            name: Some("Test passkey".to_string()),
+        }
+    }
+
+    /// Respond to a login request, as `navigator.credentials.get` would.
+    pub fn login(
+        &mut self,
+        options: &crate::auth::passkeys::PasskeyRequestOptions,
+    ) -> crate::auth::passkeys::PasskeyAssertion {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        use crate::auth::passkeys::{AssertionResponse, PasskeyAssertion};
+
+        let auth_data = self.authenticator_data(&options.rp_id, None);
+        let client_data = self.client_data("webauthn.get", &options.challenge);
+        let client_data_hash = ring::digest::digest(&ring::digest::SHA256, &client_data);
+        let message = [auth_data.as_slice(), client_data_hash.as_ref()].concat();
+        let signature = self
+            .key_pair()
+            .sign(&ring::rand::SystemRandom::new(), &message)
+            .expect("signing assertion");
+
+        PasskeyAssertion {
+            id: self.credential_id(),
+            response: AssertionResponse {
+                client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                authenticator_data: URL_SAFE_NO_PAD.encode(auth_data),
+                signature: URL_SAFE_NO_PAD.encode(signature.as_ref()),
+                user_handle: self.user_handle.as_ref().map(|h| URL_SAFE_NO_PAD.encode(h)),
+            },
+        }
+    }
+}
+
+/// Encode a DER element with the given tag
+#[cfg(feature = "local_auth")]
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len < 0x100 {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// A SAML identity provider for testing SAML login without an external service. It signs
+/// assertions with an ECDSA P-256 key, which is generated when the IdP is created.
+#[cfg(feature = "local_auth")]
+pub struct TestSamlIdp {
+    /// The IdP's entity ID
+    pub entity_id: String,
+    key_pair: ring::signature::EcdsaKeyPair,
+    certificate: Vec<u8>,
+}
+
+#[cfg(feature = "local_auth")]
+impl std::fmt::Debug for TestSamlIdp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestSamlIdp")
+            .field("entity_id", &self.entity_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "local_auth")]
+impl TestSamlIdp {
+    /// The URL that the test IdP claims to receive login requests at
+    pub const SSO_URL: &'static str = "https://idp.example.com/saml/sso";
+
+    /// Create an IdP with a new signing key
+    pub fn new(entity_id: impl Into<String>) -> Self {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .expect("generating key");
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .expect("loading key");
+
+        // A self-signed certificate in structure only. Only the public key is ever read from
+        // it, so the signature is left empty.
+        let oid_ec = der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]);
+        let oid_p256 = der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]);
+        let ecdsa_sha256 = der(
+            0x30,
+            &der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02]),
+        );
+        let common_name = [der(0x06, &[0x55, 0x04, 0x03]), der(0x0c, b"Test IdP")].concat();
+        let name = der(0x30, &der(0x31, &der(0x30, &common_name)));
+        let validity = der(
+            0x30,
+            &[der(0x17, b"240101000000Z"), der(0x17, b"491231235959Z")].concat(),
+        );
+        let public_key = [&[0u8][..], key_pair.public_key().as_ref()].concat();
+        let spki = der(
+            0x30,
+            &[
+                der(0x30, &[oid_ec, oid_p256].concat()),
+                der(0x03, &public_key),
+            ]
+            .concat(),
+        );
+        let tbs = der(
+            0x30,
+            &[
+                der(0xa0, &der(0x02, &[2])),
+                der(0x02, &[1]),
+                ecdsa_sha256.clone(),
+                name.clone(),
+                validity,
+                name,
+                spki,
+            ]
+            .concat(),
+        );
+        let certificate = der(0x30, &[tbs, ecdsa_sha256, der(0x03, &[0])].concat());
+
+        Self {
+            entity_id: entity_id.into(),
+            key_pair,
+            certificate,
+        }
+    }
+
+    /// The IdP's certificate, PEM-encoded
+    pub fn certificate_pem(&self) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        format!(
+            "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+            STANDARD.encode(&self.certificate)
+        )
+    }
+
+    /// The IdP's metadata XML
+    pub fn metadata(&self) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        format!(
+            r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" entityID="{entity_id}">
+  <md:IDPSSODescriptor protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">
+    <md:KeyDescriptor use="signing">
+      <ds:KeyInfo xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+        <ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data>
+      </ds:KeyInfo>
+    </md:KeyDescriptor>
+    <md:SingleSignOnService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect" Location="{sso_url}"/>
+  </md:IDPSSODescriptor>
+</md:EntityDescriptor>"#,
+            entity_id = quick_xml::escape::escape(&self.entity_id),
+            certificate = STANDARD.encode(&self.certificate),
+            sso_url = Self::SSO_URL,
+        )
+    }
+
+    /// Respond to the login request in `login_redirect`, which is the URL that the app
+    /// redirected the user to, by logging in the user with the given NameID and attributes. This
+    /// returns the base64-encoded `SAMLResponse` to post to the app's ACS URL.
+    pub fn respond(
+        &self,
+        login_redirect: &str,
+        name_id: &str,
+        attributes: &[(&str, &str)],
+    ) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use quick_xml::escape::escape;
+
+        let url = url::Url::parse(login_redirect).expect("parsing login redirect");
+        let request = crate::auth::saml::read_authn_request(&url).expect("reading AuthnRequest");
+
+        let now = chrono::Utc::now();
+        let format_time =
+            |t: chrono::DateTime<chrono::Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let issue_instant = format_time(now);
+        let not_before = format_time(now - chrono::Duration::minutes(1));
+        let not_on_or_after = format_time(now + chrono::Duration::minutes(5));
+        let issuer = escape(&self.entity_id);
+        let in_response_to = escape(&request.id);
+        let acs_url = escape(&request.acs_url);
+        let audience = escape(&request.issuer);
+
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    r#"<saml:Attribute Name="{}"><saml:AttributeValue>{}</saml:AttributeValue></saml:Attribute>"#,
+                    escape(name),
+                    escape(value)
+                )
+            })
+            .collect::<String>();
+        let attribute_statement = if attributes.is_empty() {
+            String::new()
+        } else {
+            format!("<saml:AttributeStatement>{attributes}</saml:AttributeStatement>")
+        };
+
+        let assertion = format!(
+            r#"<saml:Assertion xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_{assertion_id}" Version="2.0" IssueInstant="{issue_instant}"><saml:Issuer>{issuer}</saml:Issuer><saml:Subject><saml:NameID Format="urn:oasis:names:tc:SAML:2.0:nameid-format:persistent">{name_id}</saml:NameID><saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer"><saml:SubjectConfirmationData InResponseTo="{in_response_to}" NotOnOrAfter="{not_on_or_after}" Recipient="{acs_url}"/></saml:SubjectConfirmation></saml:Subject><saml:Conditions NotBefore="{not_before}" NotOnOrAfter="{not_on_or_after}"><saml:AudienceRestriction><saml:Audience>{audience}</saml:Audience></saml:AudienceRestriction></saml:Conditions><saml:AuthnStatement AuthnInstant="{issue_instant}"><saml:AuthnContext><saml:AuthnContextClassRef>urn:oasis:names:tc:SAML:2.0:ac:classes:unspecified</saml:AuthnContextClassRef></saml:AuthnContext></saml:AuthnStatement>{attribute_statement}</saml:Assertion>"#,
+            assertion_id = uuid::Uuid::new_v4().simple(),
+            name_id = escape(name_id),
+        );
+        let assertion = crate::auth::saml::sign_enveloped(&assertion, &self.key_pair);
+
+        let response = format!(
+            r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_{response_id}" Version="2.0" IssueInstant="{issue_instant}" Destination="{acs_url}" InResponseTo="{in_response_to}"><saml:Issuer>{issuer}</saml:Issuer><samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>{assertion}</samlp:Response>"#,
+            response_id = uuid::Uuid::new_v4().simple(),
+        );
+
+        STANDARD.encode(response)
+    }
+}
@@ -8,6 +8,12 @@ use crate::{
     storage::StorageError,
 };
 
+/// Scan uploads with a ClamAV daemon
+pub mod clamav;
+mod scanner;
+
+pub use scanner::*;
+
 /// An object that can inspect chunks of a stream as it is uploaded
 pub trait UploadInspector<E> {
     /// Inspect a chunk of the stream
@@ -26,6 +32,9 @@ pub enum UploadInspectorError {
     /// An I/O error occurred while reading the request body
     #[error(transparent)]
     Read(#[from] axum::Error),
+    /// The file's content type is not in the list of allowed types
+    #[error("Content type {0} is not allowed")]
+    UnsupportedContentType(String),
 }
 
 impl HttpError for UploadInspectorError {
@@ -36,6 +45,9 @@ impl HttpError for UploadInspectorError {
             UploadInspectorError::FileSizeTooLarge => http::StatusCode::PAYLOAD_TOO_LARGE,
             UploadInspectorError::IO(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
             UploadInspectorError::Read(_) => http::StatusCode::BAD_REQUEST,
+            UploadInspectorError::UnsupportedContentType(_) => {
+                http::StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
         }
     }
 
@@ -44,6 +56,7 @@ impl HttpError for UploadInspectorError {
             UploadInspectorError::FileSizeTooLarge => ErrorKind::UploadTooLarge,
             UploadInspectorError::IO(_) => ErrorKind::IO,
             UploadInspectorError::Read(_) => ErrorKind::RequestRead,
+            UploadInspectorError::UnsupportedContentType(_) => ErrorKind::UnsupportedMediaType,
         }
         .as_str()
     }
@@ -109,3 +122,166 @@ impl<D: Digest> UploadInspector<UploadInspectorError> for UploadHasher<D> {
         Ok(())
     }
 }
+
+/// The number of bytes from the start of a file to examine when detecting its content type.
+const CONTENT_TYPE_SNIFF_LEN: usize = 8192;
+
+/// Detect the content type of an upload from its first bytes, instead of trusting the type
+/// sent by the client, and optionally reject types that are not in an allowlist.
+///
+/// Files that don't match any known binary format are detected as `text/plain` if they are
+/// valid UTF-8, and `application/octet-stream` otherwise.
+pub struct UploadContentType<'a> {
+    buffer: Vec<u8>,
+    allowed: Option<&'a [&'a str]>,
+    checked: bool,
+}
+
+impl<'a> UploadContentType<'a> {
+    /// Create a new UploadContentType inspector. If `allowed` is set, uploads with a content
+    /// type not in the list will be rejected. Entries can end in `/*` to allow any subtype,
+    /// such as `image/*`.
+    pub fn new(allowed: Option<&'a [&'a str]>) -> Self {
+        Self {
+            buffer: Vec::new(),
+            allowed,
+            checked: false,
+        }
+    }
+
+    /// Return the detected content type. This also checks the content type against the
+    /// allowlist, for files too small to have been checked while they were being uploaded.
+    pub fn finish(mut self) -> Result<String, UploadInspectorError> {
+        let content_type = self.detect();
+        if !self.checked {
+            self.check(&content_type)?;
+        }
+
+        Ok(content_type)
+    }
+
+    fn detect(&self) -> String {
+        match infer::get(&self.buffer) {
+            Some(t) => t.mime_type().to_string(),
+            None => {
+                let text = match std::str::from_utf8(&self.buffer) {
+                    Ok(_) => true,
+                    // The buffer may end in the middle of a multi-byte character.
+                    Err(e) => {
+                        e.error_len().is_none() && self.buffer.len() == CONTENT_TYPE_SNIFF_LEN
+                    }
+                };
+
+                if text {
+                    "text/plain".to_string()
+                } else {
+                    "application/octet-stream".to_string()
+                }
+            }
+        }
+    }
+
+    fn check(&mut self, content_type: &str) -> Result<(), UploadInspectorError> {
+        self.checked = true;
+        let Some(allowed) = self.allowed else {
+            return Ok(());
+        };
+
+        let matches = allowed
+            .iter()
+            .any(|allowed| match allowed.strip_suffix("/*") {
+                Some(prefix) => content_type
+                    .split_once('/')
+                    .map(|(t, _)| t == prefix)
+                    .unwrap_or(false),
+                None => *allowed == content_type,
+            });
+
+        if matches {
+            Ok(())
+        } else {
+            Err(UploadInspectorError::UnsupportedContentType(
+                content_type.to_string(),
+            ))
+        }
+    }
+}
+
+impl<'a> UploadInspector<UploadInspectorError> for UploadContentType<'a> {
+    /// Save the start of the file and, once enough has been read, check its content type so
+    /// that disallowed uploads can be rejected early.
+    fn inspect(&mut self, bytes: &Bytes) -> Result<(), UploadInspectorError> {
+        if self.buffer.len() >= CONTENT_TYPE_SNIFF_LEN {
+            return Ok(());
+        }
+
+        let needed = CONTENT_TYPE_SNIFF_LEN - self.buffer.len();
+        self.buffer
+            .extend_from_slice(&bytes[..needed.min(bytes.len())]);
+
+        if self.buffer.len() >= CONTENT_TYPE_SNIFF_LEN {
+            let content_type = self.detect();
+            self.check(&content_type)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn detect(chunks: &[&[u8]], allowed: Option<&[&str]>) -> Result<String, UploadInspectorError> {
+        let mut inspector = UploadContentType::new(allowed);
+        for chunk in chunks {
+            inspector.inspect(&Bytes::copy_from_slice(chunk))?;
+        }
+        inspector.finish()
+    }
+
+    #[test]
+    fn detect_content_type() {
+        assert_eq!(detect(&[PNG_HEADER], None).unwrap(), "image/png");
+        // Split across chunks
+        assert_eq!(
+            detect(&[&PNG_HEADER[..3], &PNG_HEADER[3..]], None).unwrap(),
+            "image/png"
+        );
+        assert_eq!(detect(&[b"hello, world"], None).unwrap(), "text/plain");
+        assert_eq!(
+            detect(&[&[0, 159, 146, 150, 1]], None).unwrap(),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn allowlist() {
+        let allowed: &[&str] = &["image/*", "application/pdf"];
+        assert_eq!(detect(&[PNG_HEADER], Some(allowed)).unwrap(), "image/png");
+        assert_eq!(
+            detect(&[b"%PDF-1.7\n"], Some(allowed)).unwrap(),
+            "application/pdf"
+        );
+
+        let err = detect(&[b"<html></html>"], Some(allowed)).unwrap_err();
+        assert!(
+            matches!(err, UploadInspectorError::UnsupportedContentType(ref t) if t == "text/html")
+        );
+        assert_eq!(err.status_code(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn reject_before_upload_finishes() {
+        let allowed: &[&str] = &["image/png"];
+        let mut inspector = UploadContentType::new(Some(allowed));
+        let chunk = Bytes::from(vec![b'a'; CONTENT_TYPE_SNIFF_LEN]);
+        let err = inspector.inspect(&chunk).unwrap_err();
+        assert!(matches!(
+            err,
+            UploadInspectorError::UnsupportedContentType(_)
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+//! Scan uploads with a ClamAV daemon, using the clamd `INSTREAM` protocol.
+
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{ScanError, ScanResult, UploadScanner};
+use crate::storage::StorageError;
+
+/// The largest chunk to send to clamd at once
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Where to connect to clamd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClamAvAddress {
+    /// A TCP address, such as `localhost:3310`
+    Tcp(String),
+    /// The path to a Unix socket
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for ClamAvAddress {
+    type Err = std::convert::Infallible;
+
+    /// Parse an address. Values starting with `unix:` or `/` are treated as a path to a Unix
+    /// socket, and anything else as a TCP host and port.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(unix)]
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(ClamAvAddress::Unix(PathBuf::from(path)));
+        } else if s.starts_with('/') {
+            return Ok(ClamAvAddress::Unix(PathBuf::from(s)));
+        }
+
+        Ok(ClamAvAddress::Tcp(
+            s.strip_prefix("tcp://").unwrap_or(s).to_string(),
+        ))
+    }
+}
+
+/// An [UploadScanner] that sends files to a ClamAV daemon
+#[derive(Debug, Clone)]
+pub struct ClamAvScanner {
+    address: ClamAvAddress,
+    timeout: Duration,
+}
+
+impl ClamAvScanner {
+    /// Create a scanner that connects to clamd at the given address.
+    pub fn new(address: ClamAvAddress) -> Self {
+        Self {
+            address,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Create a scanner from an address string, as parsed by [ClamAvAddress::from_str].
+    pub fn from_address(address: &str) -> Self {
+        let Ok(address) = address.parse();
+        Self::new(address)
+    }
+
+    /// Set how long to wait for a scan to finish. The default is 60 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Check that the daemon is reachable.
+    pub async fn ping(&self) -> Result<(), ScanError> {
+        let response = match &self.address {
+            ClamAvAddress::Tcp(address) => {
+                let conn = tokio::net::TcpStream::connect(address)
+                    .await
+                    .map_err(ScanError::Connection)?;
+                self.with_timeout_error(send_command(conn, b"zPING\0"))
+                    .await?
+            }
+            #[cfg(unix)]
+            ClamAvAddress::Unix(path) => {
+                let conn = tokio::net::UnixStream::connect(path)
+                    .await
+                    .map_err(ScanError::Connection)?;
+                self.with_timeout_error(send_command(conn, b"zPING\0"))
+                    .await?
+            }
+        };
+
+        if response == "PONG" {
+            Ok(())
+        } else {
+            Err(ScanError::Scanner(response))
+        }
+    }
+
+    async fn with_timeout_error<T>(
+        &self,
+        f: impl std::future::Future<Output = Result<T, ScanError>>,
+    ) -> Result<T, ScanError> {
+        tokio::time::timeout(self.timeout, f)
+            .await
+            .map_err(|_| ScanError::Connection(std::io::ErrorKind::TimedOut.into()))?
+    }
+}
+
+#[async_trait]
+impl UploadScanner for ClamAvScanner {
+    async fn scan(
+        &self,
+        data: BoxStream<'_, Result<Bytes, StorageError>>,
+    ) -> Result<ScanResult, ScanError> {
+        let response = match &self.address {
+            ClamAvAddress::Tcp(address) => {
+                let conn = tokio::net::TcpStream::connect(address)
+                    .await
+                    .map_err(ScanError::Connection)?;
+                self.with_timeout_error(send_stream(conn, data)).await?
+            }
+            #[cfg(unix)]
+            ClamAvAddress::Unix(path) => {
+                let conn = tokio::net::UnixStream::connect(path)
+                    .await
+                    .map_err(ScanError::Connection)?;
+                self.with_timeout_error(send_stream(conn, data)).await?
+            }
+        };
+
+        parse_scan_response(response)
+    }
+}
+
+async fn send_command(
+    mut conn: impl AsyncRead + AsyncWrite + Unpin,
+    command: &[u8],
+) -> Result<String, ScanError> {
+    conn.write_all(command)
+        .await
+        .map_err(ScanError::Connection)?;
+    read_response(&mut conn).await
+}
+
+/// Send a file to clamd with the INSTREAM command and return its response.
+async fn send_stream(
+    mut conn: impl AsyncRead + AsyncWrite + Unpin,
+    mut data: BoxStream<'_, Result<Bytes, StorageError>>,
+) -> Result<String, ScanError> {
+    conn.write_all(b"zINSTREAM\0")
+        .await
+        .map_err(ScanError::Connection)?;
+
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk.map_err(ScanError::Read)?;
+        for piece in chunk.chunks(MAX_CHUNK_SIZE) {
+            let written = write_chunk(&mut conn, piece).await;
+            if let Err(e) = written {
+                // clamd closes the connection when the file exceeds its size limit, so see if it
+                // sent a reason before giving up.
+                return match read_response(&mut conn).await {
+                    Ok(response) if !response.is_empty() => Ok(response),
+                    _ => Err(ScanError::Connection(e)),
+                };
+            }
+        }
+    }
+
+    conn.write_all(&[0, 0, 0, 0])
+        .await
+        .map_err(ScanError::Connection)?;
+    read_response(&mut conn).await
+}
+
+async fn write_chunk(
+    conn: &mut (impl AsyncWrite + Unpin),
+    chunk: &[u8],
+) -> Result<(), std::io::Error> {
+    conn.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+    conn.write_all(chunk).await
+}
+
+/// Read a response, which is terminated by a NUL byte or the end of the connection.
+async fn read_response(conn: &mut (impl AsyncRead + Unpin)) -> Result<String, ScanError> {
+    let mut response = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = conn.read(&mut buf).await.map_err(ScanError::Connection)?;
+        if n == 0 {
+            break;
+        }
+
+        if let Some(end) = buf[..n].iter().position(|b| *b == 0) {
+            response.extend_from_slice(&buf[..end]);
+            break;
+        }
+
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    Ok(String::from_utf8_lossy(&response).trim().to_string())
+}
+
+fn parse_scan_response(response: String) -> Result<ScanResult, ScanError> {
+    let result = response.strip_prefix("stream: ").unwrap_or(&response);
+    if result == "OK" {
+        Ok(ScanResult::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(ScanResult::Infected(signature.to_string()))
+    } else {
+        Err(ScanError::Scanner(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::testing::EICAR_TEST_FILE;
+
+    /// Run a fake clamd that handles a single connection.
+    async fn fake_clamd(size_limit: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::task::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 10];
+            conn.read_exact(&mut command[..6]).await.unwrap();
+            if &command[..6] == b"zPING\0" {
+                conn.write_all(b"PONG\0").await.unwrap();
+                return;
+            }
+
+            conn.read_exact(&mut command[6..]).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+
+            let mut data = Vec::new();
+            loop {
+                let len = conn.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    break;
+                }
+
+                let start = data.len();
+                data.resize(start + len, 0);
+                conn.read_exact(&mut data[start..]).await.unwrap();
+
+                if data.len() > size_limit {
+                    conn.write_all(b"INSTREAM size limit exceeded. ERROR\0")
+                        .await
+                        .unwrap();
+                    return;
+                }
+            }
+
+            let infected = data
+                .windows(EICAR_TEST_FILE.len())
+                .any(|w| w == EICAR_TEST_FILE);
+            let response: &[u8] = if infected {
+                b"stream: Win.Test.EICAR_HDB-1 FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            conn.write_all(response).await.unwrap();
+        });
+
+        address
+    }
+
+    fn stream(chunks: Vec<&'static [u8]>) -> BoxStream<'static, Result<Bytes, StorageError>> {
+        futures::stream::iter(chunks.into_iter().map(|c| Ok(Bytes::from_static(c)))).boxed()
+    }
+
+    #[tokio::test]
+    async fn clean_file() {
+        let address = fake_clamd(1000).await;
+        let scanner = ClamAvScanner::from_address(&address);
+        let result = scanner
+            .scan(stream(vec![b"hello ", b"world"]))
+            .await
+            .unwrap();
+        assert_eq!(result, ScanResult::Clean);
+    }
+
+    #[tokio::test]
+    async fn infected_file() {
+        let address = fake_clamd(1000).await;
+        let scanner = ClamAvScanner::from_address(&address);
+        let result = scanner
+            .scan(stream(vec![b"abc", EICAR_TEST_FILE]))
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            ScanResult::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn size_limit_exceeded() {
+        let address = fake_clamd(4).await;
+        let scanner = ClamAvScanner::from_address(&address);
+        let err = scanner
+            .scan(stream(vec![b"hello world"]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ScanError::Scanner(ref message) if message.contains("size limit")),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn ping() {
+        let address = fake_clamd(1000).await;
+        ClamAvScanner::from_address(&address).ping().await.unwrap();
+    }
+
+    #[test]
+    fn parse_address() {
+        assert_eq!(
+            "localhost:3310".parse::<ClamAvAddress>().unwrap(),
+            ClamAvAddress::Tcp("localhost:3310".to_string())
+        );
+        assert_eq!(
+            "tcp://clamd:3310".parse::<ClamAvAddress>().unwrap(),
+            ClamAvAddress::Tcp("clamd:3310".to_string())
+        );
+        assert_eq!(
+            "/run/clamd.sock".parse::<ClamAvAddress>().unwrap(),
+            ClamAvAddress::Unix(PathBuf::from("/run/clamd.sock"))
+        );
+        assert_eq!(
+            "unix:/run/clamd.sock".parse::<ClamAvAddress>().unwrap(),
+            ClamAvAddress::Unix(PathBuf::from("/run/clamd.sock"))
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ErrorKind, HttpError},
+    storage::{Storage, StorageError},
+};
+
+/// A service that checks uploaded files for viruses and other malware. Scanners are invoked
+/// after an upload has been saved to storage, and the result is recorded in the file model's
+/// `scan_status` field.
+#[async_trait]
+pub trait UploadScanner: std::fmt::Debug + Send + Sync {
+    /// Scan the contents of a file
+    async fn scan(
+        &self,
+        data: BoxStream<'_, Result<Bytes, StorageError>>,
+    ) -> Result<ScanResult, ScanError>;
+
+    /// Read an object from storage and scan it
+    async fn scan_object(&self, storage: &Storage, key: &str) -> Result<ScanResult, ScanError> {
+        let data = storage
+            .get(key)
+            .await
+            .map_err(|e| ScanError::Read(e.into()))?
+            .into_stream()
+            .map(|chunk| chunk.map_err(StorageError::from))
+            .boxed();
+
+        self.scan(data).await
+    }
+}
+
+/// The outcome of scanning a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    /// No threats were found
+    Clean,
+    /// The scanner found a threat, with the name of the signature that matched
+    Infected(String),
+}
+
+impl ScanResult {
+    /// The status to record for a file with this result
+    pub fn status(&self) -> ScanStatus {
+        match self {
+            ScanResult::Clean => ScanStatus::Clean,
+            ScanResult::Infected(_) => ScanStatus::Infected,
+        }
+    }
+}
+
+/// The virus scan status of an uploaded file
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case", type_name = "text")]
+pub enum ScanStatus {
+    /// The file has not been scanned yet
+    #[default]
+    Pending,
+    /// The scanner found no threats
+    Clean,
+    /// The scanner found a threat
+    Infected,
+    /// No scanner was configured when the file was uploaded
+    Unscanned,
+}
+
+impl ScanStatus {
+    /// The string form of the status, as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanStatus::Pending => "pending",
+            ScanStatus::Clean => "clean",
+            ScanStatus::Infected => "infected",
+            ScanStatus::Unscanned => "unscanned",
+        }
+    }
+
+    /// Return an error if a file with this status should not be downloaded.
+    pub fn check_download(&self) -> Result<(), ScanError> {
+        match self {
+            ScanStatus::Pending => Err(ScanError::Pending),
+            ScanStatus::Infected => Err(ScanError::Infected),
+            ScanStatus::Clean | ScanStatus::Unscanned => Ok(()),
+        }
+    }
+}
+
+impl FromStr for ScanStatus {
+    type Err = ScanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(ScanStatus::Pending),
+            "clean" => Ok(ScanStatus::Clean),
+            "infected" => Ok(ScanStatus::Infected),
+            "unscanned" => Ok(ScanStatus::Unscanned),
+            _ => Err(ScanError::InvalidStatus(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for ScanStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error related to virus scanning
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    /// Failed to communicate with the scanner
+    #[error("Failed to communicate with the virus scanner")]
+    Connection(#[source] std::io::Error),
+    /// The scanner returned an error or an unexpected response
+    #[error("Virus scanner error: {0}")]
+    Scanner(String),
+    /// Failed to read the file being scanned
+    #[error("Failed to read file for scanning")]
+    Read(#[source] StorageError),
+    /// A scan status string was not recognized
+    #[error("Unknown scan status {0}")]
+    InvalidStatus(String),
+    /// The file can not be downloaded until it has been scanned
+    #[error("File has not been scanned yet")]
+    Pending,
+    /// The file can not be downloaded because the scanner found a threat
+    #[error("File failed the virus scan")]
+    Infected,
+}
+
+impl HttpError for ScanError {
+    type Detail = ();
+
+    fn status_code(&self) -> http::StatusCode {
+        match self {
+            ScanError::Connection(_) => http::StatusCode::SERVICE_UNAVAILABLE,
+            ScanError::Scanner(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            ScanError::Read(e) => e.status_code(),
+            ScanError::InvalidStatus(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            ScanError::Pending => http::StatusCode::CONFLICT,
+            ScanError::Infected => http::StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_kind(&self) -> &'static str {
+        match self {
+            ScanError::Connection(_) => ErrorKind::ScanFailed,
+            ScanError::Scanner(_) => ErrorKind::ScanFailed,
+            ScanError::Read(_) => ErrorKind::Storage,
+            ScanError::InvalidStatus(_) => ErrorKind::ScanFailed,
+            ScanError::Pending => ErrorKind::ScanPending,
+            ScanError::Infected => ErrorKind::FileInfected,
+        }
+        .as_str()
+    }
+
+    fn error_detail(&self) -> Self::Detail {}
+}
@@ -2,5 +2,7 @@
 pub mod organization;
 /// Role creation and management
 pub mod roles;
+/// SCIM 2.0 provisioning
+pub mod scim;
 /// User management
 pub mod users;
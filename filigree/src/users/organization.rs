@@ -1,16 +1,19 @@
-use sqlx::PgExecutor;
 use tracing::instrument;
 
-use crate::auth::{OrganizationId, UserId};
+use crate::{
+    auth::{OrganizationId, UserId},
+    db::DbExecutor,
+};
 
 /// Add a user to an organization. This does not assign any roles, so you should
 /// usually call [add_roles_to_user] after this with the appropriate roles.
 #[instrument(skip(db))]
 pub async fn add_user_to_organization(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "INSERT INTO organization_members
             (organization_id, user_id)
@@ -22,6 +25,18 @@ pub async fn add_user_to_organization(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "INSERT INTO organization_members
+            (organization_id, user_id)
+            VALUES (?1, ?2)
+            ON CONFLICT DO NOTHING",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
 
@@ -29,11 +44,12 @@ pub async fn add_user_to_organization(
 /// organization.
 #[instrument(skip(db))]
 pub async fn set_user_active(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
     active: bool,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "UPDATE organization_members
             SET active = $3
@@ -47,6 +63,20 @@ pub async fn set_user_active(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "UPDATE organization_members
+            SET active = ?3
+            WHERE
+                organization_id = ?1
+                AND user_id = ?2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .bind(active)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
 
@@ -56,10 +86,11 @@ pub async fn set_user_active(
 /// organization.
 #[instrument(skip(db))]
 pub async fn remove_user_from_organization(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "DELETE FROM organization_members
             WHERE
@@ -71,5 +102,17 @@ pub async fn remove_user_from_organization(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "DELETE FROM organization_members
+            WHERE
+                organization_id = ?1
+                AND user_id = ?2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
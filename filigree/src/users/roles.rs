@@ -1,17 +1,22 @@
-use sqlx::{query, PgExecutor};
+#[cfg(not(feature = "sqlite"))]
+use sqlx::query;
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::auth::{OrganizationId, RoleId, UserId};
+use crate::{
+    auth::{OrganizationId, RoleId, UserId},
+    db::DbExecutor,
+};
 
 /// Add roles to a user
 #[instrument(skip(db))]
 pub async fn add_roles_to_user(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
     role_ids: &[RoleId],
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     query!(
         r##"
         INSERT INTO user_roles (organization_id, user_id, role_id)
@@ -27,16 +32,30 @@ pub async fn add_roles_to_user(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    if !role_ids.is_empty() {
+        let mut q =
+            sqlx::QueryBuilder::new("INSERT INTO user_roles (organization_id, user_id, role_id) ");
+        q.push_values(role_ids, |mut row, role_id| {
+            row.push_bind(organization_id)
+                .push_bind(user_id)
+                .push_bind(*role_id);
+        });
+        q.push(" ON CONFLICT DO NOTHING");
+        q.build().execute(db).await?;
+    }
+
     Ok(())
 }
 
 /// Add the default role to a user, if one is set on the organization.
 #[instrument(skip(db))]
 pub async fn add_default_role_to_user(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     query!(
         r##"
         INSERT INTO user_roles (organization_id, user_id, role_id)
@@ -53,17 +72,33 @@ pub async fn add_default_role_to_user(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        r##"
+        INSERT INTO user_roles (organization_id, user_id, role_id)
+            SELECT ?1, ?2, default_role as role_id
+            FROM organizations
+            WHERE id = ?1 AND default_role IS NOT NULL
+        ON CONFLICT DO NOTHING
+        "##,
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
     Ok(())
 }
 
 /// Remove roles from a user
 #[instrument(skip(db))]
 pub async fn remove_roles_from_user(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     user_id: UserId,
     role_ids: &[RoleId],
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     query!(
         r##"
         DELETE FROM user_roles
@@ -79,17 +114,33 @@ pub async fn remove_roles_from_user(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    if !role_ids.is_empty() {
+        let mut q = sqlx::QueryBuilder::new("DELETE FROM user_roles WHERE organization_id = ");
+        q.push_bind(organization_id);
+        q.push(" AND user_id = ");
+        q.push_bind(user_id);
+        q.push(" AND role_id IN (");
+        let mut sep = q.separated(", ");
+        for role_id in role_ids {
+            sep.push_bind(*role_id);
+        }
+        q.push(")");
+        q.build().execute(db).await?;
+    }
+
     Ok(())
 }
 
 /// Add org-wide permissions to a role or user
 #[instrument(skip(db))]
 pub async fn add_permissions_to_role(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     actor_id: impl AsRef<Uuid> + std::fmt::Debug,
     permissions: &[String],
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     query!(
         r##"
         INSERT INTO permissions (organization_id, actor_id, permission)
@@ -105,17 +156,32 @@ pub async fn add_permissions_to_role(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    if !permissions.is_empty() {
+        let mut q = sqlx::QueryBuilder::new(
+            "INSERT INTO permissions (organization_id, actor_id, permission) ",
+        );
+        q.push_values(permissions, |mut row, permission| {
+            row.push_bind(organization_id)
+                .push_bind(*actor_id.as_ref())
+                .push_bind(permission);
+        });
+        q.push(" ON CONFLICT DO NOTHING");
+        q.build().execute(db).await?;
+    }
+
     Ok(())
 }
 
 /// Remove org-wide permissions from a role or user
 #[instrument(skip(db))]
 pub async fn remove_permissions_from_role(
-    db: impl PgExecutor<'_>,
+    db: impl DbExecutor<'_>,
     organization_id: OrganizationId,
     role_id: impl AsRef<Uuid> + std::fmt::Debug,
     permissions: &[String],
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     query!(
         r##"
         DELETE FROM permissions
@@ -131,5 +197,20 @@ pub async fn remove_permissions_from_role(
     .execute(db)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    if !permissions.is_empty() {
+        let mut q = sqlx::QueryBuilder::new("DELETE FROM permissions WHERE organization_id = ");
+        q.push_bind(organization_id);
+        q.push(" AND actor_id = ");
+        q.push_bind(*role_id.as_ref());
+        q.push(" AND permission IN (");
+        let mut sep = q.separated(", ");
+        for permission in permissions {
+            sep.push_bind(permission);
+        }
+        q.push(")");
+        q.build().execute(db).await?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,496 @@
+//! SCIM 2.0 provisioning, so that an organization's identity provider can create, update, and
+//! deactivate its users and manage their roles.
+//!
+//! The identity provider authenticates with an API key that belongs to the organization instead of
+//! to a user. These keys are not accepted by the normal API authentication, and can be created
+//! with [create_scim_api_key].
+//!
+//! SCIM users map onto the organization's members. Deactivating a user marks their membership
+//! inactive, and deleting a user removes them from the organization. The name and email of a user
+//! are only changed if the organization is the user's primary organization, so that one
+//! organization's identity provider can not modify accounts that belong to another organization.
+//!
+//! SCIM groups map onto the organization's roles, and group membership adds or removes the role
+//! from the user.
+
+mod filter;
+mod patch;
+mod queries;
+
+/// HTTP endpoints for SCIM provisioning
+pub mod endpoints;
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use error_stack::{AttachmentKind, Report};
+use http::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tracing::{event, Level};
+
+pub use endpoints::create_routes;
+pub use filter::Filter;
+pub use patch::PatchRequest;
+pub use queries::*;
+
+use crate::{
+    error_stack::ContextWithAttachmentsExt,
+    errors::{ErrorKind, HttpError},
+};
+
+/// The schema URN for users
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+/// The schema URN for groups
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+/// The schema URN for list responses
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+/// The schema URN for PATCH requests
+pub const PATCH_OP_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+/// The schema URN for error responses
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+/// The content type for SCIM requests and responses
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+/// The number of results returned from a list request when the client does not specify a count
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// The maximum number of results returned from a list request
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// An error from a SCIM request
+#[derive(Error, Debug)]
+pub enum ScimError {
+    /// The API key was missing, invalid, or did not belong to an organization
+    #[error("Unauthenticated")]
+    Unauthenticated,
+    /// The requested resource does not exist in the organization
+    #[error("Resource not found")]
+    NotFound,
+    /// The request body could not be parsed
+    #[error("Invalid request syntax")]
+    InvalidSyntax,
+    /// The filter could not be parsed or is not supported
+    #[error("Invalid filter")]
+    InvalidFilter,
+    /// The path in a PATCH operation could not be parsed
+    #[error("Invalid path")]
+    InvalidPath,
+    /// The PATCH operation did not match any values
+    #[error("No values matched the path")]
+    NoTarget,
+    /// A value in the request was missing or invalid
+    #[error("Invalid value")]
+    InvalidValue,
+    /// The request conflicts with another resource, such as a user with the same email
+    #[error("Resource already exists")]
+    Uniqueness,
+    /// The database returned an error
+    #[error("Database error")]
+    Db,
+    /// Error while trying to create a new user
+    #[error("Failed to create user")]
+    UserCreation,
+}
+
+impl ScimError {
+    /// The `scimType` to return in the error response
+    pub fn scim_type(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidSyntax => Some("invalidSyntax"),
+            Self::InvalidFilter => Some("invalidFilter"),
+            Self::InvalidPath => Some("invalidPath"),
+            Self::NoTarget => Some("noTarget"),
+            Self::InvalidValue => Some("invalidValue"),
+            Self::Uniqueness => Some("uniqueness"),
+            Self::Unauthenticated | Self::NotFound | Self::Db | Self::UserCreation => None,
+        }
+    }
+}
+
+impl HttpError for ScimError {
+    type Detail = ();
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::InvalidSyntax
+            | Self::InvalidFilter
+            | Self::InvalidPath
+            | Self::NoTarget
+            | Self::InvalidValue => StatusCode::BAD_REQUEST,
+            Self::Uniqueness => StatusCode::CONFLICT,
+            Self::Db | Self::UserCreation => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_detail(&self) -> Self::Detail {}
+
+    fn error_kind(&self) -> &'static str {
+        match self {
+            Self::Unauthenticated => ErrorKind::InvalidApiKey,
+            Self::NotFound => ErrorKind::NotFound,
+            Self::InvalidSyntax
+            | Self::InvalidFilter
+            | Self::InvalidPath
+            | Self::NoTarget
+            | Self::InvalidValue => ErrorKind::InvalidScimRequest,
+            Self::Uniqueness => ErrorKind::ScimConflict,
+            Self::Db => ErrorKind::Database,
+            Self::UserCreation => ErrorKind::UserCreationError,
+        }
+        .as_str()
+    }
+}
+
+/// Wraps a [ScimError] to return it in the error format that SCIM clients expect, instead of the
+/// usual error format used by [WrapReport](crate::errors::WrapReport).
+#[derive(Debug)]
+pub struct ScimErrorResponse(pub Report<ScimError>);
+
+impl From<Report<ScimError>> for ScimErrorResponse {
+    fn from(value: Report<ScimError>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ScimError> for ScimErrorResponse {
+    fn from(value: ScimError) -> Self {
+        Self(Report::new(value))
+    }
+}
+
+impl IntoResponse for ScimErrorResponse {
+    fn into_response(self) -> Response {
+        let error = self.0.current_context();
+        let status = error.status_code();
+        event!(Level::ERROR, error.code=%status, error.kind=%error.error_kind(), error=?self.0);
+
+        // Messages attached to client errors explain what was wrong with the request.
+        let message = status
+            .is_client_error()
+            .then(|| {
+                self.0.frames().by_error().next().and_then(|e| {
+                    e.attachments.iter().find_map(|a| match a {
+                        AttachmentKind::Printable(p) => Some(p.to_string()),
+                        _ => None,
+                    })
+                })
+            })
+            .flatten();
+        let detail = match message {
+            Some(message) => format!("{error}: {message}"),
+            None => error.to_string(),
+        };
+
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": status.as_u16().to_string(),
+            "detail": detail,
+        });
+        if let Some(scim_type) = error.scim_type() {
+            body["scimType"] = scim_type.into();
+        }
+
+        (status, [(CONTENT_TYPE, SCIM_CONTENT_TYPE)], Json(body)).into_response()
+    }
+}
+
+/// A JSON response with the SCIM content type
+pub struct ScimJson<T: Serialize>(pub StatusCode, pub T);
+
+impl<T: Serialize> IntoResponse for ScimJson<T> {
+    fn into_response(self) -> Response {
+        (self.0, [(CONTENT_TYPE, SCIM_CONTENT_TYPE)], Json(self.1)).into_response()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Some identity providers send booleans as strings, such as `"False"`.
+fn lenient_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+
+    match BoolOrString::deserialize(deserializer)? {
+        BoolOrString::Bool(b) => Ok(b),
+        BoolOrString::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        BoolOrString::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        BoolOrString::String(s) => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&s),
+            &"a boolean",
+        )),
+    }
+}
+
+/// Metadata about a resource
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    /// The type of the resource, `User` or `Group`
+    #[serde(default)]
+    pub resource_type: String,
+    /// When the resource was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    /// When the resource was last modified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// The components of a user's name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    /// The full name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    /// The given name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    /// The family name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+}
+
+/// An email address for a user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimEmail {
+    /// The email address
+    pub value: String,
+    /// The type of the address, such as `work`
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// If this is the user's primary address
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub primary: bool,
+}
+
+/// A reference to a user in a group, or to a group that a user belongs to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScimMember {
+    /// The ID of the referenced resource
+    pub value: String,
+    /// The name of the referenced resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+/// A SCIM user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    /// The schemas of the resource
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The user's ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The ID of the user in the identity provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    /// The name that the user logs in with. This is always the user's email address.
+    #[serde(default)]
+    pub user_name: String,
+    /// The user's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    /// The user's name, for display
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The user's email addresses. Only the primary address is stored.
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    /// If the user is an active member of the organization
+    #[serde(default = "default_true", deserialize_with = "lenient_bool")]
+    pub active: bool,
+    /// The groups that the user belongs to. This is ignored in requests.
+    #[serde(default)]
+    pub groups: Vec<ScimMember>,
+    /// Metadata about the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+impl ScimUser {
+    /// The user's email address, from the primary email or the user name.
+    pub fn email(&self) -> Option<&str> {
+        self.emails
+            .iter()
+            .find(|e| e.primary)
+            .or_else(|| self.emails.first())
+            .map(|e| e.value.as_str())
+            .or_else(|| Some(self.user_name.as_str()).filter(|u| u.contains('@')))
+    }
+
+    /// The user's full name, from the display name or the components of the name.
+    pub fn full_name(&self) -> Option<String> {
+        let non_empty = |s: &Option<String>| s.clone().filter(|s| !s.is_empty());
+
+        non_empty(&self.display_name).or_else(|| {
+            let name = self.name.as_ref()?;
+            non_empty(&name.formatted).or_else(|| {
+                let parts = [non_empty(&name.given_name), non_empty(&name.family_name)];
+                let full = parts.into_iter().flatten().collect::<Vec<_>>().join(" ");
+                Some(full).filter(|n| !n.is_empty())
+            })
+        })
+    }
+}
+
+/// A SCIM group
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    /// The schemas of the resource
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The group's ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The group's name
+    #[serde(default)]
+    pub display_name: String,
+    /// The users in the group
+    #[serde(default)]
+    pub members: Vec<ScimMember>,
+    /// Metadata about the group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+/// A page of resources returned from a list request
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T: Serialize> {
+    /// The schemas of the response
+    pub schemas: [&'static str; 1],
+    /// The number of resources that matched the filter
+    pub total_results: usize,
+    /// The 1-based index of the first resource in this page
+    pub start_index: usize,
+    /// The number of resources in this page
+    pub items_per_page: usize,
+    /// The resources in this page
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T: Serialize> ListResponse<T> {
+    /// Return a page of `resources`. `start_index` is 1-based.
+    pub fn paginate(resources: Vec<T>, start_index: Option<usize>, count: Option<usize>) -> Self {
+        let start_index = start_index.unwrap_or(1).max(1);
+        let count = count.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let total_results = resources.len();
+        let resources = resources
+            .into_iter()
+            .skip(start_index - 1)
+            .take(count)
+            .collect::<Vec<_>>();
+
+        Self {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// The features of this SCIM implementation
+pub fn service_provider_config() -> serde_json::Value {
+    json!({
+        "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "OAuth Bearer Token",
+            "description": "Authentication with an organization API key",
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn user_details() {
+        let user: ScimUser = serde_json::from_value(json!({
+            "schemas": [USER_SCHEMA],
+            "userName": "ada",
+            "name": { "givenName": "Ada", "familyName": "Lovelace" },
+            "emails": [
+                { "value": "ada@home.example.com", "type": "home" },
+                { "value": "ada@example.com", "type": "work", "primary": "True" }
+            ],
+            "active": "False",
+            "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User": { "department": "Math" }
+        }))
+        .unwrap();
+
+        assert_eq!(user.email(), Some("ada@example.com"));
+        assert_eq!(user.full_name().as_deref(), Some("Ada Lovelace"));
+        assert!(!user.active);
+
+        let user: ScimUser = serde_json::from_value(json!({
+            "userName": "ada@example.com",
+            "displayName": "Countess of Lovelace",
+            "name": { "formatted": "Ada Lovelace" }
+        }))
+        .unwrap();
+        assert_eq!(user.email(), Some("ada@example.com"));
+        assert_eq!(user.full_name().as_deref(), Some("Countess of Lovelace"));
+        assert!(user.active);
+
+        let user: ScimUser = serde_json::from_value(json!({ "userName": "ada" })).unwrap();
+        assert_eq!(user.email(), None);
+        assert_eq!(user.full_name(), None);
+    }
+
+    #[test]
+    fn paginate() {
+        let page = ListResponse::paginate((1..=5).collect(), Some(2), Some(2));
+        assert_eq!(page.total_results, 5);
+        assert_eq!(page.start_index, 2);
+        assert_eq!(page.resources, vec![2, 3]);
+
+        let page = ListResponse::paginate((1..=5).collect(), Some(0), None);
+        assert_eq!(page.start_index, 1);
+        assert_eq!(page.items_per_page, 5);
+
+        let page = ListResponse::paginate((1..=5).collect::<Vec<i32>>(), Some(10), None);
+        assert_eq!(page.total_results, 5);
+        assert!(page.resources.is_empty());
+    }
+
+    #[test]
+    fn error_response() {
+        let err = ScimErrorResponse::from(
+            Report::new(ScimError::InvalidFilter).attach_printable("Unknown operator xx"),
+        );
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[CONTENT_TYPE], SCIM_CONTENT_TYPE);
+    }
+}
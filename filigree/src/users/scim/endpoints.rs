@@ -0,0 +1,316 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::request::Parts,
+    response::IntoResponse,
+    routing, Router,
+};
+use error_stack::Report;
+use http::{header::AUTHORIZATION, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::{
+    create_scim_group, create_scim_user, delete_scim_group, delete_scim_user, get_scim_group,
+    get_scim_user, list_scim_groups, list_scim_users, patch_scim_group, patch_scim_user,
+    replace_scim_group, replace_scim_user, service_provider_config, Filter, ListResponse,
+    PatchRequest, ScimError, ScimErrorResponse, ScimGroup, ScimJson, ScimUser,
+};
+use crate::{
+    auth::{api_key::lookup_api_key_from_bearer_token, OrganizationId, RoleId, UserId},
+    server::FiligreeState,
+};
+
+/// Authentication for the SCIM endpoints, from an API key that belongs to an organization instead
+/// of a user.
+#[derive(Debug, Clone)]
+pub struct ScimAuth {
+    /// The organization that the identity provider is provisioning
+    pub organization_id: OrganizationId,
+    /// The API key used for the request
+    pub api_key_id: Uuid,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ScimAuth
+where
+    Arc<FiligreeState>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ScimErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or(ScimError::Unauthenticated)?;
+
+        let state = Arc::<FiligreeState>::from_ref(state);
+        let key = lookup_api_key_from_bearer_token(&state.db, token.trim())
+            .await
+            .map_err(|e| e.change_context(ScimError::Unauthenticated))?;
+
+        // Only keys created for SCIM can be used here, never a normal API key.
+        if !key.scim || key.user_id.is_some() {
+            return Err(ScimError::Unauthenticated.into());
+        }
+
+        Ok(ScimAuth {
+            organization_id: key.organization_id,
+            api_key_id: key.api_key_id,
+        })
+    }
+}
+
+/// Query string for list requests
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    filter: Option<String>,
+    start_index: Option<usize>,
+    count: Option<usize>,
+    excluded_attributes: Option<String>,
+}
+
+impl ListQuery {
+    fn filter(&self) -> Result<Option<Filter>, Report<ScimError>> {
+        self.filter.as_deref().map(Filter::parse).transpose()
+    }
+
+    fn excludes(&self, attr: &str) -> bool {
+        self.excluded_attributes
+            .as_deref()
+            .map(|e| e.split(',').any(|a| a.trim().eq_ignore_ascii_case(attr)))
+            .unwrap_or(false)
+    }
+}
+
+/// Parse a request body. Identity providers use the `application/scim+json` content type, so
+/// this doesn't use the normal JSON extractor.
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, Report<ScimError>> {
+    serde_json::from_slice(body)
+        .map_err(|e| Report::new(ScimError::InvalidSyntax).attach_printable(e.to_string()))
+}
+
+/// Parse an ID from the path. IDs that can't exist are reported as not found.
+fn parse_id<T: std::str::FromStr>(id: &str) -> Result<T, ScimError> {
+    id.parse().map_err(|_| ScimError::NotFound)
+}
+
+/// Describe the features that this SCIM implementation supports
+pub async fn get_service_provider_config(_auth: ScimAuth) -> impl IntoResponse {
+    ScimJson(StatusCode::OK, service_provider_config())
+}
+
+/// List users in the organization
+#[instrument(skip(state))]
+pub async fn list_users(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let filter = query.filter()?;
+    let users = list_scim_users(&state.db, auth.organization_id, filter.as_ref()).await?;
+    Ok(ScimJson(
+        StatusCode::OK,
+        ListResponse::paginate(users, query.start_index, query.count),
+    ))
+}
+
+/// Get a user in the organization
+#[instrument(skip(state))]
+pub async fn get_user(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let user_id: UserId = parse_id(&id)?;
+    let user = get_scim_user(&state.db, auth.organization_id, user_id)
+        .await?
+        .ok_or(ScimError::NotFound)?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+/// Create a user in the organization
+#[instrument(skip(state, body))]
+pub async fn create_user(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let user: ScimUser = parse_body(&body)?;
+    let user = create_scim_user(&state, auth.organization_id, &user).await?;
+    Ok(ScimJson(StatusCode::CREATED, user))
+}
+
+/// Replace a user's details
+#[instrument(skip(state, body))]
+pub async fn replace_user(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let user_id: UserId = parse_id(&id)?;
+    let user: ScimUser = parse_body(&body)?;
+    let user = replace_scim_user(&state.db, auth.organization_id, user_id, &user).await?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+/// Update a user with PATCH operations
+#[instrument(skip(state, body))]
+pub async fn patch_user(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let user_id: UserId = parse_id(&id)?;
+    let patch: PatchRequest = parse_body(&body)?;
+    let user = patch_scim_user(&state.db, auth.organization_id, user_id, &patch).await?;
+    Ok(ScimJson(StatusCode::OK, user))
+}
+
+/// Remove a user from the organization
+#[instrument(skip(state))]
+pub async fn delete_user(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let user_id: UserId = parse_id(&id)?;
+    delete_scim_user(&state.db, auth.organization_id, user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List groups in the organization
+#[instrument(skip(state))]
+pub async fn list_groups(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let filter = query.filter()?;
+    let groups = list_scim_groups(
+        &state.db,
+        auth.organization_id,
+        filter.as_ref(),
+        !query.excludes("members"),
+    )
+    .await?;
+    Ok(ScimJson(
+        StatusCode::OK,
+        ListResponse::paginate(groups, query.start_index, query.count),
+    ))
+}
+
+/// Get a group in the organization
+#[instrument(skip(state))]
+pub async fn get_group(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let role_id: RoleId = parse_id(&id)?;
+    let group = get_scim_group(&state.db, auth.organization_id, role_id)
+        .await?
+        .ok_or(ScimError::NotFound)?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+/// Create a group in the organization
+#[instrument(skip(state, body))]
+pub async fn create_group(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let group: ScimGroup = parse_body(&body)?;
+    let group = create_scim_group(&state.db, auth.organization_id, &group).await?;
+    Ok(ScimJson(StatusCode::CREATED, group))
+}
+
+/// Replace a group's name and members
+#[instrument(skip(state, body))]
+pub async fn replace_group(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let role_id: RoleId = parse_id(&id)?;
+    let group: ScimGroup = parse_body(&body)?;
+    let group = replace_scim_group(&state.db, auth.organization_id, role_id, &group).await?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+/// Update a group with PATCH operations
+#[instrument(skip(state, body))]
+pub async fn patch_group(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let role_id: RoleId = parse_id(&id)?;
+    let patch: PatchRequest = parse_body(&body)?;
+    let group = patch_scim_group(&state.db, auth.organization_id, role_id, &patch).await?;
+    Ok(ScimJson(StatusCode::OK, group))
+}
+
+/// Delete a group from the organization
+#[instrument(skip(state))]
+pub async fn delete_group(
+    State(state): State<Arc<FiligreeState>>,
+    auth: ScimAuth,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimErrorResponse> {
+    let role_id: RoleId = parse_id(&id)?;
+    delete_scim_group(&state.db, auth.organization_id, role_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Create the SCIM endpoints. These authenticate with an organization API key, and so should not
+/// be placed behind the normal authentication layers.
+///
+/// - GET /scim/v2/ServiceProviderConfig describes the supported features
+/// - GET and POST /scim/v2/Users
+/// - GET, PUT, PATCH, and DELETE /scim/v2/Users/:id
+/// - GET and POST /scim/v2/Groups
+/// - GET, PUT, PATCH, and DELETE /scim/v2/Groups/:id
+pub fn create_routes<T>() -> Router<T>
+where
+    Arc<FiligreeState>: FromRef<T> + Clone,
+    T: Send + Sync + Clone + 'static,
+{
+    Router::new()
+        .route(
+            "/scim/v2/ServiceProviderConfig",
+            routing::get(get_service_provider_config),
+        )
+        .route("/scim/v2/Users", routing::get(list_users).post(create_user))
+        .route(
+            "/scim/v2/Users/:id",
+            routing::get(get_user)
+                .put(replace_user)
+                .patch(patch_user)
+                .delete(delete_user),
+        )
+        .route(
+            "/scim/v2/Groups",
+            routing::get(list_groups).post(create_group),
+        )
+        .route(
+            "/scim/v2/Groups/:id",
+            routing::get(get_group)
+                .put(replace_group)
+                .patch(patch_group)
+                .delete(delete_group),
+        )
+}
@@ -0,0 +1,614 @@
+//! Parsing and evaluation for the subset of the SCIM filter syntax (RFC 7644 section 3.4.2.2)
+//! that identity providers use in practice. Filters are evaluated against the JSON form of a
+//! resource, so any attribute in the resource can be filtered on.
+
+use std::{iter::Peekable, str::CharIndices};
+
+use error_stack::Report;
+use serde_json::Value;
+
+use super::ScimError;
+
+/// An attribute name, with an optional sub-attribute such as the `givenName` in `name.givenName`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrPath {
+    /// The attribute name
+    pub attr: String,
+    /// The sub-attribute name
+    pub sub_attr: Option<String>,
+}
+
+impl AttrPath {
+    /// Parse an attribute path. Attributes from the core User and Group schemas may be prefixed
+    /// with the schema URN. Returns `None` for attributes of other schemas, which are not
+    /// supported.
+    pub fn parse(path: &str) -> Option<AttrPath> {
+        let path = if path.starts_with("urn:") {
+            // The URN contains dots, so split it off before looking at sub-attributes.
+            let (schema, attr) = path.rsplit_once(':')?;
+            if !schema.eq_ignore_ascii_case(super::USER_SCHEMA)
+                && !schema.eq_ignore_ascii_case(super::GROUP_SCHEMA)
+            {
+                return None;
+            }
+            attr
+        } else {
+            path
+        };
+
+        let (attr, sub_attr) = match path.split_once('.') {
+            Some((attr, sub_attr)) => (attr, Some(sub_attr.to_string())),
+            None => (path, None),
+        };
+
+        if attr.is_empty() || sub_attr.as_deref() == Some("") {
+            return None;
+        }
+
+        Some(AttrPath {
+            attr: attr.to_string(),
+            sub_attr,
+        })
+    }
+
+    /// Look up the values of this attribute in a resource. Multi-valued attributes return one value
+    /// for each element.
+    fn values<'a>(&self, resource: &'a Value) -> Vec<&'a Value> {
+        let Some(value) = get_attr(resource, &self.attr) else {
+            return Vec::new();
+        };
+
+        let values: Vec<&Value> = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+
+        match &self.sub_attr {
+            Some(sub_attr) => values
+                .into_iter()
+                .filter_map(|v| get_attr(v, sub_attr))
+                .collect(),
+            // Comparing a multi-valued complex attribute compares its `value` sub-attribute.
+            None => values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Object(_) => get_attr(v, "value").unwrap_or(&Value::Null),
+                    v => v,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Look up an attribute in a JSON object. Attribute names are case-insensitive.
+pub fn get_attr<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    value
+        .as_object()?
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v)
+}
+
+/// A comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// Equal
+    Eq,
+    /// Not equal
+    Ne,
+    /// Contains
+    Co,
+    /// Starts with
+    Sw,
+    /// Ends with
+    Ew,
+    /// Greater than
+    Gt,
+    /// Greater than or equal
+    Ge,
+    /// Less than
+    Lt,
+    /// Less than or equal
+    Le,
+}
+
+impl CompareOp {
+    fn parse(s: &str) -> Option<CompareOp> {
+        let op = match s.to_ascii_lowercase().as_str() {
+            "eq" => Self::Eq,
+            "ne" => Self::Ne,
+            "co" => Self::Co,
+            "sw" => Self::Sw,
+            "ew" => Self::Ew,
+            "gt" => Self::Gt,
+            "ge" => Self::Ge,
+            "lt" => Self::Lt,
+            "le" => Self::Le,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    fn matches(&self, actual: &Value, expected: &Value) -> bool {
+        use std::cmp::Ordering;
+
+        let ordering = match (actual, expected) {
+            (Value::String(a), Value::String(e)) => {
+                let a = a.to_lowercase();
+                let e = e.to_lowercase();
+                match self {
+                    Self::Co => return a.contains(&e),
+                    Self::Sw => return a.starts_with(&e),
+                    Self::Ew => return a.ends_with(&e),
+                    _ => a.cmp(&e),
+                }
+            }
+            (Value::Number(a), Value::Number(e)) => {
+                let (Some(a), Some(e)) = (a.as_f64(), e.as_f64()) else {
+                    return false;
+                };
+                match a.partial_cmp(&e) {
+                    Some(o) => o,
+                    None => return false,
+                }
+            }
+            (Value::Bool(a), Value::Bool(e)) => a.cmp(e),
+            (Value::Null, Value::Null) => Ordering::Equal,
+            _ => return false,
+        };
+
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Co | Self::Sw | Self::Ew => false,
+        }
+    }
+}
+
+/// A parsed SCIM filter
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// The attribute has a non-empty value
+    Present(AttrPath),
+    /// Compare the attribute to a value
+    Compare(AttrPath, CompareOp, Value),
+    /// Both filters match
+    And(Box<Filter>, Box<Filter>),
+    /// Either filter matches
+    Or(Box<Filter>, Box<Filter>),
+    /// The filter does not match
+    Not(Box<Filter>),
+    /// An element of the multi-valued attribute matches the filter
+    ValuePath(String, Box<Filter>),
+}
+
+impl Filter {
+    /// Parse a filter expression
+    pub fn parse(filter: &str) -> Result<Filter, Report<ScimError>> {
+        let mut parser = Parser::new(filter);
+        let result = parser.parse_or()?;
+        if let Some(token) = parser.next()? {
+            return Err(invalid_filter(format!("Unexpected {token:?}")));
+        }
+        Ok(result)
+    }
+
+    /// Check if a resource matches this filter
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            Self::Present(path) => path.values(resource).into_iter().any(|v| match v {
+                Value::Null => false,
+                Value::String(s) => !s.is_empty(),
+                Value::Array(a) => !a.is_empty(),
+                _ => true,
+            }),
+            Self::Compare(path, CompareOp::Ne, expected) => !path
+                .values(resource)
+                .into_iter()
+                .any(|v| CompareOp::Eq.matches(v, expected)),
+            Self::Compare(path, op, expected) => path
+                .values(resource)
+                .into_iter()
+                .any(|v| op.matches(v, expected)),
+            Self::And(a, b) => a.matches(resource) && b.matches(resource),
+            Self::Or(a, b) => a.matches(resource) || b.matches(resource),
+            Self::Not(f) => !f.matches(resource),
+            Self::ValuePath(attr, f) => match get_attr(resource, attr) {
+                Some(Value::Array(items)) => items.iter().any(|item| f.matches(item)),
+                Some(item @ Value::Object(_)) => f.matches(item),
+                _ => false,
+            },
+        }
+    }
+
+    /// If the filter requires an attribute to equal a string, return the attribute and value. The
+    /// database queries use this to avoid loading every resource in the organization.
+    pub fn equality(&self) -> Option<(&AttrPath, &str)> {
+        match self {
+            Self::Compare(path, CompareOp::Eq, Value::String(value)) => Some((path, value)),
+            Self::And(a, b) => a.equality().or_else(|| b.equality()),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_filter(message: impl Into<String>) -> Report<ScimError> {
+    Report::new(ScimError::InvalidFilter).attach_printable(message.into())
+}
+
+/// A lexical token in a filter expression
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    Literal(Value),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    peeked: Option<Token>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+            peeked: None,
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Token>, Report<ScimError>> {
+        if let Some(token) = self.peeked.take() {
+            return Ok(Some(token));
+        }
+
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let Some((start, c)) = self.chars.next() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '"' => {
+                let mut escaped = false;
+                let end = loop {
+                    let Some((i, c)) = self.chars.next() else {
+                        return Err(invalid_filter("Unterminated string"));
+                    };
+
+                    match (c, escaped) {
+                        ('"', false) => break i + 1,
+                        ('\\', false) => escaped = true,
+                        _ => escaped = false,
+                    }
+                };
+
+                let value: String = serde_json::from_str(&self.input[start..end])
+                    .map_err(|_| invalid_filter("Invalid string"))?;
+                Token::Literal(Value::String(value))
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = self
+                    .chars
+                    .next_if(|(_, c)| !c.is_whitespace() && !"()[]\"".contains(*c))
+                {
+                    end = i + c.len_utf8();
+                }
+
+                let word = &self.input[start..end];
+                match word.to_ascii_lowercase().as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match serde_json::from_str::<serde_json::Number>(word) {
+                        Ok(n) => Token::Literal(Value::Number(n)),
+                        Err(_) => Token::Word(word.to_string()),
+                    },
+                }
+            }
+        };
+
+        Ok(Some(token))
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, Report<ScimError>> {
+        if self.peeked.is_none() {
+            self.peeked = self.next()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next_if_keyword(&mut self, keyword: &str) -> Result<bool, Report<ScimError>> {
+        let found = matches!(self.peek()?, Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword));
+        if found {
+            self.peeked = None;
+        }
+        Ok(found)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Report<ScimError>> {
+        match self.next()? {
+            Some(token) if token == expected => Ok(()),
+            token => Err(invalid_filter(format!(
+                "Expected {expected:?}, found {token:?}"
+            ))),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, Report<ScimError>> {
+        let mut filter = self.parse_and()?;
+        while self.next_if_keyword("or")? {
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, Report<ScimError>> {
+        let mut filter = self.parse_term()?;
+        while self.next_if_keyword("and")? {
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_term()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_term(&mut self) -> Result<Filter, Report<ScimError>> {
+        if self.next_if_keyword("not")? {
+            self.expect(Token::OpenParen)?;
+            let filter = self.parse_or()?;
+            self.expect(Token::CloseParen)?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+
+        let attr = match self.next()? {
+            Some(Token::OpenParen) => {
+                let filter = self.parse_or()?;
+                self.expect(Token::CloseParen)?;
+                return Ok(filter);
+            }
+            Some(Token::Word(attr)) => attr,
+            token => return Err(invalid_filter(format!("Unexpected {token:?}"))),
+        };
+
+        if self.peek()? == Some(&Token::OpenBracket) {
+            self.peeked = None;
+            let filter = self.parse_or()?;
+            self.expect(Token::CloseBracket)?;
+            let path = AttrPath::parse(&attr)
+                .filter(|p| p.sub_attr.is_none())
+                .ok_or_else(|| invalid_filter(format!("Unsupported attribute {attr}")))?;
+            return Ok(Filter::ValuePath(path.attr, Box::new(filter)));
+        }
+
+        let path = AttrPath::parse(&attr)
+            .ok_or_else(|| invalid_filter(format!("Unsupported attribute {attr}")))?;
+
+        let op = match self.next()? {
+            Some(Token::Word(op)) if op.eq_ignore_ascii_case("pr") => {
+                return Ok(Filter::Present(path));
+            }
+            Some(Token::Word(op)) => CompareOp::parse(&op)
+                .ok_or_else(|| invalid_filter(format!("Unknown operator {op}")))?,
+            token => return Err(invalid_filter(format!("Unexpected {token:?}"))),
+        };
+
+        let value = match self.next()? {
+            Some(Token::Literal(value)) => value,
+            token => return Err(invalid_filter(format!("Expected a value, found {token:?}"))),
+        };
+
+        Ok(Filter::Compare(path, op, value))
+    }
+}
+
+/// A path in a PATCH operation, such as `name.givenName` or `emails[type eq "work"].value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchPath {
+    /// The attribute to modify
+    pub attr: String,
+    /// Only modify elements of a multi-valued attribute that match this filter
+    pub filter: Option<Filter>,
+    /// The sub-attribute to modify
+    pub sub_attr: Option<String>,
+}
+
+impl PatchPath {
+    /// Parse a PATCH path. Returns `None` for attributes of unsupported schemas, which should be
+    /// ignored.
+    pub fn parse(path: &str) -> Result<Option<PatchPath>, Report<ScimError>> {
+        let invalid_path =
+            || Report::new(ScimError::InvalidPath).attach_printable(format!("Invalid path {path}"));
+
+        let Some((attr, rest)) = path.split_once('[') else {
+            return Ok(AttrPath::parse(path).map(|p| PatchPath {
+                attr: p.attr,
+                filter: None,
+                sub_attr: p.sub_attr,
+            }));
+        };
+
+        let (filter, sub_attr) = rest.rsplit_once(']').ok_or_else(invalid_path)?;
+        let sub_attr = match sub_attr {
+            "" => None,
+            s => Some(s.strip_prefix('.').ok_or_else(invalid_path)?.to_string()),
+        };
+
+        let Some(attr) = AttrPath::parse(attr) else {
+            return Ok(None);
+        };
+        if attr.sub_attr.is_some() {
+            return Err(invalid_path());
+        }
+
+        let filter = Filter::parse(filter).map_err(|e| e.change_context(ScimError::InvalidPath))?;
+
+        Ok(Some(PatchPath {
+            attr: attr.attr,
+            filter: Some(filter),
+            sub_attr,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn path(attr: &str, sub_attr: Option<&str>) -> AttrPath {
+        AttrPath {
+            attr: attr.to_string(),
+            sub_attr: sub_attr.map(|s| s.to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_filters() {
+        assert_eq!(
+            Filter::parse(r#"userName eq "ada@example.com""#).unwrap(),
+            Filter::Compare(
+                path("userName", None),
+                CompareOp::Eq,
+                json!("ada@example.com")
+            )
+        );
+
+        assert_eq!(
+            Filter::parse(r#"name.givenName sw "A" and not (active eq false) or externalId pr"#)
+                .unwrap(),
+            Filter::Or(
+                Box::new(Filter::And(
+                    Box::new(Filter::Compare(
+                        path("name", Some("givenName")),
+                        CompareOp::Sw,
+                        json!("A")
+                    )),
+                    Box::new(Filter::Not(Box::new(Filter::Compare(
+                        path("active", None),
+                        CompareOp::Eq,
+                        json!(false)
+                    ))))
+                )),
+                Box::new(Filter::Present(path("externalId", None)))
+            )
+        );
+
+        assert_eq!(
+            Filter::parse(
+                r#"urn:ietf:params:scim:schemas:core:2.0:User:emails[type eq "work" and value co "\"x"]"#
+            )
+            .unwrap(),
+            Filter::ValuePath(
+                "emails".to_string(),
+                Box::new(Filter::And(
+                    Box::new(Filter::Compare(path("type", None), CompareOp::Eq, json!("work"))),
+                    Box::new(Filter::Compare(path("value", None), CompareOp::Co, json!("\"x")))
+                ))
+            )
+        );
+
+        for bad in [
+            "",
+            "userName",
+            r#"userName eq"#,
+            r#"userName xx "a""#,
+            r#"userName eq "a" and"#,
+            r#"(userName eq "a""#,
+            r#"userName eq "a"#,
+            r#"urn:other:schema:attr eq "a""#,
+        ] {
+            let err = Filter::parse(bad).expect_err(bad);
+            assert!(
+                matches!(err.current_context(), ScimError::InvalidFilter),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn match_filters() {
+        let user = json!({
+            "userName": "Ada@Example.com",
+            "name": { "givenName": "Ada", "familyName": "Lovelace" },
+            "active": true,
+            "emails": [
+                { "value": "ada@example.com", "type": "work", "primary": true },
+                { "value": "ada@home.example.com", "type": "home" }
+            ]
+        });
+
+        for (filter, expected) in [
+            (r#"username eq "ada@example.com""#, true),
+            (r#"userName ne "ada@example.com""#, false),
+            (r#"name.familyName co "love""#, true),
+            (r#"name.familyName ew "x""#, false),
+            (r#"emails.value eq "ada@home.example.com""#, true),
+            (r#"emails eq "ada@home.example.com""#, true),
+            (r#"emails[type eq "work" and value sw "ada@home"]"#, false),
+            (r#"emails[type eq "home" and value sw "ada@home"]"#, true),
+            (r#"active eq true and externalId pr"#, false),
+            (r#"active eq true and not (externalId pr)"#, true),
+            (r#"displayName eq "x" or name.givenName gt "A""#, true),
+        ] {
+            let filter = Filter::parse(filter).unwrap();
+            assert_eq!(filter.matches(&user), expected, "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn parse_patch_paths() {
+        assert_eq!(
+            PatchPath::parse("name.givenName").unwrap().unwrap(),
+            PatchPath {
+                attr: "name".to_string(),
+                filter: None,
+                sub_attr: Some("givenName".to_string())
+            }
+        );
+
+        assert_eq!(
+            PatchPath::parse(r#"emails[type eq "work"].value"#)
+                .unwrap()
+                .unwrap(),
+            PatchPath {
+                attr: "emails".to_string(),
+                filter: Some(Filter::Compare(
+                    path("type", None),
+                    CompareOp::Eq,
+                    json!("work")
+                )),
+                sub_attr: Some("value".to_string())
+            }
+        );
+
+        assert_eq!(
+            PatchPath::parse("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager")
+                .unwrap(),
+            None
+        );
+
+        assert!(
+            PatchPath::parse(r#"emails[type eq "work""#).is_err(),
+            "missing bracket"
+        );
+        assert!(
+            PatchPath::parse(r#"emails[type eq "work"]value"#).is_err(),
+            "missing dot"
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! Apply SCIM PATCH operations (RFC 7644 section 3.5.2) to the JSON form of a resource.
+
+use error_stack::Report;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use super::{
+    filter::{CompareOp, Filter, PatchPath},
+    ScimError, PATCH_OP_SCHEMA,
+};
+
+/// The body of a PATCH request
+#[derive(Debug, Deserialize)]
+pub struct PatchRequest {
+    /// The schemas of the request
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    /// The operations to apply, in order
+    #[serde(rename = "Operations", alias = "operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+/// The type of a PATCH operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOpKind {
+    /// Add a value, or append to a multi-valued attribute
+    Add,
+    /// Replace a value
+    Replace,
+    /// Remove a value
+    Remove,
+}
+
+impl<'de> Deserialize<'de> for PatchOpKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Some identity providers capitalize the operation names.
+        let op = String::deserialize(deserializer)?;
+        match op.to_ascii_lowercase().as_str() {
+            "add" => Ok(Self::Add),
+            "replace" => Ok(Self::Replace),
+            "remove" => Ok(Self::Remove),
+            _ => Err(serde::de::Error::unknown_variant(
+                &op,
+                &["add", "replace", "remove"],
+            )),
+        }
+    }
+}
+
+/// A single PATCH operation
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    /// The operation to perform
+    pub op: PatchOpKind,
+    /// The attribute to modify. If omitted, `value` must be an object of attributes.
+    pub path: Option<String>,
+    /// The new value
+    pub value: Option<Value>,
+}
+
+impl PatchRequest {
+    /// Apply the operations to a resource
+    pub fn apply(&self, resource: &mut Value) -> Result<(), Report<ScimError>> {
+        if !self
+            .schemas
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(PATCH_OP_SCHEMA))
+        {
+            return Err(Report::new(ScimError::InvalidSyntax)
+                .attach_printable(format!("Request must have schema {PATCH_OP_SCHEMA}")));
+        }
+
+        let resource = resource.as_object_mut().ok_or(ScimError::InvalidSyntax)?;
+        for op in &self.operations {
+            op.apply(resource)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_value(message: &str) -> Report<ScimError> {
+    Report::new(ScimError::InvalidValue).attach_printable(message.to_string())
+}
+
+/// The canonical spelling of attribute names, so that attributes added with a different case can
+/// still be deserialized.
+const ATTRIBUTE_NAMES: &[&str] = &[
+    "active",
+    "display",
+    "displayName",
+    "emails",
+    "externalId",
+    "familyName",
+    "formatted",
+    "givenName",
+    "members",
+    "name",
+    "primary",
+    "type",
+    "userName",
+    "value",
+];
+
+/// Find the key for an attribute in an object, matching case-insensitively.
+fn attr_key(object: &Map<String, Value>, name: &str) -> String {
+    object
+        .keys()
+        .find(|k| k.eq_ignore_ascii_case(name))
+        .map(|k| k.as_str())
+        .or_else(|| {
+            ATTRIBUTE_NAMES
+                .iter()
+                .find(|k| k.eq_ignore_ascii_case(name))
+                .copied()
+        })
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Add or replace an attribute in an object. Complex values are merged into the existing value,
+/// and with `append` set, arrays are appended to the existing array.
+fn set_attr(object: &mut Map<String, Value>, name: &str, value: Value, append: bool) {
+    let key = attr_key(object, name);
+    match (object.get_mut(&key), value) {
+        (Some(Value::Object(existing)), Value::Object(value)) => {
+            for (k, v) in value {
+                set_attr(existing, &k, v, append);
+            }
+        }
+        (Some(Value::Array(existing)), Value::Array(value)) if append => {
+            for v in value {
+                if !existing.contains(&v) {
+                    existing.push(v);
+                }
+            }
+        }
+        (Some(Value::Array(existing)), value) if append && !value.is_null() => {
+            if !existing.contains(&value) {
+                existing.push(value);
+            }
+        }
+        (_, value) => {
+            object.insert(key, value);
+        }
+    }
+}
+
+fn remove_attr(object: &mut Map<String, Value>, name: &str) {
+    let key = attr_key(object, name);
+    object.remove(&key);
+}
+
+impl PatchOperation {
+    fn apply(&self, resource: &mut Map<String, Value>) -> Result<(), Report<ScimError>> {
+        let path = match self.path.as_deref() {
+            Some(path) => match PatchPath::parse(path)? {
+                Some(path) => Some(path),
+                // Attributes from other schemas are not stored.
+                None => return Ok(()),
+            },
+            None => None,
+        };
+
+        match (self.op, path) {
+            (PatchOpKind::Remove, None) => Err(Report::new(ScimError::NoTarget)
+                .attach_printable("Remove operations require a path")),
+            (PatchOpKind::Add | PatchOpKind::Replace, None) => {
+                let Some(Value::Object(values)) = self.value.clone() else {
+                    return Err(invalid_value(
+                        "Operations without a path require an object value",
+                    ));
+                };
+
+                for (name, value) in values {
+                    // Attributes in the value can also be paths, such as `name.givenName`.
+                    let op = PatchOperation {
+                        op: self.op,
+                        path: Some(name),
+                        value: Some(value),
+                    };
+                    op.apply(resource)?;
+                }
+                Ok(())
+            }
+            (op, Some(path)) => self.apply_to_path(resource, op, path),
+        }
+    }
+
+    fn apply_to_path(
+        &self,
+        resource: &mut Map<String, Value>,
+        op: PatchOpKind,
+        path: PatchPath,
+    ) -> Result<(), Report<ScimError>> {
+        let value = self.value.clone().unwrap_or(Value::Null);
+        if op != PatchOpKind::Remove && value.is_null() {
+            return Err(invalid_value("Operation requires a value"));
+        }
+
+        let Some(filter) = path.filter else {
+            match (op, path.sub_attr) {
+                (PatchOpKind::Remove, None) => match (get_array(resource, &path.attr), value) {
+                    // Some identity providers remove elements from a multi-valued attribute by
+                    // passing them in the value instead of in a filter.
+                    (Some(existing), Value::Array(remove)) => {
+                        existing.retain(|item| {
+                            !remove
+                                .iter()
+                                .any(|r| element_value(r) == element_value(item))
+                        });
+                    }
+                    _ => remove_attr(resource, &path.attr),
+                },
+                (PatchOpKind::Remove, Some(sub_attr)) => {
+                    if let Some(Value::Object(parent)) = get_mut(resource, &path.attr) {
+                        remove_attr(parent, &sub_attr);
+                    }
+                }
+                (op, None) => set_attr(resource, &path.attr, value, op == PatchOpKind::Add),
+                (op, Some(sub_attr)) => {
+                    let key = attr_key(resource, &path.attr);
+                    let parent = resource
+                        .entry(key)
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    if !parent.is_object() {
+                        *parent = Value::Object(Map::new());
+                    }
+                    let parent = parent.as_object_mut().expect("parent is an object");
+                    set_attr(parent, &sub_attr, value, op == PatchOpKind::Add);
+                }
+            }
+
+            return Ok(());
+        };
+
+        let key = attr_key(resource, &path.attr);
+        let items = match resource
+            .entry(key)
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(items) => items,
+            _ => {
+                return Err(Report::new(ScimError::InvalidPath)
+                    .attach_printable(format!("{} is not multi-valued", path.attr)))
+            }
+        };
+
+        if op == PatchOpKind::Remove {
+            match &path.sub_attr {
+                Some(sub_attr) => {
+                    for item in items.iter_mut().filter(|item| filter.matches(item)) {
+                        if let Value::Object(item) = item {
+                            remove_attr(item, sub_attr);
+                        }
+                    }
+                }
+                None => items.retain(|item| !filter.matches(item)),
+            }
+            return Ok(());
+        }
+
+        let mut matched = false;
+        for item in items.iter_mut().filter(|item| filter.matches(item)) {
+            matched = true;
+            match (&path.sub_attr, item) {
+                (Some(sub_attr), Value::Object(item)) => {
+                    set_attr(item, sub_attr, value.clone(), false)
+                }
+                (None, Value::Object(item)) if value.is_object() => {
+                    set_attr_all(item, value.clone());
+                }
+                (_, item) => *item = value.clone(),
+            }
+        }
+
+        if !matched {
+            // Identity providers use paths like `emails[type eq "work"].value` to set a value
+            // even when no element matches yet, so create the element from the filter.
+            let Filter::Compare(filter_path, CompareOp::Eq, filter_value) = &filter else {
+                return Err(Report::new(ScimError::NoTarget)
+                    .attach_printable("No values matched the path filter"));
+            };
+
+            let mut item = Map::new();
+            item.insert(filter_path.attr.clone(), filter_value.clone());
+            match (path.sub_attr, value) {
+                (Some(sub_attr), value) => set_attr(&mut item, &sub_attr, value, false),
+                (None, value @ Value::Object(_)) => set_attr_all(&mut item, value),
+                (None, value) => set_attr(&mut item, "value", value, false),
+            }
+            items.push(Value::Object(item));
+        }
+
+        Ok(())
+    }
+}
+
+fn set_attr_all(object: &mut Map<String, Value>, values: Value) {
+    if let Value::Object(values) = values {
+        for (k, v) in values {
+            set_attr(object, &k, v, false);
+        }
+    }
+}
+
+fn get_mut<'a>(object: &'a mut Map<String, Value>, name: &str) -> Option<&'a mut Value> {
+    let key = attr_key(object, name);
+    object.get_mut(&key)
+}
+
+fn get_array<'a>(object: &'a mut Map<String, Value>, name: &str) -> Option<&'a mut Vec<Value>> {
+    get_mut(object, name).and_then(|v| v.as_array_mut())
+}
+
+/// The `value` sub-attribute of a multi-valued attribute element
+fn element_value(item: &Value) -> &Value {
+    super::filter::get_attr(item, "value").unwrap_or(item)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn apply(resource: Value, operations: Value) -> Result<Value, Report<ScimError>> {
+        let mut resource = resource;
+        let request: PatchRequest = serde_json::from_value(json!({
+            "schemas": [PATCH_OP_SCHEMA],
+            "Operations": operations,
+        }))
+        .unwrap();
+        request.apply(&mut resource)?;
+        Ok(resource)
+    }
+
+    #[test]
+    fn user_operations() {
+        let user = json!({
+            "userName": "ada@example.com",
+            "name": { "givenName": "Ada" },
+            "active": true,
+            "emails": [{ "value": "ada@example.com", "type": "work", "primary": true }]
+        });
+
+        // Okta style
+        let result = apply(
+            user.clone(),
+            json!([{ "op": "replace", "value": { "active": false, "name.familyName": "Lovelace" } }]),
+        )
+        .unwrap();
+        assert_eq!(result["active"], false);
+        assert_eq!(
+            result["name"],
+            json!({ "givenName": "Ada", "familyName": "Lovelace" })
+        );
+
+        // Azure AD style
+        let result = apply(
+            user.clone(),
+            json!([
+                { "op": "Replace", "path": "Active", "value": "False" },
+                { "op": "Replace", "path": "emails[type eq \"work\"].value", "value": "ada@new.example.com" },
+                { "op": "Add", "path": "emails[type eq \"home\"].value", "value": "ada@home.example.com" },
+                { "op": "Add", "path": "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department", "value": "Math" },
+                { "op": "Remove", "path": "name.givenName" }
+            ]),
+        )
+        .unwrap();
+        assert_eq!(result["active"], "False");
+        assert_eq!(result["name"], json!({}));
+        assert_eq!(
+            result["emails"],
+            json!([
+                { "value": "ada@new.example.com", "type": "work", "primary": true },
+                { "value": "ada@home.example.com", "type": "home" }
+            ])
+        );
+        assert!(result.get("department").is_none());
+
+        let err = apply(user.clone(), json!([{ "op": "remove" }])).unwrap_err();
+        assert!(matches!(err.current_context(), ScimError::NoTarget));
+
+        let err = apply(
+            user.clone(),
+            json!([{ "op": "replace", "path": "userName" }]),
+        )
+        .unwrap_err();
+        assert!(matches!(err.current_context(), ScimError::InvalidValue));
+
+        let result = apply(
+            user.clone(),
+            json!([{ "op": "replace", "path": "emails[type sw \"w\"].value", "value": "x" }]),
+        )
+        .unwrap();
+        assert_eq!(result["emails"][0]["value"], "x");
+
+        let err = apply(
+            user,
+            json!([{ "op": "replace", "path": "emails[type ne \"work\"].value", "value": "x" }]),
+        )
+        .unwrap_err();
+        assert!(matches!(err.current_context(), ScimError::NoTarget));
+    }
+
+    #[test]
+    fn group_members() {
+        let group = json!({
+            "displayName": "Engineering",
+            "members": [{ "value": "usr_1" }, { "value": "usr_2" }]
+        });
+
+        let result = apply(
+            group.clone(),
+            json!([
+                { "op": "add", "path": "members", "value": [{ "value": "usr_3" }, { "value": "usr_1" }] },
+                { "op": "remove", "path": "members[value eq \"usr_2\"]" },
+                { "op": "replace", "path": "displayName", "value": "Eng" }
+            ]),
+        )
+        .unwrap();
+        assert_eq!(result["displayName"], "Eng");
+        assert_eq!(
+            result["members"],
+            json!([{ "value": "usr_1" }, { "value": "usr_3" }])
+        );
+
+        // Azure AD removes members by value
+        let result = apply(
+            group.clone(),
+            json!([{ "op": "Remove", "path": "members", "value": [{ "value": "usr_1" }] }]),
+        )
+        .unwrap();
+        assert_eq!(result["members"], json!([{ "value": "usr_2" }]));
+
+        let result = apply(
+            group,
+            json!([{ "op": "replace", "path": "members", "value": [] }]),
+        )
+        .unwrap();
+        assert_eq!(result["members"], json!([]));
+    }
+
+    #[test]
+    fn requires_schema() {
+        let request: PatchRequest =
+            serde_json::from_value(json!({ "schemas": [], "Operations": [] })).unwrap();
+        let err = request.apply(&mut json!({})).unwrap_err();
+        assert!(matches!(err.current_context(), ScimError::InvalidSyntax));
+    }
+}
@@ -0,0 +1,1196 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use error_stack::{Report, ResultExt};
+use uuid::Uuid;
+
+use super::{
+    Filter, PatchRequest, ScimEmail, ScimError, ScimGroup, ScimMember, ScimMeta, ScimName,
+    ScimUser, GROUP_SCHEMA, USER_SCHEMA,
+};
+use crate::{
+    auth::{
+        api_key::{add_api_key, ApiKey, ApiKeyData},
+        OrganizationId, RoleId, UserId,
+    },
+    db::{DbExecutor, DbPool},
+    server::FiligreeState,
+    users::{
+        organization::{remove_user_from_organization, set_user_active},
+        roles::{add_roles_to_user, remove_roles_from_user},
+        users::{add_user_email_login, CreateUserDetails},
+    },
+};
+
+/// Create an API key that an organization's identity provider can use to call the SCIM
+/// endpoints. This returns the key information along with the key itself, which is not stored
+/// and should be shown to the user.
+pub async fn create_scim_api_key(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    description: String,
+    expires_at: DateTime<Utc>,
+) -> Result<(ApiKey, String), sqlx::Error> {
+    let data = ApiKeyData::new();
+    let key = ApiKey {
+        api_key_id: data.api_key_id,
+        organization_id,
+        user_id: None,
+        inherits_user_permissions: false,
+        description,
+        active: true,
+        expires_at,
+        scim: true,
+    };
+
+    add_api_key(db, &key, &data.hash).await?;
+    Ok((key, data.key))
+}
+
+/// Delete an API key created by [create_scim_api_key]. Returns false if the organization has no
+/// such key.
+pub async fn delete_scim_api_key(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    api_key_id: &Uuid,
+) -> Result<bool, sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query!(
+        "DELETE FROM api_keys
+        WHERE api_key_id = $1 AND organization_id = $2 AND scim",
+        api_key_id,
+        organization_id.as_uuid()
+    )
+    .execute(db)
+    .await?;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query(
+        "DELETE FROM api_keys
+        WHERE api_key_id = ?1 AND organization_id = ?2 AND scim",
+    )
+    .bind(api_key_id)
+    .bind(organization_id.as_uuid())
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MemberRow {
+    id: UserId,
+    name: String,
+    email: Option<String>,
+    active: bool,
+    external_id: Option<String>,
+    /// If the organization is the user's primary organization
+    primary_organization: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+/// Optional conditions to avoid loading every member of an organization
+#[derive(Debug, Default)]
+struct MemberLookup<'a> {
+    id: Option<UserId>,
+    email: Option<&'a str>,
+    external_id: Option<&'a str>,
+}
+
+async fn fetch_members(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    lookup: MemberLookup<'_>,
+) -> Result<Vec<MemberRow>, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let rows = sqlx::query_as!(
+        MemberRow,
+        r#"SELECT u.id AS "id: UserId",
+            u.name,
+            u.email,
+            om.active,
+            om.scim_external_id AS external_id,
+            COALESCE(u.organization_id = om.organization_id, false) AS "primary_organization!",
+            u.created_at,
+            u.updated_at
+        FROM organization_members om
+        JOIN users u ON u.id = om.user_id
+        WHERE om.organization_id = $1
+            AND ($2::uuid IS NULL OR u.id = $2)
+            AND ($3::text IS NULL OR lower(u.email) = lower($3))
+            AND ($4::text IS NULL OR om.scim_external_id = $4)
+        ORDER BY u.created_at, u.id"#,
+        organization_id.as_uuid(),
+        lookup.id.as_ref().map(|id| id.as_uuid()),
+        lookup.email,
+        lookup.external_id,
+    )
+    .fetch_all(db)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let rows = sqlx::query_as::<_, MemberRow>(
+        "SELECT u.id,
+            u.name,
+            u.email,
+            om.active,
+            om.scim_external_id AS external_id,
+            COALESCE(u.organization_id = om.organization_id, false) AS primary_organization,
+            u.created_at,
+            u.updated_at
+        FROM organization_members om
+        JOIN users u ON u.id = om.user_id
+        WHERE om.organization_id = ?1
+            AND (?2 IS NULL OR u.id = ?2)
+            AND (?3 IS NULL OR lower(u.email) = lower(?3))
+            AND (?4 IS NULL OR om.scim_external_id = ?4)
+        ORDER BY u.created_at, u.id",
+    )
+    .bind(organization_id)
+    .bind(lookup.id)
+    .bind(lookup.email)
+    .bind(lookup.external_id)
+    .fetch_all(db)
+    .await;
+
+    rows.change_context(ScimError::Db)
+}
+
+/// Load the roles of each user, as SCIM group references
+async fn fetch_user_groups(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    user_ids: &[UserId],
+) -> Result<HashMap<UserId, Vec<ScimMember>>, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let rows = sqlx::query!(
+        r#"SELECT ur.user_id AS "user_id: UserId", r.id AS "role_id: RoleId", r.name
+        FROM user_roles ur
+        JOIN roles r ON r.id = ur.role_id
+        WHERE ur.organization_id = $1 AND ur.user_id = ANY($2)
+        ORDER BY r.name"#,
+        organization_id.as_uuid(),
+        user_ids as _
+    )
+    .fetch_all(db)
+    .await
+    .change_context(ScimError::Db)?
+    .into_iter()
+    .map(|r| (r.user_id, r.role_id, r.name));
+
+    #[cfg(feature = "sqlite")]
+    let rows = {
+        let mut q = sqlx::QueryBuilder::new(
+            "SELECT ur.user_id, r.id, r.name
+            FROM user_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            WHERE ur.organization_id = ",
+        );
+        q.push_bind(organization_id);
+        q.push(" AND ur.user_id IN (");
+        let mut ids = q.separated(", ");
+        for user_id in user_ids {
+            ids.push_bind(*user_id);
+        }
+        q.push(") ORDER BY r.name");
+
+        q.build_query_as::<(UserId, RoleId, String)>()
+            .fetch_all(db)
+            .await
+            .change_context(ScimError::Db)?
+    };
+
+    let mut groups: HashMap<UserId, Vec<ScimMember>> = HashMap::new();
+    for (user_id, role_id, name) in rows {
+        groups.entry(user_id).or_default().push(ScimMember {
+            value: role_id.to_string(),
+            display: Some(name),
+        });
+    }
+
+    Ok(groups)
+}
+
+async fn user_resources(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    rows: Vec<MemberRow>,
+) -> Result<Vec<ScimUser>, Report<ScimError>> {
+    let user_ids = rows.iter().map(|r| r.id).collect::<Vec<_>>();
+    let mut groups = fetch_user_groups(db, organization_id, &user_ids).await?;
+
+    let users = rows
+        .into_iter()
+        .map(|row| ScimUser {
+            schemas: vec![USER_SCHEMA.to_string()],
+            id: Some(row.id.to_string()),
+            external_id: row.external_id,
+            user_name: row.email.clone().unwrap_or_else(|| row.id.to_string()),
+            name: Some(ScimName {
+                formatted: Some(row.name.clone()),
+                ..Default::default()
+            }),
+            display_name: Some(row.name),
+            emails: row
+                .email
+                .into_iter()
+                .map(|value| ScimEmail {
+                    value,
+                    kind: Some("work".to_string()),
+                    primary: true,
+                })
+                .collect(),
+            active: row.active,
+            groups: groups.remove(&row.id).unwrap_or_default(),
+            meta: Some(ScimMeta {
+                resource_type: "User".to_string(),
+                created: Some(row.created_at),
+                last_modified: Some(row.updated_at),
+            }),
+        })
+        .collect();
+
+    Ok(users)
+}
+
+/// List the members of an organization as SCIM users, optionally limited to those that match
+/// `filter`.
+pub async fn list_scim_users(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    filter: Option<&Filter>,
+) -> Result<Vec<ScimUser>, Report<ScimError>> {
+    let mut lookup = MemberLookup::default();
+    if let Some((path, value)) = filter.and_then(|f| f.equality()) {
+        let attr = path.attr.to_ascii_lowercase();
+        match (attr.as_str(), path.sub_attr.as_deref()) {
+            ("username", None) | ("emails", None) => lookup.email = Some(value),
+            ("emails", Some(sub)) if sub.eq_ignore_ascii_case("value") => {
+                lookup.email = Some(value)
+            }
+            ("externalid", None) => lookup.external_id = Some(value),
+            ("id", None) => match value.parse() {
+                Ok(id) => lookup.id = Some(id),
+                Err(_) => return Ok(Vec::new()),
+            },
+            _ => {}
+        }
+    }
+
+    let rows = fetch_members(db, organization_id, lookup).await?;
+    let users = user_resources(db, organization_id, rows).await?;
+
+    let Some(filter) = filter else {
+        return Ok(users);
+    };
+
+    let users = users
+        .into_iter()
+        .filter(|user| {
+            serde_json::to_value(user)
+                .map(|value| filter.matches(&value))
+                .unwrap_or(false)
+        })
+        .collect();
+    Ok(users)
+}
+
+/// Get a member of the organization as a SCIM user
+pub async fn get_scim_user(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    user_id: UserId,
+) -> Result<Option<ScimUser>, Report<ScimError>> {
+    let lookup = MemberLookup {
+        id: Some(user_id),
+        ..Default::default()
+    };
+    let rows = fetch_members(db, organization_id, lookup).await?;
+    let users = user_resources(db, organization_id, rows).await?;
+    Ok(users.into_iter().next())
+}
+
+fn missing_email() -> Report<ScimError> {
+    Report::new(ScimError::InvalidValue).attach_printable("A user must have an email address")
+}
+
+/// Check if an email address belongs to a user other than `except_user`, ignoring case
+async fn email_in_use(
+    db: impl DbExecutor<'_>,
+    email: &str,
+    except_user: Option<UserId>,
+) -> Result<bool, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM email_logins WHERE lower(email) = lower($1) AND user_id IS DISTINCT FROM $2
+            UNION ALL
+            SELECT 1 FROM users WHERE lower(email) = lower($1) AND id IS DISTINCT FROM $2
+        ) AS "exists!""#,
+        email,
+        except_user.as_ref().map(|id| id.as_uuid())
+    )
+    .fetch_one(db)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(
+            SELECT 1 FROM email_logins WHERE lower(email) = lower(?1) AND user_id IS NOT ?2
+            UNION ALL
+            SELECT 1 FROM users WHERE lower(email) = lower(?1) AND id IS NOT ?2
+        )",
+    )
+    .bind(email)
+    .bind(except_user)
+    .fetch_one(db)
+    .await;
+
+    result.change_context(ScimError::Db)
+}
+
+/// Check if another member of the organization has the external ID
+async fn external_id_in_use(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    external_id: &str,
+    except_user: Option<UserId>,
+) -> Result<bool, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let result = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM organization_members
+            WHERE organization_id = $1
+                AND scim_external_id = $2
+                AND user_id IS DISTINCT FROM $3
+        ) AS "exists!""#,
+        organization_id.as_uuid(),
+        external_id,
+        except_user.as_ref().map(|id| id.as_uuid())
+    )
+    .fetch_one(db)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let result = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(
+            SELECT 1 FROM organization_members
+            WHERE organization_id = ?1
+                AND scim_external_id = ?2
+                AND user_id IS NOT ?3
+        )",
+    )
+    .bind(organization_id)
+    .bind(external_id)
+    .bind(except_user)
+    .fetch_one(db)
+    .await;
+
+    result.change_context(ScimError::Db)
+}
+
+async fn set_external_id(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    user_id: UserId,
+    external_id: Option<&str>,
+) -> Result<(), Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    sqlx::query!(
+        "UPDATE organization_members
+        SET scim_external_id = $3
+        WHERE organization_id = $1 AND user_id = $2",
+        organization_id.as_uuid(),
+        user_id.as_uuid(),
+        external_id
+    )
+    .execute(db)
+    .await
+    .change_context(ScimError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "UPDATE organization_members
+        SET scim_external_id = ?3
+        WHERE organization_id = ?1 AND user_id = ?2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .bind(external_id)
+    .execute(db)
+    .await
+    .change_context(ScimError::Db)?;
+
+    Ok(())
+}
+
+/// Create a user in the organization, using the [UserCreator](crate::users::users::UserCreator).
+/// This fails if the email address is already in use, even by a user outside the organization.
+pub async fn create_scim_user(
+    state: &FiligreeState,
+    organization_id: OrganizationId,
+    user: &ScimUser,
+) -> Result<ScimUser, Report<ScimError>> {
+    let email = user.email().ok_or_else(missing_email)?;
+
+    let mut tx = state.db.begin().await.change_context(ScimError::Db)?;
+
+    if email_in_use(&mut *tx, email, None).await? {
+        return Err(Report::new(ScimError::Uniqueness)
+            .attach_printable("A user with this email address already exists"));
+    }
+
+    if let Some(external_id) = user.external_id.as_deref() {
+        if external_id_in_use(&mut *tx, organization_id, external_id, None).await? {
+            return Err(Report::new(ScimError::Uniqueness)
+                .attach_printable("A user with this externalId already exists"));
+        }
+    }
+
+    let details = CreateUserDetails {
+        email: Some(email.to_string()),
+        name: user.full_name(),
+        ..Default::default()
+    };
+    let user_id = state
+        .user_creator
+        .create_user(&mut tx, Some(organization_id), details)
+        .await
+        .change_context(ScimError::UserCreation)?;
+
+    set_external_id(
+        &mut *tx,
+        organization_id,
+        user_id,
+        user.external_id.as_deref(),
+    )
+    .await?;
+    set_user_active(&mut *tx, organization_id, user_id, user.active)
+        .await
+        .change_context(ScimError::Db)?;
+
+    tx.commit().await.change_context(ScimError::Db)?;
+
+    get_scim_user(&state.db, organization_id, user_id)
+        .await?
+        .ok_or_else(|| Report::new(ScimError::NotFound))
+}
+
+/// Replace a user's details. The name and email are only updated if this organization is the
+/// user's primary organization.
+pub async fn replace_scim_user(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    user_id: UserId,
+    user: &ScimUser,
+) -> Result<ScimUser, Report<ScimError>> {
+    let mut tx = db.begin().await.change_context(ScimError::Db)?;
+
+    let lookup = MemberLookup {
+        id: Some(user_id),
+        ..Default::default()
+    };
+    let existing = fetch_members(&mut *tx, organization_id, lookup)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ScimError::NotFound)?;
+
+    if let Some(external_id) = user.external_id.as_deref() {
+        if external_id_in_use(&mut *tx, organization_id, external_id, Some(user_id)).await? {
+            return Err(Report::new(ScimError::Uniqueness)
+                .attach_printable("A user with this externalId already exists"));
+        }
+    }
+
+    if existing.primary_organization {
+        let email = user.email().ok_or_else(missing_email)?;
+        if existing.email.as_deref() != Some(email) {
+            if email_in_use(&mut *tx, email, Some(user_id)).await? {
+                return Err(Report::new(ScimError::Uniqueness)
+                    .attach_printable("A user with this email address already exists"));
+            }
+
+            #[cfg(not(feature = "sqlite"))]
+            let result = sqlx::query!(
+                "UPDATE email_logins SET email = $3 WHERE user_id = $1 AND email = $2",
+                user_id.as_uuid(),
+                existing.email,
+                email
+            )
+            .execute(&mut *tx)
+            .await;
+
+            #[cfg(feature = "sqlite")]
+            let result =
+                sqlx::query("UPDATE email_logins SET email = ?3 WHERE user_id = ?1 AND email = ?2")
+                    .bind(user_id)
+                    .bind(&existing.email)
+                    .bind(email)
+                    .execute(&mut *tx)
+                    .await;
+
+            if result.change_context(ScimError::Db)?.rows_affected() == 0 {
+                // The identity provider vouches for the address, so it is already verified.
+                add_user_email_login(&mut *tx, user_id, email.to_string(), true)
+                    .await
+                    .change_context(ScimError::Db)?;
+            }
+        }
+
+        let name = user.full_name().unwrap_or(existing.name);
+
+        #[cfg(not(feature = "sqlite"))]
+        sqlx::query!(
+            "UPDATE users SET name = $2, email = $3, updated_at = now() WHERE id = $1",
+            user_id.as_uuid(),
+            name,
+            email
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+        #[cfg(feature = "sqlite")]
+        sqlx::query(
+            "UPDATE users SET name = ?2, email = ?3, updated_at = unixepoch() WHERE id = ?1",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(email)
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+    }
+
+    set_external_id(
+        &mut *tx,
+        organization_id,
+        user_id,
+        user.external_id.as_deref(),
+    )
+    .await?;
+    set_user_active(&mut *tx, organization_id, user_id, user.active)
+        .await
+        .change_context(ScimError::Db)?;
+
+    tx.commit().await.change_context(ScimError::Db)?;
+
+    get_scim_user(db, organization_id, user_id)
+        .await?
+        .ok_or_else(|| Report::new(ScimError::NotFound))
+}
+
+/// Apply PATCH operations to a user
+pub async fn patch_scim_user(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    user_id: UserId,
+    patch: &PatchRequest,
+) -> Result<ScimUser, Report<ScimError>> {
+    let existing = get_scim_user(db, organization_id, user_id)
+        .await?
+        .ok_or(ScimError::NotFound)?;
+
+    let mut value = serde_json::to_value(&existing).change_context(ScimError::InvalidValue)?;
+    patch.apply(&mut value)?;
+    let mut user: ScimUser = serde_json::from_value(value)
+        .map_err(|e| Report::new(ScimError::InvalidValue).attach_printable(e.to_string()))?;
+
+    // The name is stored as a single string, so use whichever form of the name was changed.
+    let formatted = |u: &ScimUser| u.name.as_ref().and_then(|n| n.formatted.clone());
+    let name = if user.display_name != existing.display_name {
+        user.display_name.clone()
+    } else if formatted(&user) != formatted(&existing) {
+        formatted(&user)
+    } else {
+        let parts = user.name.as_ref().map(|n| ScimName {
+            formatted: None,
+            ..n.clone()
+        });
+        ScimUser {
+            name: parts,
+            ..Default::default()
+        }
+        .full_name()
+        .or(existing.display_name)
+    };
+    user.display_name = name;
+
+    replace_scim_user(db, organization_id, user_id, &user).await
+}
+
+/// Remove a user from the organization, along with all their roles in the organization.
+pub async fn delete_scim_user(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    user_id: UserId,
+) -> Result<(), Report<ScimError>> {
+    let mut tx = db.begin().await.change_context(ScimError::Db)?;
+
+    let lookup = MemberLookup {
+        id: Some(user_id),
+        ..Default::default()
+    };
+    if fetch_members(&mut *tx, organization_id, lookup)
+        .await?
+        .is_empty()
+    {
+        return Err(Report::new(ScimError::NotFound));
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    let role_ids = sqlx::query_scalar!(
+        r#"SELECT role_id AS "role_id: RoleId" FROM user_roles
+        WHERE organization_id = $1 AND user_id = $2"#,
+        organization_id.as_uuid(),
+        user_id.as_uuid()
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let role_ids = sqlx::query_scalar::<_, RoleId>(
+        "SELECT role_id FROM user_roles WHERE organization_id = ?1 AND user_id = ?2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await;
+
+    let role_ids = role_ids.change_context(ScimError::Db)?;
+    remove_roles_from_user(&mut *tx, organization_id, user_id, &role_ids)
+        .await
+        .change_context(ScimError::Db)?;
+    remove_user_from_organization(&mut *tx, organization_id, user_id)
+        .await
+        .change_context(ScimError::Db)?;
+
+    tx.commit().await.change_context(ScimError::Db)?;
+    Ok(())
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct RoleRow {
+    id: RoleId,
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+async fn fetch_roles(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    id: Option<RoleId>,
+    name: Option<&str>,
+) -> Result<Vec<RoleRow>, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let rows = sqlx::query_as!(
+        RoleRow,
+        r#"SELECT id AS "id: RoleId", name, created_at, updated_at
+        FROM roles
+        WHERE organization_id = $1
+            AND ($2::uuid IS NULL OR id = $2)
+            AND ($3::text IS NULL OR lower(name) = lower($3))
+        ORDER BY created_at, id"#,
+        organization_id.as_uuid(),
+        id.as_ref().map(|id| id.as_uuid()),
+        name
+    )
+    .fetch_all(db)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let rows = sqlx::query_as::<_, RoleRow>(
+        "SELECT id, name, created_at, updated_at
+        FROM roles
+        WHERE organization_id = ?1
+            AND (?2 IS NULL OR id = ?2)
+            AND (?3 IS NULL OR lower(name) = lower(?3))
+        ORDER BY created_at, id",
+    )
+    .bind(organization_id)
+    .bind(id)
+    .bind(name)
+    .fetch_all(db)
+    .await;
+
+    rows.change_context(ScimError::Db)
+}
+
+/// Load the members of each role, as SCIM member references
+async fn fetch_group_members(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    role_ids: &[RoleId],
+) -> Result<HashMap<RoleId, Vec<ScimMember>>, Report<ScimError>> {
+    #[cfg(not(feature = "sqlite"))]
+    let rows = sqlx::query!(
+        r#"SELECT ur.role_id AS "role_id: RoleId", u.id AS "user_id: UserId", u.name
+        FROM user_roles ur
+        JOIN organization_members om
+            ON om.organization_id = ur.organization_id AND om.user_id = ur.user_id
+        JOIN users u ON u.id = ur.user_id
+        WHERE ur.organization_id = $1 AND ur.role_id = ANY($2)
+        ORDER BY u.name"#,
+        organization_id.as_uuid(),
+        role_ids as _
+    )
+    .fetch_all(db)
+    .await
+    .change_context(ScimError::Db)?
+    .into_iter()
+    .map(|r| (r.role_id, r.user_id, r.name));
+
+    #[cfg(feature = "sqlite")]
+    let rows = {
+        let mut q = sqlx::QueryBuilder::new(
+            "SELECT ur.role_id, u.id, u.name
+            FROM user_roles ur
+            JOIN organization_members om
+                ON om.organization_id = ur.organization_id AND om.user_id = ur.user_id
+            JOIN users u ON u.id = ur.user_id
+            WHERE ur.organization_id = ",
+        );
+        q.push_bind(organization_id);
+        q.push(" AND ur.role_id IN (");
+        let mut ids = q.separated(", ");
+        for role_id in role_ids {
+            ids.push_bind(*role_id);
+        }
+        q.push(") ORDER BY u.name");
+
+        q.build_query_as::<(RoleId, UserId, String)>()
+            .fetch_all(db)
+            .await
+            .change_context(ScimError::Db)?
+    };
+
+    let mut members: HashMap<RoleId, Vec<ScimMember>> = HashMap::new();
+    for (role_id, user_id, name) in rows {
+        members.entry(role_id).or_default().push(ScimMember {
+            value: user_id.to_string(),
+            display: Some(name),
+        });
+    }
+
+    Ok(members)
+}
+
+async fn group_resources(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    rows: Vec<RoleRow>,
+    include_members: bool,
+) -> Result<Vec<ScimGroup>, Report<ScimError>> {
+    let mut members = if include_members {
+        let role_ids = rows.iter().map(|r| r.id).collect::<Vec<_>>();
+        fetch_group_members(db, organization_id, &role_ids).await?
+    } else {
+        HashMap::new()
+    };
+
+    let groups = rows
+        .into_iter()
+        .map(|row| ScimGroup {
+            schemas: vec![GROUP_SCHEMA.to_string()],
+            id: Some(row.id.to_string()),
+            display_name: row.name,
+            members: members.remove(&row.id).unwrap_or_default(),
+            meta: Some(ScimMeta {
+                resource_type: "Group".to_string(),
+                created: Some(row.created_at),
+                last_modified: Some(row.updated_at),
+            }),
+        })
+        .collect();
+
+    Ok(groups)
+}
+
+/// List the roles of an organization as SCIM groups, optionally limited to those that match
+/// `filter`. Loading the members can be skipped when the client does not need them.
+pub async fn list_scim_groups(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    filter: Option<&Filter>,
+    include_members: bool,
+) -> Result<Vec<ScimGroup>, Report<ScimError>> {
+    let mut id = None;
+    let mut name = None;
+    if let Some((path, value)) = filter.and_then(|f| f.equality()) {
+        let attr = path.attr.to_ascii_lowercase();
+        match (attr.as_str(), path.sub_attr.as_deref()) {
+            ("displayname", None) => name = Some(value),
+            ("id", None) => match value.parse() {
+                Ok(role_id) => id = Some(role_id),
+                Err(_) => return Ok(Vec::new()),
+            },
+            _ => {}
+        }
+    }
+
+    let rows = fetch_roles(db, organization_id, id, name).await?;
+    // Filters on members need the members loaded, even if they won't be returned.
+    let groups = group_resources(
+        db,
+        organization_id,
+        rows,
+        include_members || filter.is_some(),
+    )
+    .await?;
+
+    let Some(filter) = filter else {
+        return Ok(groups);
+    };
+
+    let groups = groups
+        .into_iter()
+        .filter(|group| {
+            serde_json::to_value(group)
+                .map(|value| filter.matches(&value))
+                .unwrap_or(false)
+        })
+        .map(|group| {
+            if include_members {
+                group
+            } else {
+                ScimGroup {
+                    members: Vec::new(),
+                    ..group
+                }
+            }
+        })
+        .collect();
+    Ok(groups)
+}
+
+/// Get a role in the organization as a SCIM group
+pub async fn get_scim_group(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    role_id: RoleId,
+) -> Result<Option<ScimGroup>, Report<ScimError>> {
+    let rows = fetch_roles(db, organization_id, Some(role_id), None).await?;
+    let groups = group_resources(db, organization_id, rows, true).await?;
+    Ok(groups.into_iter().next())
+}
+
+/// Check that a group name is valid and not used by another role in the organization
+async fn check_group_name(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    name: &str,
+    except_role: Option<RoleId>,
+) -> Result<(), Report<ScimError>> {
+    if name.trim().is_empty() {
+        return Err(Report::new(ScimError::InvalidValue)
+            .attach_printable("A group must have a displayName"));
+    }
+
+    let existing = fetch_roles(db, organization_id, None, Some(name)).await?;
+    if existing.iter().any(|r| Some(r.id) != except_role) {
+        return Err(Report::new(ScimError::Uniqueness)
+            .attach_printable("A group with this displayName already exists"));
+    }
+
+    Ok(())
+}
+
+/// Parse the user IDs of a group's members, and make sure that they are members of the
+/// organization.
+async fn member_user_ids(
+    db: impl DbExecutor<'_>,
+    organization_id: OrganizationId,
+    members: &[ScimMember],
+) -> Result<HashSet<UserId>, Report<ScimError>> {
+    let user_ids = members
+        .iter()
+        .map(|m| {
+            m.value.parse::<UserId>().map_err(|_| {
+                Report::new(ScimError::InvalidValue)
+                    .attach_printable(format!("Invalid member {}", m.value))
+            })
+        })
+        .collect::<Result<HashSet<_>, _>>()?;
+    let user_ids = user_ids.into_iter().collect::<Vec<_>>();
+
+    #[cfg(not(feature = "sqlite"))]
+    let found = sqlx::query_scalar!(
+        r#"SELECT user_id AS "user_id: UserId" FROM organization_members
+        WHERE organization_id = $1 AND user_id = ANY($2)"#,
+        organization_id.as_uuid(),
+        &user_ids as _
+    )
+    .fetch_all(db)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let found = {
+        let mut q = sqlx::QueryBuilder::new(
+            "SELECT user_id FROM organization_members WHERE organization_id = ",
+        );
+        q.push_bind(organization_id);
+        q.push(" AND user_id IN (");
+        let mut ids = q.separated(", ");
+        for user_id in &user_ids {
+            ids.push_bind(*user_id);
+        }
+        q.push(")");
+
+        q.build_query_scalar::<UserId>().fetch_all(db).await
+    };
+
+    let found = found
+        .change_context(ScimError::Db)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    if let Some(missing) = user_ids.iter().find(|id| !found.contains(id)) {
+        return Err(
+            Report::new(ScimError::InvalidValue).attach_printable(format!(
+                "User {missing} is not a member of the organization"
+            )),
+        );
+    }
+
+    Ok(found)
+}
+
+/// Create a role in the organization, and add it to the group's members
+pub async fn create_scim_group(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    group: &ScimGroup,
+) -> Result<ScimGroup, Report<ScimError>> {
+    let mut tx = db.begin().await.change_context(ScimError::Db)?;
+
+    check_group_name(&mut *tx, organization_id, &group.display_name, None).await?;
+    let members = member_user_ids(&mut *tx, organization_id, &group.members).await?;
+
+    let role_id = RoleId::new();
+
+    #[cfg(not(feature = "sqlite"))]
+    sqlx::query!(
+        "INSERT INTO roles (id, organization_id, name) VALUES ($1, $2, $3)",
+        role_id.as_uuid(),
+        organization_id.as_uuid(),
+        &group.display_name
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(ScimError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query("INSERT INTO roles (id, organization_id, name) VALUES (?1, ?2, ?3)")
+        .bind(role_id)
+        .bind(organization_id)
+        .bind(&group.display_name)
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+    for user_id in members {
+        add_roles_to_user(&mut *tx, organization_id, user_id, &[role_id])
+            .await
+            .change_context(ScimError::Db)?;
+    }
+
+    tx.commit().await.change_context(ScimError::Db)?;
+
+    get_scim_group(db, organization_id, role_id)
+        .await?
+        .ok_or_else(|| Report::new(ScimError::NotFound))
+}
+
+/// Rename a role and replace its members
+pub async fn replace_scim_group(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    role_id: RoleId,
+    group: &ScimGroup,
+) -> Result<ScimGroup, Report<ScimError>> {
+    let mut tx = db.begin().await.change_context(ScimError::Db)?;
+
+    if fetch_roles(&mut *tx, organization_id, Some(role_id), None)
+        .await?
+        .is_empty()
+    {
+        return Err(Report::new(ScimError::NotFound));
+    }
+
+    check_group_name(
+        &mut *tx,
+        organization_id,
+        &group.display_name,
+        Some(role_id),
+    )
+    .await?;
+    let members = member_user_ids(&mut *tx, organization_id, &group.members).await?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let current = sqlx::query_scalar!(
+        r#"SELECT user_id AS "user_id: UserId" FROM user_roles
+        WHERE organization_id = $1 AND role_id = $2"#,
+        organization_id.as_uuid(),
+        role_id.as_uuid()
+    )
+    .fetch_all(&mut *tx)
+    .await;
+
+    #[cfg(feature = "sqlite")]
+    let current = sqlx::query_scalar::<_, UserId>(
+        "SELECT user_id FROM user_roles WHERE organization_id = ?1 AND role_id = ?2",
+    )
+    .bind(organization_id)
+    .bind(role_id)
+    .fetch_all(&mut *tx)
+    .await;
+
+    let current = current
+        .change_context(ScimError::Db)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    #[cfg(not(feature = "sqlite"))]
+    sqlx::query!(
+        "UPDATE roles SET name = $3, updated_at = now() WHERE id = $1 AND organization_id = $2",
+        role_id.as_uuid(),
+        organization_id.as_uuid(),
+        &group.display_name
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(ScimError::Db)?;
+
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "UPDATE roles SET name = ?3, updated_at = unixepoch() WHERE id = ?1 AND organization_id = ?2",
+    )
+    .bind(role_id)
+    .bind(organization_id)
+    .bind(&group.display_name)
+    .execute(&mut *tx)
+    .await
+    .change_context(ScimError::Db)?;
+
+    for user_id in members.difference(&current) {
+        add_roles_to_user(&mut *tx, organization_id, *user_id, &[role_id])
+            .await
+            .change_context(ScimError::Db)?;
+    }
+
+    for user_id in current.difference(&members) {
+        remove_roles_from_user(&mut *tx, organization_id, *user_id, &[role_id])
+            .await
+            .change_context(ScimError::Db)?;
+    }
+
+    tx.commit().await.change_context(ScimError::Db)?;
+
+    get_scim_group(db, organization_id, role_id)
+        .await?
+        .ok_or_else(|| Report::new(ScimError::NotFound))
+}
+
+/// Apply PATCH operations to a group
+pub async fn patch_scim_group(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    role_id: RoleId,
+    patch: &PatchRequest,
+) -> Result<ScimGroup, Report<ScimError>> {
+    let existing = get_scim_group(db, organization_id, role_id)
+        .await?
+        .ok_or(ScimError::NotFound)?;
+
+    let mut value = serde_json::to_value(&existing).change_context(ScimError::InvalidValue)?;
+    patch.apply(&mut value)?;
+    let group: ScimGroup = serde_json::from_value(value)
+        .map_err(|e| Report::new(ScimError::InvalidValue).attach_printable(e.to_string()))?;
+
+    replace_scim_group(db, organization_id, role_id, &group).await
+}
+
+/// Delete a role from the organization, along with its permissions
+pub async fn delete_scim_group(
+    db: &DbPool,
+    organization_id: OrganizationId,
+    role_id: RoleId,
+) -> Result<(), Report<ScimError>> {
+    let mut tx = db.begin().await.change_context(ScimError::Db)?;
+
+    #[cfg(not(feature = "sqlite"))]
+    let deleted = {
+        let deleted = sqlx::query!(
+            "DELETE FROM roles WHERE id = $1 AND organization_id = $2",
+            role_id.as_uuid(),
+            organization_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+        sqlx::query!(
+            "DELETE FROM permissions WHERE organization_id = $2 AND actor_id = $1",
+            role_id.as_uuid(),
+            organization_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+        sqlx::query!(
+            "DELETE FROM object_permissions WHERE organization_id = $2 AND actor_id = $1",
+            role_id.as_uuid(),
+            organization_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+        sqlx::query!(
+            "UPDATE organizations SET default_role = NULL WHERE id = $2 AND default_role = $1",
+            role_id.as_uuid(),
+            organization_id.as_uuid()
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(ScimError::Db)?;
+
+        deleted
+    };
+
+    #[cfg(feature = "sqlite")]
+    let deleted = {
+        let deleted = sqlx::query("DELETE FROM roles WHERE id = ?1 AND organization_id = ?2")
+            .bind(role_id)
+            .bind(organization_id)
+            .execute(&mut *tx)
+            .await
+            .change_context(ScimError::Db)?;
+
+        for query in [
+            "DELETE FROM permissions WHERE organization_id = ?2 AND actor_id = ?1",
+            "DELETE FROM object_permissions WHERE organization_id = ?2 AND actor_id = ?1",
+            "UPDATE organizations SET default_role = NULL WHERE id = ?2 AND default_role = ?1",
+        ] {
+            sqlx::query(query)
+                .bind(role_id)
+                .bind(organization_id)
+                .execute(&mut *tx)
+                .await
+                .change_context(ScimError::Db)?;
+        }
+
+        deleted
+    };
+
+    if deleted.rows_affected() == 0 {
+        return Err(Report::new(ScimError::NotFound));
+    }
+
+    tx.commit().await.change_context(ScimError::Db)?;
+    Ok(())
+}
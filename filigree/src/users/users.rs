@@ -1,19 +1,22 @@
 use async_trait::async_trait;
 use error_stack::Report;
-use sqlx::{PgConnection, PgExecutor};
 use thiserror::Error;
 use url::Url;
 
-use crate::auth::{OrganizationId, UserId};
+use crate::{
+    auth::{OrganizationId, UserId},
+    db::{DbConnection, DbExecutor},
+};
 
 /// Add a new user email login mapping. If `preverfied` is false, the verification token will be
 /// returned.
 pub async fn add_user_email_login(
-    tx: impl PgExecutor<'_>,
+    tx: impl DbExecutor<'_>,
     user_id: UserId,
     email: String,
     preverified: bool,
 ) -> Result<(), sqlx::Error> {
+    #[cfg(not(feature = "sqlite"))]
     sqlx::query!(
         "INSERT INTO email_logins (user_id, email, verified)
        VALUES ($1, $2, $3)",
@@ -24,6 +27,17 @@ pub async fn add_user_email_login(
     .execute(tx)
     .await?;
 
+    #[cfg(feature = "sqlite")]
+    sqlx::query(
+        "INSERT INTO email_logins (user_id, email, verified)
+       VALUES (?1, ?2, ?3)",
+    )
+    .bind(user_id)
+    .bind(email)
+    .bind(preverified)
+    .execute(tx)
+    .await?;
+
     Ok(())
 }
 
@@ -52,7 +66,7 @@ pub trait UserCreator: Send + Sync + 'static {
     /// Create a new user
     async fn create_user(
         &self,
-        tx: &mut PgConnection,
+        tx: &mut DbConnection,
         add_to_organization: Option<OrganizationId>,
         details: CreateUserDetails,
     ) -> Result<UserId, Report<UserCreatorError>>;
@@ -0,0 +1,482 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+/// The header containing the timestamp and signature of a webhook delivery, in the form
+/// `t=<unix timestamp>,v1=<hex HMAC-SHA256>`
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// The header containing the type of the delivered event, such as `post.created`
+pub const EVENT_HEADER: &str = "x-webhook-event";
+/// The header containing the unique ID of the delivered event. This is the same across retries,
+/// so receivers can use it to ignore duplicate deliveries.
+pub const ID_HEADER: &str = "x-webhook-id";
+
+/// How long to wait for the receiver to respond before considering the delivery failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A reason that a webhook URL can not be used
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WebhookUrlError {
+    /// The URL could not be parsed
+    #[error("Invalid URL")]
+    Invalid,
+    /// The URL does not use HTTP or HTTPS
+    #[error("Webhook URLs must use http or https")]
+    Scheme,
+    /// The URL's host could not be resolved
+    #[error("Could not resolve host")]
+    Resolve,
+    /// The URL's host resolves to a loopback, private, or otherwise internal address
+    #[error("Webhook URL resolves to a non-public address {0}")]
+    NonPublicAddress(IpAddr),
+}
+
+/// Return true if the address is on the public internet. This rejects loopback, private,
+/// link-local (which includes cloud metadata services), shared, multicast, and other special
+/// purpose addresses.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network"
+        || a == 0
+        // Shared address space for carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // Benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local addresses
+        || (first & 0xfe00) == 0xfc00
+        // Link-local addresses
+        || (first & 0xffc0) == 0xfe80
+        // Documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        // IPv4-compatible and NAT64 addresses can reach IPv4 hosts, so reject them instead of
+        // trying to check the embedded address.
+        || ip.segments()[..6] == [0, 0, 0, 0, 0, 0]
+        || ip.segments()[..6] == [0x64, 0xff9b, 0, 0, 0, 0])
+}
+
+/// Check that a webhook URL uses HTTP or HTTPS and that every address its host resolves to is
+/// public, and return the parsed URL along with those addresses.
+///
+/// Set `allow_non_public` to skip the address check, for example when testing against a local
+/// server.
+pub async fn resolve_webhook_url(
+    url: &str,
+    allow_non_public: bool,
+) -> Result<(Url, Vec<SocketAddr>), WebhookUrlError> {
+    let url = Url::parse(url).map_err(|_| WebhookUrlError::Invalid)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(WebhookUrlError::Scheme);
+    }
+
+    let host = url.host_str().ok_or(WebhookUrlError::Invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url
+        .port_or_known_default()
+        .ok_or(WebhookUrlError::Invalid)?;
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| WebhookUrlError::Resolve)?
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() {
+        return Err(WebhookUrlError::Resolve);
+    }
+
+    if !allow_non_public {
+        if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr.ip())) {
+            return Err(WebhookUrlError::NonPublicAddress(addr.ip()));
+        }
+    }
+
+    Ok((url, addrs))
+}
+
+/// An event sent to webhook subscribers
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WebhookEvent {
+    /// A unique ID for this event
+    pub id: Uuid,
+    /// The type of event, such as `post.created`
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The organization that the object belongs to
+    pub organization_id: Uuid,
+    /// The ID of the object that changed
+    pub object_id: String,
+    /// When the event happened
+    pub created_at: DateTime<Utc>,
+    /// The object that changed. For deletions, this only contains the ID.
+    pub data: serde_json::Value,
+}
+
+impl WebhookEvent {
+    /// Create a new event
+    pub fn new(
+        event_type: impl Into<String>,
+        organization_id: Uuid,
+        object_id: impl ToString,
+        data: impl Serialize,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            id: Uuid::now_v7(),
+            event_type: event_type.into(),
+            organization_id,
+            object_id: object_id.to_string(),
+            created_at: Utc::now(),
+            data: serde_json::to_value(data)?,
+        })
+    }
+}
+
+/// Check if an event type matches a subscription's event filter. The filter is a comma-separated
+/// list of event types, where `*` matches every event and `post.*` matches every event for the
+/// `post` model.
+pub fn event_matches(filter: &str, event_type: &str) -> bool {
+    filter.split(',').map(|f| f.trim()).any(|f| {
+        if f == "*" {
+            return true;
+        }
+
+        match f.strip_suffix(".*") {
+            Some(prefix) => event_type
+                .strip_prefix(prefix)
+                .map(|rest| rest.starts_with('.'))
+                .unwrap_or(false),
+            None => f == event_type,
+        }
+    })
+}
+
+fn signature_mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Sign a webhook body, returning the value for the [SIGNATURE_HEADER] header. The signature is
+/// an HMAC-SHA256 of `<timestamp>.<body>`, keyed with the subscription's secret.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let signature = signature_mac(secret, timestamp, body)
+        .finalize()
+        .into_bytes();
+    format!("t={timestamp},v1={}", hex::encode(signature))
+}
+
+/// Verify a [SIGNATURE_HEADER] value against a body, returning the signed timestamp if the
+/// signature is valid. Receivers should also reject timestamps that are too old, to prevent
+/// replayed deliveries.
+pub fn verify_signature(secret: &str, header: &str, body: &[u8]) -> Option<i64> {
+    let mut timestamp = None;
+    let mut signature = None;
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) => timestamp = t.parse::<i64>().ok(),
+            Some(("v1", s)) => signature = hex::decode(s).ok(),
+            _ => {}
+        }
+    }
+
+    let timestamp = timestamp?;
+    let signature = signature?;
+
+    signature_mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .ok()
+        .map(|_| timestamp)
+}
+
+/// The outcome of trying to deliver a webhook event
+#[derive(Debug, Clone)]
+pub struct DeliveryResult {
+    /// The HTTP status returned by the receiver, if it responded
+    pub status_code: Option<u16>,
+    /// A description of the failure, if the delivery did not succeed
+    pub error: Option<String>,
+}
+
+impl DeliveryResult {
+    /// If the receiver accepted the event
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn failed(error: impl ToString) -> Self {
+        Self {
+            status_code: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Send a signed event to a webhook URL. Any 2xx response counts as a successful delivery.
+///
+/// The URL is checked with [resolve_webhook_url] again before sending, and the request is pinned
+/// to the checked addresses, so a DNS change after the webhook was created can not redirect
+/// the delivery to an internal address. Redirects from the receiver are not followed.
+pub async fn deliver(
+    url: &str,
+    secret: &str,
+    event: &WebhookEvent,
+    allow_non_public: bool,
+) -> DeliveryResult {
+    let (url, addrs) = match resolve_webhook_url(url, allow_non_public).await {
+        Ok(resolved) => resolved,
+        Err(e) => return DeliveryResult::failed(e),
+    };
+
+    let mut client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+    if let Some(url::Host::Domain(domain)) = url.host() {
+        client = client.resolve_to_addrs(domain, &addrs);
+    }
+
+    let client = match client.build() {
+        Ok(client) => client,
+        Err(e) => return DeliveryResult::failed(e),
+    };
+
+    send(&client, url, secret, event).await
+}
+
+async fn send(
+    client: &reqwest::Client,
+    url: Url,
+    secret: &str,
+    event: &WebhookEvent,
+) -> DeliveryResult {
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(e) => return DeliveryResult::failed(format!("Failed to serialize event: {e}")),
+    };
+
+    let signature = sign(secret, Utc::now().timestamp(), &body);
+    let response = client
+        .post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(EVENT_HEADER, &event.event_type)
+        .header(ID_HEADER, event.id.to_string())
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            DeliveryResult {
+                status_code: Some(status.as_u16()),
+                error: (!status.is_success()).then(|| format!("Receiver returned {status}")),
+            }
+        }
+        Err(e) => DeliveryResult::failed(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::State, http::HeaderMap, routing::post, Router};
+
+    use super::*;
+
+    #[test]
+    fn matching_events() {
+        assert!(event_matches("*", "post.created"));
+        assert!(event_matches("post.created", "post.created"));
+        assert!(event_matches("task.updated, post.created", "post.created"));
+        assert!(event_matches("post.*", "post.deleted"));
+        assert!(!event_matches("post.*", "poster.deleted"));
+        assert!(!event_matches("post.created", "post.updated"));
+        assert!(!event_matches("", "post.updated"));
+    }
+
+    #[test]
+    fn signature_round_trip() {
+        let body = br#"{"a":1}"#;
+        let header = sign("the-secret", 1700000000, body);
+
+        assert_eq!(
+            verify_signature("the-secret", &header, body),
+            Some(1700000000)
+        );
+        assert_eq!(verify_signature("wrong-secret", &header, body), None);
+        assert_eq!(verify_signature("the-secret", &header, br#"{"a":2}"#), None);
+        assert_eq!(verify_signature("the-secret", "v1=abcd", body), None);
+    }
+
+    #[derive(Clone, Default)]
+    struct Received(Arc<Mutex<Vec<(HeaderMap, bytes::Bytes)>>>);
+
+    async fn start_receiver(status: u16) -> (String, Received) {
+        let received = Received::default();
+        let status = axum::http::StatusCode::from_u16(status).unwrap();
+        let handler = move |State(received): State<Received>,
+                            headers: HeaderMap,
+                            body: bytes::Bytes| async move {
+            received.0.lock().unwrap().push((headers, body));
+            status
+        };
+        let app = Router::new()
+            .route("/hook", post(handler))
+            .with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{addr}/hook"), received)
+    }
+
+    #[tokio::test]
+    async fn deliver_event() {
+        let (url, received) = start_receiver(204).await;
+        let event = WebhookEvent::new(
+            "post.created",
+            Uuid::now_v7(),
+            "pst123",
+            serde_json::json!({ "subject": "Hello" }),
+        )
+        .unwrap();
+
+        let result = deliver(&url, "the-secret", &event, true).await;
+        assert!(result.succeeded(), "{result:?}");
+        assert_eq!(result.status_code, Some(204));
+
+        let received = received.0.lock().unwrap();
+        let (headers, body) = &received[0];
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify_signature("the-secret", signature, body).is_some());
+        assert_eq!(headers[EVENT_HEADER], "post.created");
+        assert_eq!(headers[ID_HEADER], event.id.to_string().as_str());
+
+        let body: WebhookEvent = serde_json::from_slice(body).unwrap();
+        assert_eq!(body.id, event.id);
+        assert_eq!(body.data["subject"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn failed_delivery() {
+        let (url, _) = start_receiver(500).await;
+        let event = WebhookEvent::new("post.created", Uuid::now_v7(), "pst123", ()).unwrap();
+
+        let result = deliver(&url, "the-secret", &event, true).await;
+        assert!(!result.succeeded());
+        assert_eq!(result.status_code, Some(500));
+    }
+
+    #[tokio::test]
+    async fn redirects_are_not_followed() {
+        let (target_url, received) = start_receiver(204).await;
+        let app = Router::new().route(
+            "/hook",
+            post(move || async move {
+                (
+                    axum::http::StatusCode::TEMPORARY_REDIRECT,
+                    [(axum::http::header::LOCATION, target_url)],
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let event = WebhookEvent::new("post.created", Uuid::now_v7(), "pst123", ()).unwrap();
+        let result = deliver(&url, "the-secret", &event, true).await;
+        assert!(!result.succeeded());
+        assert_eq!(result.status_code, Some(307));
+        assert!(received.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_non_public_urls() {
+        let (url, received) = start_receiver(204).await;
+        let event = WebhookEvent::new("post.created", Uuid::now_v7(), "pst123", ()).unwrap();
+
+        let result = deliver(&url, "the-secret", &event, false).await;
+        assert!(!result.succeeded());
+        assert_eq!(result.status_code, None);
+        assert!(received.0.lock().unwrap().is_empty());
+
+        for url in [
+            "http://localhost/hook",
+            "http://10.1.2.3/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[::ffff:192.168.0.1]/hook",
+            "http://[fd00:ec2::254]/hook",
+        ] {
+            let result = resolve_webhook_url(url, false).await;
+            assert!(
+                matches!(result, Err(WebhookUrlError::NonPublicAddress(_))),
+                "{url}: {result:?}"
+            );
+        }
+
+        assert_eq!(
+            resolve_webhook_url("file:///etc/passwd", true).await,
+            Err(WebhookUrlError::Scheme)
+        );
+        assert_eq!(
+            resolve_webhook_url("not a url", true).await,
+            Err(WebhookUrlError::Invalid)
+        );
+        assert!(resolve_webhook_url("https://93.184.216.34/hook", false)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn public_addresses() {
+        assert!(is_public_address("93.184.216.34".parse().unwrap()));
+        assert!(is_public_address("2606:2800:220:1::1".parse().unwrap()));
+        assert!(!is_public_address("127.0.0.1".parse().unwrap()));
+        assert!(!is_public_address("0.0.0.0".parse().unwrap()));
+        assert!(!is_public_address("172.16.5.4".parse().unwrap()));
+        assert!(!is_public_address("192.168.1.1".parse().unwrap()));
+        assert!(!is_public_address("100.100.100.200".parse().unwrap()));
+        assert!(!is_public_address("fe80::1".parse().unwrap()));
+        assert!(!is_public_address("::ffff:127.0.0.1".parse().unwrap()));
+    }
+}